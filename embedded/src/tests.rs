@@ -18,11 +18,12 @@ use lightning_signer::lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecre
 use lightning_signer::lightning_invoice::{
     Currency, InvoiceBuilder, RawDataPart, RawHrp, RawInvoice, SignedRawInvoice,
 };
-use lightning_signer::node::{Node, NodeConfig, SpendType};
+use lightning_signer::node::{Capabilities, Node, NodeConfig, SpendType};
 use lightning_signer::persist::{DummyPersister, Persist};
 use lightning_signer::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
 use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
 use lightning_signer::tx::tx::HTLCInfo2;
+use lightning_signer::util::{DEFAULT_MIN_INVOICE_CLTV_EXPIRY, INITIAL_COMMITMENT_NUMBER};
 use lightning_signer::wallet::Wallet;
 use lightning_signer::Arc;
 
@@ -89,9 +90,12 @@ pub fn make_test_channel_setup(
         holder_selected_contest_delay: 6,
         holder_shutdown_script: None,
         counterparty_points,
+        counterparty_node_id: None,
         counterparty_selected_contest_delay: 6,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        splice_pending_amount: None,
+        splice_funding: None,
     }
 }
 
@@ -127,6 +131,14 @@ pub fn test_lightning_signer(postscript: fn()) {
     let config = NodeConfig {
         network: bitcoin::Network::Signet,
         key_derivation_style: KeyDerivationStyle::Native,
+        require_static_remote_key: false,
+        low_r_grinding: false,
+        allow_rbf_funding: true,
+        maximum_commitment_number: INITIAL_COMMITMENT_NUMBER,
+        export_allowed: false,
+        allowlist_match_derivable: false,
+        min_invoice_cltv_expiry: DEFAULT_MIN_INVOICE_CLTV_EXPIRY,
+        capabilities: Capabilities::ALL,
     };
     let seed = [0u8; 32];
     let seed1 = [1u8; 32];
@@ -138,7 +150,7 @@ pub fn test_lightning_signer(postscript: fn()) {
     let node = Arc::new(Node::new(config, &seed, &persister, Vec::new(), factory.clone()));
     let node1 = Arc::new(Node::new(config, &seed1, &persister, Vec::new(), factory));
 
-    assert_eq!(node.ecdh(&node1.get_id()), node1.ecdh(&node.get_id()));
+    assert_eq!(node.ecdh(&node1.get_id()).unwrap(), node1.ecdh(&node.get_id()).unwrap());
 
     let (channel_id, _) = node.new_channel(None, None, &node).unwrap();
     let (channel_id1, _) = node1.new_channel(None, None, &node).unwrap();
@@ -206,7 +218,7 @@ pub fn test_lightning_signer(postscript: fn()) {
     node.remove_allowlist(&vec!["helloworld".to_string()]).expect_err("bad address");
     node.sign_node_announcement(&vec![]).unwrap();
     node.sign_channel_update(&vec![]).unwrap();
-    channel.sign_channel_announcement(&vec![]);
+    channel.sign_channel_announcement(&vec![]).unwrap();
 
     postscript();
 }
@@ -1,14 +1,16 @@
 use core::any::Any;
 use core::fmt;
 use core::fmt::{Debug, Error, Formatter};
+use core::time::Duration;
 
-use bitcoin::hashes::hex;
+use bitcoin::hashes::hash160::Hash as Hash160;
+use bitcoin::hashes::hex::{self, ToHex};
 use bitcoin::hashes::sha256::Hash as Sha256Hash;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::{self, All, Message, PublicKey, Secp256k1, SecretKey, Signature};
 use bitcoin::util::bip143::SigHashCache;
-use bitcoin::{Network, OutPoint, Script, SigHashType, Transaction};
+use bitcoin::{Network, OutPoint, Script, SigHashType, Transaction, Txid};
 use lightning::chain;
 use lightning::chain::keysinterface::{BaseSign, InMemorySigner, KeysInterface};
 use lightning::ln::chan_utils::{
@@ -22,21 +24,25 @@ use lightning::ln::{chan_utils, PaymentHash, PaymentPreimage};
 use log::{debug, trace, warn};
 
 use crate::monitor::ChainMonitor;
-use crate::node::Node;
+use crate::node::{Capabilities, Node};
 use crate::policy::error::policy_error;
 use crate::policy::validator::{ChainState, EnforcementState, Validator};
 use crate::prelude::*;
 use crate::tx::tx::{
-    build_commitment_tx, get_commitment_transaction_number_obscure_factor, CommitmentInfo2,
-    HTLCInfo2,
+    build_commitment_tx, get_commitment_transaction_number_obscure_factor,
+    parse_offered_htlc_script, parse_received_htlc_script, parse_revokeable_redeemscript,
+    CommitmentFormat, CommitmentInfo, CommitmentInfo2, HTLCInfo2,
 };
 use crate::util::crypto_utils::{
-    derive_private_revocation_key, derive_public_key, derive_revocation_pubkey,
+    derive_private_revocation_key, derive_public_key, derive_revocation_pubkey, payload_for_p2wsh,
+    sign_maybe_low_r, signature_to_bitcoin_vec,
+};
+use crate::util::debug_utils::{
+    DebugChannelPublicKeys, DebugHTLCOutputInCommitment, DebugInMemorySigner, DebugVecVecU8,
 };
-use crate::util::debug_utils::{DebugHTLCOutputInCommitment, DebugInMemorySigner, DebugVecVecU8};
 use crate::util::status::{internal_error, invalid_argument, Status};
 use crate::util::INITIAL_COMMITMENT_NUMBER;
-use crate::wallet::Wallet;
+use crate::wallet::{AuthorizedScriptsWallet, Wallet};
 use crate::{Arc, Weak};
 
 /// Channel identifier
@@ -93,8 +99,27 @@ pub enum CommitmentType {
     StaticRemoteKey,
     /// Anchors
     Anchors,
+    /// Simple taproot channels, from the draft BOLT-3 extension.
+    /// These use anchors.  Commitment signing is not supported yet.
+    SimpleTaprootChannels,
+}
+
+impl CommitmentType {
+    /// The expected version, locktime and sequence encoding of commitment
+    /// transactions of this type
+    pub fn commitment_format(&self) -> CommitmentFormat {
+        match self {
+            CommitmentType::Legacy
+            | CommitmentType::StaticRemoteKey
+            | CommitmentType::Anchors
+            | CommitmentType::SimpleTaprootChannels => CommitmentFormat::BOLT3,
+        }
+    }
 }
 
+/// A relative locktime (CSV) delay, in blocks
+pub type CsvDelay = u16;
+
 /// The negotiated parameters for the [Channel]
 #[derive(Clone)]
 pub struct ChannelSetup {
@@ -113,6 +138,8 @@ pub struct ChannelSetup {
     pub holder_shutdown_script: Option<Script>,
     /// The counterparty's basepoints and pubkeys
     pub counterparty_points: ChannelPublicKeys,
+    /// The counterparty's node id, if known
+    pub counterparty_node_id: Option<PublicKey>,
     // DUP keys.inner.remote_channel_pubkeys
     /// remotely imposed requirement on the local commitment transaction to_self_delay
     pub counterparty_selected_contest_delay: u16,
@@ -120,6 +147,12 @@ pub struct ChannelSetup {
     pub counterparty_shutdown_script: Option<Script>,
     /// The negotiated commitment type
     pub commitment_type: CommitmentType,
+    /// The new channel value agreed in a splice negotiation, until the
+    /// splice transaction is signed
+    pub splice_pending_amount: Option<u64>,
+    /// The new funding outpoint and channel value of a signed splice,
+    /// until the splice transaction is confirmed
+    pub splice_funding: Option<(OutPoint, u64)>,
 }
 
 // Need to define manually because ChannelPublicKeys doesn't derive Debug.
@@ -133,9 +166,12 @@ impl fmt::Debug for ChannelSetup {
             .field("holder_selected_contest_delay", &self.holder_selected_contest_delay)
             .field("holder_shutdown_script", &self.holder_shutdown_script)
             .field("counterparty_points", log_channel_public_keys!(&self.counterparty_points))
+            .field("counterparty_node_id", &self.counterparty_node_id)
             .field("counterparty_selected_contest_delay", &self.counterparty_selected_contest_delay)
             .field("counterparty_shutdown_script", &self.counterparty_shutdown_script)
             .field("commitment_type", &self.commitment_type)
+            .field("splice_pending_amount", &self.splice_pending_amount)
+            .field("splice_funding", &self.splice_funding)
             .finish()
     }
 }
@@ -148,6 +184,32 @@ impl ChannelSetup {
     /// True if this channel uses anchors.
     pub fn option_anchor_outputs(&self) -> bool {
         self.commitment_type == CommitmentType::Anchors
+            || self.commitment_type == CommitmentType::SimpleTaprootChannels
+    }
+
+    /// True if this is a simple taproot channel
+    pub fn option_simple_taproot_channels(&self) -> bool {
+        self.commitment_type == CommitmentType::SimpleTaprootChannels
+    }
+
+    /// The CSV delay selected by the local side (the holder), which is
+    /// [ChannelSetup::holder_selected_contest_delay].
+    ///
+    /// The holder imposes this delay on the counterparty - it applies to
+    /// the `to_local` output of counterparty commitment transactions, and
+    /// to the counterparty's second-level HTLC transactions.
+    pub fn local_csv(&self) -> CsvDelay {
+        self.holder_selected_contest_delay
+    }
+
+    /// The CSV delay selected by the remote side (the counterparty), which
+    /// is [ChannelSetup::counterparty_selected_contest_delay].
+    ///
+    /// The counterparty imposes this delay on us - it applies to the
+    /// `to_local` output of holder commitment transactions, and to our
+    /// second-level HTLC transactions.
+    pub fn remote_csv(&self) -> CsvDelay {
+        self.counterparty_selected_contest_delay
     }
 }
 
@@ -157,6 +219,8 @@ pub trait ChannelBase: Any {
     fn get_channel_basepoints(&self) -> ChannelPublicKeys;
     /// Get the per-commitment point for a holder commitment transaction
     fn get_per_commitment_point(&self, commitment_number: u64) -> Result<PublicKey, Status>;
+    /// Get the channel transaction parameters, or None if the channel is not ready yet
+    fn get_channel_parameters(&self) -> Option<ChannelTransactionParameters>;
     /// Get the per-commitment secret for a holder commitment transaction
     // TODO leaking secret
     fn get_per_commitment_secret(&self, commitment_number: u64) -> Result<SecretKey, Status>;
@@ -196,14 +260,28 @@ impl ChannelSlot {
         }
     }
 
-    /// The initial channel ID
+    /// The initial channel ID, same as [ChannelSlot::channel_id]
     pub fn id(&self) -> ChannelId {
+        self.channel_id()
+    }
+
+    /// The primary (initial) channel ID, which the channel is persisted under
+    pub fn channel_id(&self) -> ChannelId {
         match self {
             ChannelSlot::Stub(stub) => stub.id0,
             ChannelSlot::Ready(chan) => chan.id0,
         }
     }
 
+    /// The alternate channel ID supplied to [Node::ready_channel], if any.
+    /// Stubs never have one.
+    pub fn alternate_channel_id(&self) -> Option<ChannelId> {
+        match self {
+            ChannelSlot::Stub(_) => None,
+            ChannelSlot::Ready(chan) => chan.id,
+        }
+    }
+
     /// The basepoints
     pub fn get_channel_basepoints(&self) -> ChannelPublicKeys {
         match self {
@@ -226,6 +304,12 @@ pub struct ChannelStub {
     // Incomplete, channel_value_sat is placeholder.
     /// The initial channel ID, used to find the channel in the node
     pub id0: ChannelId,
+    /// When the stub was created, per the node clock.  Stubs restored
+    /// from persistence use the time of restore.
+    pub creation_time: Duration,
+    /// Counterparty points supplied before the channel is ready.  If set,
+    /// [Node::ready_channel] requires the channel setup to match.
+    pub counterparty_points: Option<ChannelPublicKeys>,
 }
 
 // Need to define manually because InMemorySigner doesn't derive Debug.
@@ -235,6 +319,11 @@ impl fmt::Debug for ChannelStub {
             .field("nonce", &self.nonce)
             .field("keys", &DebugInMemorySigner(&self.keys))
             .field("id0", &self.id0)
+            .field("creation_time", &self.creation_time)
+            .field(
+                "counterparty_points",
+                &self.counterparty_points.as_ref().map(DebugChannelPublicKeys),
+            )
             .finish()
     }
 }
@@ -257,6 +346,11 @@ impl ChannelBase for ChannelStub {
         ))
     }
 
+    fn get_channel_parameters(&self) -> Option<ChannelTransactionParameters> {
+        // The counterparty parameters and funding outpoint aren't known yet
+        None
+    }
+
     fn get_per_commitment_secret(&self, _commitment_number: u64) -> Result<SecretKey, Status> {
         // We can't release a commitment_secret from a ChannelStub ever.
         Err(policy_error(format!("channel stub cannot release commitment secret")).into())
@@ -278,20 +372,26 @@ impl ChannelBase for ChannelStub {
 
     fn validator(&self) -> Arc<dyn Validator> {
         let node = self.node.upgrade().unwrap();
-        let v = node.validator_factory.lock().unwrap().make_validator(
-            node.network(),
-            node.get_id(),
-            Some(self.id0),
-        );
-        v
+        node.make_validator(Some(self.id0))
     }
 }
 
 impl ChannelStub {
+    /// Store the counterparty points, so that they can be checked
+    /// when the channel is readied.  The points are persisted.
+    pub fn set_counterparty_points(&mut self, points: ChannelPublicKeys) -> Result<(), Status> {
+        let node = self.node.upgrade().unwrap();
+        node.persister
+            .update_channel_stub_points(&node.get_id(), &self.id0, &points)
+            .map_err(|_| internal_error("persist failed"))?;
+        self.counterparty_points = Some(points);
+        Ok(())
+    }
+
     pub(crate) fn channel_keys_with_channel_value(&self, channel_value_sat: u64) -> InMemorySigner {
         let secp_ctx = Secp256k1::signing_only();
         let keys = &self.keys;
-        let node_secret = self.node.upgrade().unwrap().get_node_secret();
+        let node_secret = self.node.upgrade().unwrap().node_secret();
         InMemorySigner::new(
             &secp_ctx,
             node_secret,
@@ -305,6 +405,33 @@ impl ChannelStub {
             keys.channel_keys_id(),
         )
     }
+
+    /// Execute a function with the holder commitment keys for
+    /// `per_commitment_point`, before the channel is ready.
+    ///
+    /// The stub does not know the counterparty's basepoints yet,
+    /// so they must be supplied.
+    pub fn with_commitment_keys<F, T>(
+        &self,
+        per_commitment_point: &PublicKey,
+        counterparty_points: &ChannelPublicKeys,
+        f: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(TxCreationKeys) -> Result<T, Status>,
+    {
+        let holder_points = self.keys.pubkeys();
+        let keys = TxCreationKeys::derive_new(
+            &self.secp_ctx,
+            per_commitment_point,
+            &holder_points.delayed_payment_basepoint,
+            &holder_points.htlc_basepoint,
+            &counterparty_points.revocation_basepoint,
+            &counterparty_points.htlc_basepoint,
+        )
+        .map_err(|err| invalid_argument(format!("failed to derive keys: {}", err)))?;
+        f(keys)
+    }
 }
 
 /// After [Node::ready_channel]
@@ -365,6 +492,10 @@ impl ChannelBase for Channel {
         ))
     }
 
+    fn get_channel_parameters(&self) -> Option<ChannelTransactionParameters> {
+        Some(self.make_channel_parameters())
+    }
+
     fn get_per_commitment_secret(&self, commitment_number: u64) -> Result<SecretKey, Status> {
         let next_holder_commit_num = self.enforcement_state.next_holder_commit_num;
         // policy-revoke-new-commitment-signed
@@ -396,13 +527,7 @@ impl ChannelBase for Channel {
     }
 
     fn validator(&self) -> Arc<dyn Validator> {
-        let node = self.get_node();
-        let v = node.validator_factory.lock().unwrap().make_validator(
-            self.network(),
-            node.get_id(),
-            Some(self.id0),
-        );
-        v
+        self.get_node().make_validator(Some(self.id0))
     }
 }
 
@@ -412,6 +537,19 @@ impl Channel {
         self.id.unwrap_or(self.id0)
     }
 
+    /// The 2-of-2 redeemscript of the funding output
+    pub fn funding_redeemscript(&self) -> Script {
+        make_funding_redeemscript(
+            &self.keys.pubkeys().funding_pubkey,
+            &self.setup.counterparty_points.funding_pubkey,
+        )
+    }
+
+    /// The P2WSH script_pubkey of the funding output
+    pub fn funding_script_pubkey(&self) -> Script {
+        payload_for_p2wsh(&self.funding_redeemscript()).script_pubkey()
+    }
+
     #[allow(missing_docs)]
     #[cfg(feature = "test_utils")]
     pub fn set_next_counterparty_commit_num_for_testing(
@@ -428,13 +566,146 @@ impl Channel {
         self.enforcement_state.set_next_counterparty_revoke_num_for_testing(num);
     }
 
+    // policy-commitment-signing-rate
+    fn check_signing_rate(&self) -> Result<(), Status> {
+        self.get_node().check_signing_rate(&self.id0, self.validator().signing_rate_limit())
+    }
+
+    // Only the initial commitments may be signed before the funding is
+    // established, later ones could spend a funding output that doesn't exist yet
+    fn check_funding_established(&self, commitment_number: u64) -> Result<(), Status> {
+        if commitment_number > 0 && !self.enforcement_state.is_funding_established() {
+            return Err(policy_error(format!(
+                "funding not established: initial holder commitment not validated \
+                 before signing commitment {}",
+                commitment_number
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     fn get_chain_state(&self) -> ChainState {
-        self.monitor.as_chain_state()
+        let mut chain_state = self.monitor.as_chain_state();
+        // The node may have been notified of blocks beyond what the monitor has seen
+        chain_state.current_height =
+            core::cmp::max(chain_state.current_height, self.get_node().get_notified_height());
+        chain_state
     }
 }
 
+/// The parameters a commitment transaction is built from,
+/// see [Channel::recompose_and_compare]
+#[derive(Clone, Debug)]
+pub struct CommitmentParams {
+    /// The counterparty's per-commitment point for a counterparty
+    /// commitment, or None for a holder commitment
+    pub counterparty_point: Option<PublicKey>,
+    /// The commitment number
+    pub commitment_number: u64,
+    /// The feerate
+    pub feerate_per_kw: u32,
+    /// The amount to the holder
+    pub to_holder_value_sat: u64,
+    /// The amount to the counterparty
+    pub to_counterparty_value_sat: u64,
+    /// HTLCs offered by the broadcaster
+    pub offered_htlcs: Vec<HTLCInfo2>,
+    /// HTLCs received by the broadcaster
+    pub received_htlcs: Vec<HTLCInfo2>,
+}
+
+/// The holder's side of a `channel_reestablish` exchange,
+/// see [Channel::expected_holder_reestablish]
+#[derive(Clone, Debug)]
+pub struct HolderReestablishInfo {
+    /// The next holder commitment number we expect to receive a signature for
+    pub next_holder_commit_num: u64,
+    /// The next counterparty commitment number we will sign
+    pub next_counterparty_commit_num: u64,
+    /// The next counterparty commitment number we expect to be revoked
+    pub next_counterparty_revoke_num: u64,
+    /// The secret of our most recently revoked commitment, or None if
+    /// no holder commitment has been revoked yet
+    pub last_holder_secret: Option<SecretKey>,
+    /// The counterparty point for the current counterparty commitment
+    pub current_counterparty_point: Option<PublicKey>,
+}
+
+/// The fields of the `channel_reestablish` message we send,
+/// see [Channel::build_channel_reestablish]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelReestablishData {
+    /// The next holder commitment number we expect to receive a signature for
+    pub next_commitment_number: u64,
+    /// The next counterparty commitment number we expect to be revoked
+    pub next_revocation_number: u64,
+    /// The secret of the last revoked counterparty commitment, or None if
+    /// the counterparty hasn't revoked a commitment yet
+    pub your_last_per_commitment_secret: Option<SecretKey>,
+    /// The per-commitment point of the current holder commitment
+    pub my_current_per_commitment_point: PublicKey,
+}
+
+/// The outcome of [Channel::validate_counterparty_revocation]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterpartyRevocation {
+    /// The counterparty commitment number that was revoked
+    pub revoked_commit_num: u64,
+    /// True if only the current counterparty commitment remains unrevoked,
+    /// so the previous counterparty commitment info is no longer needed
+    /// and has been dropped
+    pub previous_commit_info_released: bool,
+}
+
 // Phase 2
 impl Channel {
+    /// Rebuild a commitment transaction from `params` and compare it to `tx`.
+    ///
+    /// This does not validate policy or change any state, so it can be
+    /// used to check a transaction before asking for it to be signed.
+    pub fn recompose_and_compare(
+        &self,
+        tx: &Transaction,
+        params: &CommitmentParams,
+    ) -> Result<(), Status> {
+        self.recompose_checked(tx, params).map(|_| ())
+    }
+
+    // Rebuild a commitment transaction from `params`, failing if it
+    // doesn't match `tx`
+    fn recompose_checked(
+        &self,
+        tx: &Transaction,
+        params: &CommitmentParams,
+    ) -> Result<CommitmentTransaction, Status> {
+        let htlcs =
+            Self::htlcs_info2_to_oic(params.offered_htlcs.clone(), params.received_htlcs.clone());
+        let recomposed_tx = match &params.counterparty_point {
+            Some(point) => self.make_counterparty_commitment_tx(
+                point,
+                params.commitment_number,
+                params.feerate_per_kw,
+                params.to_holder_value_sat,
+                params.to_counterparty_value_sat,
+                htlcs,
+            ),
+            None => self.make_holder_commitment_tx(
+                params.commitment_number,
+                params.feerate_per_kw,
+                params.to_holder_value_sat,
+                params.to_counterparty_value_sat,
+                htlcs,
+            )?,
+        };
+        if recomposed_tx.trust().built_transaction().transaction != *tx {
+            debug!("ORIGINAL_TX={:#?}", &tx);
+            debug!("RECOMPOSED_TX={:#?}", &recomposed_tx.trust().built_transaction().transaction);
+            return Err(self.recomposed_tx_mismatch(tx, params.commitment_number));
+        }
+        Ok(recomposed_tx)
+    }
+
     // Phase 2
     pub(crate) fn make_counterparty_tx_keys(
         &self,
@@ -501,6 +772,29 @@ impl Channel {
         )
     }
 
+    // Describe why a commitment tx doesn't match its recomposition,
+    // checking the fields fixed by the commitment format first
+    fn recomposed_tx_mismatch(&self, tx: &Transaction, commitment_number: u64) -> Status {
+        let format = self.setup.commitment_type.commitment_format();
+        let obscured_commit_num =
+            self.get_commitment_transaction_number_obscure_factor() ^ commitment_number;
+        let reason = if tx.version != format.version {
+            // policy-commitment-version
+            format!(": bad commitment version: {}", tx.version)
+        } else if tx.lock_time != format.lock_time(obscured_commit_num) {
+            // policy-commitment-locktime
+            format!(": bad commitment locktime: {}", tx.lock_time)
+        } else if tx.input.len() == 1
+            && tx.input[0].sequence != format.sequence(obscured_commit_num)
+        {
+            // policy-commitment-sequence
+            format!(": bad commitment sequence: {}", tx.input[0].sequence)
+        } else {
+            String::new()
+        };
+        policy_error(format!("recomposed tx mismatch{}", reason)).into()
+    }
+
     // forward counting commitment number
     #[allow(dead_code)]
     pub(crate) fn build_commitment_tx(
@@ -531,6 +825,7 @@ impl Channel {
             info,
             obscured_commitment_transaction_number,
             self.setup.funding_outpoint,
+            &self.setup.commitment_type.commitment_format(),
             self.setup.option_anchor_outputs(),
             workaround_local_funding_pubkey,
             workaround_remote_funding_pubkey,
@@ -550,18 +845,71 @@ impl Channel {
         offered_htlcs: Vec<HTLCInfo2>,
         received_htlcs: Vec<HTLCInfo2>,
     ) -> Result<(Signature, Vec<Signature>), Status> {
-        // Since we didn't have the value at the real open, validate it now.
-        let validator = self.validator();
-        validator.validate_channel_value(&self.setup)?;
-
         let info2 = self.build_counterparty_commitment_info(
             remote_per_commitment_point,
             to_holder_value_sat,
             to_counterparty_value_sat,
-            offered_htlcs.clone(),
-            received_htlcs.clone(),
+            offered_htlcs,
+            received_htlcs,
             feerate_per_kw,
         )?;
+        self.sign_counterparty_commitment_info(
+            remote_per_commitment_point,
+            commitment_number,
+            info2,
+        )
+    }
+
+    /// Sign a counterparty commitment transaction described by `info`,
+    /// which supplies the balances, HTLCs and feerate.
+    ///
+    /// The keys in `info` must be the ones derived for `per_commitment_point`.
+    /// Returns the commitment signature and the HTLC signatures, each in
+    /// bitcoin format (DER with the sighash type appended).
+    pub fn sign_counterparty_commitment_tx_v2(
+        &mut self,
+        per_commitment_point: &PublicKey,
+        commit_num: u64,
+        info: &CommitmentInfo2,
+    ) -> Result<(Vec<u8>, Vec<Vec<u8>>), Status> {
+        if !info.is_counterparty_broadcaster {
+            return Err(invalid_argument("commitment info is not for a counterparty commitment"));
+        }
+        let info2 = self.build_counterparty_commitment_info(
+            per_commitment_point,
+            info.to_countersigner_value_sat,
+            info.to_broadcaster_value_sat,
+            info.offered_htlcs.clone(),
+            info.received_htlcs.clone(),
+            info.feerate_per_kw,
+        )?;
+        if info2 != *info {
+            return Err(invalid_argument(format!(
+                "commitment info does not match per_commitment_point {}",
+                per_commitment_point
+            )));
+        }
+        let (sig, htlc_sigs) =
+            self.sign_counterparty_commitment_info(per_commitment_point, commit_num, info2)?;
+        Ok((
+            signature_to_bitcoin_vec(sig),
+            htlc_sigs.into_iter().map(signature_to_bitcoin_vec).collect(),
+        ))
+    }
+
+    fn sign_counterparty_commitment_info(
+        &mut self,
+        remote_per_commitment_point: &PublicKey,
+        commitment_number: u64,
+        info2: CommitmentInfo2,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        self.get_node().check_capability(Capabilities::COMMITMENTS)?;
+        self.check_signing_rate()?;
+        self.check_funding_established(commitment_number)?;
+
+        // Since we didn't have the value at the real open, validate it now.
+        let validator = self.validator();
+        validator.validate_channel_value(&self.setup)?;
 
         let node = self.get_node();
         let mut state = node.get_state();
@@ -572,6 +920,7 @@ impl Channel {
             self.enforcement_state.incoming_payments_summary(None, Some(&info2));
 
         validator.validate_counterparty_commitment_tx(
+            &self.get_node().node_config,
             &self.enforcement_state,
             commitment_number,
             &remote_per_commitment_point,
@@ -580,21 +929,23 @@ impl Channel {
             &info2,
         )?;
 
-        let htlcs = Self::htlcs_info2_to_oic(offered_htlcs, received_htlcs);
+        let htlcs =
+            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
 
         let commitment_tx = self.make_counterparty_commitment_tx(
             remote_per_commitment_point,
             commitment_number,
-            feerate_per_kw,
-            to_holder_value_sat,
-            to_counterparty_value_sat,
+            info2.feerate_per_kw,
+            info2.to_countersigner_value_sat,
+            info2.to_broadcaster_value_sat,
             htlcs,
         );
 
-        let (sig, htlc_sigs) = self
+        let sigs = self
             .keys
             .sign_counterparty_commitment(&commitment_tx, Vec::new(), &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        let (sig, htlc_sigs) = self.maybe_low_r_commitment_sigs(&commitment_tx, true, sigs)?;
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
         state.validate_payments(
@@ -605,12 +956,19 @@ impl Channel {
             validator.clone(),
         )?;
 
+        let fee_increment_msat = self.enforcement_state.counterparty_fee_increment_msat(
+            commitment_number,
+            &info2,
+            &self.setup,
+        );
         // Only advance the state if nothing goes wrong.
         self.enforcement_state.set_next_counterparty_commit_num(
             commitment_number + 1,
             remote_per_commitment_point.clone(),
             info2,
         )?;
+        self.enforcement_state.total_fees_paid_msat =
+            self.enforcement_state.total_fees_paid_after_msat(fee_increment_msat);
 
         state.apply_payments(
             &self.id0,
@@ -681,38 +1039,71 @@ impl Channel {
         counterparty_htlc_sigs: &Vec<Signature>,
         recomposed_tx: CommitmentTransaction,
     ) -> Result<(), Status> {
-        let redeemscript = make_funding_redeemscript(
-            &self.keys.pubkeys().funding_pubkey,
-            &self.setup.counterparty_points.funding_pubkey,
-        );
+        self.check_holder_commitment_signature(
+            &recomposed_tx.trust().built_transaction().transaction,
+            counterparty_commit_sig,
+        )?;
 
-        let sighash =
-            Message::from_slice(
-                &SigHashCache::new(&recomposed_tx.trust().built_transaction().transaction)
-                    .signature_hash(
-                        0,
-                        &redeemscript,
-                        self.setup.channel_value_sat,
-                        SigHashType::All,
-                    )[..],
-            )
-            .map_err(|ve| internal_error(format!("sighash failed: {}", ve)))?;
+        self.validate_holder_commitment_htlc_signatures(
+            commitment_number,
+            feerate_per_kw,
+            counterparty_htlc_sigs,
+            &recomposed_tx,
+        )
+    }
 
-        let secp_ctx = Secp256k1::new();
-        secp_ctx
+    // Check the counterparty's signature on the funding input of a holder
+    // commitment transaction
+    fn check_holder_commitment_signature(
+        &self,
+        tx: &Transaction,
+        counterparty_commit_sig: &Signature,
+    ) -> Result<(), Status> {
+        let sighash = Message::from_slice(
+            &SigHashCache::new(tx).signature_hash(
+                0,
+                &self.funding_redeemscript(),
+                self.setup.channel_value_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|ve| internal_error(format!("sighash failed: {}", ve)))?;
+
+        self.secp_ctx
             .verify(
                 &sighash,
-                &counterparty_commit_sig,
+                counterparty_commit_sig,
                 &self.setup.counterparty_points.funding_pubkey,
             )
-            .map_err(|ve| policy_error(format!("commit sig verify failed: {}", ve)))?;
+            .map_err(|ve| policy_error(format!("commit sig verify failed: {}", ve)).into())
+    }
+
+    /// Verify the counterparty's signatures on the second-level HTLC
+    /// transactions of a holder commitment.  Each HTLC transaction is
+    /// rebuilt from the recomposed commitment and the signature is
+    /// checked against the counterparty's HTLC pubkey for this commitment.
+    pub fn validate_holder_commitment_htlc_signatures(
+        &self,
+        commitment_number: u64,
+        feerate_per_kw: u32,
+        counterparty_htlc_sigs: &Vec<Signature>,
+        recomposed_tx: &CommitmentTransaction,
+    ) -> Result<(), Status> {
+        if counterparty_htlc_sigs.len() != recomposed_tx.htlcs().len() {
+            return Err(policy_error(format!(
+                "htlc sig count mismatch: {} != {}",
+                counterparty_htlc_sigs.len(),
+                recomposed_tx.htlcs().len()
+            ))
+            .into());
+        }
 
+        let secp_ctx = Secp256k1::new();
         let per_commitment_point = self.get_per_commitment_point(commitment_number)?;
         let txkeys = self
             .make_holder_tx_keys(&per_commitment_point)
             .map_err(|err| internal_error(format!("make_holder_tx_keys failed: {}", err)))?;
         let commitment_txid = recomposed_tx.trust().txid();
-        let to_self_delay = self.setup.counterparty_selected_contest_delay;
 
         let htlc_pubkey = derive_public_key(
             &secp_ctx,
@@ -721,37 +1112,14 @@ impl Channel {
         )
         .map_err(|err| internal_error(format!("derive_public_key failed: {}", err)))?;
 
-        let sig_hash_type = if self.setup.option_anchor_outputs() {
-            SigHashType::SinglePlusAnyoneCanPay
-        } else {
-            SigHashType::All
-        };
-
         for ndx in 0..recomposed_tx.htlcs().len() {
             let htlc = &recomposed_tx.htlcs()[ndx];
 
-            let htlc_redeemscript =
-                get_htlc_redeemscript(htlc, self.setup.option_anchor_outputs(), &txkeys);
-
-            let recomposed_htlc_tx = build_htlc_transaction(
-                &commitment_txid,
-                feerate_per_kw,
-                to_self_delay,
-                htlc,
-                self.setup.option_anchor_outputs(),
-                &txkeys.broadcaster_delayed_payment_key,
-                &txkeys.revocation_key,
-            );
-
-            let recomposed_tx_sighash = Message::from_slice(
-                &SigHashCache::new(&recomposed_htlc_tx).signature_hash(
-                    0,
-                    &htlc_redeemscript,
-                    htlc.amount_msat / 1000,
-                    sig_hash_type,
-                )[..],
-            )
-            .map_err(|err| invalid_argument(format!("sighash failed for htlc {}: {}", ndx, err)))?;
+            let recomposed_tx_sighash = self
+                .counterparty_htlc_sighash(&commitment_txid, feerate_per_kw, &txkeys, htlc)
+                .map_err(|err| {
+                    invalid_argument(format!("sighash failed for htlc {}: {}", ndx, err))
+                })?;
 
             secp_ctx
                 .verify(&recomposed_tx_sighash, &counterparty_htlc_sigs[ndx], &htlc_pubkey)
@@ -762,6 +1130,104 @@ impl Channel {
         Ok(())
     }
 
+    /// Check the counterparty's signature on a single second-level HTLC
+    /// transaction of the current holder commitment `commit_num`.
+    ///
+    /// The HTLC must be one of the commitment's HTLCs, including its
+    /// output index.  The HTLC transaction is rebuilt and the signature
+    /// verified against the counterparty's HTLC pubkey derived from
+    /// `per_commitment_point`.
+    pub fn check_counterparty_htlc_sig(
+        &self,
+        htlc: &HTLCOutputInCommitment,
+        sig: &Signature,
+        per_commitment_point: &PublicKey,
+        commit_num: u64,
+    ) -> Result<(), Status> {
+        let info2 = self.enforcement_state.get_current_holder_commitment_info(commit_num)?;
+
+        let htlcs =
+            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
+
+        let txkeys = self.make_holder_tx_keys(per_commitment_point)?;
+        let recomposed_tx = self.make_holder_commitment_tx_with_keys(
+            txkeys.clone(),
+            commit_num,
+            info2.feerate_per_kw,
+            info2.to_broadcaster_value_sat,
+            info2.to_countersigner_value_sat,
+            htlcs,
+        );
+
+        if !recomposed_tx.htlcs().contains(htlc) {
+            return Err(policy_error(format!(
+                "htlc not in holder commitment {}: {:?}",
+                commit_num,
+                DebugHTLCOutputInCommitment(htlc)
+            ))
+            .into());
+        }
+
+        let htlc_pubkey = derive_public_key(
+            &self.secp_ctx,
+            per_commitment_point,
+            &self.keys.counterparty_pubkeys().htlc_basepoint,
+        )
+        .map_err(|err| internal_error(format!("derive_public_key failed: {}", err)))?;
+
+        let sighash = self
+            .counterparty_htlc_sighash(
+                &recomposed_tx.trust().txid(),
+                info2.feerate_per_kw,
+                &txkeys,
+                htlc,
+            )
+            .map_err(|err| invalid_argument(format!("sighash failed: {}", err)))?;
+
+        self.secp_ctx
+            .verify(&sighash, sig, &htlc_pubkey)
+            .map_err(|err| policy_error(format!("htlc sig verify failed: {}", err)))?;
+        Ok(())
+    }
+
+    // The sighash the counterparty signs for a second-level HTLC
+    // transaction hanging off a holder commitment
+    fn counterparty_htlc_sighash(
+        &self,
+        commitment_txid: &Txid,
+        feerate_per_kw: u32,
+        txkeys: &TxCreationKeys,
+        htlc: &HTLCOutputInCommitment,
+    ) -> Result<Message, secp256k1::Error> {
+        let htlc_redeemscript =
+            get_htlc_redeemscript(htlc, self.setup.option_anchor_outputs(), txkeys);
+
+        let recomposed_htlc_tx = build_htlc_transaction(
+            commitment_txid,
+            feerate_per_kw,
+            self.setup.counterparty_selected_contest_delay,
+            htlc,
+            self.setup.option_anchor_outputs(),
+            &txkeys.broadcaster_delayed_payment_key,
+            &txkeys.revocation_key,
+        );
+
+        let sig_hash_type = if self.setup.option_anchor_outputs() {
+            SigHashType::SinglePlusAnyoneCanPay
+        } else {
+            SigHashType::All
+        };
+
+        Message::from_slice(
+            &SigHashCache::new(&recomposed_htlc_tx).signature_hash(
+                0,
+                &htlc_redeemscript,
+                htlc.amount_msat / 1000,
+                sig_hash_type,
+            )[..],
+        )
+    }
+
     fn advance_holder_commitment_state(
         &mut self,
         commitment_number: u64,
@@ -820,6 +1286,7 @@ impl Channel {
         let validator = self.validator();
         validator
             .validate_holder_commitment_tx(
+                &self.get_node().node_config,
                 &self.enforcement_state,
                 commitment_number,
                 &commitment_point,
@@ -888,6 +1355,9 @@ impl Channel {
         &self,
         commitment_number: u64,
     ) -> Result<(Signature, Vec<Signature>), Status> {
+        self.get_node().check_capability(Capabilities::COMMITMENTS)?;
+        self.check_signing_rate()?;
+        self.check_funding_established(commitment_number)?;
         let info2 = self.enforcement_state.get_current_holder_commitment_info(commitment_number)?;
 
         let htlcs =
@@ -923,22 +1393,187 @@ impl Channel {
         );
 
         // Sign the recomposed commitment.
-        let (sig, htlc_sigs) = self
+        let sigs = self
             .keys
             .sign_holder_commitment_and_htlcs(&recomposed_holder_tx, &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        let (sig, htlc_sigs) =
+            self.maybe_low_r_commitment_sigs(&recomposed_holder_tx, false, sigs)?;
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
         Ok((sig, htlc_sigs))
     }
 
-    /// Sign a holder commitment transaction after rebuilding it
-    /// from the supplied arguments.
-    /// Use [`sign_counterparty_commitment_tx_phase2`] instead of this,
-    /// since that one uses the last counter-signed holder tx, which is simpler
-    /// and doesn't require re-validation of the holder tx.
-    // TODO anchors support once upstream supports it
+    /// Sign all of the second-level HTLC transactions of the current
+    /// holder commitment when force-closing.  The HTLC transactions are
+    /// rebuilt from the last counter-signed holder commitment, so the
+    /// caller doesn't have to supply them.  Returns the HTLCs in
+    /// commitment output order, each with the holder's signature.
+    pub fn sign_all_holder_htlc_txs(
+        &self,
+        commitment_number: u64,
+    ) -> Result<Vec<(HTLCOutputInCommitment, Signature)>, Status> {
+        self.get_node().check_capability(Capabilities::HTLC)?;
+        let info2 = self.enforcement_state.get_current_holder_commitment_info(commitment_number)?;
+
+        let htlcs =
+            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
+
+        let recomposed_tx = self.make_holder_commitment_tx(
+            commitment_number,
+            info2.feerate_per_kw,
+            info2.to_broadcaster_value_sat,
+            info2.to_countersigner_value_sat,
+            htlcs,
+        )?;
+
+        let per_commitment_point = self.get_per_commitment_point(commitment_number)?;
+        let txkeys = self.make_holder_tx_keys(&per_commitment_point)?;
+        let htlc_privkey = self.derive_htlc_signing_key(&per_commitment_point)?;
+
+        let commitment_txid = recomposed_tx.trust().txid();
+        let to_self_delay = self.setup.counterparty_selected_contest_delay;
+        let validator = self.validator();
+        let chain_state = self.get_chain_state();
+        let low_r = self.get_node().node_config.low_r_grinding;
+
+        let mut sigs = Vec::with_capacity(recomposed_tx.htlcs().len());
+        for htlc in recomposed_tx.htlcs() {
+            let is_counterparty = false;
+            validator.validate_htlc_tx(
+                &self.setup,
+                &chain_state,
+                is_counterparty,
+                htlc,
+                info2.feerate_per_kw,
+            )?;
+
+            let htlc_redeemscript =
+                get_htlc_redeemscript(htlc, self.setup.option_anchor_outputs(), &txkeys);
+
+            let htlc_tx = build_htlc_transaction(
+                &commitment_txid,
+                info2.feerate_per_kw,
+                to_self_delay,
+                htlc,
+                self.setup.option_anchor_outputs(),
+                &txkeys.broadcaster_delayed_payment_key,
+                &txkeys.revocation_key,
+            );
+
+            let htlc_sighash = Message::from_slice(
+                &SigHashCache::new(&htlc_tx).signature_hash(
+                    0,
+                    &htlc_redeemscript,
+                    htlc.amount_msat / 1000,
+                    SigHashType::All,
+                )[..],
+            )
+            .map_err(|_| Status::internal("failed to sighash recomposed"))?;
+
+            let sig = sign_maybe_low_r(&self.secp_ctx, &htlc_sighash, &htlc_privkey, low_r);
+            sigs.push((htlc.clone(), sig));
+        }
+
+        Ok(sigs)
+    }
+
+    // LDK doesn't grind for low R, so if the node is configured for it, the
+    // commitment and HTLC signatures are re-done here with the same keys and
+    // sighashes LDK uses.  LDK's signatures must verify against the rebuilt
+    // sighashes, so that the new ones are known to sign the same transactions.
+    fn maybe_low_r_commitment_sigs(
+        &self,
+        commitment_tx: &CommitmentTransaction,
+        is_counterparty: bool,
+        sigs: (Signature, Vec<Signature>),
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        if !self.get_node().node_config.low_r_grinding {
+            return Ok(sigs);
+        }
+
+        let trusted_tx = commitment_tx.trust();
+        let built_tx = trusted_tx.built_transaction();
+        let txkeys = trusted_tx.keys();
+        let opt_anchors = self.setup.option_anchor_outputs();
+
+        let sighash = Message::from_slice(
+            &SigHashCache::new(&built_tx.transaction).signature_hash(
+                0,
+                &self.funding_redeemscript(),
+                self.setup.channel_value_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|ve| internal_error(format!("sighash failed: {}", ve)))?;
+        let funding_pubkey = &self.keys.pubkeys().funding_pubkey;
+        self.secp_ctx
+            .verify(&sighash, &sigs.0, funding_pubkey)
+            .map_err(|_| internal_error("low-R commitment sighash mismatch"))?;
+        let sig = sign_maybe_low_r(&self.secp_ctx, &sighash, &self.keys.funding_key, true);
+        self.secp_ctx
+            .verify(&sighash, &sig, funding_pubkey)
+            .map_err(|_| internal_error("low-R commitment signature does not verify"))?;
+
+        // The broadcaster's to_self_delay is the one selected by the other side
+        let (to_self_delay, htlc_sighash_type) = if is_counterparty {
+            let sighash_type =
+                if opt_anchors { SigHashType::SinglePlusAnyoneCanPay } else { SigHashType::All };
+            (self.setup.holder_selected_contest_delay, sighash_type)
+        } else {
+            (self.setup.counterparty_selected_contest_delay, SigHashType::All)
+        };
+        let htlc_privkey = self.derive_htlc_signing_key(&txkeys.per_commitment_point)?;
+        let htlc_pubkey = PublicKey::from_secret_key(&self.secp_ctx, &htlc_privkey);
+
+        if sigs.1.len() != commitment_tx.htlcs().len() {
+            return Err(internal_error(format!(
+                "low-R htlc signature count mismatch: {} != {}",
+                sigs.1.len(),
+                commitment_tx.htlcs().len()
+            )));
+        }
+        let mut htlc_sigs = Vec::with_capacity(commitment_tx.htlcs().len());
+        for (htlc, ldk_sig) in commitment_tx.htlcs().iter().zip(sigs.1.iter()) {
+            let htlc_redeemscript = get_htlc_redeemscript(htlc, opt_anchors, txkeys);
+            let htlc_tx = build_htlc_transaction(
+                &built_tx.txid,
+                commitment_tx.feerate_per_kw(),
+                to_self_delay,
+                htlc,
+                opt_anchors,
+                &txkeys.broadcaster_delayed_payment_key,
+                &txkeys.revocation_key,
+            );
+            let htlc_sighash = Message::from_slice(
+                &SigHashCache::new(&htlc_tx).signature_hash(
+                    0,
+                    &htlc_redeemscript,
+                    htlc.amount_msat / 1000,
+                    htlc_sighash_type,
+                )[..],
+            )
+            .map_err(|_| Status::internal("failed to sighash htlc"))?;
+            self.secp_ctx
+                .verify(&htlc_sighash, ldk_sig, &htlc_pubkey)
+                .map_err(|_| internal_error("low-R htlc sighash mismatch"))?;
+            let htlc_sig = sign_maybe_low_r(&self.secp_ctx, &htlc_sighash, &htlc_privkey, true);
+            self.secp_ctx
+                .verify(&htlc_sighash, &htlc_sig, &htlc_pubkey)
+                .map_err(|_| internal_error("low-R htlc signature does not verify"))?;
+            htlc_sigs.push(htlc_sig);
+        }
+
+        Ok((sig, htlc_sigs))
+    }
+
+    /// Sign a holder commitment transaction after rebuilding it
+    /// from the supplied arguments.
+    /// Use [`sign_counterparty_commitment_tx_phase2`] instead of this,
+    /// since that one uses the last counter-signed holder tx, which is simpler
+    /// and doesn't require re-validation of the holder tx.
+    // TODO anchors support once upstream supports it
     pub fn sign_holder_commitment_tx_phase2_redundant(
         &self,
         commitment_number: u64,
@@ -948,6 +1583,9 @@ impl Channel {
         offered_htlcs: Vec<HTLCInfo2>,
         received_htlcs: Vec<HTLCInfo2>,
     ) -> Result<(Signature, Vec<Signature>), Status> {
+        self.get_node().check_capability(Capabilities::COMMITMENTS)?;
+        self.check_signing_rate()?;
+        self.check_funding_established(commitment_number)?;
         let commitment_point = &self.get_per_commitment_point(commitment_number)?;
 
         let info2 = self.build_holder_commitment_info(
@@ -960,6 +1598,7 @@ impl Channel {
         )?;
 
         self.validator().validate_holder_commitment_tx(
+            &self.get_node().node_config,
             &self.enforcement_state,
             commitment_number,
             &commitment_point,
@@ -998,10 +1637,12 @@ impl Channel {
             &self.keys.counterparty_pubkeys().funding_pubkey,
         );
 
-        let (sig, htlc_sigs) = self
+        let sigs = self
             .keys
             .sign_holder_commitment_and_htlcs(&holder_commitment_tx, &self.secp_ctx)
             .map_err(|_| internal_error("failed to sign"))?;
+        let (sig, htlc_sigs) =
+            self.maybe_low_r_commitment_sigs(&holder_commitment_tx, false, sigs)?;
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
@@ -1115,42 +1756,520 @@ impl Channel {
         self.node.upgrade().unwrap()
     }
 
+    /// Record that the funding transaction confirmed at `block_height`
+    pub fn confirm_funding(&mut self, outpoint: OutPoint, block_height: u32) -> Result<(), Status> {
+        self.enforcement_state.funding_confirmation = Some((outpoint, block_height));
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()
+    }
+
+    /// Forget the funding confirmation because the funding transaction
+    /// was reorged out
+    pub fn handle_funding_reorg(&mut self) -> Result<(), Status> {
+        self.enforcement_state.funding_confirmation = None;
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()
+    }
+
+    /// The index of the funding output in the funding transaction
+    pub fn funding_output_index(&self) -> u32 {
+        self.setup.funding_outpoint.vout
+    }
+
+    /// The short channel ID, for channel announcements, given the height of
+    /// the block the funding transaction confirmed in and its index in the block.
+    ///
+    /// The height and transaction index must fit in three bytes each, and the
+    /// funding output index in two bytes.  If the funding confirmation was
+    /// recorded with [Channel::confirm_funding], it must be for this outpoint
+    /// and height.
+    pub fn short_channel_id(&self, block_height: u32, tx_index: u32) -> Result<u64, Status> {
+        let output_index = self.funding_output_index();
+        if block_height >= 1 << 24 || tx_index >= 1 << 24 || output_index >= 1 << 16 {
+            return Err(invalid_argument(format!(
+                "short_channel_id out of range: {}x{}x{}",
+                block_height, tx_index, output_index
+            )));
+        }
+        if let Some((outpoint, height)) = self.enforcement_state.funding_confirmation {
+            if outpoint != self.setup.funding_outpoint || height != block_height {
+                return Err(invalid_argument(format!(
+                    "short_channel_id: funding confirmed as {}:{} at height {}, not {}:{} at {}",
+                    outpoint.txid,
+                    outpoint.vout,
+                    height,
+                    self.setup.funding_outpoint.txid,
+                    output_index,
+                    block_height
+                )));
+            }
+        }
+        Ok((block_height as u64) << 40 | (tx_index as u64) << 16 | output_index as u64)
+    }
+
+    /// Check the peer's `channel_reestablish` against the enforcement state.
+    ///
+    /// The peer may be one step behind, if it didn't receive our last
+    /// `commitment_signed` or `revoke_and_ack`, but may not claim a state
+    /// ahead of ours.  The secret and point are checked if supplied.
+    pub fn validate_channel_reestablish(
+        &self,
+        next_local_commitment_number: u64,
+        next_remote_revocation_number: u64,
+        your_last_per_commitment_secret: Option<SecretKey>,
+        my_current_per_commitment_point: Option<PublicKey>,
+    ) -> Result<(), Status> {
+        let estate = &self.enforcement_state;
+
+        // The peer's next commitment is the next counterparty commitment we sign
+        let next_counterparty_commit_num = estate.next_counterparty_commit_num;
+        if next_local_commitment_number > next_counterparty_commit_num {
+            return Err(policy_error(format!(
+                "channel_reestablish: next_local_commitment_number {} ahead of {}",
+                next_local_commitment_number, next_counterparty_commit_num
+            ))
+            .into());
+        }
+        if next_local_commitment_number + 1 < next_counterparty_commit_num {
+            return Err(policy_error(format!(
+                "channel_reestablish: next_local_commitment_number {} behind {}",
+                next_local_commitment_number, next_counterparty_commit_num
+            ))
+            .into());
+        }
+
+        // The peer expects us to revoke a holder commitment, and we can't
+        // have revoked the current one
+        let current_holder_commit_num = estate.next_holder_commit_num.saturating_sub(1);
+        if next_remote_revocation_number > current_holder_commit_num {
+            return Err(policy_error(format!(
+                "channel_reestablish: next_remote_revocation_number {} ahead of {}",
+                next_remote_revocation_number, current_holder_commit_num
+            ))
+            .into());
+        }
+        if next_remote_revocation_number + 1 < current_holder_commit_num {
+            return Err(policy_error(format!(
+                "channel_reestablish: next_remote_revocation_number {} behind {}",
+                next_remote_revocation_number, current_holder_commit_num
+            ))
+            .into());
+        }
+
+        if next_remote_revocation_number > 0 {
+            let secret = your_last_per_commitment_secret.ok_or_else(|| {
+                invalid_argument("channel_reestablish: missing your_last_per_commitment_secret")
+            })?;
+            let expected = self.get_per_commitment_secret(next_remote_revocation_number - 1)?;
+            if secret != expected {
+                return Err(policy_error(format!(
+                    "channel_reestablish: your_last_per_commitment_secret mismatch for {}",
+                    next_remote_revocation_number - 1
+                ))
+                .into());
+            }
+        }
+
+        if let Some(point) = my_current_per_commitment_point {
+            let expected = if next_local_commitment_number == next_counterparty_commit_num {
+                estate.current_counterparty_point
+            } else {
+                estate.previous_counterparty_point
+            };
+            if let Some(expected) = expected {
+                if point != expected {
+                    return Err(policy_error(format!(
+                        "channel_reestablish: my_current_per_commitment_point mismatch for {}",
+                        next_local_commitment_number.saturating_sub(1)
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Sign a mutual close transaction after rebuilding it from the supplied arguments
     pub fn sign_mutual_close_tx_phase2(
         &mut self,
         to_holder_value_sat: u64,
         to_counterparty_value_sat: u64,
-        holder_script: &Option<Script>,
-        counterparty_script: &Option<Script>,
-        holder_wallet_path_hint: &Vec<u32>,
-    ) -> Result<Signature, Status> {
-        self.validator().validate_mutual_close_tx(
-            &*self.get_node(),
-            &self.setup,
-            &self.enforcement_state,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        self.sign_mutual_close_tx_to(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+            &[],
+        )
+    }
+
+    /// Sign a mutual close transaction, additionally allowing the holder
+    /// output to pay to one of `authorized_scripts`.
+    ///
+    /// The authorized scripts are only valid for this close and are not
+    /// added to the node's allowlist or persisted.
+    pub fn sign_mutual_close_tx_to(
+        &mut self,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+        authorized_scripts: &[Script],
+    ) -> Result<Signature, Status> {
+        let node = self.get_node();
+        let wallet = AuthorizedScriptsWallet { wallet: &*node, authorized_scripts };
+        self.validator().validate_mutual_close_tx(
+            &wallet,
+            &self.setup,
+            &self.enforcement_state,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        )?;
+
+        self.sign_validated_mutual_close_tx(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+        )
+    }
+
+    /// Sign a mutual close transaction for a channel which never had a
+    /// commitment signed, paying to the shutdown scripts.
+    ///
+    /// Since there is no commitment info to compare against, only the
+    /// destinations and the minimum fee are checked.
+    pub fn sign_mutual_close_tx_from_shutdown_scripts(
+        &mut self,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        self.validator().validate_mutual_close_tx_without_commitments(
+            &*self.get_node(),
+            &self.setup,
+            &self.enforcement_state,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        )?;
+
+        self.sign_validated_mutual_close_tx(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+        )
+    }
+
+    /// Build the mutual close transaction paying the supplied values and
+    /// scripts, after validating it as [Channel::sign_mutual_close_tx_phase2]
+    /// would.
+    ///
+    /// The returned transaction can be passed to [Channel::sign_mutual_close_tx]
+    /// along with the wallet paths of its outputs.
+    pub fn build_mutual_close_tx(
+        &self,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<Transaction, Status> {
+        self.validator().validate_mutual_close_tx(
+            &*self.get_node(),
+            &self.setup,
+            &self.enforcement_state,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        )?;
+
+        let tx = self.make_closing_tx(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+        );
+        let built_tx = tx.trust().built_transaction().clone();
+        Ok(built_tx)
+    }
+
+    fn make_closing_tx(
+        &self,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+    ) -> ClosingTransaction {
+        ClosingTransaction::new(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script.clone().unwrap_or_else(|| Script::new()),
+            counterparty_script.clone().unwrap_or_else(|| Script::new()),
+            self.setup.funding_outpoint,
+        )
+    }
+
+    fn sign_validated_mutual_close_tx(
+        &mut self,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+    ) -> Result<Signature, Status> {
+        let tx = self.make_closing_tx(
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+        );
+
+        let sig = self
+            .keys
+            .sign_closing_transaction(&tx, &self.secp_ctx)
+            .map_err(|_| Status::internal("failed to sign"))?;
+        self.enforcement_state.mutual_close_signed = true;
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()?;
+        Ok(sig)
+    }
+
+    /// Sign a splice transaction, which spends the current funding output
+    /// and creates a new funding output with value `new_value_sat` at
+    /// `new_funding_vout`.
+    ///
+    /// `to_holder_value_sat` is the holder's balance after the splice, and
+    /// `holder_reduction_sat` is the amount by which the holder consents to
+    /// reduce its balance (for example, when splicing out to its wallet).
+    /// The reduction must be paid to outputs that are spendable by the
+    /// wallet at the paths in `opaths`, or that are allowlisted.
+    ///
+    /// The channel keeps using the current funding output until the splice
+    /// is confirmed with [Channel::confirm_splice].
+    pub fn sign_splice_tx(
+        &mut self,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+        new_funding_vout: u32,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+    ) -> Result<Signature, Status> {
+        if opaths.len() != tx.output.len() {
+            return Err(invalid_argument(format!(
+                "{}: bad opath len {} with tx.output len {}",
+                short_function!(),
+                opaths.len(),
+                tx.output.len()
+            )));
+        }
+
+        let redeemscript = self.funding_redeemscript();
+
+        self.validator().validate_splice_tx(
+            &*self.get_node(),
+            &self.setup,
+            &self.enforcement_state,
+            tx,
+            opaths,
+            &redeemscript,
+            new_funding_vout,
+            new_value_sat,
+            to_holder_value_sat,
+            holder_reduction_sat,
+        )?;
+
+        let input_index = tx
+            .input
+            .iter()
+            .position(|i| i.previous_output == self.setup.funding_outpoint)
+            .ok_or_else(|| internal_error("splice does not spend the funding outpoint"))?;
+        let sighash = Message::from_slice(
+            &SigHashCache::new(tx).signature_hash(
+                input_index,
+                &redeemscript,
+                self.setup.channel_value_sat,
+                SigHashType::All,
+            )[..],
+        )
+        .map_err(|ve| internal_error(format!("sighash failed: {}", ve)))?;
+        let low_r = self.get_node().node_config.low_r_grinding;
+        let sig = sign_maybe_low_r(&self.secp_ctx, &sighash, &self.keys.funding_key, low_r);
+
+        let new_funding_outpoint = OutPoint { txid: tx.txid(), vout: new_funding_vout };
+        self.setup.splice_funding = Some((new_funding_outpoint, new_value_sat));
+        self.setup.splice_pending_amount = None;
+        self.monitor.add_splice(&new_funding_outpoint);
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()?;
+        Ok(sig)
+    }
+
+    /// Record that the splice signed with [Channel::sign_splice_tx] confirmed
+    /// at `block_height`.  The new funding output becomes the channel's
+    /// funding output.
+    pub fn confirm_splice(&mut self, block_height: u32) -> Result<(), Status> {
+        let (outpoint, channel_value_sat) = self
+            .setup
+            .splice_funding
+            .take()
+            .ok_or_else(|| invalid_argument("no splice was signed"))?;
+        self.setup.funding_outpoint = outpoint;
+        self.setup.channel_value_sat = channel_value_sat;
+        self.keys = self.keys_for_setup();
+        self.enforcement_state.funding_confirmation = Some((outpoint, block_height));
+        trace_enforcement_state!(&self.enforcement_state);
+        self.persist()
+    }
+
+    /// Forget the splice signed with [Channel::sign_splice_tx], because
+    /// it will not confirm.  The channel keeps the current funding output.
+    pub fn abandon_splice(&mut self) -> Result<(), Status> {
+        self.setup.splice_funding = None;
+        self.monitor.abandon_splice();
+        self.persist()
+    }
+
+    /// Record the new channel value agreed in a splice negotiation, or
+    /// cancel a pending splice with `None`.
+    ///
+    /// The pending splice amount is cleared by [Channel::sign_splice_tx].
+    pub fn set_splice_pending_amount(
+        &mut self,
+        new_channel_value_sat: Option<u64>,
+    ) -> Result<(), Status> {
+        self.setup.splice_pending_amount = new_channel_value_sat;
+        self.persist()
+    }
+
+    /// Sign the current counterparty commitment rebuilt on the new funding
+    /// output of a pending splice, `new_funding_outpoint` with a value of
+    /// `new_channel_value_sat`.
+    ///
+    /// The new value must match the one recorded with
+    /// [Channel::set_splice_pending_amount].  `holder_reduction_sat` is the
+    /// amount by which the holder consents to reduce its balance, as in
+    /// [Channel::sign_splice_tx].  The channel keeps using the current
+    /// funding output and the enforcement state does not advance.
+    pub fn sign_splice_commitment(
+        &mut self,
+        new_funding_outpoint: OutPoint,
+        new_channel_value_sat: u64,
+        per_commitment_point: &PublicKey,
+        commit_num: u64,
+        feerate_per_kw: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        offered_htlcs: Vec<HTLCInfo2>,
+        received_htlcs: Vec<HTLCInfo2>,
+        holder_reduction_sat: u64,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        self.get_node().check_capability(Capabilities::COMMITMENTS)?;
+        self.check_signing_rate()?;
+        self.check_funding_established(commit_num)?;
+
+        // Switch to the spliced channel while building and signing
+        let setup = self.setup.clone();
+        let keys = self.keys.clone();
+        self.setup.funding_outpoint = new_funding_outpoint;
+        self.setup.channel_value_sat = new_channel_value_sat;
+        self.keys = self.keys_for_setup();
+        let result = self.sign_spliced_counterparty_commitment(
+            per_commitment_point,
+            commit_num,
+            feerate_per_kw,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            offered_htlcs,
+            received_htlcs,
+            holder_reduction_sat,
+        );
+        self.setup = setup;
+        self.keys = keys;
+        result
+    }
+
+    fn sign_spliced_counterparty_commitment(
+        &self,
+        per_commitment_point: &PublicKey,
+        commit_num: u64,
+        feerate_per_kw: u32,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        offered_htlcs: Vec<HTLCInfo2>,
+        received_htlcs: Vec<HTLCInfo2>,
+        holder_reduction_sat: u64,
+    ) -> Result<(Signature, Vec<Signature>), Status> {
+        let info2 = self.build_counterparty_commitment_info(
+            per_commitment_point,
             to_holder_value_sat,
             to_counterparty_value_sat,
-            holder_script,
-            counterparty_script,
-            holder_wallet_path_hint,
+            offered_htlcs,
+            received_htlcs,
+            feerate_per_kw,
+        )?;
+        self.validator().validate_splice_commitment(
+            &self.setup,
+            &self.enforcement_state,
+            commit_num,
+            per_commitment_point,
+            &info2,
+            holder_reduction_sat,
         )?;
 
-        let tx = ClosingTransaction::new(
-            to_holder_value_sat,
-            to_counterparty_value_sat,
-            holder_script.clone().unwrap_or_else(|| Script::new()),
-            counterparty_script.clone().unwrap_or_else(|| Script::new()),
-            self.setup.funding_outpoint,
+        let htlcs =
+            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
+        let commitment_tx = self.make_counterparty_commitment_tx(
+            per_commitment_point,
+            commit_num,
+            info2.feerate_per_kw,
+            info2.to_countersigner_value_sat,
+            info2.to_broadcaster_value_sat,
+            htlcs,
         );
-
-        let sig = self
+        let sigs = self
             .keys
-            .sign_closing_transaction(&tx, &self.secp_ctx)
-            .map_err(|_| Status::internal("failed to sign"))?;
-        self.enforcement_state.mutual_close_signed = true;
-        trace_enforcement_state!(&self.enforcement_state);
-        self.persist()?;
-        Ok(sig)
+            .sign_counterparty_commitment(&commitment_tx, Vec::new(), &self.secp_ctx)
+            .map_err(|_| internal_error("failed to sign"))?;
+        self.maybe_low_r_commitment_sigs(&commitment_tx, true, sigs)
+    }
+
+    // Rebuild the keys with the current channel value and parameters
+    fn keys_for_setup(&self) -> InMemorySigner {
+        let secp_ctx = Secp256k1::signing_only();
+        let node_secret = self.get_node().node_secret();
+        let mut keys = InMemorySigner::new(
+            &secp_ctx,
+            node_secret,
+            self.keys.funding_key,
+            self.keys.revocation_base_key,
+            self.keys.payment_key,
+            self.keys.delayed_payment_base_key,
+            self.keys.htlc_base_key,
+            self.keys.commitment_seed,
+            self.setup.channel_value_sat,
+            self.keys.channel_keys_id(),
+        );
+        let channel_transaction_parameters =
+            Node::channel_setup_to_channel_transaction_parameters(&self.setup, keys.pubkeys());
+        keys.ready_channel(&channel_transaction_parameters);
+        keys
     }
 
     /// Sign a delayed output that goes to us while sweeping a transaction we broadcast
@@ -1244,12 +2363,7 @@ impl Channel {
         )
         .map_err(|_| Status::internal("failed to sighash"))?;
 
-        let htlc_privkey = derive_private_key(
-            &self.secp_ctx,
-            &remote_per_commitment_point,
-            &self.keys.htlc_base_key,
-        )
-        .map_err(|_| Status::internal("failed to derive key"))?;
+        let htlc_privkey = self.derive_htlc_signing_key(&remote_per_commitment_point)?;
 
         let sig = self.secp_ctx.sign(&htlc_sighash, &htlc_privkey);
         trace_enforcement_state!(&self.enforcement_state);
@@ -1294,12 +2408,7 @@ impl Channel {
         )
         .map_err(|_| Status::internal("failed to sighash"))?;
 
-        let privkey = derive_private_revocation_key(
-            &self.secp_ctx,
-            revocation_secret,
-            &self.keys.revocation_base_key,
-        )
-        .map_err(|_| Status::internal("failed to derive key"))?;
+        let privkey = self.compute_revocation_private_key(revocation_secret)?;
 
         let sig = self.secp_ctx.sign(&sighash, &privkey);
         trace_enforcement_state!(&self.enforcement_state);
@@ -1307,15 +2416,238 @@ impl Channel {
         Ok(sig)
     }
 
+    /// Sign a justice transaction sweeping several outputs of a revoked
+    /// counterparty commitment, such as the to_local output and all HTLCs.
+    ///
+    /// * `commit_num` - the number of the revoked commitment
+    /// * `revocation_secret` - the per-commitment secret the counterparty
+    ///   released when revoking the commitment
+    /// * `per_output` - the input index, redeemscript and amount of each
+    ///   revoked output being spent
+    /// * `wallet_path` - the wallet derivation path of the destination
+    ///
+    /// Each redeemscript must be spendable with the revocation key derived
+    /// from `revocation_secret`.
+    ///
+    /// Returns one signature per entry in `per_output`.
+    pub fn sign_justice_tx_all(
+        &self,
+        tx: &bitcoin::Transaction,
+        commit_num: u64,
+        revocation_secret: &SecretKey,
+        per_output: &[(usize, Script, u64)],
+        wallet_path: &Vec<u32>,
+    ) -> Result<Vec<Signature>, Status> {
+        let estate = &self.enforcement_state;
+        if commit_num >= estate.next_counterparty_revoke_num {
+            return Err(policy_error(format!(
+                "sign_justice_tx_all: counterparty commitment {} is not revoked, \
+                 next revoke num is {}",
+                commit_num, estate.next_counterparty_revoke_num
+            ))
+            .into());
+        }
+        let revocation_point = PublicKey::from_secret_key(&self.secp_ctx, revocation_secret);
+        // Only recent points are kept, but those we have must agree
+        if let Some(known_num) = estate.get_counterparty_point_commit_num(&revocation_point) {
+            if known_num != commit_num {
+                return Err(policy_error(format!(
+                    "sign_justice_tx_all: revocation secret for point {} \
+                     is for commitment {}, not {}",
+                    revocation_point, known_num, commit_num
+                ))
+                .into());
+            }
+        }
+        let revocation_pubkey = self.get_revocation_pubkey(&revocation_point)?;
+
+        let validator = self.validator();
+        let chain_state = self.get_chain_state();
+        for (input, redeemscript, amount_sat) in per_output {
+            if *input >= tx.input.len() {
+                return Err(invalid_argument(format!(
+                    "sign_justice_tx_all: bad input index: {} >= {}",
+                    input,
+                    tx.input.len()
+                )));
+            }
+            if !self.is_revocable_by(redeemscript, &revocation_pubkey) {
+                return Err(policy_error(format!(
+                    "sign_justice_tx_all: redeemscript for input {} \
+                     does not pay to revocation pubkey {}",
+                    input, revocation_pubkey
+                ))
+                .into());
+            }
+            validator.validate_justice_sweep(
+                &*self.get_node(),
+                &self.setup,
+                &chain_state,
+                tx,
+                *input,
+                *amount_sat,
+                wallet_path,
+            )?;
+        }
+
+        let privkey = self.compute_revocation_private_key(revocation_secret)?;
+        let mut sighash_cache = SigHashCache::new(tx);
+        let mut sigs = Vec::with_capacity(per_output.len());
+        for (input, redeemscript, amount_sat) in per_output {
+            let sighash = Message::from_slice(
+                &sighash_cache.signature_hash(*input, redeemscript, *amount_sat, SigHashType::All)
+                    [..],
+            )
+            .map_err(|_| Status::internal("failed to sighash"))?;
+            sigs.push(self.secp_ctx.sign(&sighash, &privkey));
+        }
+        Ok(sigs)
+    }
+
+    // Whether a revoked to_local or HTLC output with this redeemscript can
+    // be spent with the revocation key
+    fn is_revocable_by(&self, redeemscript: &Script, revocation_pubkey: &PublicKey) -> bool {
+        let anchors = self.setup.option_anchor_outputs();
+        let key = revocation_pubkey.serialize();
+        if let Ok((revocation_key, _, _)) = parse_revokeable_redeemscript(redeemscript, anchors) {
+            return revocation_key == key.to_vec();
+        }
+        let revocation_hash = match parse_offered_htlc_script(redeemscript, anchors) {
+            Ok((revocation_hash, _, _, _)) => revocation_hash,
+            Err(_) => match parse_received_htlc_script(redeemscript, anchors) {
+                Ok((revocation_hash, _, _, _, _)) => revocation_hash,
+                Err(_) => return false,
+            },
+        };
+        revocation_hash == Hash160::hash(&key).into_inner().to_vec()
+    }
+
+    /// Compute the revocation private key for a revoked counterparty
+    /// commitment, given the per-commitment secret they released.
+    pub fn compute_revocation_private_key(
+        &self,
+        per_commitment_secret: &SecretKey,
+    ) -> Result<SecretKey, Status> {
+        derive_private_revocation_key(
+            &self.secp_ctx,
+            per_commitment_secret,
+            &self.keys.revocation_base_key,
+        )
+        .map_err(|_| Status::internal("failed to derive key"))
+    }
+
+    /// Get the revocation pubkey for a counterparty commitment with the
+    /// given per-commitment point, based on our revocation basepoint
+    pub fn get_revocation_pubkey(
+        &self,
+        per_commitment_point: &PublicKey,
+    ) -> Result<PublicKey, Status> {
+        derive_revocation_pubkey(
+            &self.secp_ctx,
+            per_commitment_point,
+            &self.keys.pubkeys().revocation_basepoint,
+        )
+        .map_err(|err| internal_error(format!("could not derive revocation key: {}", err)))
+    }
+
+    /// Whether the current holder or counterparty commitment has pending HTLCs,
+    /// in either direction
+    pub fn has_pending_htlcs(&self) -> bool {
+        self.enforcement_state.has_pending_htlcs()
+    }
+
+    /// The per-commitment point of the latest counterparty commitment we signed,
+    /// which the peer should report in `channel_reestablish`.
+    ///
+    /// None if we haven't signed a counterparty commitment yet.
+    pub fn next_counterparty_commitment_point(&self) -> Option<PublicKey> {
+        self.enforcement_state.current_counterparty_point
+    }
+
+    /// Gather the holder data needed for `channel_reestablish`.
+    ///
+    /// Only a secret we could already have released with
+    /// [ChannelBase::get_per_commitment_secret] is included, so the
+    /// current holder commitment is never revoked by this.
+    pub fn expected_holder_reestablish(&self) -> HolderReestablishInfo {
+        let estate = &self.enforcement_state;
+        // policy-revoke-new-commitment-signed
+        let last_holder_secret = if estate.next_holder_commit_num >= 2 {
+            let secret = self.keys.release_commitment_secret(
+                INITIAL_COMMITMENT_NUMBER - (estate.next_holder_commit_num - 2),
+            );
+            Some(SecretKey::from_slice(&secret).expect("secret"))
+        } else {
+            None
+        };
+        HolderReestablishInfo {
+            next_holder_commit_num: estate.next_holder_commit_num,
+            next_counterparty_commit_num: estate.next_counterparty_commit_num,
+            next_counterparty_revoke_num: estate.next_counterparty_revoke_num,
+            last_holder_secret,
+            current_counterparty_point: estate.current_counterparty_point,
+        }
+    }
+
+    /// Assemble our `channel_reestablish` from the enforcement state.
+    ///
+    /// The signer doesn't store the counterparty's revealed secrets, so the
+    /// caller supplies the last one, which is checked against the point we
+    /// signed that counterparty commitment with.  No holder secret is revealed.
+    pub fn build_channel_reestablish(
+        &self,
+        last_counterparty_secret: Option<SecretKey>,
+    ) -> Result<ChannelReestablishData, Status> {
+        let estate = &self.enforcement_state;
+        let next_revocation_number = estate.next_counterparty_revoke_num;
+        let your_last_per_commitment_secret = if next_revocation_number > 0 {
+            let revoked_num = next_revocation_number - 1;
+            let secret = last_counterparty_secret.ok_or_else(|| {
+                invalid_argument(format!(
+                    "missing counterparty secret for commitment {}",
+                    revoked_num
+                ))
+            })?;
+            let point = PublicKey::from_secret_key(&self.secp_ctx, &secret);
+            let known = estate.get_counterparty_point_commit_num(&point) == Some(revoked_num)
+                || (revoked_num + 1 == estate.next_counterparty_commit_num
+                    && estate.current_counterparty_point == Some(point))
+                || (revoked_num + 2 == estate.next_counterparty_commit_num
+                    && estate.previous_counterparty_point == Some(point));
+            if !known {
+                return Err(policy_error(format!(
+                    "counterparty secret does not match the point for commitment {}",
+                    revoked_num
+                ))
+                .into());
+            }
+            Some(secret)
+        } else {
+            None
+        };
+        let my_current_per_commitment_point =
+            self.get_per_commitment_point(estate.next_holder_commit_num.saturating_sub(1))?;
+        Ok(ChannelReestablishData {
+            next_commitment_number: estate.next_holder_commit_num,
+            next_revocation_number,
+            your_last_per_commitment_secret,
+            my_current_per_commitment_point,
+        })
+    }
+
     /// Sign a channel announcement with both the node key and the funding key
-    pub fn sign_channel_announcement(&self, announcement: &Vec<u8>) -> (Signature, Signature) {
+    pub fn sign_channel_announcement(
+        &self,
+        announcement: &Vec<u8>,
+    ) -> Result<(Signature, Signature), Status> {
+        self.get_node().check_capability(Capabilities::ANNOUNCEMENTS)?;
         let ann_hash = Sha256dHash::hash(announcement);
         let encmsg = secp256k1::Message::from_slice(&ann_hash[..]).expect("encmsg failed");
 
-        (
-            self.secp_ctx.sign(&encmsg, &self.get_node().get_node_secret()),
+        Ok((
+            self.get_node().node_key_signer().sign_digest(&encmsg)?,
             self.secp_ctx.sign(&encmsg, &self.keys.funding_key),
-        )
+        ))
     }
 
     fn persist(&self) -> Result<(), Status> {
@@ -1355,7 +2687,6 @@ impl Channel {
         received_htlcs: Vec<HTLCInfo2>,
         feerate_per_kw: u32,
     ) -> Result<CommitmentInfo2, Status> {
-        let holder_points = self.keys.pubkeys();
         let secp_ctx = &self.secp_ctx;
 
         let to_counterparty_delayed_pubkey = derive_public_key(
@@ -1368,12 +2699,7 @@ impl Channel {
         })?;
         let counterparty_payment_pubkey =
             self.derive_counterparty_payment_pubkey(remote_per_commitment_point)?;
-        let revocation_pubkey = derive_revocation_pubkey(
-            secp_ctx,
-            &remote_per_commitment_point,
-            &holder_points.revocation_basepoint,
-        )
-        .map_err(|err| internal_error(format!("could not derive revocation key: {}", err)))?;
+        let revocation_pubkey = self.get_revocation_pubkey(remote_per_commitment_point)?;
         let to_holder_pubkey = counterparty_payment_pubkey.clone();
         Ok(CommitmentInfo2::new(
             true,
@@ -1459,6 +2785,9 @@ impl Channel {
         if tx.output.len() != output_witscripts.len() {
             return Err(invalid_argument("len(tx.output) != len(witscripts)"));
         }
+        self.get_node().check_capability(Capabilities::COMMITMENTS)?;
+        self.check_signing_rate()?;
+        self.check_funding_established(commitment_number)?;
 
         // Since we didn't have the value at the real open, validate it now.
         let validator = self.validator();
@@ -1493,6 +2822,7 @@ impl Channel {
 
         validator
             .validate_counterparty_commitment_tx(
+                &self.get_node().node_config,
                 &self.enforcement_state,
                 commitment_number,
                 &remote_per_commitment_point,
@@ -1512,23 +2842,18 @@ impl Channel {
                 ve
             })?;
 
-        let htlcs =
-            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
-
-        let recomposed_tx = self.make_counterparty_commitment_tx(
-            remote_per_commitment_point,
-            commitment_number,
-            feerate_per_kw,
-            info.to_countersigner_value_sat,
-            info.to_broadcaster_value_sat,
-            htlcs,
-        );
-
-        if recomposed_tx.trust().built_transaction().transaction != *tx {
-            debug!("ORIGINAL_TX={:#?}", &tx);
-            debug!("RECOMPOSED_TX={:#?}", &recomposed_tx.trust().built_transaction().transaction);
-            return Err(policy_error("recomposed tx mismatch".to_string()).into());
-        }
+        let recomposed_tx = self.recompose_checked(
+            tx,
+            &CommitmentParams {
+                counterparty_point: Some(*remote_per_commitment_point),
+                commitment_number,
+                feerate_per_kw,
+                to_holder_value_sat: info.to_countersigner_value_sat,
+                to_counterparty_value_sat: info.to_broadcaster_value_sat,
+                offered_htlcs: info2.offered_htlcs.clone(),
+                received_htlcs: info2.received_htlcs.clone(),
+            },
+        )?;
 
         // The comparison in the previous block will fail if any of the
         // following policies are violated:
@@ -1554,6 +2879,7 @@ impl Channel {
             .keys
             .sign_counterparty_commitment(&recomposed_tx, Vec::new(), &self.secp_ctx)
             .map_err(|_| internal_error(format!("sign_counterparty_commitment failed")))?;
+        let sigs = self.maybe_low_r_commitment_sigs(&recomposed_tx, true, sigs)?;
 
         let outgoing_payment_summary = self.enforcement_state.payments_summary(None, Some(&info2));
         state.validate_payments(
@@ -1564,8 +2890,12 @@ impl Channel {
             validator.clone(),
         )?;
 
+        let fee_increment_msat =
+            self.enforcement_state.counterparty_fee_increment_msat(commit_num, &info2, &self.setup);
         // Only advance the state if nothing goes wrong.
         self.enforcement_state.set_next_counterparty_commit_num(commit_num + 1, point, info2)?;
+        self.enforcement_state.total_fees_paid_msat =
+            self.enforcement_state.total_fees_paid_after_msat(fee_increment_msat);
 
         state.apply_payments(
             &self.id0,
@@ -1582,15 +2912,11 @@ impl Channel {
         Ok(sigs.0)
     }
 
-    fn make_validated_recomposed_holder_commitment_tx(
+    fn decode_holder_commitment_tx(
         &self,
         tx: &bitcoin::Transaction,
         output_witscripts: &Vec<Vec<u8>>,
-        commitment_number: u64,
-        feerate_per_kw: u32,
-        offered_htlcs: Vec<HTLCInfo2>,
-        received_htlcs: Vec<HTLCInfo2>,
-    ) -> Result<(CommitmentTransaction, CommitmentInfo2, Map<PaymentHash, u64>), Status> {
+    ) -> Result<CommitmentInfo, Status> {
         if tx.output.len() != output_witscripts.len() {
             return Err(invalid_argument(format!(
                 "len(tx.output):{} != len(witscripts):{}",
@@ -1598,19 +2924,30 @@ impl Channel {
                 output_witscripts.len()
             )));
         }
-
-        // Since we didn't have the value at the real open, validate it now.
-        self.validator().validate_channel_value(&self.setup)?;
-
-        // Derive a CommitmentInfo first, convert to CommitmentInfo2 below ...
         let is_counterparty = false;
-        let info = self.validator().decode_commitment_tx(
+        Ok(self.validator().decode_commitment_tx(
             &self.keys,
             &self.setup,
             is_counterparty,
             tx,
             output_witscripts,
-        )?;
+        )?)
+    }
+
+    fn make_validated_recomposed_holder_commitment_tx(
+        &self,
+        tx: &bitcoin::Transaction,
+        output_witscripts: &Vec<Vec<u8>>,
+        commitment_number: u64,
+        feerate_per_kw: u32,
+        offered_htlcs: Vec<HTLCInfo2>,
+        received_htlcs: Vec<HTLCInfo2>,
+    ) -> Result<(CommitmentTransaction, CommitmentInfo2, Map<PaymentHash, u64>), Status> {
+        // Since we didn't have the value at the real open, validate it now.
+        self.validator().validate_channel_value(&self.setup)?;
+
+        // Derive a CommitmentInfo first, convert to CommitmentInfo2 below ...
+        let info = self.decode_holder_commitment_tx(tx, output_witscripts)?;
 
         let commitment_point = &self.get_per_commitment_point(commitment_number)?;
         let info2 = self.build_holder_commitment_info(
@@ -1627,6 +2964,7 @@ impl Channel {
 
         self.validator()
             .validate_holder_commitment_tx(
+                &self.get_node().node_config,
                 &self.enforcement_state,
                 commitment_number,
                 &commitment_point,
@@ -1646,33 +2984,32 @@ impl Channel {
                 ve
             })?;
 
-        let htlcs =
-            Self::htlcs_info2_to_oic(info2.offered_htlcs.clone(), info2.received_htlcs.clone());
-
-        let recomposed_tx = self.make_holder_commitment_tx(
+        let params = CommitmentParams {
+            counterparty_point: None,
             commitment_number,
             feerate_per_kw,
-            info.to_broadcaster_value_sat,
-            info.to_countersigner_value_sat,
-            htlcs.clone(),
-        )?;
-
-        if recomposed_tx.trust().built_transaction().transaction != *tx {
-            debug_vals!(
-                &self.setup,
-                &self.enforcement_state,
-                tx,
-                DebugVecVecU8(output_witscripts),
-                commitment_number,
-                feerate_per_kw,
-                &offered_htlcs,
-                &received_htlcs
-            );
-            warn!("RECOMPOSITION FAILED");
-            warn!("ORIGINAL_TX={:#?}", &tx);
-            warn!("RECOMPOSED_TX={:#?}", &recomposed_tx.trust().built_transaction().transaction);
-            return Err(policy_error("recomposed tx mismatch".to_string()).into());
-        }
+            to_holder_value_sat: info.to_broadcaster_value_sat,
+            to_counterparty_value_sat: info.to_countersigner_value_sat,
+            offered_htlcs: info2.offered_htlcs.clone(),
+            received_htlcs: info2.received_htlcs.clone(),
+        };
+        let recomposed_tx = match self.recompose_checked(tx, &params) {
+            Ok(recomposed_tx) => recomposed_tx,
+            Err(err) => {
+                debug_vals!(
+                    &self.setup,
+                    &self.enforcement_state,
+                    tx,
+                    DebugVecVecU8(output_witscripts),
+                    commitment_number,
+                    feerate_per_kw,
+                    &offered_htlcs,
+                    &received_htlcs
+                );
+                warn!("RECOMPOSITION FAILED");
+                return Err(err);
+            }
+        };
 
         // The comparison in the previous block will fail if any of the
         // following policies are violated:
@@ -1758,15 +3095,64 @@ impl Channel {
         Ok((next_holder_commitment_point, maybe_old_secret))
     }
 
+    /// Verify the counterparty's signature on a holder commitment transaction,
+    /// without validating or changing the channel state.
+    ///
+    /// The transaction must spend the funding output, be numbered `commit_num`
+    /// and pay to the keys derived from `per_commitment_point`.
+    pub fn verify_counterparty_commitment_signature(
+        &self,
+        commitment_tx: &Transaction,
+        output_witscripts: &[Vec<u8>],
+        counterparty_sig: &Signature,
+        per_commitment_point: &PublicKey,
+        commit_num: u64,
+    ) -> Result<(), Status> {
+        if commitment_tx.input.len() != 1
+            || commitment_tx.input[0].previous_output != self.setup.funding_outpoint
+        {
+            return Err(invalid_argument("commitment tx does not spend the funding output"));
+        }
+
+        let format = self.setup.commitment_type.commitment_format();
+        let obscured_commit_num =
+            self.get_commitment_transaction_number_obscure_factor() ^ commit_num;
+        if commitment_tx.lock_time != format.lock_time(obscured_commit_num)
+            || commitment_tx.input[0].sequence != format.sequence(obscured_commit_num)
+        {
+            return Err(invalid_argument(format!(
+                "commitment tx is not for commit_num {}",
+                commit_num
+            )));
+        }
+
+        let info = self.decode_holder_commitment_tx(commitment_tx, &output_witscripts.to_vec())?;
+        let keys = self.make_holder_tx_keys(per_commitment_point)?;
+        if info.revocation_pubkey.map_or(false, |key| key != keys.revocation_key)
+            || info
+                .to_broadcaster_delayed_pubkey
+                .map_or(false, |key| key != keys.broadcaster_delayed_payment_key)
+        {
+            return Err(invalid_argument(format!(
+                "commitment tx does not match per_commitment_point {}",
+                per_commitment_point
+            )));
+        }
+
+        self.check_holder_commitment_signature(commitment_tx, counterparty_sig)
+    }
+
     /// Process the counterparty's revocation
     ///
     /// When this is provided, we know that the counterparty has committed to
     /// the next state.
+    ///
+    /// A retry of the most recent revocation succeeds without changing state.
     pub fn validate_counterparty_revocation(
         &mut self,
         revoke_num: u64,
         old_secret: &SecretKey,
-    ) -> Result<(), Status> {
+    ) -> Result<CounterpartyRevocation, Status> {
         // TODO - need to store the revealed secret.
 
         self.validator().validate_counterparty_revocation(
@@ -1778,7 +3164,11 @@ impl Channel {
 
         trace_enforcement_state!(&self.enforcement_state);
         self.persist()?;
-        Ok(())
+        Ok(CounterpartyRevocation {
+            revoked_commit_num: revoke_num,
+            previous_commit_info_released: revoke_num + 2
+                == self.enforcement_state.next_counterparty_commit_num,
+        })
     }
 
     /// Phase 1
@@ -1849,6 +3239,81 @@ impl Channel {
         )
     }
 
+    /// Complete an inbound HTLC on the holder commitment `commit_num`.
+    ///
+    /// The preimage is checked against the HTLC's payment hash and the
+    /// counterparty's signature on the HTLC-success transaction is verified.
+    /// The transaction is then signed via [Channel::sign_holder_htlc_tx]
+    /// and returned with its full witness.
+    pub fn complete_inbound_htlc(
+        &self,
+        htlc: &HTLCOutputInCommitment,
+        preimage: &PaymentPreimage,
+        htlc_tx: &Transaction,
+        commit_num: u64,
+        counterparty_htlc_sig: &Signature,
+    ) -> Result<Transaction, Status> {
+        if htlc.offered {
+            return Err(invalid_argument("complete_inbound_htlc: htlc is not inbound"));
+        }
+        let payment_hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+        if payment_hash != htlc.payment_hash {
+            return Err(invalid_argument(format!(
+                "complete_inbound_htlc: preimage does not match payment hash {}",
+                htlc.payment_hash.0.to_hex()
+            )));
+        }
+        if htlc_tx.input.len() != 1 {
+            return Err(invalid_argument(format!(
+                "complete_inbound_htlc: bad number of inputs: {}",
+                htlc_tx.input.len()
+            )));
+        }
+
+        let per_commitment_point = self.get_per_commitment_point(commit_num)?;
+        self.check_counterparty_htlc_sig(
+            htlc,
+            counterparty_htlc_sig,
+            &per_commitment_point,
+            commit_num,
+        )?;
+
+        let txkeys = self.make_holder_tx_keys(&per_commitment_point)?;
+        let redeemscript = get_htlc_redeemscript(htlc, self.setup.option_anchor_outputs(), &txkeys);
+        let output_witscript = chan_utils::get_revokeable_redeemscript(
+            &txkeys.revocation_key,
+            self.setup.counterparty_selected_contest_delay,
+            &txkeys.broadcaster_delayed_payment_key,
+        );
+        let holder_sig = self.sign_holder_htlc_tx(
+            htlc_tx,
+            commit_num,
+            Some(per_commitment_point),
+            &redeemscript,
+            htlc.amount_msat / 1000,
+            &output_witscript,
+        )?;
+
+        let counterparty_sig = TypedSignature {
+            sig: *counterparty_htlc_sig,
+            typ: if self.setup.option_anchor_outputs() {
+                SigHashType::SinglePlusAnyoneCanPay
+            } else {
+                SigHashType::All
+            },
+        };
+
+        let mut tx = htlc_tx.clone();
+        tx.input[0].witness = vec![
+            vec![], // OP_CHECKMULTISIG bug
+            counterparty_sig.serialize(),
+            holder_sig.serialize(),
+            preimage.0.to_vec(),
+            redeemscript.into_bytes(),
+        ];
+        Ok(tx)
+    }
+
     /// Phase 1
     pub fn sign_counterparty_htlc_tx(
         &self,
@@ -1884,6 +3349,7 @@ impl Channel {
         is_counterparty: bool,
         txkeys: TxCreationKeys,
     ) -> Result<TypedSignature, Status> {
+        self.get_node().check_capability(Capabilities::HTLC)?;
         let (feerate_per_kw, htlc, recomposed_tx_sighash, sighashtype) =
             self.validator().decode_and_validate_htlc_tx(
                 is_counterparty,
@@ -1923,19 +3389,29 @@ impl Channel {
                 ve
             })?;
 
-        let htlc_privkey =
-            derive_private_key(&self.secp_ctx, &per_commitment_point, &self.keys.htlc_base_key)
-                .map_err(|_| Status::internal("failed to derive key"))?;
+        let htlc_privkey = self.derive_htlc_signing_key(&per_commitment_point)?;
 
         let htlc_sighash = Message::from_slice(&recomposed_tx_sighash[..])
             .map_err(|_| Status::internal("failed to sighash recomposed"))?;
 
+        let low_r = self.get_node().node_config.low_r_grinding;
         Ok(TypedSignature {
-            sig: self.secp_ctx.sign(&htlc_sighash, &htlc_privkey),
+            sig: sign_maybe_low_r(&self.secp_ctx, &htlc_sighash, &htlc_privkey, low_r),
             typ: sighashtype,
         })
     }
 
+    /// Derive the HTLC signing key for the commitment with `per_commitment_point`,
+    /// for sweep code that builds the witness itself
+    // TODO leaking secret
+    pub fn derive_htlc_signing_key(
+        &self,
+        per_commitment_point: &PublicKey,
+    ) -> Result<SecretKey, Status> {
+        derive_private_key(&self.secp_ctx, per_commitment_point, &self.keys.htlc_base_key)
+            .map_err(|_| Status::internal("failed to derive key"))
+    }
+
     /// Get the unilateral close key and the witness stack suffix,
     /// for sweeping the to-remote output of a counterparty's force-close
     // TODO(devrandom) key leaking from this layer
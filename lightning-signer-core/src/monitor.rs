@@ -28,6 +28,11 @@ pub struct State {
     pub funding_double_spent_height: Option<u32>,
     /// Number of confirmations of the closing transaction
     pub closing_height: Option<u32>,
+    /// The new funding outpoint of a signed splice that was not confirmed yet
+    pub splice_outpoint: Option<OutPoint>,
+    /// The funding outpoint and height replaced by the last confirmed splice,
+    /// so that the splice can be reorged-out
+    pub pre_splice_funding: Option<(OutPoint, u32)>,
 }
 
 /// Keep track of channel on-chain events.
@@ -73,6 +78,8 @@ impl ChainMonitor {
             funding_outpoint: None,
             funding_double_spent_height: None,
             closing_height: None,
+            splice_outpoint: None,
+            pre_splice_funding: None,
         };
 
         Self { funding_outpoint, state: Arc::new(Mutex::new(state)) }
@@ -109,6 +116,20 @@ impl ChainMonitor {
         state.funding_inputs.extend(tx.input.iter().map(|i| i.previous_output));
     }
 
+    /// Keep track of a signed splice, which spends the current funding outpoint
+    /// and creates `outpoint`.  When the splice confirms, `outpoint` becomes
+    /// the funding outpoint instead of the channel being considered closed.
+    pub fn add_splice(&self, outpoint: &OutPoint) {
+        let mut state = self.state.lock().expect("lock");
+        state.splice_outpoint = Some(*outpoint);
+    }
+
+    /// Stop keeping track of a splice that will not confirm
+    pub fn abandon_splice(&self) {
+        let mut state = self.state.lock().expect("lock");
+        state.splice_outpoint = None;
+    }
+
     /// Returns the number of confirmations of the funding transaction, or zero
     /// if it wasn't confirmed yet.
     pub fn funding_depth(&self) -> u32 {
@@ -167,6 +188,17 @@ impl ChainListener for ChainMonitor {
                 if state.funding_double_spent_height.is_none() {
                     state.funding_double_spent_height = Some(state.height);
                 }
+            } else if state.splice_outpoint.map(|o| o.txid) == Some(txid)
+                && spent.iter().any(|i| Some(*i) == state.funding_outpoint)
+            {
+                // A splice was confirmed, the new output is now the funding output
+                let outpoint = state.splice_outpoint.take().expect("splice outpoint");
+                let previous = state.funding_outpoint.expect("funding outpoint");
+                let previous_height = state.funding_height.expect("funding height");
+                state.pre_splice_funding = Some((previous, previous_height));
+                state.funding_height = Some(state.height);
+                state.funding_outpoint = Some(outpoint);
+                outpoints.push(outpoint);
             } else if spent.iter().any(|i| Some(*i) == state.funding_outpoint) {
                 // Closed on-chain
                 state.closing_height = Some(state.height);
@@ -183,7 +215,18 @@ impl ChainListener for ChainMonitor {
         for tx in txs {
             let spent: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
             let txid = tx.txid();
-            if let Some(_) = state.funding_txids.iter().position(|i| *i == txid) {
+            if state.pre_splice_funding.is_some()
+                && state.funding_outpoint.map(|o| o.txid) == Some(txid)
+            {
+                // A splice was reorged-out, go back to the previous funding output.
+                // Only the last splice can be reorged-out.
+                assert_eq!(state.funding_height, Some(state.height));
+                let (previous, previous_height) =
+                    state.pre_splice_funding.take().expect("pre-splice funding");
+                state.splice_outpoint = state.funding_outpoint;
+                state.funding_outpoint = Some(previous);
+                state.funding_height = Some(previous_height);
+            } else if let Some(_) = state.funding_txids.iter().position(|i| *i == txid) {
                 // A funding tx was reorged-out
                 assert_eq!(state.funding_height, Some(state.height));
                 state.funding_height = None;
@@ -213,6 +256,8 @@ impl SendSync for ChainMonitor {}
 
 #[cfg(test)]
 mod tests {
+    use bitcoin::TxIn;
+
     use crate::util::test_utils::*;
 
     use super::*;
@@ -258,4 +303,24 @@ mod tests {
         monitor.on_remove_block(vec![]);
         assert_eq!(monitor.funding_double_spent_depth(), 0);
     }
+
+    #[test]
+    fn test_splice() {
+        let tx = make_tx(vec![make_txin(1), make_txin(2)]);
+        let outpoint = OutPoint::new(tx.txid(), 0);
+        let splice_tx = make_tx(vec![TxIn { previous_output: outpoint, ..make_txin(0) }]);
+        let splice_outpoint = OutPoint::new(splice_tx.txid(), 0);
+        let monitor = ChainMonitor::new(outpoint, 0);
+        monitor.add_funding(&tx, 0);
+        monitor.on_add_block(vec![&tx]);
+        monitor.add_splice(&splice_outpoint);
+        assert_eq!(monitor.on_add_block(vec![&splice_tx]), vec![splice_outpoint]);
+        assert_eq!(monitor.get_state().funding_outpoint, Some(splice_outpoint));
+        assert_eq!(monitor.funding_depth(), 1);
+        assert_eq!(monitor.as_chain_state().closing_depth, 0);
+        monitor.on_remove_block(vec![&splice_tx]);
+        assert_eq!(monitor.get_state().funding_outpoint, Some(outpoint));
+        assert_eq!(monitor.get_state().splice_outpoint, Some(splice_outpoint));
+        assert_eq!(monitor.funding_depth(), 1);
+    }
 }
@@ -2,54 +2,77 @@ use core::convert::TryFrom;
 use core::convert::TryInto;
 use core::fmt::{self, Debug, Formatter};
 use core::iter::FromIterator;
+use core::ops::BitOr;
 use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 
 use bitcoin;
-use bitcoin::bech32::{u5, FromBase32};
+use bitcoin::bech32::{u5, FromBase32, ToBase32};
 use bitcoin::blockdata::constants::genesis_block;
-use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoin::hashes::sha256::Hash as Sha256Hash;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
-use bitcoin::hashes::Hash;
-use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::hashes::{Hash, HashEngine, Hmac, HmacEngine};
 use bitcoin::secp256k1::recovery::RecoverableSignature;
 use bitcoin::secp256k1::{schnorrsig, All, Message, PublicKey, Secp256k1, SecretKey, Signature};
+use bitcoin::util::base58;
 use bitcoin::util::bip143::SigHashCache;
 use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
-use bitcoin::{secp256k1, Address, Transaction, TxOut};
+use bitcoin::{secp256k1, Address, Transaction, TxIn, TxOut};
 use bitcoin::{Network, OutPoint, Script, SigHashType};
 use lightning::chain;
 use lightning::chain::keysinterface::{
     BaseSign, KeyMaterial, KeysInterface, Recipient, SpendableOutputDescriptor,
 };
 use lightning::ln::chan_utils::{
-    ChannelPublicKeys, ChannelTransactionParameters, CounterpartyChannelTransactionParameters,
+    get_revokeable_redeemscript, ChannelPublicKeys, ChannelTransactionParameters,
+    CounterpartyChannelTransactionParameters,
+};
+use lightning::ln::msgs::{
+    UnsignedChannelAnnouncement, UnsignedChannelUpdate, UnsignedNodeAnnouncement,
 };
 use lightning::ln::script::ShutdownScript;
-use lightning::ln::{PaymentHash, PaymentPreimage};
+use lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
 use lightning::util::invoice::construct_invoice_preimage;
 use lightning::util::logger::Logger;
-use lightning_invoice::{Invoice, RawDataPart, RawHrp, RawInvoice, SignedRawInvoice};
+use lightning::util::ser::{Readable, Writeable};
+use lightning_invoice::{
+    Currency, Invoice, InvoiceBuilder, RawDataPart, RawHrp, RawInvoice, SignedRawInvoice,
+};
 
 #[allow(unused_imports)]
-use log::{debug, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 use secp256k1_xonly::XOnlyPublicKey;
 
 use crate::chain::tracker::ChainTracker;
-use crate::channel::{Channel, ChannelBase, ChannelId, ChannelSetup, ChannelSlot, ChannelStub};
+use crate::channel::{
+    channel_nonce_to_id, Channel, ChannelBase, ChannelId, ChannelSetup, ChannelSlot, ChannelStub,
+    CommitmentType,
+};
 use crate::monitor::ChainMonitor;
 use crate::persist::model::NodeEntry;
 use crate::persist::Persist;
 use crate::policy::error::{policy_error, unbalanced_error, ValidationError};
+use crate::policy::observer::{ObservedValidator, PolicyObserver};
+use crate::policy::rate_limit::{RateLimit, TokenBucket};
 use crate::policy::validator::{BalanceDelta, ValidatorFactory};
-use crate::policy::validator::{EnforcementState, Validator};
+use crate::policy::validator::{EnforcementState, FundingTxPolicy, Validator};
 use crate::prelude::*;
 use crate::signer::my_keys_manager::{KeyDerivationStyle, MyKeysManager};
+use crate::signer::node_key_signer::{InMemoryNodeKeySigner, NodeKeySigner};
 use crate::sync::{Arc, Weak};
+use crate::tx::script::{
+    get_p2wpkh_redeemscript, get_to_countersignatory_with_anchors_redeemscript,
+};
 use crate::tx::tx::PreimageMap;
-use crate::util::crypto_utils::signature_to_bitcoin_vec;
-use crate::util::status::{failed_precondition, internal_error, invalid_argument, Status};
+use crate::util::clock::{default_clock, Clock};
+use crate::util::crypto_utils::{
+    derive_public_key, payload_for_p2tr, sign_maybe_low_r, signature_to_bitcoin_vec,
+};
+use crate::util::status::{
+    failed_precondition, internal_error, invalid_argument, permission_denied, Status,
+};
 use crate::wallet::Wallet;
 
 /// Node configuration parameters.
@@ -60,6 +83,105 @@ pub struct NodeConfig {
     pub network: Network,
     /// The derivation style to use when deriving purpose-specific keys
     pub key_derivation_style: KeyDerivationStyle,
+    /// Refuse to ready channels with the legacy commitment type
+    pub require_static_remote_key: bool,
+    /// Grind commitment, HTLC and funding signatures to low-R, saving a byte on-chain
+    pub low_r_grinding: bool,
+    /// Allow funding transactions which signal replace-by-fee
+    pub allow_rbf_funding: bool,
+    /// The highest commitment number we will sign, normally
+    /// [crate::util::INITIAL_COMMITMENT_NUMBER] (2^48 - 1)
+    pub maximum_commitment_number: u64,
+    /// Allow the node secret and the layer-1 account keys to be exported,
+    /// see [Node::get_node_secret]
+    pub export_allowed: bool,
+    /// Let an allowlisted p2wpkh address also match its p2sh-p2wpkh form,
+    /// and vice versa.  Other script types only match exactly.
+    pub allowlist_match_derivable: bool,
+    /// The minimum `min_final_cltv_expiry` of invoices we sign, if the
+    /// invoice has the field, normally [crate::util::DEFAULT_MIN_INVOICE_CLTV_EXPIRY]
+    pub min_invoice_cltv_expiry: u32,
+    /// The kinds of operations the node is permitted to perform
+    pub capabilities: Capabilities,
+}
+
+/// A set of operation kinds that a node may perform, see [NodeConfig::capabilities].
+///
+/// Operations whose capability is not in the set fail with a
+/// "capability disabled" error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Sign commitment transactions
+    pub const COMMITMENTS: Capabilities = Capabilities(1 << 0);
+    /// Sign second-level HTLC transactions
+    pub const HTLC: Capabilities = Capabilities(1 << 1);
+    /// Sign onchain transactions, such as channel funding
+    pub const FUNDING: Capabilities = Capabilities(1 << 2);
+    /// Sign BOLT-11 invoices and BOLT-12 messages
+    pub const INVOICES: Capabilities = Capabilities(1 << 3);
+    /// Sign arbitrary messages with the node key
+    pub const MESSAGES: Capabilities = Capabilities(1 << 4);
+    /// Sign gossip node announcements and channel updates
+    pub const ANNOUNCEMENTS: Capabilities = Capabilities(1 << 5);
+    /// No capabilities
+    pub const NONE: Capabilities = Capabilities(0);
+    /// All capabilities
+    pub const ALL: Capabilities = Capabilities((1 << 6) - 1);
+
+    const NAMES: [(Capabilities, &'static str); 6] = [
+        (Capabilities::COMMITMENTS, "commitments"),
+        (Capabilities::HTLC, "htlc"),
+        (Capabilities::FUNDING, "funding"),
+        (Capabilities::INVOICES, "invoices"),
+        (Capabilities::MESSAGES, "messages"),
+        (Capabilities::ANNOUNCEMENTS, "announcements"),
+    ];
+
+    /// The raw bits, e.g. for persistence
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Convert from raw bits, ignoring unknown bits
+    pub fn from_bits_truncate(bits: u32) -> Self {
+        Capabilities(bits & Capabilities::ALL.0)
+    }
+
+    /// Whether all of the capabilities in `other` are in this set
+    pub fn contains(&self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Add the capabilities in `other`
+    pub fn insert(&mut self, other: Capabilities) {
+        self.0 |= other.0;
+    }
+
+    /// Remove the capabilities in `other`
+    pub fn remove(&mut self, other: Capabilities) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Capabilities::NAMES
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "{}", names.join("|"))
+    }
 }
 
 /// Invoice payment details and payment state
@@ -79,6 +201,25 @@ pub struct InvoiceState {
     pub is_fulfilled: bool,
 }
 
+/// The fields of a BOLT11 invoice to be signed by [Node::sign_bolt11]
+#[derive(Clone, Debug)]
+pub struct Bolt11InvoiceFields {
+    /// Invoiced amount, or None for an open amount
+    pub amount_msat: Option<u64>,
+    /// The payment hash
+    pub payment_hash: PaymentHash,
+    /// The payment secret
+    pub payment_secret: PaymentSecret,
+    /// The description
+    pub description: String,
+    /// Timestamp of invoice, as duration since the UNIX epoch
+    pub duration_since_epoch: Duration,
+    /// Expiry, as duration since the timestamp
+    pub expiry_duration: Duration,
+    /// The `min_final_cltv_expiry` in blocks
+    pub min_final_cltv_expiry: u64,
+}
+
 /// Keeps track of incoming and outgoing HTLCs for a routed payment
 #[derive(Clone)]
 pub struct RoutedPayment {
@@ -412,6 +553,17 @@ impl NodeState {
     }
 }
 
+/// Channel counts by state, see [Node::count_channels_by_state]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelStateCounts {
+    /// Channels which were not readied yet
+    pub stub_count: usize,
+    /// Ready channels, including ones with a signed mutual close
+    pub ready_count: usize,
+    /// Ready channels for which we signed a mutual close
+    pub mutual_close_signed_count: usize,
+}
+
 /// Allowlist entry
 #[derive(Eq, PartialEq, Hash, Clone)]
 pub enum Allowable {
@@ -421,6 +573,15 @@ pub enum Allowable {
     Payee(PublicKey),
 }
 
+// The p2sh-p2wpkh form of a p2wpkh script
+fn wrapped_p2wpkh(script: &Script) -> Option<Script> {
+    if script.is_v0_p2wpkh() {
+        Some(Script::new_p2sh(&script.script_hash()))
+    } else {
+        None
+    }
+}
+
 /// Convert to String for a specified Bitcoin network type
 pub trait ToStringForNetwork {
     /// Convert to String for a specified Bitcoin network type
@@ -434,7 +595,7 @@ impl ToStringForNetwork for Allowable {
                 let addr_opt = Address::from_script(&script, network);
                 addr_opt
                     .map(|a| format!("address:{}", a.to_string()))
-                    .unwrap_or_else(|| format!("invalid_script:{}", script.to_hex()))
+                    .unwrap_or_else(|| format!("script:{}", script.to_hex()))
             }
             Allowable::Payee(pubkey) => format!("payee:{}", pubkey.to_hex()),
         }
@@ -456,6 +617,9 @@ impl Allowable {
             } else if prefix == "payee" {
                 let pubkey = PublicKey::from_str(body).map_err(|_| s.to_string())?;
                 Ok(Allowable::Payee(pubkey))
+            } else if prefix == "script" {
+                let script = Vec::from_hex(body).map_err(|_| s.to_string())?;
+                Ok(Allowable::Script(Script::from(script)))
             } else {
                 Err(s.to_string())
             }
@@ -502,11 +666,19 @@ impl Allowable {
 pub struct Node {
     pub(crate) node_config: NodeConfig,
     pub(crate) keys_manager: MyKeysManager,
+    node_key_signer: Mutex<Arc<dyn NodeKeySigner>>,
     channels: Mutex<OrderedMap<ChannelId, Arc<Mutex<ChannelSlot>>>>,
     pub(crate) validator_factory: Mutex<Arc<dyn ValidatorFactory>>,
+    policy_observer: Mutex<Option<Arc<dyn PolicyObserver>>>,
     pub(crate) persister: Arc<dyn Persist>,
     allowlist: Mutex<UnorderedSet<Allowable>>,
+    watched_scripts: Mutex<UnorderedSet<Script>>,
     tracker: Mutex<ChainTracker<ChainMonitor>>,
+    notified_height: Mutex<u32>,
+    sealed: AtomicBool,
+    clock: Mutex<Arc<dyn Clock>>,
+    signing_rate_buckets: Mutex<OrderedMap<ChannelId, TokenBucket>>,
+    funding_tx_policy: Mutex<FundingTxPolicy>,
     pub(crate) state: Mutex<NodeState>,
 }
 
@@ -548,9 +720,24 @@ impl Wallet for Node {
         Ok(Address::p2shwpkh(&pubkey, self.network()).expect("p2wpkh failed"))
     }
 
-    /// Returns true if script_pubkey is in the node's allowlist.
+    /// Returns true if script_pubkey is in the node's allowlist,
+    /// or was derived from an imported watch descriptor.
     fn allowlist_contains(&self, script_pubkey: &Script) -> bool {
-        self.allowlist.lock().unwrap().contains(&Allowable::Script(script_pubkey.clone()))
+        let allowlist = self.allowlist.lock().unwrap();
+        if allowlist.contains(&Allowable::Script(script_pubkey.clone()))
+            || self.watched_scripts.lock().unwrap().contains(script_pubkey)
+        {
+            return true;
+        }
+        if !self.node_config.allowlist_match_derivable {
+            return false;
+        }
+        allowlist.iter().any(|a| match a {
+            Allowable::Script(s) =>
+                wrapped_p2wpkh(s).as_ref() == Some(script_pubkey)
+                    || wrapped_p2wpkh(script_pubkey).as_ref() == Some(s),
+            Allowable::Payee(_) => false,
+        })
     }
 
     fn network(&self) -> Network {
@@ -559,6 +746,17 @@ impl Wallet for Node {
 }
 
 impl Node {
+    /// The node public key that a node constructed with this config and
+    /// seed would have, e.g. to look up its persisted state before
+    /// constructing it.
+    pub fn node_pubkey_from_seed(node_config: NodeConfig, seed: &[u8]) -> PublicKey {
+        MyKeysManager::node_pubkey_from_seed(
+            node_config.key_derivation_style,
+            seed,
+            node_config.network,
+        )
+    }
+
     /// Create a node.
     ///
     /// NOTE: you must persist the node yourself if it is new.
@@ -620,19 +818,30 @@ impl Node {
             now.as_secs(),
             now.subsec_nanos(),
         );
-        let node_id = Self::id_from_key(&keys_manager.get_node_secret(Recipient::Node).unwrap());
+        let node_key_signer = InMemoryNodeKeySigner::new(
+            keys_manager.get_node_secret(Recipient::Node).expect("node secret"),
+        );
+        let node_id = node_key_signer.node_id();
         let log_prefix = &node_id.to_hex()[0..4];
 
         let state = Mutex::new(state.with_log_prefix(log_prefix.to_string()));
 
         Node {
             keys_manager,
+            node_key_signer: Mutex::new(Arc::new(node_key_signer)),
             node_config,
             channels: Mutex::new(OrderedMap::new()),
             validator_factory: Mutex::new(validator_factory),
+            policy_observer: Mutex::new(None),
             persister: Arc::clone(persister),
             allowlist: Mutex::new(UnorderedSet::from_iter(allowlist)),
+            watched_scripts: Mutex::new(UnorderedSet::new()),
             tracker: Mutex::new(tracker),
+            notified_height: Mutex::new(0),
+            sealed: AtomicBool::new(false),
+            clock: Mutex::new(default_clock()),
+            signing_rate_buckets: Mutex::new(OrderedMap::new()),
+            funding_tx_policy: Mutex::new(FundingTxPolicy::default()),
             state,
         }
     }
@@ -650,26 +859,185 @@ impl Node {
         merkleroot: &[u8; 32],
         publictweak_opt: Option<&[u8]>,
     ) -> Result<schnorrsig::Signature, Status> {
+        self.check_capability(Capabilities::INVOICES)?;
         self.keys_manager
             .sign_bolt12(messagename, fieldname, merkleroot, publictweak_opt)
             .map_err(|_| internal_error("signature operation failed"))
     }
 
+    /// Seal the node, so that new channels can't be created or readied.
+    /// Existing channels can still sign, so that they can be resolved.
+    pub fn seal(&self) {
+        info!("{} sealed", self.log_prefix());
+        self.sealed.store(true, Ordering::SeqCst);
+    }
+
+    /// Allow new channels again after [Node::seal]
+    pub fn unseal(&self) {
+        info!("{} unsealed", self.log_prefix());
+        self.sealed.store(false, Ordering::SeqCst);
+    }
+
+    /// Whether the node is sealed
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.load(Ordering::SeqCst)
+    }
+
+    fn check_not_sealed(&self) -> Result<(), Status> {
+        if self.is_sealed() {
+            return Err(failed_precondition("node sealed"));
+        }
+        Ok(())
+    }
+
+    /// The current height for policy checks, which is the higher of the
+    /// chain tracker height and the latest height the node was notified of.
+    ///
+    /// This locks the tracker, so it must not be called with a channel locked.
+    pub fn get_block_height(&self) -> u32 {
+        core::cmp::max(self.get_tracker().height(), self.get_notified_height())
+    }
+
+    // The latest height passed to notify_new_block.  Channels combine this
+    // with the height their monitor gets from the tracker.
+    pub(crate) fn get_notified_height(&self) -> u32 {
+        *self.notified_height.lock().unwrap()
+    }
+
+    /// Notify the node of a new chain tip.
+    ///
+    /// The height is used by subsequent validations, and the validator
+    /// factory is informed via [ValidatorFactory::on_new_block].  The
+    /// height is persisted, so that it is restored with the node.
+    ///
+    /// A height below one previously notified is ignored, so that a reorg
+    /// can't move the policy height backwards.
+    pub fn notify_new_block(&self, height: u32, block_hash: [u8; 32]) -> Result<(), Status> {
+        {
+            let mut notified_height = self.notified_height.lock().unwrap();
+            if height < *notified_height {
+                debug!(
+                    "{} ignoring block height {} below {}",
+                    self.log_prefix(),
+                    height,
+                    *notified_height
+                );
+                return Ok(());
+            }
+            *notified_height = height;
+        }
+        self.validator_factory.lock().unwrap().on_new_block(height, &block_hash);
+        self.persister
+            .update_node_block_height(&self.get_id(), height)
+            .map_err(|_| internal_error("persist failed"))
+    }
+
+    /// Set the signer used for node key operations, such as an external
+    /// hardware signer.
+    ///
+    /// The signer must be for the same node key.
+    pub fn set_node_key_signer(&self, signer: Arc<dyn NodeKeySigner>) -> Result<(), Status> {
+        if signer.node_id() != self.get_id() {
+            return Err(invalid_argument(format!(
+                "node key signer is for {} instead of {}",
+                signer.node_id(),
+                self.get_id()
+            )));
+        }
+        *self.node_key_signer.lock().unwrap() = signer;
+        Ok(())
+    }
+
+    pub(crate) fn node_key_signer(&self) -> Arc<dyn NodeKeySigner> {
+        Arc::clone(&*self.node_key_signer.lock().unwrap())
+    }
+
+    /// Set the source of wall-clock time, which defaults to the system
+    /// time where available
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.lock().unwrap() = clock;
+    }
+
+    pub(crate) fn clock_now(&self) -> Duration {
+        self.clock.lock().unwrap().now()
+    }
+
+    /// Set the funding transaction policy, for example to raise the feerate
+    /// floor when fees are high.  The policy is not persisted.
+    pub fn set_funding_tx_policy(&self, policy: FundingTxPolicy) {
+        *self.funding_tx_policy.lock().unwrap() = policy;
+    }
+
+    /// The current funding transaction policy
+    pub fn funding_tx_policy(&self) -> FundingTxPolicy {
+        *self.funding_tx_policy.lock().unwrap()
+    }
+
+    /// Fail unless the node is configured with all of the capabilities in `required`
+    pub(crate) fn check_capability(&self, required: Capabilities) -> Result<(), Status> {
+        if !self.node_config.capabilities.contains(required) {
+            return Err(failed_precondition(format!("capability disabled: {}", required)));
+        }
+        Ok(())
+    }
+
+    /// Consume one signing operation from the channel's allowance under
+    /// `limit`, failing if it is exhausted
+    pub(crate) fn check_signing_rate(
+        &self,
+        channel_id: &ChannelId,
+        limit: Option<RateLimit>,
+    ) -> Result<(), Status> {
+        let limit = match limit {
+            None => return Ok(()),
+            Some(limit) => limit,
+        };
+        let now = self.clock_now();
+        let mut buckets = self.signing_rate_buckets.lock().unwrap();
+        let bucket = buckets.entry(*channel_id).or_insert_with(|| TokenBucket::new(limit, now));
+        if bucket.limit != limit {
+            // The policy changed
+            *bucket = TokenBucket::new(limit, now);
+        }
+        if !bucket.try_take(now) {
+            return Err(policy_error(format!(
+                "signing rate limit exceeded for channel {}: {} per {:?}",
+                channel_id, limit.max_signs, limit.interval
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     /// Set the node's validator factory
     pub fn set_validator_factory(&self, validator_factory: Arc<dyn ValidatorFactory>) {
         let mut vfac = self.validator_factory.lock().unwrap();
         *vfac = validator_factory;
     }
 
-    /// Get the node ID, which is the same as the node public key
-    pub fn get_id(&self) -> PublicKey {
-        let key = &self.keys_manager.get_node_secret(Recipient::Node).unwrap();
-        Self::id_from_key(key)
+    /// Set an observer to be notified of every policy decision
+    pub fn set_policy_observer(&self, observer: Arc<dyn PolicyObserver>) {
+        *self.policy_observer.lock().unwrap() = Some(observer);
     }
 
-    fn id_from_key(key: &SecretKey) -> PublicKey {
-        let secp_ctx = Secp256k1::signing_only();
-        PublicKey::from_secret_key(&secp_ctx, key)
+    /// Make a validator for the node or one of its channels, reporting
+    /// to the policy observer if one is set
+    pub(crate) fn make_validator(&self, channel_id: Option<ChannelId>) -> Arc<dyn Validator> {
+        let validator = self.validator_factory.lock().unwrap().make_validator(
+            self.network(),
+            self.get_id(),
+            channel_id,
+        );
+        match &*self.policy_observer.lock().unwrap() {
+            Some(observer) =>
+                Arc::new(ObservedValidator::new(validator, Arc::clone(observer), channel_id)),
+            None => validator,
+        }
+    }
+
+    /// Get the node ID, which is the same as the node public key
+    pub fn get_id(&self) -> PublicKey {
+        self.node_key_signer().node_id()
     }
 
     fn log_prefix(&self) -> String {
@@ -686,6 +1054,19 @@ impl Node {
         self.keys_manager.get_secure_random_bytes()
     }
 
+    /// Derive a fresh onion session key, persisting the session key counter
+    /// so that keys stay unique after a restart
+    pub fn rotate_session_key(&self) -> Result<SecretKey, Status> {
+        let key = self.keys_manager.rotate_session_key();
+        self.persister
+            .update_node_session_key_counter(
+                &self.get_id(),
+                self.keys_manager.session_key_counter(),
+            )
+            .map_err(|_| internal_error("persist failed"))?;
+        Ok(key)
+    }
+
     /// Get secret key material as bytes for use in encrypting and decrypting inbound payment data.
     ///
     /// This method must return the same value each time it is called.
@@ -734,13 +1115,160 @@ impl Node {
         }
     }
 
+    /// Sign a mutual close for a ready channel which never had a
+    /// commitment signed, paying to the shutdown scripts.
+    ///
+    /// See [Channel::sign_mutual_close_tx_from_shutdown_scripts].
+    pub fn sign_closing_tx_from_shutdown_scripts(
+        &self,
+        channel_id: &ChannelId,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        self.with_ready_channel(channel_id, |chan| {
+            chan.sign_mutual_close_tx_from_shutdown_scripts(
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_script,
+                counterparty_script,
+                holder_wallet_path_hint,
+            )
+        })
+    }
+
+    /// Check a peer's `channel_reestablish` for a ready channel.
+    ///
+    /// See [Channel::validate_channel_reestablish].
+    pub fn on_channel_reestablish(
+        &self,
+        channel_id: &ChannelId,
+        next_local_commitment_number: u64,
+        next_remote_revocation_number: u64,
+        your_last_per_commitment_secret: Option<SecretKey>,
+        my_current_per_commitment_point: Option<PublicKey>,
+    ) -> Result<(), Status> {
+        self.with_ready_channel(channel_id, |chan| {
+            chan.validate_channel_reestablish(
+                next_local_commitment_number,
+                next_remote_revocation_number,
+                your_last_per_commitment_secret,
+                my_current_per_commitment_point,
+            )
+        })
+    }
+
+    /// Get the holder's funding pubkey for a ready channel.
+    ///
+    /// An invalid_argument [Status] will be returned if the channel does not exist
+    /// or is not ready.
+    pub fn get_funding_pubkey(&self, channel_id: &ChannelId) -> Result<PublicKey, Status> {
+        self.with_ready_channel(channel_id, |chan| Ok(chan.keys.pubkeys().funding_pubkey))
+    }
+
+    /// Get the holder's basepoints for a ready channel.
+    ///
+    /// Use [`Node::with_channel_base`] if the channel may still be a stub.
+    pub fn get_channel_basepoints_ready(
+        &self,
+        channel_id: &ChannelId,
+    ) -> Result<ChannelPublicKeys, Status> {
+        self.with_ready_channel(channel_id, |chan| Ok(chan.get_channel_basepoints()))
+    }
+
     /// Get a channel given its funding outpoint, or None if no such channel exists.
     pub fn find_channel_with_funding_outpoint(
         &self,
         outpoint: &OutPoint,
     ) -> Option<Arc<Mutex<ChannelSlot>>> {
-        let channels_lock = self.channels.lock().unwrap();
-        find_channel_with_funding_outpoint(&channels_lock, outpoint)
+        let mut found = None;
+        self.for_each_channel(|slot_arc| {
+            if found.is_some() {
+                return;
+            }
+            if let ChannelSlot::Ready(chan) = &*slot_arc.lock().unwrap() {
+                if chan.setup.funding_outpoint == *outpoint {
+                    found = Some(Arc::clone(slot_arc));
+                }
+            }
+        });
+        found
+    }
+
+    /// Call `f` on each channel slot, in channel ID order.
+    ///
+    /// The channel list is snapshotted and the channels lock released before
+    /// `f` is called, so `f` may lock the slot (or even the node's channels)
+    /// without holding two locks at once.  A channel with an alternate ID is
+    /// visited once.  Channels added during the iteration are not visited.
+    pub fn for_each_channel<F>(&self, mut f: F)
+    where
+        F: FnMut(&Arc<Mutex<ChannelSlot>>),
+    {
+        let slots: Vec<Arc<Mutex<ChannelSlot>>> = {
+            let channels = self.channels.lock().unwrap();
+            let mut seen = UnorderedSet::new();
+            channels
+                .values()
+                .filter(|slot| seen.insert(Arc::as_ptr(slot)))
+                .map(Arc::clone)
+                .collect()
+        };
+        for slot in slots.iter() {
+            f(slot);
+        }
+    }
+
+    /// Execute a function with all ready channels locked at the same time,
+    /// for policies that must see a consistent view across channels.
+    ///
+    /// As in [Node::sign_onchain_tx], the channel map is locked for the whole
+    /// traversal, so that acquiring several channel locks can't deadlock
+    /// against another multi-channel operation.  This must not be called
+    /// while holding a channel lock, and `f` must not call back into the node.
+    ///
+    /// Returns the result of `f` for each ready channel, in channel ID order.
+    /// A channel with an alternate ID is visited once.  Stubs are skipped.
+    pub fn with_all_ready_channels<F, T>(&self, f: F) -> Vec<Result<T, Status>>
+    where
+        F: Fn(&mut Channel) -> Result<T, Status>,
+    {
+        let channels = self.channels.lock().unwrap();
+        let mut seen = UnorderedSet::new();
+        let mut guards: Vec<_> = channels
+            .values()
+            .filter(|slot| seen.insert(Arc::as_ptr(slot)))
+            .map(|slot| slot.lock().unwrap())
+            .collect();
+        guards
+            .iter_mut()
+            .filter_map(|guard| match &mut **guard {
+                ChannelSlot::Stub(_) => None,
+                ChannelSlot::Ready(chan) => Some(f(chan)),
+            })
+            .collect()
+    }
+
+    /// Count the channels in each state, in a single pass over the channels.
+    /// A channel with an alternate ID is counted once.
+    pub fn count_channels_by_state(&self) -> ChannelStateCounts {
+        let channels = self.channels.lock().unwrap();
+        let mut seen = UnorderedSet::new();
+        let mut counts = ChannelStateCounts::default();
+        for slot in channels.values().filter(|slot| seen.insert(Arc::as_ptr(slot))) {
+            match &*slot.lock().unwrap() {
+                ChannelSlot::Stub(_) => counts.stub_count += 1,
+                ChannelSlot::Ready(chan) => {
+                    counts.ready_count += 1;
+                    if chan.enforcement_state.mutual_close_signed {
+                        counts.mutual_close_signed_count += 1;
+                    }
+                }
+            }
+        }
+        counts
     }
 
     /// Create a new channel, which starts out as a stub.
@@ -765,6 +1293,7 @@ impl Node {
         opt_channel_nonce0: Option<Vec<u8>>,
         arc_self: &Arc<Node>,
     ) -> Result<(ChannelId, Option<ChannelStub>), Status> {
+        self.check_not_sealed()?;
         let channel_id =
             opt_channel_id.unwrap_or_else(|| ChannelId(self.keys_manager.get_channel_id()));
         let channel_nonce0 = opt_channel_nonce0.unwrap_or_else(|| channel_id.0.to_vec());
@@ -810,6 +1339,8 @@ impl Node {
             secp_ctx: Secp256k1::new(),
             keys,
             id0: channel_id,
+            creation_time: self.clock_now(),
+            counterparty_points: None,
         };
         // TODO this clone is expensive
         channels.insert(channel_id, Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone()))));
@@ -821,6 +1352,35 @@ impl Node {
         Ok((channel_id, Some(stub)))
     }
 
+    /// Remove channel stubs that were created more than `older_than` ago
+    /// and were never readied, e.g. because channel negotiation failed.
+    ///
+    /// The stubs are also deleted from the persister.  Ready channels are
+    /// never pruned.  Returns the IDs of the pruned stubs.
+    pub fn prune_stale_stubs(&self, older_than: Duration) -> Result<Vec<ChannelId>, Status> {
+        let now = self.clock_now();
+        let mut channels = self.channels.lock().unwrap();
+        let stale: Vec<ChannelId> = channels
+            .iter()
+            .filter_map(|(id, slot)| match &*slot.lock().unwrap() {
+                ChannelSlot::Stub(stub)
+                    if stub.id0 == *id && now.saturating_sub(stub.creation_time) > older_than =>
+                    Some(*id),
+                _ => None,
+            })
+            .collect();
+        for id0 in stale.iter() {
+            self.persister
+                .delete_channel(&self.get_id(), id0)
+                .map_err(|_| internal_error(format!("could not delete stub {}", id0)))?;
+            let slot = channels.remove(id0).expect("stale stub");
+            // A restored stub may also be indexed by its permanent ID
+            channels.retain(|_, other| !Arc::ptr_eq(other, &slot));
+            info!("pruned stale channel stub {}", id0);
+        }
+        Ok(stale)
+    }
+
     pub(crate) fn restore_channel(
         &self,
         channel_id0: ChannelId,
@@ -829,8 +1389,12 @@ impl Node {
         channel_value_sat: u64,
         channel_setup: Option<ChannelSetup>,
         enforcement_state: EnforcementState,
+        counterparty_points: Option<ChannelPublicKeys>,
         arc_self: &Arc<Node>,
     ) -> Result<Arc<Mutex<ChannelSlot>>, ()> {
+        enforcement_state.check_invariants().map_err(|msg| {
+            error!("restore_channel: inconsistent enforcement state for {}: {}", channel_id0, msg)
+        })?;
         let mut channels = self.channels.lock().unwrap();
         assert!(!channels.contains_key(&channel_id0));
         let mut keys = self.keys_manager.get_channel_keys_with_id(
@@ -847,6 +1411,8 @@ impl Node {
                     secp_ctx: Secp256k1::new(),
                     keys,
                     id0: channel_id0,
+                    creation_time: self.clock_now(),
+                    counterparty_points,
                 };
                 // TODO this clone is expensive
                 let slot = Arc::new(Mutex::new(ChannelSlot::Stub(stub.clone())));
@@ -861,6 +1427,9 @@ impl Node {
                 let funding_outpoint = setup.funding_outpoint;
                 // FIXME correct persistence
                 let monitor = ChainMonitor::new(funding_outpoint, 0);
+                if let Some((splice_outpoint, _)) = setup.splice_funding {
+                    monitor.add_splice(&splice_outpoint);
+                }
                 let channel = Channel {
                     node: Arc::downgrade(arc_self),
                     nonce,
@@ -885,28 +1454,45 @@ impl Node {
 
     /// Restore a node from a persisted [NodeEntry].
     ///
-    /// You can get the [NodeEntry] from [Persist::get_nodes].
+    /// You can get the [NodeEntry] from [Persist::get_node].
     ///
-    /// The channels are also restored from the `persister`.
+    /// The channels are also restored from the `persister`.  If any of them
+    /// can't be restored, an internal error [Status] listing their IDs is
+    /// returned.
     pub fn restore_node(
         node_id: &PublicKey,
         node_entry: NodeEntry,
         persister: Arc<dyn Persist>,
         validator_factory: Arc<dyn ValidatorFactory>,
-    ) -> Arc<Node> {
+    ) -> Result<Arc<Node>, Status> {
         let network = Network::from_str(node_entry.network.as_str()).expect("bad network");
         let config = NodeConfig {
             network,
             key_derivation_style: KeyDerivationStyle::try_from(node_entry.key_derivation_style)
                 .unwrap(),
+            require_static_remote_key: node_entry.require_static_remote_key,
+            low_r_grinding: node_entry.low_r_grinding,
+            allow_rbf_funding: node_entry.allow_rbf_funding,
+            maximum_commitment_number: node_entry.maximum_commitment_number,
+            export_allowed: node_entry.export_allowed,
+            allowlist_match_derivable: node_entry.allowlist_match_derivable,
+            min_invoice_cltv_expiry: node_entry.min_invoice_cltv_expiry,
+            capabilities: Capabilities::from_bits_truncate(node_entry.capabilities),
         };
 
+        // A bad entry is skipped rather than failing the restore, but
+        // is logged so that a corrupted store doesn't go unnoticed
         let allowlist = persister
             .get_node_allowlist(node_id)
             .iter()
-            .map(|e| Allowable::from_str(e, network))
-            .collect::<Result<_, _>>()
-            .expect("allowable parse error");
+            .filter_map(|e| match Allowable::from_str(e, network) {
+                Ok(allowable) => Some(allowable),
+                Err(err) => {
+                    warn!("node {}: ignoring bad allowlist entry {}", node_id, err);
+                    None
+                }
+            })
+            .collect();
         let tracker = persister.get_tracker(node_id).expect("tracker");
         // FIXME persist node state
         let state = NodeState::new();
@@ -921,41 +1507,132 @@ impl Node {
             state,
         ));
         assert_eq!(&node.get_id(), node_id);
+        node.keys_manager.set_session_key_counter(persister.get_node_session_key_counter(node_id));
+        *node.notified_height.lock().unwrap() = persister.get_node_block_height(node_id);
         info!("Restore node {}", node_id);
+        let mut failed = Vec::new();
         for (channel_id0, channel_entry) in persister.get_node_channels(node_id) {
             info!("  Restore channel {}", channel_id0);
-            node.restore_channel(
+            let result = node.restore_channel(
                 channel_id0,
                 channel_entry.id,
                 channel_entry.nonce,
                 channel_entry.channel_value_satoshis,
                 channel_entry.channel_setup,
                 channel_entry.enforcement_state,
+                channel_entry.counterparty_points,
                 &node,
-            )
-            .expect("restore channel");
+            );
+            if result.is_err() {
+                // Already logged
+                failed.push(channel_id0.to_string());
+            }
         }
-        node
+        if !failed.is_empty() {
+            return Err(internal_error(format!(
+                "restore_node: node {}: could not restore channels: {}",
+                node_id,
+                failed.join(", ")
+            )));
+        }
+        Ok(node)
     }
 
     /// Restore all nodes from `persister`.
     ///
-    /// The channels of each node are also restored.
+    /// The channels of each node are also restored.  Fails if any node
+    /// fails to restore, see [Node::restore_node].
     pub fn restore_nodes(
         persister: Arc<dyn Persist>,
         validator_factory: Arc<dyn ValidatorFactory>,
-    ) -> Map<PublicKey, Arc<Node>> {
+    ) -> Result<Map<PublicKey, Arc<Node>>, Status> {
         let mut nodes = Map::new();
-        for (node_id, node_entry) in persister.get_nodes() {
+        for node_id in persister.list_node_ids() {
+            let node_entry = match persister.get_node(&node_id) {
+                Some(entry) => entry,
+                None => {
+                    // removed since we listed it
+                    warn!("node {} disappeared during restore", node_id);
+                    continue;
+                }
+            };
             let node = Node::restore_node(
                 &node_id,
                 node_entry,
                 Arc::clone(&persister),
                 validator_factory.clone(),
-            );
+            )?;
             nodes.insert(node_id, node);
         }
-        nodes
+        Ok(nodes)
+    }
+
+    /// Create a copy of this node using keys derived from `new_seed`.
+    ///
+    /// Each channel's keys are re-derived from its original nonce and must
+    /// match the keys the channel is currently using, otherwise an
+    /// invalid_argument [Status] is returned.  Since channel keys are derived
+    /// from the seed, a different seed can only be used if there are no channels.
+    ///
+    /// The allowlist and chain position are carried over, but the payment
+    /// state is not.  Nothing is persisted.
+    pub fn rekey(&self, new_seed: &[u8; 32]) -> Result<Arc<Node>, Status> {
+        let allowlist = self.allowlist.lock().unwrap().iter().cloned().collect();
+        let tracker = {
+            let tracker = self.get_tracker();
+            ChainTracker::new(self.node_config.network, tracker.height(), tracker.tip())
+                .map_err(|_| internal_error("rekey: bad chain tip"))?
+        };
+        let validator_factory = Arc::clone(&*self.validator_factory.lock().unwrap());
+        let node = Arc::new(Node::new_from_persistence(
+            self.node_config,
+            new_seed,
+            &self.persister,
+            allowlist,
+            tracker,
+            validator_factory,
+            NodeState::new(),
+        ));
+        *node.notified_height.lock().unwrap() = self.get_notified_height();
+
+        let mut slots = Vec::new();
+        self.for_each_channel(|slot| slots.push(Arc::clone(slot)));
+        for slot in slots {
+            let slot = slot.lock().unwrap();
+            let (channel_id, channel_value_sat, setup, enforcement_state, counterparty_points) =
+                match &*slot {
+                    ChannelSlot::Stub(stub) =>
+                        (None, 0, None, EnforcementState::new(0), stub.counterparty_points.clone()),
+                    ChannelSlot::Ready(chan) => (
+                        chan.id,
+                        chan.setup.channel_value_sat,
+                        Some(chan.setup.clone()),
+                        chan.enforcement_state.clone(),
+                        None,
+                    ),
+                };
+            let new_slot = node
+                .restore_channel(
+                    slot.channel_id(),
+                    channel_id,
+                    slot.nonce(),
+                    channel_value_sat,
+                    setup,
+                    enforcement_state,
+                    counterparty_points,
+                    &node,
+                )
+                .map_err(|()| {
+                    internal_error(format!("rekey: could not restore {}", slot.channel_id()))
+                })?;
+            if new_slot.lock().unwrap().get_channel_basepoints() != slot.get_channel_basepoints() {
+                return Err(invalid_argument(format!(
+                    "rekey: channel {} keys do not match the new seed",
+                    slot.channel_id()
+                )));
+            }
+        }
+        Ok(node)
     }
 
     /// Ready a new channel, making it available for use.
@@ -975,12 +1652,9 @@ impl Node {
         setup: ChannelSetup,
         holder_shutdown_key_path: &Vec<u32>,
     ) -> Result<Channel, Status> {
+        self.check_not_sealed()?;
         let mut tracker = self.tracker.lock().unwrap();
-        let validator = self.validator_factory.lock().unwrap().make_validator(
-            self.network(),
-            self.get_id(),
-            Some(channel_id0),
-        );
+        let validator = self.make_validator(Some(channel_id0));
 
         let chan = {
             let channels = self.channels.lock().unwrap();
@@ -993,6 +1667,15 @@ impl Node {
                 ChannelSlot::Ready(_) =>
                     Err(invalid_argument(format!("channel already ready: {}", channel_id0))),
             }?;
+            if let Some(points) = &stub.counterparty_points {
+                if *points != setup.counterparty_points {
+                    return Err(policy_error(format!(
+                        "counterparty points do not match the points stored in stub {}",
+                        channel_id0
+                    ))
+                    .into());
+                }
+            }
             let mut keys = stub.channel_keys_with_channel_value(setup.channel_value_sat);
             let holder_pubkeys = keys.pubkeys();
             let channel_transaction_parameters =
@@ -1031,7 +1714,12 @@ impl Node {
             }
         };
 
-        validator.validate_ready_channel(self, &setup, holder_shutdown_key_path)?;
+        validator.validate_ready_channel(
+            self,
+            &self.node_config,
+            &setup,
+            holder_shutdown_key_path,
+        )?;
 
         let mut channels = self.channels.lock().unwrap();
 
@@ -1100,17 +1788,42 @@ impl Node {
         uniclosekeys: Vec<Option<(SecretKey, Vec<Vec<u8>>)>>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<Vec<Vec<Vec<u8>>>, Status> {
+        self.check_capability(Capabilities::FUNDING)?;
+        let input_lens = [
+            ("ipaths", ipaths.len()),
+            ("values_sat", values_sat.len()),
+            ("spendtypes", spendtypes.len()),
+            ("uniclosekeys", uniclosekeys.len()),
+        ];
+        for (name, len) in input_lens.iter() {
+            if *len != tx.input.len() {
+                return Err(invalid_argument(format!(
+                    "{}: bad {} len {} with tx.input len {}",
+                    short_function!(),
+                    name,
+                    len,
+                    tx.input.len()
+                )));
+            }
+        }
+        if opaths.len() != tx.output.len() {
+            return Err(invalid_argument(format!(
+                "{}: bad opath len {} with tx.output len {}",
+                short_function!(),
+                opaths.len(),
+                tx.output.len()
+            )));
+        }
+
+        // Before locking the channels, since this locks the tracker
+        let current_height = self.get_block_height();
         let channels_lock = self.channels.lock().unwrap();
         let secp_ctx = Secp256k1::signing_only();
 
         // Funding transactions cannot be associated with just a single channel;
         // a single transaction may fund multiple channels
 
-        let validator = self.validator_factory.lock().unwrap().make_validator(
-            self.network(),
-            self.get_id(),
-            None,
-        );
+        let validator = self.make_validator(None);
 
         let txid = tx.txid();
 
@@ -1121,7 +1834,16 @@ impl Node {
             })
             .collect();
 
-        validator.validate_onchain_tx(self, channels.clone(), tx, values_sat, opaths)?;
+        validator.validate_onchain_tx(
+            self,
+            &self.node_config,
+            current_height,
+            &self.funding_tx_policy(),
+            channels.clone(),
+            tx,
+            values_sat,
+            opaths,
+        )?;
 
         let mut witvec: Vec<Vec<Vec<u8>>> = Vec::new();
         for (idx, uck) in uniclosekeys.into_iter().enumerate() {
@@ -1177,7 +1899,12 @@ impl Node {
                 let message = Message::from_slice(&sighash).map_err(|err| {
                     internal_error(format!("sighash {:?} failed: {}", spendtypes[idx], err))
                 })?;
-                let sig = secp_ctx.sign(&message, &privkey.key);
+                let sig = sign_maybe_low_r(
+                    &secp_ctx,
+                    &message,
+                    &privkey.key,
+                    self.node_config.low_r_grinding,
+                );
                 let sigvec = signature_to_bitcoin_vec(sig);
                 witness.insert(0, sigvec);
 
@@ -1192,6 +1919,7 @@ impl Node {
         // lock to ensure no deadlock.  We grab the self.channels mutex above
         // for this purpose.
         // TODO(devrandom) consider sorting instead
+        let mut slots = Vec::new();
         for (vout, slot_opt) in channels.iter().enumerate() {
             if let Some(slot_mutex) = slot_opt {
                 let slot = slot_mutex.lock().unwrap();
@@ -1204,6 +1932,7 @@ impl Node {
                         chan.funding_signed(tx, vout as u32)
                     }
                 }
+                slots.push(slot);
             }
         }
 
@@ -1212,11 +1941,22 @@ impl Node {
             .update_tracker(&self.get_id(), &tracker)
             .map_err(|_| internal_error("tracker persist failed"))?;
 
-        // TODO(devrandom) self.persist_channel(node_id, chan);
+        // All of the funded channels are persisted together
+        let funded_channels: Vec<&Channel> = slots
+            .iter()
+            .filter_map(|slot| match &**slot {
+                ChannelSlot::Stub(_) => None,
+                ChannelSlot::Ready(chan) => Some(chan),
+            })
+            .collect();
+        self.persister
+            .update_channels_batch(&self.get_id(), &funded_channels)
+            .map_err(|_| internal_error("persist failed"))?;
+
         Ok(witvec)
     }
 
-    fn channel_setup_to_channel_transaction_parameters(
+    pub(crate) fn channel_setup_to_channel_transaction_parameters(
         setup: &ChannelSetup,
         holder_pubkeys: &ChannelPublicKeys,
     ) -> ChannelTransactionParameters {
@@ -1250,7 +1990,7 @@ impl Node {
             )));
         }
         // Start with the base xpriv for this wallet.
-        let mut xkey = self.get_account_extended_key().clone();
+        let mut xkey = self.keys_manager.get_account_extended_key().clone();
 
         // Derive the rest of the child_path.
         for elem in child_path {
@@ -1269,50 +2009,375 @@ impl Node {
         Ok(self.get_wallet_privkey(secp_ctx, child_path)?.public_key(secp_ctx))
     }
 
+    /// Get the native segwit (P2WPKH) address of the layer-1 wallet key at `path`.
+    ///
+    /// Fails with an invalid_argument [Status] if the path length doesn't
+    /// match the key derivation style of the node.
+    pub fn get_wallet_address(&self, path: &[u32]) -> Result<Address, Status> {
+        self.get_native_address(&path.to_vec())
+    }
+
+    /// Get the taproot (P2TR) address of the layer-1 wallet key at `path`,
+    /// for a BIP-86 key path only spend.
+    ///
+    /// Fails with an invalid_argument [Status] if the path length doesn't
+    /// match the key derivation style of the node.
+    pub fn get_wallet_address_p2tr(&self, path: &[u32]) -> Result<Address, Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        let pubkey = self.get_wallet_pubkey(&secp_ctx, &path.to_vec())?;
+        let payload = payload_for_p2tr(&Secp256k1::verification_only(), &pubkey.key);
+        Ok(Address { network: self.network(), payload })
+    }
+
+    fn check_export_allowed(&self) -> Result<(), Status> {
+        if !self.node_config.export_allowed {
+            return Err(permission_denied("export disabled"));
+        }
+        Ok(())
+    }
+
     /// Get the node secret key
-    /// This function will be eliminated once the node key related items
-    /// are implemented.  This includes onion decoding and p2p handshake.
-    // TODO leaking secret
-    pub fn get_node_secret(&self) -> SecretKey {
+    ///
+    /// Fails with a permission denied [Status] unless
+    /// [NodeConfig::export_allowed] is set.
+    pub fn get_node_secret(&self) -> Result<SecretKey, Status> {
+        self.check_export_allowed()?;
+        Ok(self.node_secret())
+    }
+
+    pub(crate) fn node_secret(&self) -> SecretKey {
         self.keys_manager.get_node_secret(Recipient::Node).unwrap()
     }
 
     /// Get shutdown_pubkey to use as PublicKey at channel closure
-    // FIXME - this method is deprecated
+    // FIXME - this method is deprecated, use holder_shutdown_script instead
     pub fn get_ldk_shutdown_scriptpubkey(&self) -> ShutdownScript {
         self.keys_manager.get_shutdown_scriptpubkey()
     }
 
-    /// Get the layer-1 xprv
-    // TODO leaking private key
-    pub fn get_account_extended_key(&self) -> &ExtendedPrivKey {
-        self.keys_manager.get_account_extended_key()
+    /// Get a p2wpkh shutdown script for the layer-1 wallet key at `path`.
+    ///
+    /// Supplying the script as [ChannelSetup::holder_shutdown_script] and the
+    /// same path as the `holder_shutdown_key_path` to [Node::ready_channel]
+    /// passes the wallet check, since [Wallet::can_spend] recognizes it.
+    pub fn holder_shutdown_script(&self, path: &[u32]) -> Result<Script, Status> {
+        Ok(self.get_native_address(&path.to_vec())?.script_pubkey())
     }
 
-    /// Get the layer-1 xpub
-    pub fn get_account_extended_pubkey(&self) -> ExtendedPubKey {
+    /// Get the destination script for sweeps, along with the wallet
+    /// derivation path of its key.
+    ///
+    /// The path can be supplied as the `ipaths` entry to [Node::sign_onchain_tx]
+    /// when spending outputs paid to this script.
+    ///
+    /// This is a layer-1 wallet key, unlike the destination script that
+    /// [MyKeysManager] uses for spendable outputs, which is derived from the
+    /// hardened `m/1'` key.
+    pub fn get_destination_script_with_path(&self) -> (Script, Vec<u32>) {
+        let wallet_path = vec![1];
+        let address = self.get_native_address(&wallet_path).expect("native address");
+        (address.script_pubkey(), wallet_path)
+    }
+
+    /// Sign the sweep of the `to_local` output of a holder commitment
+    /// that we broadcast, once the CSV delay has passed.
+    ///
+    /// The `to_local` redeemscript is rebuilt from `revocation_pubkey`,
+    /// `to_self_delay` and our delayed payment key for `commit_num`.
+    /// The `wallet_path` is the wallet derivation path of the sweep
+    /// destination, or empty if the destination is in the allowlist.
+    pub fn sign_closing_sweep_tx(
+        &self,
+        channel_id: &ChannelId,
+        sweep_tx: &Transaction,
+        input_idx: usize,
+        commit_num: u64,
+        revocation_pubkey: &PublicKey,
+        to_self_delay: u16,
+        to_local_amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<Signature, Status> {
+        self.with_ready_channel(channel_id, |chan| {
+            if to_self_delay != chan.setup.counterparty_selected_contest_delay {
+                return Err(invalid_argument(format!(
+                    "sign_closing_sweep_tx: bad to_self_delay: {} != {}",
+                    to_self_delay, chan.setup.counterparty_selected_contest_delay
+                )));
+            }
+            let per_commitment_point = chan.get_per_commitment_point(commit_num)?;
+            let delayed_pubkey = derive_public_key(
+                &chan.secp_ctx,
+                &per_commitment_point,
+                &chan.keys.pubkeys().delayed_payment_basepoint,
+            )
+            .map_err(|err| internal_error(format!("derive_public_key failed: {}", err)))?;
+            let redeemscript =
+                get_revokeable_redeemscript(revocation_pubkey, to_self_delay, &delayed_pubkey);
+            chan.sign_delayed_sweep(
+                sweep_tx,
+                input_idx,
+                commit_num,
+                &redeemscript,
+                to_local_amount_sat,
+                wallet_path,
+            )
+        })
+    }
+
+    /// Sweep our `to_remote` output from a commitment transaction that
+    /// the counterparty broadcast, without any channel state.
+    ///
+    /// This is a recovery tool for when the persisted channel is lost.
+    /// The channel keys are re-derived from the seed and `channel_nonce`,
+    /// and `setup_hint` supplies the funding outpoint, channel value and
+    /// commitment type.  Legacy commitments can't be swept this way, since
+    /// their `to_remote` key depends on the per-commitment point.
+    ///
+    /// The output is swept to [Node::get_destination_script_with_path],
+    /// paying a fee at `feerate_per_kw`.  Returns the signed sweep transaction.
+    pub fn sweep_counterparty_commitment(
+        &self,
+        commitment_tx: &Transaction,
+        channel_nonce: &[u8],
+        setup_hint: &ChannelSetup,
+        feerate_per_kw: u32,
+    ) -> Result<Transaction, Status> {
+        if let KeyDerivationStyle::Lnd = self.node_config.key_derivation_style {
+            return Err(failed_precondition(
+                "sweep_counterparty_commitment: channel keys not derivable from the nonce",
+            ));
+        }
+        match setup_hint.commitment_type {
+            CommitmentType::StaticRemoteKey | CommitmentType::Anchors => {}
+            commitment_type =>
+                return Err(invalid_argument(format!(
+                    "sweep_counterparty_commitment: {:?} commitments not supported",
+                    commitment_type
+                ))),
+        }
+        if commitment_tx.input.len() != 1
+            || commitment_tx.input[0].previous_output != setup_hint.funding_outpoint
+        {
+            return Err(invalid_argument(format!(
+                "sweep_counterparty_commitment: commitment does not spend funding outpoint {}",
+                setup_hint.funding_outpoint
+            )));
+        }
+
+        let keys = self.keys_manager.get_channel_keys_with_id(
+            channel_nonce_to_id(&channel_nonce.to_vec()),
+            channel_nonce,
+            setup_hint.channel_value_sat,
+        );
+        let payment_point = keys.pubkeys().payment_point;
+        let option_anchor_outputs = setup_hint.option_anchor_outputs();
+        let (script_pubkey, script_code) = if option_anchor_outputs {
+            let redeemscript = get_to_countersignatory_with_anchors_redeemscript(&payment_point);
+            (redeemscript.to_v0_p2wsh(), redeemscript)
+        } else {
+            let pubkey = bitcoin::PublicKey { compressed: true, key: payment_point };
+            let script_code = Address::p2pkh(&pubkey, self.network()).script_pubkey();
+            (get_p2wpkh_redeemscript(&payment_point), script_code)
+        };
+        let vout = commitment_tx
+            .output
+            .iter()
+            .position(|out| out.script_pubkey == script_pubkey)
+            .ok_or_else(|| {
+            invalid_argument(format!(
+                "sweep_counterparty_commitment: no to_remote output for payment point {}",
+                payment_point
+            ))
+        })?;
+        let amount_sat = commitment_tx.output[vout].value;
+
+        let weight = if option_anchor_outputs {
+            SWEEP_TO_REMOTE_ANCHORS_WEIGHT
+        } else {
+            SWEEP_TO_REMOTE_WEIGHT
+        };
+        let fee = feerate_per_kw as u64 * weight / 1000;
+        if fee >= amount_sat {
+            return Err(invalid_argument(format!(
+                "sweep_counterparty_commitment: to_remote value {} can't pay fee {}",
+                amount_sat, fee
+            )));
+        }
+
+        let (destination, _) = self.get_destination_script_with_path();
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: commitment_tx.txid(), vout: vout as u32 },
+                script_sig: Script::new(),
+                // the anchors to_remote script has a one block CSV
+                sequence: if option_anchor_outputs { 1 } else { 0xffff_ffff },
+                witness: vec![],
+            }],
+            output: vec![TxOut { script_pubkey: destination, value: amount_sat - fee }],
+        };
+
+        let sighash =
+            SigHashCache::new(&tx).signature_hash(0, &script_code, amount_sat, SigHashType::All);
+        let message = Message::from_slice(&sighash[..])
+            .map_err(|err| internal_error(format!("sighash failed: {}", err)))?;
         let secp_ctx = Secp256k1::signing_only();
-        ExtendedPubKey::from_private(&secp_ctx, &self.get_account_extended_key())
+        let sig = sign_maybe_low_r(
+            &secp_ctx,
+            &message,
+            &keys.payment_key,
+            self.node_config.low_r_grinding,
+        );
+        let last_witness_element = if option_anchor_outputs {
+            script_code.into_bytes()
+        } else {
+            payment_point.serialize().to_vec()
+        };
+        tx.input[0].witness = vec![signature_to_bitcoin_vec(sig), last_witness_element];
+        Ok(tx)
+    }
+
+    /// Get the layer-1 xprv
+    ///
+    /// Requires [NodeConfig::export_allowed].
+    pub fn get_account_extended_key(&self) -> Result<&ExtendedPrivKey, Status> {
+        self.check_export_allowed()?;
+        Ok(self.keys_manager.get_account_extended_key())
+    }
+
+    /// Get the layer-1 xpub
+    ///
+    /// Requires [NodeConfig::export_allowed].
+    pub fn get_account_extended_pubkey(&self) -> Result<ExtendedPubKey, Status> {
+        let secp_ctx = Secp256k1::signing_only();
+        Ok(ExtendedPubKey::from_private(&secp_ctx, self.get_account_extended_key()?))
+    }
+
+    /// Get the BIP-32 fingerprint of the layer-1 xpub, for example to fill
+    /// in the key origin of PSBT inputs and outputs.
+    ///
+    /// Unlike the xpub itself, this does not require [NodeConfig::export_allowed].
+    pub fn account_key_fingerprint(&self) -> [u8; 4] {
+        let secp_ctx = Secp256k1::signing_only();
+        let xpub =
+            ExtendedPubKey::from_private(&secp_ctx, self.keys_manager.get_account_extended_key());
+        let mut fingerprint = [0u8; 4];
+        fingerprint.copy_from_slice(&xpub.fingerprint()[..]);
+        fingerprint
+    }
+
+    /// Get the layer-1 xpub encoded with SLIP-132 version bytes
+    ///
+    /// The format must match the node's network - mainnet formats for
+    /// Bitcoin, testnet formats otherwise.
+    pub fn account_extended_pubkey_slip132(&self, format: Slip132Format) -> Result<String, Status> {
+        if format.is_mainnet() != (self.network() == Network::Bitcoin) {
+            return Err(invalid_argument(format!(
+                "{:?} not valid for network {}",
+                format,
+                self.network()
+            )));
+        }
+        let mut data = self.get_account_extended_pubkey()?.encode();
+        data[0..4].copy_from_slice(&format.version_bytes());
+        Ok(base58::check_encode_slice(&data))
     }
 
     /// Sign a node announcement using the node key
     pub fn sign_node_announcement(&self, na: &Vec<u8>) -> Result<Signature, Status> {
-        let secp_ctx = Secp256k1::signing_only();
+        self.check_capability(Capabilities::ANNOUNCEMENTS)?;
         let na_hash = Sha256dHash::hash(na);
         let encmsg = secp256k1::Message::from_slice(&na_hash[..])
             .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
-        let sig = secp_ctx.sign(&encmsg, &self.get_node_secret());
-        Ok(sig)
+        self.node_key_signer().sign_digest(&encmsg)
     }
 
     /// Sign a channel update using the node key
     pub fn sign_channel_update(&self, cu: &Vec<u8>) -> Result<Signature, Status> {
-        let secp_ctx = Secp256k1::signing_only();
+        self.check_capability(Capabilities::ANNOUNCEMENTS)?;
         let cu_hash = Sha256dHash::hash(cu);
         let encmsg = secp256k1::Message::from_slice(&cu_hash[..])
             .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
-        let sig = secp_ctx.sign(&encmsg, &self.get_node_secret());
-        Ok(sig)
+        self.node_key_signer().sign_digest(&encmsg)
+    }
+
+    /// Sign an LDK node announcement using the node key
+    pub fn sign_node_announcement_v2(
+        &self,
+        ann: &UnsignedNodeAnnouncement,
+    ) -> Result<Signature, Status> {
+        self.sign_node_announcement(&ann.encode())
+    }
+
+    /// Sign an LDK channel update using the node key
+    pub fn sign_channel_update_v2(&self, cu: &UnsignedChannelUpdate) -> Result<Signature, Status> {
+        self.sign_channel_update(&cu.encode())
+    }
+
+    /// Verify a signature made by another node over a gossip message.
+    ///
+    /// `sig_bytes` is DER encoded.  The message is hashed the same way as
+    /// in [Node::sign_node_announcement].
+    pub fn verify_node_signature(
+        &self,
+        msg: &[u8],
+        sig_bytes: &[u8],
+        signer_pubkey: &PublicKey,
+    ) -> Result<(), Status> {
+        let msg_hash = Sha256dHash::hash(msg);
+        let encmsg = secp256k1::Message::from_slice(&msg_hash[..])
+            .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
+        let sig = Signature::from_der(sig_bytes)
+            .map_err(|err| invalid_argument(format!("bad signature: {}", err)))?;
+        Secp256k1::verification_only()
+            .verify(&encmsg, &sig, signer_pubkey)
+            .map_err(|_| invalid_argument("signature verify failed"))
+    }
+
+    /// Validate a channel announcement before forwarding it.
+    ///
+    /// `msg` is the unsigned announcement and the signatures are in the
+    /// 64 byte compact wire format.  The announcement must be for this
+    /// node's chain and all four signatures must be valid.
+    pub fn validate_channel_announcement(
+        &self,
+        msg: &[u8],
+        node_sig1: &[u8],
+        node_sig2: &[u8],
+        bitcoin_sig1: &[u8],
+        bitcoin_sig2: &[u8],
+    ) -> Result<(), Status> {
+        let mut reader = msg;
+        let ann = UnsignedChannelAnnouncement::read(&mut reader).map_err(|err| {
+            invalid_argument(format!("malformed channel announcement: {:?}", err))
+        })?;
+        let chain_hash = genesis_block(self.node_config.network).header.block_hash();
+        if ann.chain_hash != chain_hash {
+            return Err(invalid_argument(format!(
+                "channel announcement chain_hash {} does not match {}",
+                ann.chain_hash, chain_hash
+            )));
+        }
+
+        let ann_hash = Sha256dHash::hash(msg);
+        let encmsg = secp256k1::Message::from_slice(&ann_hash[..])
+            .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
+        let checks = [
+            ("node_sig1", node_sig1, &ann.node_id_1),
+            ("node_sig2", node_sig2, &ann.node_id_2),
+            ("bitcoin_sig1", bitcoin_sig1, &ann.bitcoin_key_1),
+            ("bitcoin_sig2", bitcoin_sig2, &ann.bitcoin_key_2),
+        ];
+        let secp_ctx = Secp256k1::verification_only();
+        for (name, sig, pubkey) in checks.iter() {
+            let sig = Signature::from_compact(sig)
+                .map_err(|err| invalid_argument(format!("bad {}: {}", name, err)))?;
+            secp_ctx
+                .verify(&encmsg, &sig, pubkey)
+                .map_err(|_| invalid_argument(format!("{} verify failed", name)))?;
+        }
+        Ok(())
     }
 
     /// Sign an invoice and start tracking incoming payment for its payment hash
@@ -1321,6 +2386,7 @@ impl Node {
         hrp_bytes: &[u8],
         invoice_data: &[u5],
     ) -> Result<RecoverableSignature, Status> {
+        self.check_capability(Capabilities::INVOICES)?;
         let signed_raw_invoice = self.do_sign_invoice(hrp_bytes, invoice_data)?;
 
         let sig = signed_raw_invoice.signature().0;
@@ -1348,6 +2414,110 @@ impl Node {
         Ok(sig)
     }
 
+    /// Sign a parsed invoice, as with [Node::sign_invoice].
+    ///
+    /// Any existing signature on `invoice` is ignored.
+    pub fn sign_invoice_from_ldk(&self, invoice: &Invoice) -> Result<RecoverableSignature, Status> {
+        let signed_raw_invoice = invoice.clone().into_signed_raw();
+        let raw_invoice = signed_raw_invoice.raw_invoice();
+        let hrp_str = raw_invoice.hrp.to_string();
+        self.sign_invoice(hrp_str.as_bytes(), &raw_invoice.data.to_base32())
+    }
+
+    /// Construct a BOLT11 invoice for the node's network from `fields`, sign it
+    /// and start tracking incoming payment for its payment hash.
+    ///
+    /// The result is the 64 byte compact signature followed by the recovery id,
+    /// as in the invoice encoding.
+    pub fn sign_bolt11(&self, fields: &Bolt11InvoiceFields) -> Result<Vec<u8>, Status> {
+        let mut builder = InvoiceBuilder::new(self.invoice_currency())
+            .duration_since_epoch(fields.duration_since_epoch)
+            .payment_hash(Sha256Hash::from_inner(fields.payment_hash.0))
+            .payment_secret(fields.payment_secret)
+            .description(fields.description.clone())
+            .expiry_time(fields.expiry_duration)
+            .min_final_cltv_expiry(fields.min_final_cltv_expiry);
+        if let Some(amount_msat) = fields.amount_msat {
+            builder = builder.amount_milli_satoshis(amount_msat);
+        }
+        let raw_invoice = builder
+            .build_raw()
+            .map_err(|e| invalid_argument(format!("could not build invoice: {}", e)))?;
+        let hrp_str = raw_invoice.hrp.to_string();
+        let data = raw_invoice.data.to_base32();
+
+        let sig = self.sign_invoice(hrp_str.as_bytes(), &data)?;
+        let (rid, sig) = sig.serialize_compact();
+        let mut res = sig.to_vec();
+        res.push(rid.to_i32() as u8);
+        Ok(res)
+    }
+
+    /// Create and sign an invoice for a keysend payment, for display purposes.
+    ///
+    /// The payment preimage and secret are derived from `payment_nonce`, see
+    /// [MyKeysManager::derive_keysend_preimage].  The invoice is tracked as
+    /// with [Node::sign_invoice].  Returns the encoded invoice.
+    pub fn sign_keysend_invoice(
+        &self,
+        amount_msat: u64,
+        description: &str,
+        payment_nonce: &[u8],
+    ) -> Result<String, Status> {
+        let preimage = self.keys_manager.derive_keysend_preimage(payment_nonce);
+        let payment_secret = self.keys_manager.derive_keysend_payment_secret(payment_nonce);
+        let raw_invoice = InvoiceBuilder::new(self.invoice_currency())
+            .duration_since_epoch(self.clock_now())
+            .amount_milli_satoshis(amount_msat)
+            .payment_hash(Sha256Hash::hash(&preimage.0))
+            .payment_secret(payment_secret)
+            .description(description.to_string())
+            .min_final_cltv_expiry(self.node_config.min_invoice_cltv_expiry as u64)
+            .build_raw()
+            .map_err(|e| invalid_argument(format!("could not build invoice: {}", e)))?;
+        let hrp_str = raw_invoice.hrp.to_string();
+        let sig = self.sign_invoice(hrp_str.as_bytes(), &raw_invoice.data.to_base32())?;
+        let signed_raw_invoice = raw_invoice
+            .sign::<_, ()>(|_| Ok(sig))
+            .map_err(|()| internal_error("failed to sign invoice"))?;
+        Ok(signed_raw_invoice.to_string())
+    }
+
+    fn invoice_currency(&self) -> Currency {
+        match self.node_config.network {
+            Network::Bitcoin => Currency::Bitcoin,
+            Network::Testnet => Currency::BitcoinTestnet,
+            Network::Regtest => Currency::Regtest,
+            Network::Signet => Currency::Signet,
+        }
+    }
+
+    /// Verify the signature of an encoded BOLT11 invoice, returning the
+    /// payee node id recovered from the signature.
+    ///
+    /// If the invoice has an explicit payee pubkey (the `n` field), the
+    /// signature must have been made by that key.  Nothing is signed or
+    /// tracked.
+    pub fn verify_invoice_signature(invoice: &str) -> Result<PublicKey, Status> {
+        let signed_raw_invoice = SignedRawInvoice::from_str(invoice)
+            .map_err(|e| invalid_argument(format!("parse error: {}", e)))?;
+        let payee = signed_raw_invoice
+            .recover_payee_pub_key()
+            .map_err(|e| invalid_argument(format!("could not recover payee: {}", e)))?;
+        if let Some(expected) = signed_raw_invoice.payee_pub_key() {
+            if *expected != payee {
+                return Err(invalid_argument(format!(
+                    "invoice signed by {} instead of payee {}",
+                    payee.0, expected.0
+                )));
+            }
+        }
+        if !signed_raw_invoice.check_signature() {
+            return Err(invalid_argument("invoice signature verify failed"));
+        }
+        Ok(payee.0)
+    }
+
     pub(crate) fn do_sign_invoice(
         &self,
         hrp_bytes: &[u8],
@@ -1361,11 +2531,22 @@ impl Node {
             .map_err(|e| invalid_argument(format!("parse error: {}", e)))?;
         let raw_invoice = RawInvoice { hrp, data };
 
+        // policy-invoice-min-final-cltv-expiry
+        if let Some(expiry) = raw_invoice.min_final_cltv_expiry() {
+            let minimum = self.node_config.min_invoice_cltv_expiry;
+            if expiry.0 < minimum as u64 {
+                return Err(policy_error(format!(
+                    "invoice min_final_cltv_expiry {} less than minimum {}",
+                    expiry.0, minimum
+                ))
+                .into());
+            }
+        }
+
         let invoice_preimage = construct_invoice_preimage(&hrp_bytes, &invoice_data);
-        let secp_ctx = Secp256k1::signing_only();
         let hash = Sha256Hash::hash(&invoice_preimage);
         let message = secp256k1::Message::from_slice(&hash).unwrap();
-        let sig = secp_ctx.sign_recoverable(&message, &self.get_node_secret());
+        let sig = self.node_key_signer().sign_digest_recoverable(&message)?;
 
         raw_invoice
             .sign::<_, ()>(|_| Ok(sig))
@@ -1373,20 +2554,69 @@ impl Node {
     }
 
     /// Sign a Lightning message
+    ///
+    /// The result is the 64 byte compact signature followed by the raw
+    /// recovery id (0-3).  See [`Node::sign_message_with_recid_encoding`]
+    /// for other encodings.
     pub fn sign_message(&self, message: &Vec<u8>) -> Result<Vec<u8>, Status> {
+        self.sign_message_with_recid_encoding(message, RecoveryIdEncoding::Raw)
+    }
+
+    /// Sign a Lightning message, encoding the recovery id as requested
+    pub fn sign_message_with_recid_encoding(
+        &self,
+        message: &Vec<u8>,
+        encoding: RecoveryIdEncoding,
+    ) -> Result<Vec<u8>, Status> {
+        self.check_capability(Capabilities::MESSAGES)?;
         let mut buffer = String::from("Lightning Signed Message:").into_bytes();
         buffer.extend(message);
-        let secp_ctx = Secp256k1::signing_only();
         let hash = Sha256dHash::hash(&buffer);
         let encmsg = secp256k1::Message::from_slice(&hash[..])
             .map_err(|err| internal_error(format!("encmsg failed: {}", err)))?;
-        let sig = secp_ctx.sign_recoverable(&encmsg, &self.get_node_secret());
+        let sig = self.node_key_signer().sign_digest_recoverable(&encmsg)?;
         let (rid, sig) = sig.serialize_compact();
-        let mut res = sig.to_vec();
-        res.push(rid.to_i32() as u8);
+        let rid = rid.to_i32() as u8;
+        let res = match encoding {
+            RecoveryIdEncoding::Raw => {
+                let mut res = sig.to_vec();
+                res.push(rid);
+                res
+            }
+            RecoveryIdEncoding::Offset => {
+                let mut res = vec![RECOVERY_ID_COMPRESSED_OFFSET + rid];
+                res.extend_from_slice(&sig);
+                res
+            }
+        };
         Ok(res)
     }
 
+    /// Sign a digest with the node key, also returning the nonce point R.
+    ///
+    /// The nonce point is recovered from the signature.  The digest is
+    /// signed twice, and an error is returned if the nonces differ.
+    /// Intended for tests and audits of signing determinism.
+    #[cfg(feature = "test_utils")]
+    pub fn sign_with_recorded_nonce(
+        &self,
+        digest: &Message,
+    ) -> Result<(Signature, PublicKey), Status> {
+        let signer = self.node_key_signer();
+        let sig = signer.sign_digest_recoverable(digest)?;
+        let (rid, compact) = sig.serialize_compact();
+        let (rid2, compact2) = signer.sign_digest_recoverable(digest)?.serialize_compact();
+        if rid != rid2 || compact[..] != compact2[..] {
+            return Err(internal_error("signature nonce is not deterministic"));
+        }
+        let mut nonce_point = [0u8; 33];
+        nonce_point[0] = 0x02 | (rid.to_i32() & 1) as u8;
+        nonce_point[1..].copy_from_slice(&compact[..32]);
+        let nonce_point = PublicKey::from_slice(&nonce_point)
+            .map_err(|_| internal_error("invalid nonce point"))?;
+        Ok((sig.to_standard(), nonce_point))
+    }
+
     /// Get the channels this node knows about.
     /// Currently, channels are not pruned once closed, but this will change.
     pub fn channels(&self) -> MutexGuard<OrderedMap<ChannelId, Arc<Mutex<ChannelSlot>>>> {
@@ -1395,10 +2625,24 @@ impl Node {
 
     /// Perform an ECDH operation between the node key and a public key
     /// This can be used for onion packet decoding
-    pub fn ecdh(&self, other_key: &PublicKey) -> Vec<u8> {
-        let our_key = self.keys_manager.get_node_secret(Recipient::Node).unwrap();
-        let ss = SharedSecret::new(&other_key, &our_key);
-        ss[..].to_vec()
+    pub fn ecdh(&self, other_key: &PublicKey) -> Result<Vec<u8>, Status> {
+        self.node_key_signer().ecdh(other_key)
+    }
+
+    /// Compute a MAC over an onion payload, keyed by the ECDH shared secret
+    /// between the node key and `session_key`.
+    ///
+    /// This is for protocol use, such as authenticating trampoline payloads,
+    /// and is unrelated to the user-facing [Node::sign_message].
+    pub fn sign_message_for_onion(
+        &self,
+        session_key: &PublicKey,
+        message: &[u8],
+    ) -> Result<[u8; 32], Status> {
+        let shared_secret = self.ecdh(session_key)?;
+        let mut hmac = HmacEngine::<Sha256Hash>::new(&shared_secret);
+        hmac.input(message);
+        Ok(Hmac::from_engine(hmac).into_inner())
     }
 
     /// See [`MyKeysManager::spend_spendable_outputs`].
@@ -1422,21 +2666,40 @@ impl Node {
     }
 
     /// Returns the node's current allowlist.
+    ///
+    /// Scripts that don't have an address on the node's network are
+    /// skipped, see [Node::allowlist_scripts].
     pub fn allowlist(&self) -> Result<Vec<String>, Status> {
         let alset = self.allowlist.lock().unwrap();
-        (*alset)
+        let mut res = Vec::new();
+        for allowable in alset.iter() {
+            if let Allowable::Script(script) = allowable {
+                if Address::from_script(script, self.network()).is_none() {
+                    warn!("allowlist: skipping script without address {}", script.to_hex());
+                    continue;
+                }
+            }
+            res.push(allowable.to_string(self.network()));
+        }
+        Ok(res)
+    }
+
+    /// Returns the scripts in the node's current allowlist, including
+    /// any that don't have an address.
+    pub fn allowlist_scripts(&self) -> Vec<Script> {
+        let alset = self.allowlist.lock().unwrap();
+        alset
             .iter()
-            .map(|allowable| Ok(allowable.to_string(self.network())))
-            .collect::<Result<Vec<String>, Status>>()
+            .filter_map(|allowable| match allowable {
+                Allowable::Script(script) => Some(script.clone()),
+                Allowable::Payee(_) => None,
+            })
+            .collect()
     }
 
     /// Adds addresses to the node's current allowlist.
     pub fn add_allowlist(&self, addlist: &Vec<String>) -> Result<(), Status> {
-        let allowables = addlist
-            .iter()
-            .map(|addrstr| Allowable::from_str(addrstr, self.network()))
-            .collect::<Result<Vec<Allowable>, String>>()
-            .map_err(|s| invalid_argument(format!("could not parse {}", s)))?;
+        let allowables = self.parse_allowables(addlist)?;
         let mut alset = self.allowlist.lock().unwrap();
         for a in allowables {
             alset.insert(a);
@@ -1454,11 +2717,7 @@ impl Node {
 
     /// Removes addresses from the node's current allowlist.
     pub fn remove_allowlist(&self, rmlist: &Vec<String>) -> Result<(), Status> {
-        let allowables = rmlist
-            .iter()
-            .map(|addrstr| Allowable::from_str(addrstr, self.network()))
-            .collect::<Result<Vec<Allowable>, String>>()
-            .map_err(|s| invalid_argument(format!("could not parse {}", s)))?;
+        let allowables = self.parse_allowables(rmlist)?;
         let mut alset = self.allowlist.lock().unwrap();
         for a in allowables {
             alset.remove(&a);
@@ -1467,6 +2726,80 @@ impl Node {
         Ok(())
     }
 
+    /// Adds and removes addresses from the node's current allowlist,
+    /// persisting once.
+    ///
+    /// Both lists are parsed before anything is changed, so if any entry
+    /// is invalid the allowlist is left untouched.  Removals are applied
+    /// before additions.
+    pub fn apply_allowlist_delta(&self, add: &[String], remove: &[String]) -> Result<(), Status> {
+        let to_add = self.parse_allowables(add)?;
+        let to_remove = self.parse_allowables(remove)?;
+        let mut alset = self.allowlist.lock().unwrap();
+        for a in to_remove {
+            alset.remove(&a);
+        }
+        for a in to_add {
+            alset.insert(a);
+        }
+        self.update_allowlist(&alset)?;
+        Ok(())
+    }
+
+    fn parse_allowables(&self, list: &[String]) -> Result<Vec<Allowable>, Status> {
+        list.iter()
+            .map(|addrstr| Allowable::from_str(addrstr, self.network()))
+            .collect::<Result<Vec<Allowable>, String>>()
+            .map_err(|s| invalid_argument(format!("could not parse {}", s)))
+    }
+
+    /// Import an external extended pubkey, such as one from a hardware wallet.
+    ///
+    /// The native and wrapped segwit scripts of the first `count` addresses
+    /// at `xpub/path_prefix/i` are then treated like allowlisted destinations.
+    /// Only non-hardened derivation is possible, and `count` may be at
+    /// most [MAX_WATCH_DESCRIPTOR_COUNT].  The watched scripts
+    /// are not persisted, so they must be imported again after a restart.
+    pub fn import_watch_descriptor(
+        &self,
+        xpub: ExtendedPubKey,
+        path_prefix: Vec<u32>,
+        count: u32,
+    ) -> Result<(), Status> {
+        if count > MAX_WATCH_DESCRIPTOR_COUNT {
+            return Err(invalid_argument(format!(
+                "{}: count {} exceeds {}",
+                short_function!(),
+                count,
+                MAX_WATCH_DESCRIPTOR_COUNT
+            )));
+        }
+        let secp_ctx = Secp256k1::verification_only();
+        let derive = |xkey: &ExtendedPubKey, elem: u32| {
+            let child = ChildNumber::from_normal_idx(elem)
+                .map_err(|_| invalid_argument(format!("hardened path element {}", elem)))?;
+            xkey.ckd_pub(&secp_ctx, child)
+                .map_err(|err| internal_error(format!("derive path failed: {}", err)))
+        };
+
+        let mut base = xpub;
+        for elem in path_prefix {
+            base = derive(&base, elem)?;
+        }
+
+        let mut scripts = Vec::with_capacity(count as usize * 2);
+        for ndx in 0..count {
+            let pubkey = derive(&base, ndx)?.public_key;
+            let native_addr = Address::p2wpkh(&pubkey, self.network()).expect("p2wpkh failed");
+            let wrapped_addr = Address::p2shwpkh(&pubkey, self.network()).expect("p2shwpkh failed");
+            scripts.push(native_addr.script_pubkey());
+            scripts.push(wrapped_addr.script_pubkey());
+        }
+
+        self.watched_scripts.lock().unwrap().extend(scripts);
+        Ok(())
+    }
+
     /// Chain tracker with lock
     pub fn get_tracker(&self) -> MutexGuard<'_, ChainTracker<ChainMonitor>> {
         self.tracker.lock().unwrap()
@@ -1600,13 +2933,75 @@ impl TryFrom<i32> for SpendType {
     }
 }
 
+// Estimated weight of a one input, one p2wpkh output sweep of a to_remote
+// output, for a p2wpkh to_remote and for the anchors p2wsh to_remote
+const SWEEP_TO_REMOTE_WEIGHT: u64 = 439;
+const SWEEP_TO_REMOTE_ANCHORS_WEIGHT: u64 = 443;
+
+// Header byte offset for a recovery id of a compressed pubkey,
+// as in Bitcoin message signing
+const RECOVERY_ID_COMPRESSED_OFFSET: u8 = 31;
+
+/// The maximum number of addresses imported by [Node::import_watch_descriptor]
+pub const MAX_WATCH_DESCRIPTOR_COUNT: u32 = 10_000;
+
+/// The encoding of the recovery id in a recoverable message signature
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RecoveryIdEncoding {
+    /// The compact signature followed by the raw recovery id (0-3).
+    /// This is the format used by CLN.
+    Raw,
+    /// A header byte of 31 plus the recovery id, followed by the compact
+    /// signature.  This is the Bitcoin message signing format, as used by LND.
+    Offset,
+}
+
+/// SLIP-132 extended public key formats
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Slip132Format {
+    /// Mainnet P2PKH or P2SH
+    Xpub,
+    /// Mainnet P2WPKH nested in P2SH
+    Ypub,
+    /// Mainnet native P2WPKH
+    Zpub,
+    /// Testnet P2PKH or P2SH
+    Tpub,
+    /// Testnet P2WPKH nested in P2SH
+    Upub,
+    /// Testnet native P2WPKH
+    Vpub,
+}
+
+impl Slip132Format {
+    /// The four version bytes prefixing the serialized key
+    pub fn version_bytes(&self) -> [u8; 4] {
+        match self {
+            Slip132Format::Xpub => [0x04, 0x88, 0xb2, 0x1e],
+            Slip132Format::Ypub => [0x04, 0x9d, 0x7c, 0xb2],
+            Slip132Format::Zpub => [0x04, 0xb2, 0x47, 0x46],
+            Slip132Format::Tpub => [0x04, 0x35, 0x87, 0xcf],
+            Slip132Format::Upub => [0x04, 0x4a, 0x52, 0x62],
+            Slip132Format::Vpub => [0x04, 0x5f, 0x1c, 0x6f],
+        }
+    }
+
+    /// Whether this format is for mainnet
+    pub fn is_mainnet(&self) -> bool {
+        match self {
+            Slip132Format::Xpub | Slip132Format::Ypub | Slip132Format::Zpub => true,
+            Slip132Format::Tpub | Slip132Format::Upub | Slip132Format::Vpub => false,
+        }
+    }
+}
+
 /// Marker trait for LDK compatible logger
 pub trait SyncLogger: Logger + SendSync {}
 
 #[cfg(test)]
 mod tests {
     use bitcoin;
-    use bitcoin::bech32::{CheckBase32, ToBase32};
+    use bitcoin::bech32::CheckBase32;
     use bitcoin::consensus::deserialize;
     use bitcoin::hashes::sha256d::Hash as Sha256dHash;
     use bitcoin::hashes::Hash;
@@ -1615,13 +3010,17 @@ mod tests {
     use bitcoin::secp256k1::SecretKey;
     use bitcoin::util::bip143::SigHashCache;
     use bitcoin::{Address, OutPoint, SigHashType};
+    use lightning::ln::chan_utils;
     use lightning::ln::chan_utils::derive_private_key;
-    use lightning::ln::{chan_utils, PaymentSecret};
-    use lightning_invoice::{Currency, InvoiceBuilder};
+    use lightning::ln::features::ChannelFeatures;
+    use lightning::util::ser::Writeable;
     use test_log::test;
 
-    use crate::channel::ChannelBase;
+    use crate::channel::{ChannelBase, TypedSignature};
     use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use crate::util::clock::ManualClock;
+    use crate::util::crypto_utils::bitcoin_vec_to_signature;
+    use crate::util::key_utils::{make_test_key, make_test_pubkey};
     use crate::util::status::{internal_error, invalid_argument, Code, Status};
     use crate::util::test_utils::*;
 
@@ -1666,6 +3065,519 @@ mod tests {
         assert!(node.get_channel(&channel_id).is_ok());
     }
 
+    #[test]
+    fn stub_counterparty_points_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let setup = make_test_channel_setup();
+        let set_points = |channel_id: &ChannelId, points: ChannelPublicKeys| {
+            let slot = node.get_channel(channel_id).unwrap();
+            let mut slot = slot.lock().unwrap();
+            match &mut *slot {
+                ChannelSlot::Stub(stub) => stub.set_counterparty_points(points),
+                ChannelSlot::Ready(_) => panic!("expected a stub"),
+            }
+        };
+
+        let (channel_id, _) = node.new_channel(None, None, &node).unwrap();
+        let mut other_points = setup.counterparty_points.clone();
+        other_points.funding_pubkey = make_test_pubkey(99);
+        assert_status_ok!(set_points(&channel_id, other_points));
+        assert_failed_precondition_err!(
+            node.ready_channel(channel_id, None, setup.clone(), &vec![]),
+            format!(
+                "policy failure: counterparty points do not match the points stored in stub {}",
+                channel_id
+            )
+        );
+
+        // Points can be replaced before the channel is ready
+        assert_status_ok!(set_points(&channel_id, setup.counterparty_points.clone()));
+        assert_status_ok!(node.ready_channel(channel_id, None, setup, &vec![]));
+    }
+
+    #[test]
+    fn short_channel_id_test() {
+        let mut setup = make_test_channel_setup();
+        setup.funding_outpoint.vout = 1;
+        let (node, channel_id) = init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup);
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.funding_output_index(), 1);
+            // 539268x845x1
+            assert_eq!(chan.short_channel_id(539268, 845)?, 592931436542885889);
+            assert_invalid_argument_err!(
+                chan.short_channel_id(1 << 24, 845),
+                "short_channel_id out of range: 16777216x845x1"
+            );
+
+            let outpoint = chan.setup.funding_outpoint;
+            chan.confirm_funding(outpoint, 539268)?;
+            assert_eq!(chan.short_channel_id(539268, 845)?, 592931436542885889);
+            assert!(chan.short_channel_id(539269, 845).is_err());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn on_channel_reestablish_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let secret = |num: u64| {
+            node.with_ready_channel(&channel_id, |chan| chan.get_per_commitment_secret(num))
+                .unwrap()
+        };
+        node.with_ready_channel(&channel_id, |chan| {
+            let estate = &mut chan.enforcement_state;
+            estate.next_holder_commit_num = 5;
+            estate.next_counterparty_commit_num = 8;
+            estate.next_counterparty_revoke_num = 7;
+            estate.current_counterparty_point = Some(make_test_pubkey(8));
+            estate.previous_counterparty_point = Some(make_test_pubkey(7));
+            Ok(())
+        })
+        .unwrap();
+
+        // In sync
+        assert_status_ok!(node.on_channel_reestablish(
+            &channel_id,
+            8,
+            4,
+            Some(secret(3)),
+            Some(make_test_pubkey(8))
+        ));
+
+        // The peer missed our last commitment_signed and revoke_and_ack
+        assert_status_ok!(node.on_channel_reestablish(
+            &channel_id,
+            7,
+            3,
+            Some(secret(2)),
+            Some(make_test_pubkey(7))
+        ));
+
+        assert_failed_precondition_err!(
+            node.on_channel_reestablish(&channel_id, 9, 4, Some(secret(3)), None),
+            "policy failure: channel_reestablish: next_local_commitment_number 9 ahead of 8"
+        );
+        assert_failed_precondition_err!(
+            node.on_channel_reestablish(&channel_id, 6, 4, Some(secret(3)), None),
+            "policy failure: channel_reestablish: next_local_commitment_number 6 behind 8"
+        );
+        assert_failed_precondition_err!(
+            node.on_channel_reestablish(&channel_id, 8, 5, None, None),
+            "policy failure: channel_reestablish: next_remote_revocation_number 5 ahead of 4"
+        );
+        assert_failed_precondition_err!(
+            node.on_channel_reestablish(&channel_id, 8, 4, Some(secret(2)), None),
+            "policy failure: channel_reestablish: your_last_per_commitment_secret mismatch for 3"
+        );
+        assert_failed_precondition_err!(
+            node.on_channel_reestablish(
+                &channel_id,
+                8,
+                4,
+                Some(secret(3)),
+                Some(make_test_pubkey(7))
+            ),
+            "policy failure: channel_reestablish: my_current_per_commitment_point mismatch for 7"
+        );
+        assert_invalid_argument_err!(
+            node.on_channel_reestablish(&channel_id, 8, 4, None, None),
+            "channel_reestablish: missing your_last_per_commitment_secret"
+        );
+    }
+
+    #[test]
+    fn prune_stale_stubs_test() {
+        let clock = Arc::new(ManualClock::new(Duration::from_secs(1_000_000)));
+        let (node, ready_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        node.set_clock(clock.clone());
+        let (stale_id, _) = node.new_channel(None, None, &node).unwrap();
+
+        clock.advance(Duration::from_secs(3600));
+        let (fresh_id, _) = node.new_channel(None, None, &node).unwrap();
+        assert!(node.prune_stale_stubs(Duration::from_secs(3600)).unwrap().is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(node.prune_stale_stubs(Duration::from_secs(3600)).unwrap(), vec![stale_id]);
+        assert!(node.get_channel(&stale_id).is_err());
+        assert!(node.get_channel(&fresh_id).is_ok());
+
+        // Ready channels are never pruned
+        clock.advance(Duration::from_secs(100_000));
+        assert_eq!(node.prune_stale_stubs(Duration::from_secs(0)).unwrap(), vec![fresh_id]);
+        assert!(node.get_channel(&ready_id).is_ok());
+        assert_eq!(node.channels().len(), 1);
+    }
+
+    #[test]
+    fn seal_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let (stub_id, _) = node.new_channel(None, None, &node).unwrap();
+
+        node.seal();
+        assert!(node.is_sealed());
+        assert_failed_precondition_err!(node.new_channel(None, None, &node), "node sealed");
+        assert_failed_precondition_err!(
+            node.ready_channel(stub_id, None, make_test_channel_setup(), &vec![]),
+            "node sealed"
+        );
+
+        // existing channels can still sign
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10),
+                0,
+                0,
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )
+        })
+        .expect("sign while sealed");
+
+        node.unseal();
+        assert!(!node.is_sealed());
+        node.ready_channel(stub_id, None, make_test_channel_setup(), &vec![])
+            .expect("ready after unseal");
+        node.new_channel(None, None, &node).expect("new channel after unseal");
+    }
+
+    #[test]
+    fn sweep_counterparty_commitment_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let (channel_id, _) = node.new_channel(None, Some(channel_nonce.clone()), &node).unwrap();
+        let payment_point = node
+            .with_channel_base(&channel_id, |base| Ok(base.get_channel_basepoints().payment_point))
+            .unwrap();
+        let mut setup = make_test_channel_setup();
+        let funding_outpoint = setup.funding_outpoint;
+        let (destination, _) = node.get_destination_script_with_path();
+
+        let make_commitment_tx = |to_remote_script: Script| Transaction {
+            version: 2,
+            lock_time: 0x20000000,
+            input: vec![TxIn {
+                previous_output: funding_outpoint,
+                script_sig: Script::new(),
+                sequence: 0x80000000,
+                witness: vec![],
+            }],
+            output: vec![
+                TxOut {
+                    script_pubkey: get_p2wpkh_redeemscript(&make_test_pubkey(1)),
+                    value: 900_000,
+                },
+                TxOut { script_pubkey: to_remote_script, value: 100_000 },
+            ],
+        };
+
+        let commitment_tx = make_commitment_tx(get_p2wpkh_redeemscript(&payment_point));
+        let tx = node
+            .sweep_counterparty_commitment(&commitment_tx, &channel_nonce, &setup, 1000)
+            .expect("sweep");
+        assert_eq!(tx.input[0].previous_output, OutPoint { txid: commitment_tx.txid(), vout: 1 });
+        assert_eq!(tx.output[0].script_pubkey, destination);
+        assert_eq!(tx.output[0].value, 100_000 - 439);
+        let pubkey = bitcoin::PublicKey { compressed: true, key: payment_point };
+        let script_code = Address::p2pkh(&pubkey, Network::Testnet).script_pubkey();
+        let sig = bitcoin_vec_to_signature(&tx.input[0].witness[0], SigHashType::All).unwrap();
+        check_signature(&tx, 0, TypedSignature::all(sig), &payment_point, 100_000, &script_code);
+        assert_eq!(tx.input[0].witness[1], payment_point.serialize().to_vec());
+
+        // the output must be for our derived payment key
+        let err = node
+            .sweep_counterparty_commitment(&commitment_tx, "nonce2".as_bytes(), &setup, 1000)
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().starts_with("sweep_counterparty_commitment: no to_remote output"));
+
+        setup.commitment_type = CommitmentType::Anchors;
+        let redeemscript = get_to_countersignatory_with_anchors_redeemscript(&payment_point);
+        let commitment_tx = make_commitment_tx(redeemscript.to_v0_p2wsh());
+        let tx = node
+            .sweep_counterparty_commitment(&commitment_tx, &channel_nonce, &setup, 1000)
+            .expect("sweep anchors");
+        assert_eq!(tx.input[0].sequence, 1);
+        assert_eq!(tx.output[0].value, 100_000 - 443);
+        let sig = bitcoin_vec_to_signature(&tx.input[0].witness[0], SigHashType::All).unwrap();
+        check_signature(&tx, 0, TypedSignature::all(sig), &payment_point, 100_000, &redeemscript);
+        assert_eq!(tx.input[0].witness[1], redeemscript.into_bytes());
+
+        setup.commitment_type = CommitmentType::Legacy;
+        assert_invalid_argument_err!(
+            node.sweep_counterparty_commitment(&commitment_tx, &channel_nonce, &setup, 1000),
+            "sweep_counterparty_commitment: Legacy commitments not supported"
+        );
+    }
+
+    struct RecordingObserver {
+        decisions: Mutex<Vec<(String, Option<ChannelId>, bool)>>,
+    }
+
+    impl PolicyObserver for RecordingObserver {
+        fn on_policy_decision(
+            &self,
+            operation: &str,
+            channel_id: Option<&ChannelId>,
+            result: Result<(), &ValidationError>,
+        ) {
+            let decision = (operation.to_string(), channel_id.cloned(), result.is_ok());
+            self.decisions.lock().unwrap().push(decision);
+        }
+    }
+
+    #[test]
+    fn policy_observer_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let observer = Arc::new(RecordingObserver { decisions: Mutex::new(vec![]) });
+        node.set_policy_observer(observer.clone());
+        let (channel_id, _) = node.new_channel(None, None, &node).unwrap();
+
+        let mut setup = make_test_channel_setup();
+        setup.holder_shutdown_script = Some(Script::from(
+            Vec::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565").unwrap(),
+        ));
+        let err = node.ready_channel(channel_id, None, setup, &vec![]).unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        node.ready_channel(channel_id, None, make_test_channel_setup(), &vec![])
+            .expect("ready channel");
+
+        let decisions = observer.decisions.lock().unwrap();
+        let ready_decisions: Vec<_> =
+            decisions.iter().filter(|d| d.0 == "validate_ready_channel").collect();
+        assert_eq!(ready_decisions.len(), 2);
+        assert_eq!(ready_decisions[0].1, Some(channel_id));
+        assert!(!ready_decisions[0].2);
+        assert!(ready_decisions[1].2);
+    }
+
+    #[test]
+    fn channel_slot_ids_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let (channel_id0, _) = node.new_channel(None, None, &node).unwrap();
+        let slot = node.get_channel(&channel_id0).unwrap();
+        assert_eq!(slot.lock().unwrap().channel_id(), channel_id0);
+        assert_eq!(slot.lock().unwrap().alternate_channel_id(), None);
+
+        let channel_id1 = ChannelId([42; 32]);
+        node.ready_channel(channel_id0, Some(channel_id1), make_test_channel_setup(), &vec![])
+            .unwrap();
+        let slot = node.get_channel(&channel_id1).unwrap();
+        assert_eq!(slot.lock().unwrap().channel_id(), channel_id0);
+        assert_eq!(slot.lock().unwrap().alternate_channel_id(), Some(channel_id1));
+    }
+
+    fn test_seed(seedstr: &str) -> [u8; 32] {
+        let mut seed = [0; 32];
+        seed.copy_from_slice(Vec::from_hex(seedstr).unwrap().as_slice());
+        seed
+    }
+
+    #[test]
+    fn notify_new_block_regression_test() {
+        let (node, _channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let tracker_height = node.get_tracker().height();
+        assert_eq!(node.get_block_height(), tracker_height);
+        node.notify_new_block(tracker_height + 100, [0; 32]).unwrap();
+        assert_eq!(node.get_block_height(), tracker_height + 100);
+        // A lower height, as after a reorg, doesn't move the height back
+        node.notify_new_block(tracker_height + 99, [1; 32]).unwrap();
+        assert_eq!(node.get_block_height(), tracker_height + 100);
+    }
+
+    #[test]
+    fn rekey_same_seed_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let (stub_id, _) = node.new_channel(None, None, &node).unwrap();
+
+        let new_node = node.rekey(&test_seed(TEST_SEED[1])).unwrap();
+        assert_eq!(new_node.get_id(), node.get_id());
+        assert_eq!(new_node.get_block_height(), node.get_block_height());
+        for id in &[channel_id, stub_id] {
+            let old_keys = node.get_channel(id).unwrap().lock().unwrap().get_channel_basepoints();
+            let new_keys =
+                new_node.get_channel(id).unwrap().lock().unwrap().get_channel_basepoints();
+            assert!(old_keys == new_keys);
+        }
+        let slot = new_node.get_channel(&channel_id).unwrap();
+        assert!(matches!(*slot.lock().unwrap(), ChannelSlot::Ready(_)));
+    }
+
+    #[test]
+    fn rekey_different_seed_with_channel_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        assert_invalid_argument_err!(
+            node.rekey(&test_seed(TEST_SEED[0])),
+            format!("rekey: channel {} keys do not match the new seed", channel_id)
+        );
+    }
+
+    #[test]
+    fn rekey_different_seed_without_channels_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let new_node = node.rekey(&test_seed(TEST_SEED[0])).unwrap();
+        assert_eq!(new_node.get_id(), init_node(TEST_NODE_CONFIG, TEST_SEED[0]).get_id());
+        assert_ne!(new_node.get_id(), node.get_id());
+    }
+
+    #[test]
+    fn expected_holder_reestablish_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.next_counterparty_commitment_point(), None);
+            let info = chan.expected_holder_reestablish();
+            assert_eq!(info.next_holder_commit_num, 0);
+            assert_eq!(info.next_counterparty_commit_num, 0);
+            assert_eq!(info.next_counterparty_revoke_num, 0);
+            assert_eq!(info.last_holder_secret, None);
+
+            // The current holder commitment can't be revoked
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+            assert_eq!(chan.expected_holder_reestablish().last_holder_secret, None);
+
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(3);
+            assert_eq!(
+                chan.expected_holder_reestablish().last_holder_secret,
+                Some(chan.get_per_commitment_secret(1)?)
+            );
+
+            let point = make_test_pubkey(0x10);
+            chan.enforcement_state.set_next_counterparty_commit_num_for_testing(5, point);
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(4);
+            assert_eq!(chan.next_counterparty_commitment_point(), Some(point));
+            let info = chan.expected_holder_reestablish();
+            assert_eq!(info.next_counterparty_commit_num, 5);
+            assert_eq!(info.next_counterparty_revoke_num, 4);
+            assert_eq!(info.current_counterparty_point, Some(point));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn build_channel_reestablish_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+            let data = chan.build_channel_reestablish(None)?;
+            assert_eq!(data.next_commitment_number, 1);
+            assert_eq!(data.next_revocation_number, 0);
+            assert_eq!(data.your_last_per_commitment_secret, None);
+            assert_eq!(data.my_current_per_commitment_point, chan.get_per_commitment_point(0)?);
+
+            let (point6, secret6) = make_test_key(6);
+            let estate = &mut chan.enforcement_state;
+            estate.next_holder_commit_num = 5;
+            estate.next_counterparty_commit_num = 8;
+            estate.next_counterparty_revoke_num = 7;
+            estate.current_counterparty_point = Some(make_test_pubkey(8));
+            estate.previous_counterparty_point = Some(point6);
+            let data = chan.build_channel_reestablish(Some(secret6))?;
+            assert_eq!(data.next_commitment_number, 5);
+            assert_eq!(data.next_revocation_number, 7);
+            assert_eq!(data.your_last_per_commitment_secret, Some(secret6));
+            assert_eq!(data.my_current_per_commitment_point, chan.get_per_commitment_point(4)?);
+
+            let (_, secret5) = make_test_key(5);
+            assert_failed_precondition_err!(
+                chan.build_channel_reestablish(Some(secret5)),
+                "policy failure: counterparty secret does not match the point for commitment 6"
+            );
+            assert_invalid_argument_err!(
+                chan.build_channel_reestablish(None),
+                "missing counterparty secret for commitment 6"
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn for_each_channel_test() {
+        let setup = make_test_channel_setup();
+        let (node, ready_id) = init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let (stub_id, _) = node.new_channel(None, None, &node).unwrap();
+
+        let mut visited = Vec::new();
+        node.for_each_channel(|slot| {
+            // the channels lock is not held here
+            assert_eq!(node.channels().len(), 2);
+            visited.push(slot.lock().unwrap().channel_id());
+        });
+        let mut expected = vec![ready_id, stub_id];
+        expected.sort();
+        assert_eq!(visited, expected);
+
+        let found = node.find_channel_with_funding_outpoint(&setup.funding_outpoint).unwrap();
+        assert_eq!(found.lock().unwrap().channel_id(), ready_id);
+        let unknown = OutPoint { txid: setup.funding_outpoint.txid, vout: 99 };
+        assert!(node.find_channel_with_funding_outpoint(&unknown).is_none());
+    }
+
+    #[test]
+    fn with_all_ready_channels_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let (stub_id, _) = node.new_channel(None, None, &node).unwrap();
+        let (ready_id, _) = node.new_channel(None, None, &node).unwrap();
+        node.ready_channel(ready_id, None, make_test_channel_setup(), &vec![]).unwrap();
+
+        let ids = node.with_all_ready_channels(|chan| Ok(chan.id0));
+        let mut expected = vec![channel_id, ready_id];
+        expected.sort();
+        assert_eq!(ids.into_iter().collect::<Result<Vec<_>, _>>().unwrap(), expected);
+        assert!(!expected.contains(&stub_id));
+
+        // A failure on one channel does not prevent visiting the others
+        let results = node.with_all_ready_channels(|chan| {
+            if chan.id0 == channel_id {
+                Err(invalid_argument("skip"))
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+        assert_eq!(results.len(), 2);
+
+        // Concurrent callers, including single channel ones, don't deadlock
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let node = Arc::clone(&node);
+                let channel_id = if i % 2 == 0 { channel_id } else { ready_id };
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let results = node.with_all_ready_channels(|chan| {
+                            chan.enforcement_state.total_fees_paid_msat += 1;
+                            Ok(())
+                        });
+                        assert_eq!(results.len(), 2);
+                        node.with_ready_channel(&channel_id, |chan| {
+                            chan.enforcement_state.total_fees_paid_msat += 1;
+                            Ok(())
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let totals =
+            node.with_all_ready_channels(|chan| Ok(chan.enforcement_state.total_fees_paid_msat));
+        assert_eq!(totals.into_iter().map(Result::unwrap).sum::<u64>(), 4 * 100 * 2);
+    }
+
     #[test]
     fn bad_channel_lookup_test() -> Result<(), ()> {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
@@ -1854,36 +3766,177 @@ mod tests {
         let (node, channel_id) =
             init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
 
-        let mut policy = make_simple_policy(Network::Testnet);
-        policy.require_invoices = true;
-        policy.enforce_balance = true;
-        let factory = SimpleValidatorFactory::new_with_policy(policy);
-        let invoice_validator = factory.make_validator(Network::Testnet, node.get_id(), None);
-        node.set_validator_factory(Arc::new(factory));
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_invoices = true;
+        policy.enforce_balance = true;
+        let factory = SimpleValidatorFactory::new_with_policy(policy);
+        let invoice_validator = factory.make_validator(Network::Testnet, node.get_id(), None);
+        node.set_validator_factory(Arc::new(factory));
+
+        {
+            let mut state = node.state.lock().unwrap();
+            assert_eq!(
+                state.validate_and_apply_payments(
+                    &channel_id,
+                    &Map::new(),
+                    &Map::new(),
+                    &BalanceDelta(0, 0),
+                    invoice_validator.clone()
+                ),
+                Ok(())
+            );
+            assert_eq!(
+                state.validate_and_apply_payments(
+                    &channel_id,
+                    &Map::new(),
+                    &Map::new(),
+                    &BalanceDelta(1, 0),
+                    invoice_validator.clone()
+                ),
+                Err(policy_error("shortfall 0 + 0 - 1"))
+            );
+        }
+    }
+
+    #[test]
+    fn sign_bolt11_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+
+        // Sign with the private key of the BOLT #11 examples
+        let spec_key = SecretKey::from_slice(
+            &hex_decode("e126f68f7eafcc8b74f54d269fe206be715000f94dac067d1c04a8ca3b2db734")
+                .unwrap(),
+        )
+        .unwrap();
+        *node.node_key_signer.lock().unwrap() = Arc::new(InMemoryNodeKeySigner::new(spec_key));
+        let spec_pubkey = PublicKey::from_secret_key(&secp_ctx, &spec_key);
+        assert_eq!(
+            spec_pubkey.to_hex(),
+            "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad"
+        );
+
+        // The BOLT #11 "donation of any amount" example is reproduced exactly
+        let spec_invoice = "lnbc1pvjluezpp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq8rkx3yf5tcsyz3d73gafnh3cax9rn449d9p5uxz9ezhhypd0elx87sjle52x86fux2ypatgddc6k63n7erqz25le42c4u4ecky03ylcqca784w";
+        let raw_invoice = SignedRawInvoice::from_str(spec_invoice).unwrap().raw_invoice().clone();
+        let signed = node
+            .do_sign_invoice(raw_invoice.hrp.to_string().as_bytes(), &raw_invoice.data.to_base32())
+            .unwrap();
+        let (rid, spec_sig) = signed.signature().0.serialize_compact();
+        assert_eq!(spec_sig.to_hex(), "38ec6891345e204145be8a3a99de38e98a39d6a569434e1845c8af7205afcfcc7f425fcd1463e93c32881ead0d6e356d467ec8c02553f9aab15e5738b11f127f");
+        assert_eq!(rid.to_i32(), 0);
+        assert_eq!(signed.to_string(), spec_invoice);
+
+        let payment_hash = PaymentHash([3; 32]);
+        let fields = Bolt11InvoiceFields {
+            amount_msat: Some(100_000),
+            payment_hash,
+            payment_secret: PaymentSecret([4; 32]),
+            description: "coffee".to_string(),
+            duration_since_epoch: Duration::from_secs(123456789),
+            expiry_duration: Duration::from_secs(600),
+            min_final_cltv_expiry: 18,
+        };
+        let sig = node.sign_bolt11(&fields).unwrap();
+        assert_eq!(sig.len(), 65);
+
+        // The signature is by the node key over the invoice with these fields
+        let raw_invoice = InvoiceBuilder::new(Currency::BitcoinTestnet)
+            .duration_since_epoch(Duration::from_secs(123456789))
+            .amount_milli_satoshis(100_000)
+            .payment_hash(Sha256Hash::from_slice(&payment_hash.0).unwrap())
+            .payment_secret(PaymentSecret([4; 32]))
+            .description("coffee".to_string())
+            .expiry_time(Duration::from_secs(600))
+            .min_final_cltv_expiry(18)
+            .build_raw()
+            .expect("build");
+        let message = secp256k1::Message::from_slice(&raw_invoice.signable_hash()).unwrap();
+        let rsig = RecoverableSignature::from_compact(
+            &sig[..64],
+            RecoveryId::from_i32(sig[64] as i32).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(secp_ctx.recover(&message, &rsig).unwrap(), spec_pubkey);
+
+        // The invoice is tracked, so the same payment hash can't be reused
+        let mut other_fields = fields.clone();
+        other_fields.description = "tea".to_string();
+        assert_failed_precondition_err!(
+            node.sign_bolt11(&other_fields),
+            "already have a different invoice for same secret"
+        );
+    }
+
+    #[test]
+    fn sign_invoice_from_ldk_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let other_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let hash = PaymentHash([6; 32]);
+        let invoice =
+            Invoice::from_signed(make_test_invoice(&other_node, "invoice", hash)).unwrap();
+
+        let sig = node.sign_invoice_from_ldk(&invoice).unwrap();
+        let (hrp, data) = build_test_invoice("invoice", &hash);
+        assert_eq!(node.sign_invoice(&hrp, &data).unwrap(), sig);
+
+        let signed = invoice.into_signed_raw();
+        let message =
+            secp256k1::Message::from_slice(&signed.raw_invoice().signable_hash()).unwrap();
+        let secp_ctx = secp256k1::Secp256k1::new();
+        assert_eq!(secp_ctx.recover(&message, &sig).unwrap(), node.get_id());
+    }
+
+    #[test]
+    fn verify_invoice_signature_test() {
+        let payee_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let hash = PaymentHash([5; 32]);
+        let invoice = make_test_invoice(&payee_node, "invoice", hash).to_string();
+        assert_eq!(Node::verify_invoice_signature(&invoice).unwrap(), payee_node.get_id());
+
+        // An explicit payee must match the signer
+        let other_id = make_test_pubkey(9);
+        let raw_invoice = InvoiceBuilder::new(Currency::Bitcoin)
+            .duration_since_epoch(Duration::from_secs(123456789))
+            .payment_hash(Sha256Hash::from_slice(&hash.0).unwrap())
+            .payment_secret(PaymentSecret([0; 32]))
+            .description("invoice".to_string())
+            .payee_pub_key(other_id)
+            .build_raw()
+            .expect("build");
+        let invoice = payee_node
+            .do_sign_invoice(raw_invoice.hrp.to_string().as_bytes(), &raw_invoice.data.to_base32())
+            .unwrap()
+            .to_string();
+        assert_invalid_argument_err!(
+            Node::verify_invoice_signature(&invoice),
+            format!("invoice signed by {} instead of payee {}", payee_node.get_id(), other_id)
+        );
+
+        let status = Node::verify_invoice_signature("lnbc1garbage").unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
 
-        {
-            let mut state = node.state.lock().unwrap();
-            assert_eq!(
-                state.validate_and_apply_payments(
-                    &channel_id,
-                    &Map::new(),
-                    &Map::new(),
-                    &BalanceDelta(0, 0),
-                    invoice_validator.clone()
-                ),
-                Ok(())
-            );
-            assert_eq!(
-                state.validate_and_apply_payments(
-                    &channel_id,
-                    &Map::new(),
-                    &Map::new(),
-                    &BalanceDelta(1, 0),
-                    invoice_validator.clone()
-                ),
-                Err(policy_error("shortfall 0 + 0 - 1"))
-            );
-        }
+    #[test]
+    fn sign_invoice_min_final_cltv_expiry_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let sign = |payment_hash: PaymentHash, min_final_cltv_expiry: u64| {
+            let raw_invoice = InvoiceBuilder::new(Currency::BitcoinTestnet)
+                .duration_since_epoch(Duration::from_secs(123456789))
+                .payment_hash(Sha256Hash::from_slice(&payment_hash.0).unwrap())
+                .payment_secret(PaymentSecret([0; 32]))
+                .description("invoice".to_string())
+                .min_final_cltv_expiry(min_final_cltv_expiry)
+                .build_raw()
+                .expect("build");
+            let hrp_str = raw_invoice.hrp.to_string();
+            node.sign_invoice(hrp_str.as_bytes(), &raw_invoice.data.to_base32())
+        };
+        assert_failed_precondition_err!(
+            sign(PaymentHash([1; 32]), 8),
+            "policy failure: invoice min_final_cltv_expiry 8 less than minimum 9"
+        );
+        assert_status_ok!(sign(PaymentHash([2; 32]), 9));
     }
 
     #[test]
@@ -2011,6 +4064,58 @@ mod tests {
         assert_eq!(notcorrect, false);
     }
 
+    #[test]
+    fn validate_channel_announcement_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+        let keys: Vec<(PublicKey, SecretKey)> = (1..=4).map(make_test_key).collect();
+        let ann = UnsignedChannelAnnouncement {
+            features: ChannelFeatures::known(),
+            chain_hash: genesis_block(TEST_NODE_CONFIG.network).header.block_hash(),
+            short_channel_id: 42,
+            node_id_1: keys[0].0,
+            node_id_2: keys[1].0,
+            bitcoin_key_1: keys[2].0,
+            bitcoin_key_2: keys[3].0,
+            excess_data: vec![],
+        };
+        let msg = ann.encode();
+        let ann_hash = Sha256dHash::hash(&msg);
+        let encmsg = secp256k1::Message::from_slice(&ann_hash[..]).unwrap();
+        let sigs: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|(_, key)| secp_ctx.sign(&encmsg, key).serialize_compact().to_vec())
+            .collect();
+
+        assert_status_ok!(
+            node.validate_channel_announcement(&msg, &sigs[0], &sigs[1], &sigs[2], &sigs[3])
+        );
+        assert_invalid_argument_err!(
+            node.validate_channel_announcement(&msg, &sigs[0], &sigs[1], &sigs[3], &sigs[2]),
+            "bitcoin_sig1 verify failed"
+        );
+        let err = node
+            .validate_channel_announcement(&msg, &sigs[0], &sigs[1][..63], &sigs[2], &sigs[3])
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().starts_with("bad node_sig2"));
+        assert!(node
+            .validate_channel_announcement(&msg[..10], &sigs[0], &sigs[1], &sigs[2], &sigs[3])
+            .is_err());
+
+        let mut other_chain_ann = ann.clone();
+        other_chain_ann.chain_hash = genesis_block(Network::Bitcoin).header.block_hash();
+        assert!(node
+            .validate_channel_announcement(
+                &other_chain_ann.encode(),
+                &sigs[0],
+                &sigs[1],
+                &sigs[2],
+                &sigs[3]
+            )
+            .is_err());
+    }
+
     #[test]
     fn sign_channel_announcement_test() {
         let (node, channel_id) =
@@ -2018,7 +4123,7 @@ mod tests {
 
         let ann = hex_decode("0123456789abcdef").unwrap();
         let (nsig, bsig) = node
-            .with_ready_channel(&channel_id, |chan| Ok(chan.sign_channel_announcement(&ann)))
+            .with_ready_channel(&channel_id, |chan| chan.sign_channel_announcement(&ann))
             .unwrap();
 
         let ca_hash = Sha256dHash::hash(&ann);
@@ -2049,6 +4154,70 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_node_announcement_v2_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let ann_bytes = hex_decode("000302aaa25e445fef0265b6ab5ec860cd257865d61ef0bbf5b3339c36cbda8b26b74e7f1dca490b65180265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
+        let ann = UnsignedNodeAnnouncement::read(&mut &ann_bytes[..]).unwrap();
+        assert_eq!(ann.encode(), ann_bytes);
+        let sig = node.sign_node_announcement_v2(&ann).unwrap();
+        assert_eq!(sig, node.sign_node_announcement(&ann_bytes).unwrap());
+    }
+
+    #[test]
+    fn sign_channel_update_v2_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let cu_bytes = hex_decode("06226e46111a0b59caaf126043eb5bbf28c34f3a5e332a1fc7b2b73cf188910f00006700000100015e42ddc6010000060000000000000000000000010000000a000000003b023380").unwrap();
+        let cu = UnsignedChannelUpdate::read(&mut &cu_bytes[..]).unwrap();
+        assert_eq!(cu.encode(), cu_bytes);
+        let sig = node.sign_channel_update_v2(&cu).unwrap();
+        assert_eq!(sig, node.sign_channel_update(&cu_bytes).unwrap());
+    }
+
+    #[test]
+    fn verify_node_signature_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let other = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let ann = hex_decode("000302aaa25e445fef0265b6ab5ec860cd257865d61ef0bbf5b3339c36cbda8b26b74e7f1dca490b65180265b64c4f554450484f544f2d2e302d3139392d67613237336639642d6d6f646465640000").unwrap();
+        let sig = other.sign_node_announcement(&ann).unwrap().serialize_der().to_vec();
+        assert_status_ok!(node.verify_node_signature(&ann, &sig, &other.get_id()));
+
+        assert_invalid_argument_err!(
+            node.verify_node_signature(&ann, &sig, &node.get_id()),
+            "signature verify failed"
+        );
+        let mut other_ann = ann.clone();
+        other_ann[2] ^= 1;
+        assert_invalid_argument_err!(
+            node.verify_node_signature(&other_ann, &sig, &other.get_id()),
+            "signature verify failed"
+        );
+        let err = node.verify_node_signature(&ann, &sig[1..], &other.get_id()).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().starts_with("bad signature: "));
+    }
+
+    #[test]
+    fn sign_keysend_invoice_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let encoded = node.sign_keysend_invoice(100_000, "keysend", &[7; 16]).unwrap();
+        let invoice = Invoice::from_str(&encoded).unwrap();
+        let preimage = node.keys_manager.derive_keysend_preimage(&[7; 16]);
+        assert_eq!(invoice.payment_hash(), &Sha256Hash::hash(&preimage.0));
+        assert_eq!(invoice.amount_milli_satoshis(), Some(100_000));
+        assert_eq!(invoice.currency(), Currency::BitcoinTestnet);
+        assert_eq!(invoice.recover_payee_pub_key(), node.get_id());
+        let hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+        assert!(node.get_state().issued_invoices.contains_key(&hash));
+
+        // A different nonce derives a different payment
+        let other =
+            Invoice::from_str(&node.sign_keysend_invoice(100_000, "keysend", &[8; 16]).unwrap())
+                .unwrap();
+        assert_ne!(other.payment_hash(), invoice.payment_hash());
+        assert_ne!(other.payment_secret(), invoice.payment_secret());
+    }
+
     #[test]
     fn sign_invoice_test() -> Result<(), ()> {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2099,13 +4268,121 @@ mod tests {
                 .unwrap();
         let other_key = PublicKey::from_slice(pointvec.as_slice()).unwrap();
 
-        let ssvec = node.ecdh(&other_key);
+        let ssvec = node.ecdh(&other_key).unwrap();
         assert_eq!(
             ssvec,
             hex_decode("48db1582f4b42a0068b5727fd37090a65fbf1f9bd842f4393afc2e794719ae47").unwrap()
         );
     }
 
+    struct CountingNodeKeySigner {
+        inner: InMemoryNodeKeySigner,
+        count: core::sync::atomic::AtomicUsize,
+    }
+
+    impl CountingNodeKeySigner {
+        fn bump(&self) {
+            self.count.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    impl NodeKeySigner for CountingNodeKeySigner {
+        fn node_id(&self) -> PublicKey {
+            self.inner.node_id()
+        }
+
+        fn ecdh(&self, other_key: &PublicKey) -> Result<Vec<u8>, Status> {
+            self.bump();
+            self.inner.ecdh(other_key)
+        }
+
+        fn sign_digest(&self, digest: &Message) -> Result<Signature, Status> {
+            self.bump();
+            self.inner.sign_digest(digest)
+        }
+
+        fn sign_digest_recoverable(
+            &self,
+            digest: &Message,
+        ) -> Result<RecoverableSignature, Status> {
+            self.bump();
+            self.inner.sign_digest_recoverable(digest)
+        }
+    }
+
+    #[test]
+    fn node_key_signer_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let other_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let message = b"hello".to_vec();
+        let expected_sig = node.sign_message(&message).unwrap();
+        let expected_ss = node.ecdh(&other_node.get_id()).unwrap();
+
+        let wrong_signer = Arc::new(CountingNodeKeySigner {
+            inner: InMemoryNodeKeySigner::new(other_node.node_secret()),
+            count: Default::default(),
+        });
+        assert_invalid_argument_err!(
+            node.set_node_key_signer(wrong_signer.clone()),
+            format!("node key signer is for {} instead of {}", other_node.get_id(), node.get_id())
+        );
+
+        let signer = Arc::new(CountingNodeKeySigner {
+            inner: InMemoryNodeKeySigner::new(node.node_secret()),
+            count: Default::default(),
+        });
+        node.set_node_key_signer(signer.clone()).unwrap();
+        assert_eq!(node.sign_message(&message).unwrap(), expected_sig);
+        assert_eq!(node.ecdh(&other_node.get_id()).unwrap(), expected_ss);
+        node.sign_node_announcement(&vec![1, 2, 3]).unwrap();
+        node.sign_channel_update(&vec![4, 5, 6]).unwrap();
+        assert_eq!(signer.count.load(core::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn sign_message_for_onion_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let other_node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let message = b"trampoline payload";
+
+        let mac = node.sign_message_for_onion(&other_node.get_id(), message).unwrap();
+        // the counterparty derives the same key from its side of the ECDH
+        let other_mac = other_node.sign_message_for_onion(&node.get_id(), message).unwrap();
+        assert_eq!(mac, other_mac);
+
+        let mut hmac = HmacEngine::<Sha256Hash>::new(&node.ecdh(&other_node.get_id()).unwrap());
+        hmac.input(message);
+        assert_eq!(mac, Hmac::from_engine(hmac).into_inner());
+
+        let other_message_mac =
+            node.sign_message_for_onion(&other_node.get_id(), b"other payload").unwrap();
+        assert_ne!(mac, other_message_mac);
+    }
+
+    #[test]
+    fn stub_with_commitment_keys_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let (channel_id, stub) = node.new_channel(None, None, &node).unwrap();
+        let stub = stub.expect("stub");
+        let setup = make_test_channel_setup();
+        let point = make_test_pubkey(12);
+
+        let stub_keys = stub
+            .with_commitment_keys(&point, &setup.counterparty_points, |keys| Ok(keys))
+            .expect("stub keys");
+
+        node.ready_channel(channel_id, None, setup, &vec![]).expect("ready channel");
+        let keys = node
+            .with_ready_channel(&channel_id, |chan| chan.make_holder_tx_keys(&point))
+            .expect("keys");
+
+        assert_eq!(stub_keys.per_commitment_point, keys.per_commitment_point);
+        assert_eq!(stub_keys.revocation_key, keys.revocation_key);
+        assert_eq!(stub_keys.broadcaster_htlc_key, keys.broadcaster_htlc_key);
+        assert_eq!(stub_keys.countersignatory_htlc_key, keys.countersignatory_htlc_key);
+        assert_eq!(stub_keys.broadcaster_delayed_payment_key, keys.broadcaster_delayed_payment_key);
+    }
+
     #[test]
     fn get_unilateral_close_key_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
@@ -2178,12 +4455,140 @@ mod tests {
     }
 
     #[test]
-    fn get_account_ext_pub_key_test() {
+    fn get_destination_script_with_path_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let (script, path) = node.get_destination_script_with_path();
+        assert!(node.can_spend(&path, &script).unwrap());
+        assert_eq!(script, node.get_native_address(&path).unwrap().script_pubkey());
+    }
+
+    #[test]
+    fn restore_channel_inconsistent_state_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let mut state = EnforcementState::new(0);
+        state.next_counterparty_revoke_num = 1;
+        assert!(node
+            .restore_channel(
+                channel_id0,
+                None,
+                channel_nonce,
+                3_000_000,
+                Some(make_test_channel_setup()),
+                state,
+                None,
+                &node,
+            )
+            .is_err());
+        assert!(node.get_channel(&channel_id0).is_err());
+    }
+
+    #[test]
+    fn find_wallet_path_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let native = node.get_native_address(&vec![7]).unwrap().script_pubkey();
+        let wrapped = node.get_wrapped_address(&vec![3]).unwrap().script_pubkey();
+        assert_eq!(node.find_wallet_path(&native, 10), Some(vec![7]));
+        assert_eq!(node.find_wallet_path(&wrapped, 10), Some(vec![3]));
+        // Outside of the search range
+        assert_eq!(node.find_wallet_path(&native, 7), None);
+        let foreign = Address::from_str("tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z")
+            .unwrap()
+            .script_pubkey();
+        assert_eq!(node.find_wallet_path(&foreign, 10), None);
+    }
+
+    #[test]
+    fn get_wallet_address_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let address = node.get_wallet_address(&[7]).unwrap();
+        assert_eq!(address, node.get_native_address(&vec![7]).unwrap());
+        assert!(address.to_string().starts_with("tb1q"));
+        assert_eq!(node.find_wallet_path(&address.script_pubkey(), 10), Some(vec![7]));
+
+        let address = node.get_wallet_address_p2tr(&[7]).unwrap();
+        // bech32m encoding of a segwit v1 witness program of 32 bytes
+        assert!(address.to_string().starts_with("tb1p"));
+        assert_eq!(address.to_string().len(), 62);
+        assert_eq!(address.script_pubkey().len(), 34);
+        assert_ne!(address, node.get_wallet_address_p2tr(&[8]).unwrap());
+
+        assert_invalid_argument_err!(
+            node.get_wallet_address(&[7, 1]),
+            "get_wallet_key: bad child_path len : 2"
+        );
+        assert_invalid_argument_err!(
+            node.get_wallet_address_p2tr(&[]),
+            "get_wallet_key: bad child_path len : 0"
+        );
+    }
+
+    #[test]
+    fn export_disabled_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
-        let xpub = node.get_account_extended_pubkey();
+        let assert_denied = |status: Status| {
+            assert_eq!(status.code(), Code::PermissionDenied);
+            assert_eq!(status.message(), "export disabled");
+        };
+        assert_denied(node.get_node_secret().unwrap_err());
+        assert_denied(node.get_account_extended_key().unwrap_err());
+        assert_denied(node.get_account_extended_pubkey().unwrap_err());
+        assert_denied(node.account_extended_pubkey_slip132(Slip132Format::Tpub).unwrap_err());
+
+        let mut config = TEST_NODE_CONFIG;
+        config.export_allowed = true;
+        let node = init_node(config, TEST_SEED[1]);
+        assert_eq!(
+            PublicKey::from_secret_key(&Secp256k1::new(), &node.get_node_secret().unwrap()),
+            node.get_id()
+        );
+    }
+
+    #[test]
+    fn get_account_ext_pub_key_test() {
+        let mut config = TEST_NODE_CONFIG;
+        config.export_allowed = true;
+        let node = init_node(config, TEST_SEED[1]);
+        let xpub = node.get_account_extended_pubkey().unwrap();
         assert_eq!(format!("{}", xpub), "tpubDAu312RD7nE6R9qyB4xJk9QAMyi3ppq3UJ4MMUGpB9frr6eNDd8FJVPw27zTVvWAfYFVUtJamgfh5ZLwT23EcymYgLx7MHsU8zZxc9L3GKk");
     }
 
+    #[test]
+    fn account_key_fingerprint_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        assert_eq!(node.account_key_fingerprint(), [0x7a, 0x4d, 0xaa, 0x53]);
+
+        let mut config = TEST_NODE_CONFIG;
+        config.export_allowed = true;
+        let node = init_node(config, TEST_SEED[1]);
+        let xpub = node.get_account_extended_pubkey().unwrap();
+        assert_eq!(&node.account_key_fingerprint()[..], &xpub.fingerprint()[..]);
+    }
+
+    #[test]
+    fn account_extended_pubkey_slip132_test() {
+        let mut config = TEST_NODE_CONFIG;
+        config.export_allowed = true;
+        let node = init_node(config, TEST_SEED[1]);
+        let xpub = node.get_account_extended_pubkey().unwrap();
+        assert_eq!(
+            node.account_extended_pubkey_slip132(Slip132Format::Tpub).unwrap(),
+            xpub.to_string()
+        );
+
+        let vpub = node.account_extended_pubkey_slip132(Slip132Format::Vpub).unwrap();
+        assert!(vpub.starts_with("vpub"));
+        let data = base58::from_check(&vpub).unwrap();
+        assert_eq!(data[0..4], Slip132Format::Vpub.version_bytes());
+        assert_eq!(data[4..], xpub.encode()[4..]);
+
+        assert_invalid_argument_err!(
+            node.account_extended_pubkey_slip132(Slip132Format::Zpub),
+            "Zpub not valid for network testnet"
+        );
+    }
+
     #[test]
     fn sign_message_test() {
         let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
@@ -2205,6 +4610,182 @@ mod tests {
         assert_eq!(pubkey.serialize().to_vec(), node.get_id().serialize().to_vec());
     }
 
+    #[test]
+    fn capabilities_test() {
+        let mut capabilities = Capabilities::ALL;
+        capabilities.remove(Capabilities::INVOICES);
+        let config = NodeConfig { capabilities, ..TEST_NODE_CONFIG };
+        let (node, channel_id) =
+            init_node_and_channel(config, TEST_SEED[1], make_test_channel_setup());
+
+        let human_readable_part = String::from("lnbcrt1230n");
+        let data_part = hex_decode("010f0418090a010101141917110f01040e050f06100003021e1b0e13161c150301011415060204130c0018190d07070a18070a1c1101111e111f130306000d00120c11121706181b120d051807081a0b0f0d18060004120e140018000105100114000b130b01110c001a05041a181716020007130c091d11170d10100d0b1a1b00030e05190208171e16080d00121a00110719021005000405001000").unwrap().check_base32().unwrap();
+        assert_failed_precondition_err!(
+            node.sign_invoice(human_readable_part.as_bytes(), &data_part),
+            "capability disabled: invoices"
+        );
+        assert!(node.get_state().issued_invoices.is_empty());
+        assert_status_ok!(node.sign_message(&"hello".as_bytes().to_vec()));
+
+        // commitment signing is unaffected
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10),
+                0,
+                0,
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )
+        })
+        .expect("sign commitment");
+
+        let config = NodeConfig {
+            capabilities: Capabilities::INVOICES | Capabilities::ANNOUNCEMENTS,
+            ..TEST_NODE_CONFIG
+        };
+        let node = init_node(config, TEST_SEED[1]);
+        assert_status_ok!(node.sign_invoice(human_readable_part.as_bytes(), &data_part));
+        assert_failed_precondition_err!(
+            node.sign_message(&"hello".as_bytes().to_vec()),
+            "capability disabled: messages"
+        );
+        assert_eq!(config.capabilities.to_string(), "invoices|announcements");
+
+        let mut capabilities = Capabilities::ALL;
+        capabilities.remove(Capabilities::ANNOUNCEMENTS);
+        let config = NodeConfig { capabilities, ..TEST_NODE_CONFIG };
+        let (node, channel_id) =
+            init_node_and_channel(config, TEST_SEED[1], make_test_channel_setup());
+        let announcement = vec![0u8; 32];
+        assert_failed_precondition_err!(
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.sign_channel_announcement(&announcement)
+            }),
+            "capability disabled: announcements"
+        );
+        assert_failed_precondition_err!(
+            node.sign_node_announcement(&announcement),
+            "capability disabled: announcements"
+        );
+    }
+
+    fn recover_message_signer(message: &Vec<u8>, rsig: &RecoverableSignature) -> PublicKey {
+        let secp_ctx = secp256k1::Secp256k1::new();
+        let mut buffer = String::from("Lightning Signed Message:").into_bytes();
+        buffer.extend(message);
+        let hash = Sha256dHash::hash(&buffer);
+        let encmsg = secp256k1::Message::from_slice(&hash[..]).unwrap();
+        secp_ctx.recover(&encmsg, rsig).unwrap()
+    }
+
+    #[test]
+    fn import_watch_descriptor_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = Secp256k1::new();
+        let xprv = ExtendedPrivKey::new_master(Network::Testnet, &[3u8; 32]).unwrap();
+        let xpub = ExtendedPubKey::from_private(&secp_ctx, &xprv);
+
+        let watched_pubkey = |path: &[u32]| {
+            let path: Vec<ChildNumber> =
+                path.iter().map(|elem| ChildNumber::from_normal_idx(*elem).unwrap()).collect();
+            xpub.derive_pub(&secp_ctx, &path).unwrap().public_key
+        };
+        let native_script = |path: &[u32]| {
+            Address::p2wpkh(&watched_pubkey(path), Network::Testnet).unwrap().script_pubkey()
+        };
+        let wrapped_script = |path: &[u32]| {
+            Address::p2shwpkh(&watched_pubkey(path), Network::Testnet).unwrap().script_pubkey()
+        };
+
+        assert!(!node.allowlist_contains(&native_script(&[0, 1])));
+
+        node.import_watch_descriptor(xpub, vec![0], 2).unwrap();
+
+        assert!(node.allowlist_contains(&native_script(&[0, 0])));
+        assert!(node.allowlist_contains(&native_script(&[0, 1])));
+        assert!(node.allowlist_contains(&wrapped_script(&[0, 1])));
+        assert!(!node.allowlist_contains(&native_script(&[0, 2])));
+        assert!(!node.allowlist_contains(&native_script(&[1, 0])));
+
+        // The watched scripts don't show up in the persisted allowlist
+        assert!(node.allowlist().unwrap().is_empty());
+
+        assert_invalid_argument_err!(
+            node.import_watch_descriptor(xpub, vec![1 << 31], 1),
+            "hardened path element 2147483648"
+        );
+
+        assert_invalid_argument_err!(
+            node.import_watch_descriptor(xpub, vec![0], MAX_WATCH_DESCRIPTOR_COUNT + 1),
+            "import_watch_descriptor: count 10001 exceeds 10000"
+        );
+    }
+
+    #[test]
+    fn sign_message_raw_recid_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let message = String::from("Testing 1 2 3").into_bytes();
+        let rsigvec =
+            node.sign_message_with_recid_encoding(&message, RecoveryIdEncoding::Raw).unwrap();
+        assert_eq!(rsigvec.len(), 65);
+        let rid = rsigvec[64] as i32;
+        assert!(rid <= 3);
+        let rsig =
+            RecoverableSignature::from_compact(&rsigvec[..64], RecoveryId::from_i32(rid).unwrap())
+                .unwrap();
+        assert_eq!(recover_message_signer(&message, &rsig), node.get_id());
+        assert_eq!(rsigvec, node.sign_message(&message).unwrap());
+    }
+
+    #[test]
+    fn sign_message_offset_recid_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let message = String::from("Testing 1 2 3").into_bytes();
+        let rsigvec =
+            node.sign_message_with_recid_encoding(&message, RecoveryIdEncoding::Offset).unwrap();
+        assert_eq!(rsigvec.len(), 65);
+        assert!(rsigvec[0] >= 31 && rsigvec[0] <= 34);
+        let rid = (rsigvec[0] - 31) as i32;
+        let rsig =
+            RecoverableSignature::from_compact(&rsigvec[1..], RecoveryId::from_i32(rid).unwrap())
+                .unwrap();
+        assert_eq!(recover_message_signer(&message, &rsig), node.get_id());
+    }
+
+    #[test]
+    fn node_pubkey_from_seed_test() {
+        let seed = [0x11u8; 32];
+        let node = init_node(TEST_NODE_CONFIG, &seed.to_hex());
+        assert_eq!(Node::node_pubkey_from_seed(TEST_NODE_CONFIG, &seed), node.get_id());
+
+        let mut lnd_config = TEST_NODE_CONFIG;
+        lnd_config.key_derivation_style = KeyDerivationStyle::Lnd;
+        let lnd_node = init_node(lnd_config, &seed.to_hex());
+        assert_eq!(Node::node_pubkey_from_seed(lnd_config, &seed), lnd_node.get_id());
+        assert_ne!(lnd_node.get_id(), node.get_id());
+    }
+
+    #[test]
+    fn sign_with_recorded_nonce_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let secp_ctx = secp256k1::Secp256k1::new();
+        let digest = secp256k1::Message::from_slice(&[0x42; 32]).unwrap();
+        let (sig, nonce_point) = node.sign_with_recorded_nonce(&digest).unwrap();
+        assert!(secp_ctx.verify(&digest, &sig, &node.get_id()).is_ok());
+        assert_eq!(sig.serialize_compact()[..32], nonce_point.serialize()[1..]);
+        // The nonce is the RFC6979 one
+        let nonce = crate::util::crypto_utils::rfc6979_nonce(&node.node_secret(), &digest);
+        let expected_point = PublicKey::from_secret_key(&secp_ctx, &nonce);
+        assert_eq!(expected_point.serialize()[1..], nonce_point.serialize()[1..]);
+        // Signing again must use the same nonce
+        assert_eq!(node.sign_with_recorded_nonce(&digest).unwrap(), (sig, nonce_point));
+        // A different digest yields a different nonce
+        let other = secp256k1::Message::from_slice(&[0x43; 32]).unwrap();
+        assert_ne!(node.sign_with_recorded_nonce(&other).unwrap().1, nonce_point);
+    }
+
     // TODO move this elsewhere
     #[test]
     fn transaction_verify_test() {
@@ -2336,4 +4917,94 @@ mod tests {
             "could not parse 1287uUybCYgf7Tb76qnfPf8E1ohCgSZATp: expected network testnet"
         );
     }
+
+    #[test]
+    fn node_allowlist_bare_script_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        // a bare 1-of-1 multisig has no address
+        let script_hex = format!("5121{}51ae", make_test_pubkey(1).serialize().to_hex());
+        let script = Script::from(Vec::from_hex(&script_hex).unwrap());
+        let address = "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z";
+        assert_status_ok!(
+            node.add_allowlist(&vec![format!("script:{}", script_hex), address.to_string()])
+        );
+
+        assert!(node.allowlist_contains(&script));
+        assert_eq!(node.allowlist().unwrap(), vec![format!("address:{}", address)]);
+        let scripts = node.allowlist_scripts();
+        assert_eq!(scripts.len(), 2);
+        assert!(scripts.contains(&script));
+        assert!(scripts.contains(&Address::from_str(address).unwrap().script_pubkey()));
+
+        assert_status_ok!(node.remove_allowlist(&vec![format!("script:{}", script_hex)]));
+        assert!(!node.allowlist_contains(&script));
+        assert_eq!(node.allowlist_scripts().len(), 1);
+    }
+
+    #[test]
+    fn node_allowlist_match_derivable_test() {
+        let addr = |s: &str| Address::from_str(s).unwrap().script_pubkey();
+        let p2wpkh = addr("tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z");
+        let p2sh_p2wpkh = Script::new_p2sh(&p2wpkh.script_hash());
+        let p2wsh = addr("tb1qycu764qwuvhn7u0enpg0x8gwumyuw565f3mspnn58rsgar5hkjmqtjegrh");
+        let p2sh_p2wsh = Script::new_p2sh(&p2wsh.script_hash());
+        let allowlist = vec![
+            "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z".to_string(),
+            "tb1qycu764qwuvhn7u0enpg0x8gwumyuw565f3mspnn58rsgar5hkjmqtjegrh".to_string(),
+        ];
+
+        // exact matching by default
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        node.add_allowlist(&allowlist).unwrap();
+        assert!(node.allowlist_contains(&p2wpkh));
+        assert!(!node.allowlist_contains(&p2sh_p2wpkh));
+
+        let mut config = TEST_NODE_CONFIG;
+        config.allowlist_match_derivable = true;
+        let node = init_node(config, TEST_SEED[1]);
+        node.add_allowlist(&allowlist).unwrap();
+        assert!(node.allowlist_contains(&p2wpkh));
+        assert!(node.allowlist_contains(&p2sh_p2wpkh));
+        assert!(node.allowlist_contains(&p2wsh));
+        // a p2wsh address doesn't expand
+        assert!(!node.allowlist_contains(&p2sh_p2wsh));
+
+        // the wrapped form covers the native one too
+        let node = init_node(config, TEST_SEED[1]);
+        let wrapped = Address::from_script(&p2sh_p2wpkh, Network::Testnet).unwrap().to_string();
+        node.add_allowlist(&vec![wrapped]).unwrap();
+        assert!(node.allowlist_contains(&p2wpkh));
+        assert!(!node.allowlist_contains(&p2wsh));
+    }
+
+    #[test]
+    fn node_allowlist_delta_test() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let addrs: Vec<String> = vec![
+            "mv4rnyY3Su5gjcDNzbMLKBQkBicCtHUtFB",
+            "2N6i2gfgTonx88yvYm32PRhnHxqxtEfocbt",
+            "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_status_ok!(node.add_allowlist(&vec![addrs[0].clone(), addrs[1].clone()]));
+
+        // remove one entry and add another in one step
+        assert_status_ok!(node.apply_allowlist_delta(&addrs[2..], &addrs[..1]));
+        assert!(vecs_match(
+            node.allowlist().expect("allowlist"),
+            vec![format!("address:{}", addrs[1]), format!("address:{}", addrs[2])]
+        ));
+
+        // a bad entry in either list leaves the allowlist untouched
+        assert_invalid_argument_err!(
+            node.apply_allowlist_delta(&addrs[..1], &vec!["1234567890".to_string()]),
+            "could not parse 1234567890"
+        );
+        assert!(vecs_match(
+            node.allowlist().expect("allowlist"),
+            vec![format!("address:{}", addrs[1]), format!("address:{}", addrs[2])]
+        ));
+    }
 }
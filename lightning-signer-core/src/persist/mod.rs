@@ -1,5 +1,6 @@
 use crate::chain::tracker::ChainTracker;
 use bitcoin::secp256k1::PublicKey;
+use lightning::ln::chan_utils::ChannelPublicKeys;
 
 use crate::channel::{Channel, ChannelId, ChannelStub};
 use crate::monitor::ChainMonitor;
@@ -20,6 +21,16 @@ pub trait Persist: Sync + Send {
     fn delete_node(&self, node_id: &PublicKey);
     /// Will error if exists
     fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()>;
+    /// Delete a channel.  Will error if it doesn't exist.
+    fn delete_channel(&self, node_id: &PublicKey, channel_id: &ChannelId) -> Result<(), ()>;
+    /// Store the counterparty points of a channel stub, before the channel is ready.
+    /// Will error if the stub doesn't exist.
+    fn update_channel_stub_points(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        points: &ChannelPublicKeys,
+    ) -> Result<(), ()>;
 
     /// Create a new tracker
     fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>);
@@ -37,6 +48,11 @@ pub trait Persist: Sync + Send {
     /// * `id0` original channel ID supplied to [`Persist::new_channel()`]
     /// * `id` an optional additional permanent channel ID
     fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()>;
+    /// Update several channels at once.  Will error if any doesn't exist.
+    ///
+    /// A durable store should apply all of the updates in a single transaction,
+    /// so that a crash can't leave only some of them persisted.
+    fn update_channels_batch(&self, node_id: &PublicKey, updates: &[&Channel]) -> Result<(), ()>;
     /// Get a channel from store
     fn get_channel(
         &self,
@@ -49,8 +65,20 @@ pub trait Persist: Sync + Send {
     fn update_node_allowlist(&self, node_id: &PublicKey, allowlist: Vec<String>) -> Result<(), ()>;
     /// Get the allowlist from the store.
     fn get_node_allowlist(&self, node_id: &PublicKey) -> Vec<String>;
+    /// Persist the number of onion session keys derived by the node
+    fn update_node_session_key_counter(&self, node_id: &PublicKey, counter: u64) -> Result<(), ()>;
+    /// Get the session key counter from the store, or zero if it was never persisted
+    fn get_node_session_key_counter(&self, node_id: &PublicKey) -> u64;
+    /// Persist the latest block height the node was notified of
+    fn update_node_block_height(&self, node_id: &PublicKey, height: u32) -> Result<(), ()>;
+    /// Get the block height from the store, or zero if it was never persisted
+    fn get_node_block_height(&self, node_id: &PublicKey) -> u32;
     /// Get all nodes from store
     fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)>;
+    /// Get the IDs of all nodes in the store, without loading the node entries
+    fn list_node_ids(&self) -> Vec<PublicKey>;
+    /// Get a single node from store, or None if it doesn't exist
+    fn get_node(&self, node_id: &PublicKey) -> Option<model::NodeEntry>;
     /// Clears the database.  Not for production use.
     fn clear_database(&self);
 }
@@ -68,6 +96,19 @@ impl Persist for DummyPersister {
         Ok(())
     }
 
+    fn delete_channel(&self, node_id: &PublicKey, channel_id: &ChannelId) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn update_channel_stub_points(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        points: &ChannelPublicKeys,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
     fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {}
 
     fn update_tracker(
@@ -86,6 +127,13 @@ impl Persist for DummyPersister {
         Ok(())
     }
 
+    fn update_channels_batch(&self, node_id: &PublicKey, updates: &[&Channel]) -> Result<(), ()> {
+        for channel in updates {
+            self.update_channel(node_id, channel)?;
+        }
+        Ok(())
+    }
+
     fn get_channel(
         &self,
         node_id: &PublicKey,
@@ -106,9 +154,33 @@ impl Persist for DummyPersister {
         Vec::new()
     }
 
+    fn update_node_session_key_counter(&self, node_id: &PublicKey, counter: u64) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn get_node_session_key_counter(&self, node_id: &PublicKey) -> u64 {
+        0
+    }
+
+    fn update_node_block_height(&self, node_id: &PublicKey, height: u32) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn get_node_block_height(&self, node_id: &PublicKey) -> u32 {
+        0
+    }
+
     fn get_nodes(&self) -> Vec<(PublicKey, model::NodeEntry)> {
         Vec::new()
     }
 
+    fn list_node_ids(&self) -> Vec<PublicKey> {
+        Vec::new()
+    }
+
+    fn get_node(&self, node_id: &PublicKey) -> Option<model::NodeEntry> {
+        None
+    }
+
     fn clear_database(&self) {}
 }
@@ -1,7 +1,12 @@
+use core::fmt;
+
+use lightning::ln::chan_utils::ChannelPublicKeys;
+
 use crate::channel::ChannelId;
 use crate::channel::ChannelSetup;
 use crate::policy::validator::EnforcementState;
 use crate::prelude::*;
+use crate::util::debug_utils::DebugChannelPublicKeys;
 
 /// A persistence layer entry for a Node
 #[allow(missing_docs)]
@@ -9,11 +14,18 @@ pub struct NodeEntry {
     pub seed: Vec<u8>,
     pub key_derivation_style: u8,
     pub network: String,
+    pub require_static_remote_key: bool,
+    pub low_r_grinding: bool,
+    pub allow_rbf_funding: bool,
+    pub maximum_commitment_number: u64,
+    pub export_allowed: bool,
+    pub allowlist_match_derivable: bool,
+    pub min_invoice_cltv_expiry: u32,
+    pub capabilities: u32,
 }
 
 /// A persistence layer entry for a channel
 #[allow(missing_docs)]
-#[derive(Debug)]
 pub struct ChannelEntry {
     pub nonce: Vec<u8>,
     pub channel_value_satoshis: u64,
@@ -21,4 +33,23 @@ pub struct ChannelEntry {
     // Permanent channel ID if different from the initial channel ID
     pub id: Option<ChannelId>,
     pub enforcement_state: EnforcementState,
+    // Counterparty points supplied to a stub before it was readied
+    pub counterparty_points: Option<ChannelPublicKeys>,
+}
+
+// Need to define manually because ChannelPublicKeys doesn't derive Debug.
+impl fmt::Debug for ChannelEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelEntry")
+            .field("nonce", &self.nonce)
+            .field("channel_value_satoshis", &self.channel_value_satoshis)
+            .field("channel_setup", &self.channel_setup)
+            .field("id", &self.id)
+            .field("enforcement_state", &self.enforcement_state)
+            .field(
+                "counterparty_points",
+                &self.counterparty_points.as_ref().map(DebugChannelPublicKeys),
+            )
+            .finish()
+    }
 }
@@ -4,8 +4,12 @@ pub mod error;
 /// Null policy enforcement
 #[cfg(feature = "test_utils")]
 pub mod null_validator;
+/// Observation of policy decisions
+pub mod observer;
 /// Basic policy enforcement plus on-chain validation
 pub mod onchain_validator;
+/// Signing rate limits
+pub mod rate_limit;
 /// Basic policy enforcement
 pub mod simple_validator;
 /// Policy enforcement interface
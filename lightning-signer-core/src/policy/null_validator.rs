@@ -4,9 +4,10 @@ use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::NodeConfig;
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, FundingTxPolicy, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
@@ -48,6 +49,7 @@ impl Validator for NullValidator {
     fn validate_ready_channel(
         &self,
         _wallet: &Wallet,
+        _node_config: &NodeConfig,
         _setup: &ChannelSetup,
         _holder_shutdown_key_path: &Vec<u32>,
     ) -> Result<(), ValidationError> {
@@ -61,6 +63,9 @@ impl Validator for NullValidator {
     fn validate_onchain_tx(
         &self,
         _wallet: &Wallet,
+        _node_config: &NodeConfig,
+        _current_height: u32,
+        _funding_policy: &FundingTxPolicy,
         _channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         _tx: &Transaction,
         _values_sat: &Vec<u64>,
@@ -83,6 +88,7 @@ impl Validator for NullValidator {
 
     fn validate_counterparty_commitment_tx(
         &self,
+        _node_config: &NodeConfig,
         _estate: &EnforcementState,
         _commit_num: u64,
         _commitment_point: &PublicKey,
@@ -95,6 +101,7 @@ impl Validator for NullValidator {
 
     fn validate_holder_commitment_tx(
         &self,
+        _node_config: &NodeConfig,
         _estate: &EnforcementState,
         _commit_num: u64,
         _commitment_point: &PublicKey,
@@ -175,6 +182,48 @@ impl Validator for NullValidator {
         Ok(())
     }
 
+    fn validate_mutual_close_tx_without_commitments(
+        &self,
+        _wallet: &Wallet,
+        _setup: &ChannelSetup,
+        _estate: &EnforcementState,
+        _to_holder_value_sat: u64,
+        _to_counterparty_value_sat: u64,
+        _holder_script: &Option<Script>,
+        _counterparty_script: &Option<Script>,
+        _holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_splice_tx(
+        &self,
+        _wallet: &Wallet,
+        _setup: &ChannelSetup,
+        _estate: &EnforcementState,
+        _tx: &Transaction,
+        _opaths: &Vec<Vec<u32>>,
+        _funding_redeemscript: &Script,
+        _new_funding_vout: u32,
+        _new_value_sat: u64,
+        _to_holder_value_sat: u64,
+        _holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
+    fn validate_splice_commitment(
+        &self,
+        _setup: &ChannelSetup,
+        _estate: &EnforcementState,
+        _commit_num: u64,
+        _commitment_point: &PublicKey,
+        _info2: &CommitmentInfo2,
+        _holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        Ok(())
+    }
+
     fn validate_delayed_sweep(
         &self,
         _wallet: &Wallet,
@@ -0,0 +1,435 @@
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{self, Script, SigHash, SigHashType, Transaction};
+use lightning::chain::keysinterface::InMemorySigner;
+use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
+
+use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::NodeConfig;
+use crate::policy::validator::{ChainState, EnforcementState, FundingTxPolicy, Validator};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
+use crate::wallet::Wallet;
+
+use super::error::ValidationError;
+use super::rate_limit::RateLimit;
+
+/// Observes policy decisions, e.g. for audit logging
+///
+/// The observer is notified of every decision made by the validator,
+/// whether it accepts or rejects the operation.  It is called before
+/// the result is returned to the caller and cannot alter it.
+pub trait PolicyObserver: Send + Sync {
+    /// Called with the name of the validator operation, the channel
+    /// (if the operation is channel specific) and the outcome.
+    fn on_policy_decision(
+        &self,
+        operation: &str,
+        channel_id: Option<&ChannelId>,
+        result: Result<(), &ValidationError>,
+    );
+}
+
+/// A validator that reports each decision of the inner validator to
+/// a [PolicyObserver]
+pub(crate) struct ObservedValidator {
+    inner: Arc<dyn Validator>,
+    observer: Arc<dyn PolicyObserver>,
+    channel_id: Option<ChannelId>,
+}
+
+impl ObservedValidator {
+    pub(crate) fn new(
+        inner: Arc<dyn Validator>,
+        observer: Arc<dyn PolicyObserver>,
+        channel_id: Option<ChannelId>,
+    ) -> Self {
+        ObservedValidator { inner, observer, channel_id }
+    }
+
+    fn observe<T>(
+        &self,
+        operation: &str,
+        result: Result<T, ValidationError>,
+    ) -> Result<T, ValidationError> {
+        self.observer.on_policy_decision(
+            operation,
+            self.channel_id.as_ref(),
+            result.as_ref().map(|_| ()),
+        );
+        result
+    }
+}
+
+impl Validator for ObservedValidator {
+    fn validate_ready_channel(
+        &self,
+        wallet: &Wallet,
+        node_config: &NodeConfig,
+        setup: &ChannelSetup,
+        holder_shutdown_key_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_ready_channel",
+            self.inner.validate_ready_channel(wallet, node_config, setup, holder_shutdown_key_path),
+        )
+    }
+
+    fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError> {
+        self.observe("validate_channel_value", self.inner.validate_channel_value(setup))
+    }
+
+    fn validate_onchain_tx(
+        &self,
+        wallet: &Wallet,
+        node_config: &NodeConfig,
+        current_height: u32,
+        funding_policy: &FundingTxPolicy,
+        channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
+        tx: &Transaction,
+        values_sat: &Vec<u64>,
+        opaths: &Vec<Vec<u32>>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_onchain_tx",
+            self.inner.validate_onchain_tx(
+                wallet,
+                node_config,
+                current_height,
+                funding_policy,
+                channels,
+                tx,
+                values_sat,
+                opaths,
+            ),
+        )
+    }
+
+    fn decode_commitment_tx(
+        &self,
+        keys: &InMemorySigner,
+        setup: &ChannelSetup,
+        is_counterparty: bool,
+        tx: &bitcoin::Transaction,
+        output_witscripts: &Vec<Vec<u8>>,
+    ) -> Result<CommitmentInfo, ValidationError> {
+        self.observe(
+            "decode_commitment_tx",
+            self.inner.decode_commitment_tx(keys, setup, is_counterparty, tx, output_witscripts),
+        )
+    }
+
+    fn validate_counterparty_commitment_tx(
+        &self,
+        node_config: &NodeConfig,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        info2: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_counterparty_commitment_tx",
+            self.inner.validate_counterparty_commitment_tx(
+                node_config,
+                estate,
+                commit_num,
+                commitment_point,
+                setup,
+                cstate,
+                info2,
+            ),
+        )
+    }
+
+    fn validate_holder_commitment_tx(
+        &self,
+        node_config: &NodeConfig,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        info2: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_holder_commitment_tx",
+            self.inner.validate_holder_commitment_tx(
+                node_config,
+                estate,
+                commit_num,
+                commitment_point,
+                setup,
+                cstate,
+                info2,
+            ),
+        )
+    }
+
+    fn validate_counterparty_revocation(
+        &self,
+        state: &EnforcementState,
+        revoke_num: u64,
+        commitment_secret: &SecretKey,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_counterparty_revocation",
+            self.inner.validate_counterparty_revocation(state, revoke_num, commitment_secret),
+        )
+    }
+
+    fn decode_and_validate_htlc_tx(
+        &self,
+        is_counterparty: bool,
+        setup: &ChannelSetup,
+        txkeys: &TxCreationKeys,
+        tx: &Transaction,
+        redeemscript: &Script,
+        htlc_amount_sat: u64,
+        output_witscript: &Script,
+    ) -> Result<(u32, HTLCOutputInCommitment, SigHash, SigHashType), ValidationError> {
+        self.observe(
+            "decode_and_validate_htlc_tx",
+            self.inner.decode_and_validate_htlc_tx(
+                is_counterparty,
+                setup,
+                txkeys,
+                tx,
+                redeemscript,
+                htlc_amount_sat,
+                output_witscript,
+            ),
+        )
+    }
+
+    fn validate_htlc_tx(
+        &self,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        is_counterparty: bool,
+        htlc: &HTLCOutputInCommitment,
+        feerate_per_kw: u32,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_htlc_tx",
+            self.inner.validate_htlc_tx(setup, cstate, is_counterparty, htlc, feerate_per_kw),
+        )
+    }
+
+    fn decode_and_validate_mutual_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        wallet_paths: &Vec<Vec<u32>>,
+    ) -> Result<ClosingTransaction, ValidationError> {
+        self.observe(
+            "decode_and_validate_mutual_close_tx",
+            self.inner.decode_and_validate_mutual_close_tx(wallet, setup, estate, tx, wallet_paths),
+        )
+    }
+
+    fn validate_mutual_close_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_mutual_close_tx",
+            self.inner.validate_mutual_close_tx(
+                wallet,
+                setup,
+                state,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_script,
+                counterparty_script,
+                holder_wallet_path_hint,
+            ),
+        )
+    }
+
+    fn validate_mutual_close_tx_without_commitments(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_mutual_close_tx_without_commitments",
+            self.inner.validate_mutual_close_tx_without_commitments(
+                wallet,
+                setup,
+                state,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_script,
+                counterparty_script,
+                holder_wallet_path_hint,
+            ),
+        )
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+        funding_redeemscript: &Script,
+        new_funding_vout: u32,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_splice_tx",
+            self.inner.validate_splice_tx(
+                wallet,
+                setup,
+                estate,
+                tx,
+                opaths,
+                funding_redeemscript,
+                new_funding_vout,
+                new_value_sat,
+                to_holder_value_sat,
+                holder_reduction_sat,
+            ),
+        )
+    }
+
+    fn validate_splice_commitment(
+        &self,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        info2: &CommitmentInfo2,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_splice_commitment",
+            self.inner.validate_splice_commitment(
+                setup,
+                estate,
+                commit_num,
+                commitment_point,
+                info2,
+                holder_reduction_sat,
+            ),
+        )
+    }
+
+    fn validate_delayed_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_delayed_sweep",
+            self.inner.validate_delayed_sweep(
+                wallet,
+                setup,
+                cstate,
+                tx,
+                input,
+                amount_sat,
+                wallet_path,
+            ),
+        )
+    }
+
+    fn validate_counterparty_htlc_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        redeemscript: &Script,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_counterparty_htlc_sweep",
+            self.inner.validate_counterparty_htlc_sweep(
+                wallet,
+                setup,
+                cstate,
+                tx,
+                redeemscript,
+                input,
+                amount_sat,
+                wallet_path,
+            ),
+        )
+    }
+
+    fn validate_justice_sweep(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        cstate: &ChainState,
+        tx: &Transaction,
+        input: usize,
+        amount_sat: u64,
+        wallet_path: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_justice_sweep",
+            self.inner.validate_justice_sweep(
+                wallet,
+                setup,
+                cstate,
+                tx,
+                input,
+                amount_sat,
+                wallet_path,
+            ),
+        )
+    }
+
+    fn validate_payment_balance(
+        &self,
+        incoming: u64,
+        outgoing: u64,
+        invoiced_amount: Option<u64>,
+    ) -> Result<(), ValidationError> {
+        self.observe(
+            "validate_payment_balance",
+            self.inner.validate_payment_balance(incoming, outgoing, invoiced_amount),
+        )
+    }
+
+    fn enforce_balance(&self) -> bool {
+        self.inner.enforce_balance()
+    }
+
+    fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
+        self.inner.minimum_initial_balance(holder_value_msat)
+    }
+
+    fn signing_rate_limit(&self) -> Option<RateLimit> {
+        self.inner.signing_rate_limit()
+    }
+}
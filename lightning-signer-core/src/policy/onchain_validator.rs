@@ -4,10 +4,12 @@ use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::NodeConfig;
 use crate::policy::error::policy_error;
+use crate::policy::rate_limit::RateLimit;
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, FundingTxPolicy, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2};
@@ -42,6 +44,10 @@ impl ValidatorFactory for OnchainValidatorFactory {
         };
         Arc::new(validator)
     }
+
+    fn on_new_block(&self, height: u32, block_hash: &[u8; 32]) {
+        self.inner_factory.on_new_block(height, block_hash)
+    }
 }
 
 /// An on-chain validator, subsumes the policy checks of SimpleValidator
@@ -63,10 +69,11 @@ impl Validator for OnchainValidator {
     fn validate_ready_channel(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
         setup: &ChannelSetup,
         holder_shutdown_key_path: &Vec<u32>,
     ) -> Result<(), ValidationError> {
-        self.inner.validate_ready_channel(wallet, setup, holder_shutdown_key_path)
+        self.inner.validate_ready_channel(wallet, node_config, setup, holder_shutdown_key_path)
     }
 
     fn validate_channel_value(&self, setup: &ChannelSetup) -> Result<(), ValidationError> {
@@ -76,12 +83,24 @@ impl Validator for OnchainValidator {
     fn validate_onchain_tx(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
+        current_height: u32,
+        funding_policy: &FundingTxPolicy,
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         values_sat: &Vec<u64>,
         opaths: &Vec<Vec<u32>>,
     ) -> Result<(), ValidationError> {
-        self.inner.validate_onchain_tx(wallet, channels, tx, values_sat, opaths)
+        self.inner.validate_onchain_tx(
+            wallet,
+            node_config,
+            current_height,
+            funding_policy,
+            channels,
+            tx,
+            values_sat,
+            opaths,
+        )
     }
 
     fn decode_commitment_tx(
@@ -98,6 +117,7 @@ impl Validator for OnchainValidator {
 
     fn validate_counterparty_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -108,6 +128,7 @@ impl Validator for OnchainValidator {
         // Only allow state advancement if funding is buried and unspent
         self.ensure_funding_buried_and_unspent(commit_num, cstate)?;
         self.inner.validate_counterparty_commitment_tx(
+            node_config,
             estate,
             commit_num,
             commitment_point,
@@ -119,6 +140,7 @@ impl Validator for OnchainValidator {
 
     fn validate_holder_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -131,6 +153,7 @@ impl Validator for OnchainValidator {
             self.ensure_funding_buried_and_unspent(commit_num, cstate)?;
         }
         self.inner.validate_holder_commitment_tx(
+            node_config,
             estate,
             commit_num,
             commitment_point,
@@ -219,6 +242,75 @@ impl Validator for OnchainValidator {
         )
     }
 
+    fn validate_mutual_close_tx_without_commitments(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_mutual_close_tx_without_commitments(
+            wallet,
+            setup,
+            state,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script,
+            holder_wallet_path_hint,
+        )
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+        funding_redeemscript: &Script,
+        new_funding_vout: u32,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_splice_tx(
+            wallet,
+            setup,
+            estate,
+            tx,
+            opaths,
+            funding_redeemscript,
+            new_funding_vout,
+            new_value_sat,
+            to_holder_value_sat,
+            holder_reduction_sat,
+        )
+    }
+
+    fn validate_splice_commitment(
+        &self,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        info2: &CommitmentInfo2,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        self.inner.validate_splice_commitment(
+            setup,
+            estate,
+            commit_num,
+            commitment_point,
+            info2,
+            holder_reduction_sat,
+        )
+    }
+
     fn validate_delayed_sweep(
         &self,
         wallet: &Wallet,
@@ -280,6 +372,10 @@ impl Validator for OnchainValidator {
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         self.inner.minimum_initial_balance(holder_value_msat)
     }
+
+    fn signing_rate_limit(&self) -> Option<RateLimit> {
+        self.inner.signing_rate_limit()
+    }
 }
 
 impl OnchainValidator {
@@ -0,0 +1,88 @@
+use core::cmp::min;
+use core::time::Duration;
+
+/// A limit on the number of signing operations per time interval
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The maximum number of signing operations in a burst
+    pub max_signs: u32,
+    /// The time it takes to fully replenish the allowance
+    pub interval: Duration,
+}
+
+/// A token bucket enforcing a [RateLimit].
+///
+/// The bucket starts full, and tokens are replenished continuously,
+/// at `max_signs` per `interval`.
+#[derive(Clone, Debug)]
+pub(crate) struct TokenBucket {
+    pub(crate) limit: RateLimit,
+    tokens: u32,
+    last_refill: Duration,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit, now: Duration) -> Self {
+        TokenBucket { limit, tokens: limit.max_signs, last_refill: now }
+    }
+
+    fn refill(&mut self, now: Duration) {
+        let max_signs = self.limit.max_signs;
+        if max_signs == 0 {
+            return;
+        }
+        let nanos_per_token = self.limit.interval.as_nanos() / max_signs as u128;
+        let elapsed = now.saturating_sub(self.last_refill).as_nanos();
+        if nanos_per_token == 0 {
+            self.tokens = max_signs;
+            self.last_refill = now;
+            return;
+        }
+        let new_tokens = elapsed / nanos_per_token;
+        if new_tokens == 0 {
+            return;
+        }
+        self.tokens = min(max_signs as u128, self.tokens as u128 + new_tokens) as u32;
+        if self.tokens == max_signs {
+            self.last_refill = now;
+        } else {
+            // Keep the partial progress towards the next token
+            self.last_refill += Duration::from_nanos((new_tokens * nanos_per_token) as u64);
+        }
+    }
+
+    /// Take a token if one is available at time `now`
+    pub(crate) fn try_take(&mut self, now: Duration) -> bool {
+        self.refill(now);
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_test() {
+        let limit = RateLimit { max_signs: 2, interval: Duration::from_secs(10) };
+        let start = Duration::from_secs(1000);
+        let mut bucket = TokenBucket::new(limit, start);
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+        // one token every 5 seconds
+        assert!(!bucket.try_take(start + Duration::from_secs(4)));
+        assert!(bucket.try_take(start + Duration::from_secs(5)));
+        assert!(!bucket.try_take(start + Duration::from_secs(9)));
+        assert!(bucket.try_take(start + Duration::from_secs(10)));
+        // the bucket doesn't fill beyond max_signs
+        let later = start + Duration::from_secs(1000);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+}
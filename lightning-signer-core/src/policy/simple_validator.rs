@@ -1,26 +1,29 @@
+use core::convert::TryFrom;
+
 use bitcoin::hashes::hex::ToHex;
 use bitcoin::policy::DUST_RELAY_TX_FEE;
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use bitcoin::util::bip143::SigHashCache;
 use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
-use lightning::chain::keysinterface::{BaseSign, InMemorySigner};
+use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{
-    build_htlc_transaction, htlc_success_tx_weight, htlc_timeout_tx_weight,
-    make_funding_redeemscript, ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys,
+    build_htlc_transaction, htlc_success_tx_weight, htlc_timeout_tx_weight, ClosingTransaction,
+    HTLCOutputInCommitment, TxCreationKeys,
 };
 use lightning::ln::PaymentHash;
 use log::{debug, info};
 
-use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::channel::{ChannelId, ChannelSetup, ChannelSlot, CommitmentType};
+use crate::node::NodeConfig;
+use crate::policy::rate_limit::RateLimit;
 use crate::policy::validator::EnforcementState;
-use crate::policy::validator::{ChainState, Validator, ValidatorFactory};
+use crate::policy::validator::{ChainState, FundingTxPolicy, Validator, ValidatorFactory};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{
     parse_offered_htlc_script, parse_received_htlc_script, parse_revokeable_redeemscript,
-    CommitmentInfo, CommitmentInfo2,
+    CommitmentInfo, CommitmentInfo2, ANCHOR_SAT,
 };
-use crate::util::crypto_utils::payload_for_p2wsh;
 use crate::util::debug_utils::{
     script_debug, DebugHTLCOutputInCommitment, DebugInMemorySigner, DebugTxCreationKeys,
     DebugVecVecU8,
@@ -30,6 +33,20 @@ use crate::wallet::Wallet;
 
 extern crate scopeguard;
 
+// Commitment transaction weights, from BOLT #3
+const COMMITMENT_TX_BASE_WEIGHT: u64 = 724;
+const COMMITMENT_TX_BASE_ANCHOR_WEIGHT: u64 = 1124;
+const COMMITMENT_TX_WEIGHT_PER_HTLC: u64 = 172;
+
+// Witness weight of a P2WPKH input: item count, signature and pubkey,
+// used to estimate the size of a funding tx before it is signed
+const P2WPKH_WITNESS_WEIGHT: u64 = 1 + 1 + 73 + 1 + 33;
+
+/// How far from the current height a funding tx locktime may be when
+/// [SimplePolicy::require_funding_anti_fee_sniping] is set.
+/// Bitcoin Core sometimes sets the locktime up to 100 blocks in the past.
+pub const MAX_FUNDING_LOCKTIME_DRIFT: u32 = 100;
+
 use super::error::{policy_error, transaction_format_error, ValidationError};
 
 /// A factory for SimpleValidator
@@ -79,7 +96,8 @@ pub struct SimplePolicy {
     pub epsilon_sat: u64,
     /// Maximum number of in-flight HTLCs
     pub max_htlcs: usize,
-    /// Maximum value of in-flight HTLCs
+    /// Maximum total value of in-flight HTLCs on a commitment, offered
+    /// and received, similar to BOLT #2 `max_htlc_value_in_flight_msat`
     pub max_htlc_value_sat: u64,
     /// Whether to use knowledge of chain state (e.g. current_height)
     pub use_chain_state: bool,
@@ -100,6 +118,31 @@ pub struct SimplePolicy {
     pub enforce_balance: bool,
     /// Maximum layer-2 fee
     pub max_routing_fee_msat: u64,
+    /// Refuse to sign commitments other than the initial ones until
+    /// the funding is confirmed
+    pub require_funding_confirmed: bool,
+    /// Require the funding tx locktime to be near the current height,
+    /// as set by wallets which discourage fee sniping.  Only checked
+    /// when the current height is known.
+    pub require_funding_anti_fee_sniping: bool,
+    /// Reject HTLC outputs which BOLT3 would trim at the commitment feerate,
+    /// since their value after the HTLC transaction fee is below dust
+    pub require_htlc_claimable: bool,
+    /// If set and non-empty, only allow channels with these peer node ids.
+    /// The channel setup must then supply the counterparty node id.
+    pub allowed_peer_ids: Option<Vec<PublicKey>>,
+    /// If set, limit the rate of commitment signing operations for each channel
+    pub max_signs_per_interval: Option<RateLimit>,
+    /// If set, the amount by which the channel value may exceed the sum of
+    /// the commitment outputs and the fee implied by the feerate.
+    ///
+    /// Trimmed HTLCs and dust outputs legitimately go to fees, so the
+    /// tolerance must allow for them.
+    pub max_commitment_value_loss_sat: Option<u64>,
+    /// If set, the maximum cumulative fee increase over the counterparty
+    /// commitments of a channel we funded, see
+    /// [EnforcementState::total_fees_paid_msat]
+    pub max_total_fees_msat: Option<u64>,
 }
 
 /// A simple validator.
@@ -282,6 +325,7 @@ impl Validator for SimpleValidator {
     fn validate_ready_channel(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
         setup: &ChannelSetup,
         holder_shutdown_key_path: &Vec<u32>,
     ) -> Result<(), ValidationError> {
@@ -289,19 +333,34 @@ impl Validator for SimpleValidator {
 
         // NOTE - setup.channel_value_sat is not valid, set later on.
 
+        // policy-channel-static-remote-key-required
+        if node_config.require_static_remote_key && setup.commitment_type == CommitmentType::Legacy
+        {
+            return policy_err!("legacy channels not allowed by node config");
+        }
+
+        // policy-channel-counterparty-allowed
+        if let Some(allowed_peer_ids) = &self.policy.allowed_peer_ids {
+            if !allowed_peer_ids.is_empty() {
+                let counterparty_node_id = setup
+                    .counterparty_node_id
+                    .ok_or_else(|| policy_error("counterparty node id is unknown".to_string()))?;
+                if !allowed_peer_ids.contains(&counterparty_node_id) {
+                    return policy_err!(
+                        "counterparty {} is not an allowed peer",
+                        counterparty_node_id
+                    );
+                }
+            }
+        }
+
         // policy-channel-counterparty-contest-delay-range
         // policy-commitment-to-self-delay-range relies on this value
-        self.validate_delay(
-            "counterparty_selected_contest_delay",
-            setup.counterparty_selected_contest_delay as u32,
-        )?;
+        self.validate_delay("counterparty_selected_contest_delay", setup.remote_csv() as u32)?;
 
         // policy-channel-holder-contest-delay-range
         // policy-commitment-to-self-delay-range relies on this value
-        self.validate_delay(
-            "holder_selected_contest_delay",
-            setup.holder_selected_contest_delay as u32,
-        )?;
+        self.validate_delay("holder_selected_contest_delay", setup.local_csv() as u32)?;
 
         // policy-mutual-destination-allowlisted
         if let Some(holder_shutdown_script) = &setup.holder_shutdown_script {
@@ -332,6 +391,9 @@ impl Validator for SimpleValidator {
     fn validate_onchain_tx(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
+        current_height: u32,
+        funding_policy: &FundingTxPolicy,
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         holder_inputs_sat: &Vec<u64>,
@@ -344,6 +406,38 @@ impl Validator for SimpleValidator {
             return policy_err!("invalid version: {}", tx.version);
         }
 
+        let is_funding_tx = channels.iter().any(|c| c.is_some());
+
+        // policy-onchain-funding-non-rbf
+        // A replaceable funding tx could be replaced by a third party
+        if !node_config.allow_rbf_funding && is_funding_tx {
+            for (inndx, input) in tx.input.iter().enumerate() {
+                if input.sequence < 0xffff_fffe {
+                    return policy_err!(
+                        "funding input[{}] signals RBF: sequence {:#x}",
+                        inndx,
+                        input.sequence
+                    );
+                }
+            }
+        }
+
+        // policy-onchain-funding-anti-fee-sniping
+        if self.policy.require_funding_anti_fee_sniping && current_height > 0 && is_funding_tx {
+            if tx.lock_time == 0 {
+                return policy_err!("funding locktime is zero at height {}", current_height);
+            }
+            let min_locktime = current_height.saturating_sub(MAX_FUNDING_LOCKTIME_DRIFT);
+            let max_locktime = current_height.saturating_add(MAX_FUNDING_LOCKTIME_DRIFT);
+            if tx.lock_time < min_locktime || tx.lock_time > max_locktime {
+                return policy_err!(
+                    "funding locktime {} too far from height {}",
+                    tx.lock_time,
+                    current_height
+                );
+            }
+        }
+
         let mut beneficial_sum = 0u64;
         for outndx in 0..tx.output.len() {
             let output = &tx.output[outndx];
@@ -399,12 +493,9 @@ impl Validator for SimpleValidator {
                         }
 
                         // policy-onchain-output-scriptpubkey
-                        let funding_redeemscript = make_funding_redeemscript(
-                            &chan.keys.pubkeys().funding_pubkey,
-                            &chan.keys.counterparty_pubkeys().funding_pubkey,
-                        );
-                        let script_pubkey =
-                            payload_for_p2wsh(&funding_redeemscript).script_pubkey();
+                        // Recompute from both funding pubkeys, which also catches
+                        // a wrong counterparty funding pubkey in the setup
+                        let script_pubkey = chan.funding_script_pubkey();
                         if output.script_pubkey != script_pubkey {
                             return policy_err!(
                                 "funding script_pubkey mismatch w/ channel: {} != {}",
@@ -436,6 +527,11 @@ impl Validator for SimpleValidator {
                     }
                     _ => panic!("this can't happen"),
                 };
+            } else if is_funding_tx {
+                // policy-onchain-change-to-wallet
+                // The outputs of a funding tx other than the channel outputs
+                // are change, they must not be redirected to a third party
+                return policy_err!("change output[{}] not to wallet or allowlist", outndx);
             } else {
                 debug!("output {} ({}) is unknown", outndx, output.value);
             }
@@ -452,6 +548,32 @@ impl Validator for SimpleValidator {
         self.validate_beneficial_value(sum_inputs, beneficial_sum)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
+        // policy-onchain-funding-feerate-floor
+        if let Some(floor) = funding_policy.feerate_floor_per_vbyte {
+            if is_funding_tx {
+                let mut sum_outputs: u64 = 0;
+                for output in &tx.output {
+                    sum_outputs = sum_outputs
+                        .checked_add(output.value)
+                        .ok_or_else(|| policy_error(format!("funding sum outputs overflow")))?;
+                }
+                let fee = sum_inputs.checked_sub(sum_outputs).ok_or_else(|| {
+                    policy_error(format!("fee underflow: {} - {}", sum_inputs, sum_outputs))
+                })?;
+                // Assume P2WPKH inputs, and the segwit marker and flag
+                let weight =
+                    tx.get_weight() as u64 + 2 + P2WPKH_WITNESS_WEIGHT * tx.input.len() as u64;
+                let vsize = (weight + 3) / 4;
+                if fee < floor.saturating_mul(vsize) {
+                    return policy_err!(
+                        "funding feerate {} sat/vbyte is below floor {}",
+                        fee / vsize,
+                        floor
+                    );
+                }
+            }
+        }
+
         *debug_on_return = false;
         Ok(())
     }
@@ -473,7 +595,7 @@ impl Validator for SimpleValidator {
         );
 
         // policy-commitment-version
-        if tx.version != 2 {
+        if tx.version != setup.commitment_type.commitment_format().version {
             return policy_err!("bad commitment version: {}", tx.version);
         }
 
@@ -491,6 +613,7 @@ impl Validator for SimpleValidator {
 
     fn validate_counterparty_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -517,14 +640,22 @@ impl Validator for SimpleValidator {
             );
         }
         // Validate common commitment constraints
-        self.validate_commitment_tx(estate, commit_num, commitment_point, setup, cstate, info2)
-            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        self.validate_commitment_tx(
+            node_config,
+            estate,
+            commit_num,
+            commitment_point,
+            setup,
+            cstate,
+            info2,
+        )
+        .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
         let mut debug_on_return =
             scoped_debug_return!(estate, commit_num, commitment_point, setup, cstate, info2);
 
         // policy-commitment-to-self-delay-range
-        if info2.to_self_delay != setup.holder_selected_contest_delay {
+        if info2.to_self_delay != setup.local_csv() {
             return Err(policy_error("holder_selected_contest_delay mismatch".to_string()));
         }
 
@@ -571,12 +702,38 @@ impl Validator for SimpleValidator {
             }
         }
 
+        // policy-commitment-point-not-reused
+        if let Some(prev_num) = estate.get_counterparty_point_commit_num(commitment_point) {
+            if prev_num != commit_num {
+                return policy_err!(
+                    "counterparty point for commit_num {} was already used for commit_num {}",
+                    commit_num,
+                    prev_num
+                );
+            }
+        }
+
+        // policy-commitment-total-fees
+        if let Some(max_total_fees_msat) = self.policy.max_total_fees_msat {
+            let total_fees_msat = estate.total_fees_paid_after_msat(
+                estate.counterparty_fee_increment_msat(commit_num, info2, setup),
+            );
+            if total_fees_msat > max_total_fees_msat {
+                return policy_err!(
+                    "total fees paid {} msat exceeds maximum {} msat",
+                    total_fees_msat,
+                    max_total_fees_msat
+                );
+            }
+        }
+
         *debug_on_return = false;
         Ok(())
     }
 
     fn validate_holder_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -604,14 +761,22 @@ impl Validator for SimpleValidator {
         }
 
         // Validate common commitment constraints
-        self.validate_commitment_tx(estate, commit_num, commitment_point, setup, cstate, info2)
-            .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
+        self.validate_commitment_tx(
+            node_config,
+            estate,
+            commit_num,
+            commitment_point,
+            setup,
+            cstate,
+            info2,
+        )
+        .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
         let mut debug_on_return =
             scoped_debug_return!(estate, commit_num, commitment_point, setup, cstate, info2);
 
         // policy-commitment-to-self-delay-range
-        if info2.to_self_delay != setup.counterparty_selected_contest_delay {
+        if info2.to_self_delay != setup.remote_csv() {
             return Err(policy_error("counterparty_selected_contest_delay mismatch".to_string()));
         }
 
@@ -701,9 +866,9 @@ impl Validator for SimpleValidator {
         output_witscript: &Script,
     ) -> Result<(u32, HTLCOutputInCommitment, SigHash, SigHashType), ValidationError> {
         let to_self_delay = if is_counterparty {
-            setup.holder_selected_contest_delay // the local side imposes this value
+            setup.local_csv() // the local side imposes this value
         } else {
-            setup.counterparty_selected_contest_delay // the remote side imposes this value
+            setup.remote_csv() // the remote side imposes this value
         };
         let sighash_type = if is_counterparty && setup.option_anchor_outputs() {
             SigHashType::SinglePlusAnyoneCanPay
@@ -736,7 +901,12 @@ impl Validator for SimpleValidator {
         let cltv_expiry = if offered { tx.lock_time } else { 0 };
         let transaction_output_index = tx.input[0].previous_output.vout;
         let commitment_txid = tx.input[0].previous_output.txid;
-        let total_fee = htlc_amount_sat - tx.output[0].value;
+        let total_fee = htlc_amount_sat.checked_sub(tx.output[0].value).ok_or_else(|| {
+            policy_error(format!(
+                "HTLC fee underflow: {} - {}",
+                htlc_amount_sat, tx.output[0].value
+            ))
+        })?;
 
         // Derive the feerate_per_kw used to generate this
         // transaction.  Compensate for the total_fee being rounded
@@ -746,11 +916,20 @@ impl Validator for SimpleValidator {
         } else {
             htlc_success_tx_weight(setup.option_anchor_outputs())
         };
-        let feerate_per_kw = (((total_fee * 1000) + weight - 1) / weight) as u32;
+        let feerate_per_kw = total_fee
+            .checked_mul(1000)
+            .and_then(|fee| fee.checked_add(weight - 1))
+            .map(|fee| fee / weight)
+            .and_then(|feerate| u32::try_from(feerate).ok())
+            .ok_or_else(|| policy_error(format!("HTLC feerate overflow: fee {}", total_fee)))?;
+
+        let amount_msat = htlc_amount_sat
+            .checked_mul(1000)
+            .ok_or_else(|| policy_error(format!("HTLC amount overflow: {}", htlc_amount_sat)))?;
 
         let htlc = HTLCOutputInCommitment {
             offered,
-            amount_msat: htlc_amount_sat * 1000,
+            amount_msat,
             cltv_expiry,
             payment_hash: PaymentHash([0; 32]), // isn't used
             transaction_output_index: Some(transaction_output_index),
@@ -1101,7 +1280,7 @@ impl Validator for SimpleValidator {
         }
 
         // policy-mutual-no-pending-htlcs
-        if !holder_info.htlcs_is_empty() || !counterparty_info.htlcs_is_empty() {
+        if estate.has_pending_htlcs() {
             return policy_err!("cannot close with pending htlcs");
         }
 
@@ -1183,6 +1362,272 @@ impl Validator for SimpleValidator {
         Ok(())
     }
 
+    fn validate_mutual_close_tx_without_commitments(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(
+            setup,
+            estate,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_script,
+            counterparty_script
+        );
+
+        if estate.current_holder_commit_info.is_some()
+            || estate.current_counterparty_commit_info.is_some()
+        {
+            return policy_err!("channel has commitment info");
+        }
+
+        if to_holder_value_sat > 0 && holder_script.is_none() {
+            return policy_err!(
+                "missing holder_script with {} to_holder_value_sat",
+                to_holder_value_sat
+            );
+        }
+
+        if to_counterparty_value_sat > 0 && counterparty_script.is_none() {
+            return policy_err!(
+                "missing counterparty_script with {} to_counterparty_value_sat",
+                to_counterparty_value_sat
+            );
+        }
+
+        if setup.holder_shutdown_script.is_some() && to_holder_value_sat > 0 {
+            if *holder_script != setup.holder_shutdown_script {
+                return policy_err!("holder_script doesn't match upfront holder_shutdown_script");
+            }
+        }
+
+        // Without commitments there is nothing to compare the outputs to,
+        // only make sure that at least the minimum fee is paid.
+        let sum_outputs = to_holder_value_sat
+            .checked_add(to_counterparty_value_sat)
+            .ok_or_else(|| policy_error("consumed overflow".to_string()))?;
+        let max_outputs = setup.channel_value_sat.saturating_sub(self.policy.min_fee);
+        if sum_outputs > max_outputs {
+            return policy_err!(
+                "outputs {} exceed channel value {} minus minimum fee {}",
+                sum_outputs,
+                setup.channel_value_sat,
+                self.policy.min_fee
+            );
+        }
+
+        // policy-mutual-destination-allowlisted
+        if let Some(script) = &holder_script {
+            if !wallet
+                .can_spend(holder_wallet_path_hint, script)
+                .map_err(|err| policy_error(format!("wallet can_spend error: {}", err)))?
+                && !wallet.allowlist_contains(script)
+            {
+                return policy_err!("holder output not to wallet or in allowlist");
+            }
+        }
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+        funding_redeemscript: &Script,
+        new_funding_vout: u32,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(
+            setup,
+            estate,
+            tx,
+            opaths,
+            new_funding_vout,
+            new_value_sat,
+            to_holder_value_sat,
+            holder_reduction_sat
+        );
+
+        if estate.mutual_close_signed {
+            return policy_err!("mutual close already signed");
+        }
+
+        let funding_spends =
+            tx.input.iter().filter(|i| i.previous_output == setup.funding_outpoint).count();
+        if funding_spends != 1 {
+            return policy_err!("splice must spend the funding outpoint exactly once");
+        }
+
+        let output = tx.output.get(new_funding_vout as usize).ok_or_else(|| {
+            policy_error(format!("new funding vout {} out of range", new_funding_vout))
+        })?;
+        if output.script_pubkey != funding_redeemscript.to_v0_p2wsh() {
+            return policy_err!("new funding output does not pay to the funding script");
+        }
+        if output.value != new_value_sat {
+            return policy_err!("new funding output value {} != {}", output.value, new_value_sat);
+        }
+
+        if new_value_sat > self.policy.max_channel_size_sat {
+            return policy_err!("new channel value {} too large", new_value_sat);
+        }
+
+        // policy-splice-amount-agreed
+        if let Some(pending) = setup.splice_pending_amount {
+            if new_value_sat != pending {
+                return policy_err!(
+                    "new channel value {} != pending splice amount {}",
+                    new_value_sat,
+                    pending
+                );
+            }
+        }
+
+        if to_holder_value_sat > new_value_sat {
+            return policy_err!(
+                "holder value {} exceeds new channel value {}",
+                to_holder_value_sat,
+                new_value_sat
+            );
+        }
+
+        // Before any commitment is signed the holder balance is the initial value
+        let current_holder_value = if estate.current_holder_commit_info.is_none()
+            && estate.current_counterparty_commit_info.is_none()
+        {
+            estate.initial_holder_value
+        } else {
+            estate
+                .minimum_to_holder_value(self.policy.epsilon_sat)
+                .ok_or_else(|| policy_error("holder balance is ambiguous".to_string()))?
+        };
+
+        // policy-splice-holder-balance-consented
+        let allowed_value = to_holder_value_sat.saturating_add(holder_reduction_sat);
+        if allowed_value < current_holder_value {
+            return policy_err!(
+                "holder balance reduced from {} to {} with only {} consented",
+                current_holder_value,
+                to_holder_value_sat,
+                holder_reduction_sat
+            );
+        }
+
+        // policy-splice-destination-allowlisted
+        let mut to_wallet_value: u64 = 0;
+        for (outndx, output) in tx.output.iter().enumerate() {
+            if outndx == new_funding_vout as usize {
+                continue;
+            }
+            let opath = &opaths[outndx];
+            let spendable = opath.len() > 0
+                && wallet.can_spend(opath, &output.script_pubkey).map_err(|err| {
+                    policy_error(format!("output[{}]: wallet_can_spend error: {}", outndx, err))
+                })?;
+            if spendable || wallet.allowlist_contains(&output.script_pubkey) {
+                to_wallet_value = to_wallet_value.saturating_add(output.value);
+            }
+        }
+        if to_wallet_value < holder_reduction_sat {
+            return policy_err!(
+                "spliced-out value {} to wallet or allowlist is less than the holder reduction {}",
+                to_wallet_value,
+                holder_reduction_sat
+            );
+        }
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
+    fn validate_splice_commitment(
+        &self,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        info2: &CommitmentInfo2,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError> {
+        let mut debug_on_return = scoped_debug_return!(
+            setup,
+            estate,
+            commit_num,
+            commitment_point,
+            info2,
+            holder_reduction_sat
+        );
+
+        if estate.mutual_close_signed {
+            return policy_err!("mutual close already signed");
+        }
+
+        // policy-splice-amount-agreed
+        match setup.splice_pending_amount {
+            None => return policy_err!("no splice pending"),
+            Some(pending) if pending != setup.channel_value_sat =>
+                return policy_err!(
+                    "new channel value {} != pending splice amount {}",
+                    setup.channel_value_sat,
+                    pending
+                ),
+            Some(_) => {}
+        }
+
+        if commit_num + 1 != estate.next_counterparty_commit_num
+            || estate.current_counterparty_point != Some(*commitment_point)
+        {
+            return policy_err!("splice commitment {} is not the current commitment", commit_num);
+        }
+        let current = estate
+            .current_counterparty_commit_info
+            .as_ref()
+            .ok_or_else(|| policy_error("current_counterparty_commit_info missing"))?;
+
+        if info2.offered_htlcs != current.offered_htlcs
+            || info2.received_htlcs != current.received_htlcs
+        {
+            return policy_err!("splice commitment HTLCs differ from the current commitment");
+        }
+
+        if info2.total_value() > setup.channel_value_sat {
+            return policy_err!(
+                "commitment value {} exceeds new channel value {}",
+                info2.total_value(),
+                setup.channel_value_sat
+            );
+        }
+
+        // policy-splice-holder-balance-consented
+        let to_holder_value_sat = info2.to_countersigner_value_sat;
+        let current_holder_value = current.to_countersigner_value_sat;
+        if to_holder_value_sat.saturating_add(holder_reduction_sat) < current_holder_value {
+            return policy_err!(
+                "holder balance reduced from {} to {} with only {} consented",
+                current_holder_value,
+                to_holder_value_sat,
+                holder_reduction_sat
+            );
+        }
+
+        *debug_on_return = false;
+        Ok(())
+    }
+
     fn validate_delayed_sweep(
         &self,
         wallet: &Wallet,
@@ -1210,13 +1655,9 @@ impl Validator for SimpleValidator {
         }
 
         // policy-sweep-sequence
-        let seq = tx.input[0].sequence;
-        if seq != setup.counterparty_selected_contest_delay as u32 {
-            return transaction_format_err!(
-                "bad sequence: {} != {}",
-                seq,
-                setup.counterparty_selected_contest_delay
-            );
+        let seq = tx.input[input].sequence;
+        if seq != setup.remote_csv() as u32 {
+            return transaction_format_err!("bad sequence: {} != {}", seq, setup.remote_csv());
         }
 
         *debug_on_return = false;
@@ -1286,7 +1727,7 @@ impl Validator for SimpleValidator {
         };
 
         // policy-sweep-sequence
-        let seq = tx.input[0].sequence;
+        let seq = tx.input[input].sequence;
         let valid_seqs = if setup.option_anchor_outputs() {
             SimpleValidator::ANCHOR_SEQS.to_vec()
         } else {
@@ -1327,7 +1768,7 @@ impl Validator for SimpleValidator {
         }
 
         // policy-sweep-sequence
-        let seq = tx.input[0].sequence;
+        let seq = tx.input[input].sequence;
         let valid_seqs = SimpleValidator::NON_ANCHOR_SEQS.to_vec();
         if !valid_seqs.contains(&seq) {
             return transaction_format_err!("bad sequence: {} not in {:?}", seq, valid_seqs);
@@ -1363,12 +1804,17 @@ impl Validator for SimpleValidator {
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64 {
         holder_value_msat / 1000
     }
+
+    fn signing_rate_limit(&self) -> Option<RateLimit> {
+        self.policy.max_signs_per_interval
+    }
 }
 
 impl SimpleValidator {
     // Common commitment validation applicable to both holder and counterparty txs
     fn validate_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -1381,6 +1827,38 @@ impl SimpleValidator {
 
         let policy = &self.policy;
 
+        // TODO taproot commitments are built as segwit v0 for now,
+        // refuse to sign them until they are implemented
+        if setup.option_simple_taproot_channels() {
+            return policy_err!("simple taproot channel commitments not supported yet");
+        }
+
+        // policy-commitment-number-bounded
+        // Commitment numbers beyond 48 bits would wrap the obscured number
+        if commit_num > node_config.maximum_commitment_number {
+            return policy_err!(
+                "commit_num {} exceeds maximum {}",
+                commit_num,
+                node_config.maximum_commitment_number
+            );
+        }
+
+        // policy-commitment-funding-confirmed
+        // The initial commitments are signed before the funding is broadcast.
+        if policy.require_funding_confirmed && commit_num > 0 {
+            match estate.funding_confirmation {
+                None => return policy_err!("funding not confirmed"),
+                Some((outpoint, _)) if outpoint != setup.funding_outpoint => {
+                    return policy_err!(
+                        "confirmed funding outpoint {} does not match {}",
+                        outpoint,
+                        setup.funding_outpoint
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+
         // policy-commitment-outputs-trimmed
         if info.to_broadcaster_value_sat > 0
             && info.to_broadcaster_value_sat < MIN_DUST_LIMIT_SATOSHIS
@@ -1411,6 +1889,11 @@ impl SimpleValidator {
         let offered_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
             + (DUST_RELAY_TX_FEE as u64 * htlc_timeout_tx_weight(setup.option_anchor_outputs())
                 / 1000);
+        // BOLT3 trims HTLCs whose value can't pay for the second-level tx
+        // at the commitment feerate and still leave a non-dust output
+        let offered_htlc_trim_threshold = MIN_DUST_LIMIT_SATOSHIS
+            + (info.feerate_per_kw as u64 * htlc_timeout_tx_weight(setup.option_anchor_outputs())
+                / 1000);
         for htlc in &info.offered_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
@@ -1430,11 +1913,25 @@ impl SimpleValidator {
                     offered_htlc_dust_limit
                 );
             }
+
+            // policy-commitment-htlc-claimable
+            if policy.require_htlc_claimable && htlc.value_sat < offered_htlc_trim_threshold {
+                return policy_err!(
+                    "offered HTLC {} value_sat {} less than trim threshold {} at feerate_per_kw {}",
+                    htlc.payment_hash.0.to_hex(),
+                    htlc.value_sat,
+                    offered_htlc_trim_threshold,
+                    info.feerate_per_kw
+                );
+            }
         }
 
         let received_htlc_dust_limit = MIN_DUST_LIMIT_SATOSHIS
             + (DUST_RELAY_TX_FEE as u64 * htlc_success_tx_weight(setup.option_anchor_outputs())
                 / 1000);
+        let received_htlc_trim_threshold = MIN_DUST_LIMIT_SATOSHIS
+            + (info.feerate_per_kw as u64 * htlc_success_tx_weight(setup.option_anchor_outputs())
+                / 1000);
         for htlc in &info.received_htlcs {
             // TODO - this check should be converted into two checks, one the first time
             // the HTLC is introduced and the other every time it is encountered.
@@ -1454,6 +1951,17 @@ impl SimpleValidator {
                     received_htlc_dust_limit
                 );
             }
+
+            // policy-commitment-htlc-claimable
+            if policy.require_htlc_claimable && htlc.value_sat < received_htlc_trim_threshold {
+                return policy_err!(
+                    "received HTLC {} value_sat {} less than trim threshold {} at feerate_per_kw {}",
+                    htlc.payment_hash.0.to_hex(),
+                    htlc.value_sat,
+                    received_htlc_trim_threshold,
+                    info.feerate_per_kw
+                );
+            }
         }
 
         // policy-commitment-htlc-inflight-limit
@@ -1471,7 +1979,55 @@ impl SimpleValidator {
         self.validate_fee(setup.channel_value_sat, sum_outputs)
             .map_err(|ve| ve.prepend_msg(format!("{}: ", containing_function!())))?;
 
-        let (_holder_value_sat, counterparty_value_sat) = info.value_to_parties();
+        let (holder_value_sat, counterparty_value_sat) = info.value_to_parties();
+
+        // policy-commitment-fee-range
+        // The funder must be able to afford the fee implied by the feerate,
+        // otherwise its output would go negative when the fee is subtracted.
+        let num_htlcs = (info.offered_htlcs.len() + info.received_htlcs.len()) as u64;
+        let weight = if setup.option_anchor_outputs() {
+            COMMITMENT_TX_BASE_ANCHOR_WEIGHT
+        } else {
+            COMMITMENT_TX_BASE_WEIGHT
+        } + COMMITMENT_TX_WEIGHT_PER_HTLC * num_htlcs;
+        let implied_fee = (info.feerate_per_kw as u64)
+            .checked_mul(weight)
+            .ok_or_else(|| policy_error("commitment fee overflow".to_string()))?
+            / 1000;
+        let funder_value_sat =
+            if setup.is_outbound { holder_value_sat } else { counterparty_value_sat };
+        // The fee can't underflow, validate_fee checked it above
+        let funder_balance_sat = funder_value_sat
+            .checked_add(setup.channel_value_sat - sum_outputs)
+            .ok_or_else(|| policy_error("funder balance overflow".to_string()))?;
+        if implied_fee > funder_balance_sat {
+            return policy_err!(
+                "feerate_per_kw {} implies fee {} exceeding funder balance {}",
+                info.feerate_per_kw,
+                implied_fee,
+                funder_balance_sat
+            );
+        }
+
+        // policy-commitment-value-conserved
+        // The channel value must be accounted for by the outputs, the
+        // anchors and the fee, within the configured tolerance.
+        if let Some(max_loss_sat) = policy.max_commitment_value_loss_sat {
+            let anchors_sat = if setup.option_anchor_outputs() { 2 * ANCHOR_SAT } else { 0 };
+            let expected_fee = implied_fee + anchors_sat;
+            let actual_fee = setup.channel_value_sat - sum_outputs;
+            let loss_sat = actual_fee.saturating_sub(expected_fee);
+            if loss_sat > max_loss_sat {
+                return policy_err!(
+                    "channel value {} exceeds outputs {} and expected fee {} by {}, more than {}",
+                    setup.channel_value_sat,
+                    sum_outputs,
+                    expected_fee,
+                    loss_sat,
+                    max_loss_sat
+                );
+            }
+        }
 
         // Enforce additional requirements on initial commitments.
         if commit_num == 0 {
@@ -1521,6 +2077,13 @@ pub fn make_simple_policy(network: Network) -> SimplePolicy {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            require_funding_confirmed: false,
+            require_funding_anti_fee_sniping: false,
+            require_htlc_claimable: false,
+            allowed_peer_ids: None,
+            max_signs_per_interval: None,
+            max_commitment_value_loss_sat: None,
+            max_total_fees_msat: None,
         }
     } else {
         SimplePolicy {
@@ -1539,6 +2102,13 @@ pub fn make_simple_policy(network: Network) -> SimplePolicy {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            require_funding_confirmed: false,
+            require_funding_anti_fee_sniping: false,
+            require_htlc_claimable: false,
+            allowed_peer_ids: None,
+            max_signs_per_interval: None,
+            max_commitment_value_loss_sat: None,
+            max_total_fees_msat: None,
         }
     }
 }
@@ -1570,6 +2140,13 @@ mod tests {
             require_invoices: false,
             enforce_balance: false,
             max_routing_fee_msat: 10000,
+            require_funding_confirmed: false,
+            require_funding_anti_fee_sniping: false,
+            require_htlc_claimable: false,
+            allowed_peer_ids: None,
+            max_signs_per_interval: None,
+            max_commitment_value_loss_sat: None,
+            max_total_fees_msat: None,
         };
 
         SimpleValidator {
@@ -1665,6 +2242,56 @@ mod tests {
         HTLCInfo2 { value_sat: 5010, payment_hash: PaymentHash([0; 32]), cltv_expiry: expiry }
     }
 
+    // Validate `info` as the next counterparty commitment of a test channel
+    fn validate_next_counterparty_commitment(
+        validator: &SimpleValidator,
+        info: &CommitmentInfo2,
+    ) -> Result<(), ValidationError> {
+        let mut enforcement_state = EnforcementState::new(0);
+        let commit_num = 23;
+        enforcement_state
+            .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+        enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+        validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
+            &enforcement_state,
+            commit_num,
+            &make_test_pubkey(0x12),
+            &make_test_channel_setup(),
+            &make_test_chain_state(),
+            info,
+        )
+    }
+
+    // Validate a counterparty commitment with one offered and one received
+    // HTLC, both paid from the holder's side
+    fn validate_counterparty_htlc_values(
+        validator: &SimpleValidator,
+        offered_value_sat: u64,
+        received_value_sat: u64,
+        feerate_per_kw: u32,
+    ) -> Result<(), ValidationError> {
+        let offered = HTLCInfo2 {
+            value_sat: offered_value_sat,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1005,
+        };
+        let received = HTLCInfo2 {
+            value_sat: received_value_sat,
+            payment_hash: PaymentHash([2; 32]),
+            cltv_expiry: 1005,
+        };
+        let info = make_counterparty_info_with_feerate(
+            1_990_000 - offered_value_sat - received_value_sat,
+            1_000_000,
+            make_test_channel_setup().holder_selected_contest_delay,
+            vec![offered],
+            vec![received],
+            feerate_per_kw,
+        );
+        validate_next_counterparty_commitment(validator, &info)
+    }
+
     #[test]
     fn validate_commitment_tx_test() {
         let validator = make_test_validator();
@@ -1679,6 +2306,7 @@ mod tests {
         let delay = setup.holder_selected_contest_delay;
         let info = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
         assert_status_ok!(validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1688,6 +2316,109 @@ mod tests {
         ));
     }
 
+    // policy-commitment-value-conserved
+    #[test]
+    fn validate_commitment_tx_value_loss_test() {
+        let mut validator = make_test_validator();
+        validator.policy.max_commitment_value_loss_sat = Some(500);
+        let delay = make_test_channel_setup().holder_selected_contest_delay;
+        let validate = |to_counterparty_value_sat: u64| {
+            let info = make_counterparty_info_with_feerate(
+                2_000_000,
+                to_counterparty_value_sat,
+                delay,
+                vec![],
+                vec![],
+                1000,
+            );
+            validate_next_counterparty_commitment(&validator, &info)
+        };
+
+        // The fee of 1000 is within 500 of the implied fee of 724
+        assert_status_ok!(validate(999_000));
+
+        // The fee of 10_000 is within max_fee, but most of it is unaccounted for
+        assert_policy_err!(
+            validate(990_000),
+            "validate_commitment_tx: channel value 3000000 exceeds outputs 2990000 \
+             and expected fee 724 by 9276, more than 500"
+        );
+    }
+
+    // policy-commitment-htlc-claimable
+    #[test]
+    fn validate_commitment_tx_htlc_claimable_test() {
+        let mut validator = make_test_validator();
+        validator.policy.require_htlc_claimable = true;
+        let validate = |offered_value_sat: u64, received_value_sat: u64| {
+            validate_counterparty_htlc_values(
+                &validator,
+                offered_value_sat,
+                received_value_sat,
+                5000,
+            )
+        };
+
+        // 354 + 663 * 5000 / 1000 and 354 + 703 * 5000 / 1000
+        assert_status_ok!(validate(3669, 3869));
+        assert_policy_err!(
+            validate(3668, 3869),
+            format!(
+                "validate_commitment_tx: offered HTLC {} value_sat 3668 less than \
+                 trim threshold 3669 at feerate_per_kw 5000",
+                [1u8; 32].to_hex()
+            )
+        );
+        assert_policy_err!(
+            validate(3669, 3868),
+            format!(
+                "validate_commitment_tx: received HTLC {} value_sat 3868 less than \
+                 trim threshold 3869 at feerate_per_kw 5000",
+                [2u8; 32].to_hex()
+            )
+        );
+    }
+
+    // policy-commitment-number-bounded
+    #[test]
+    fn validate_commitment_tx_maximum_commitment_number_test() {
+        let validator = make_test_validator();
+        let commit_point = make_test_pubkey(0x12);
+        let cstate = make_test_chain_state();
+        let setup = make_test_channel_setup();
+        let delay = setup.holder_selected_contest_delay;
+        let info = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
+        let validate = |node_config: &NodeConfig, commit_num: u64| {
+            let mut enforcement_state = EnforcementState::new(0);
+            enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            validator.validate_commitment_tx(
+                node_config,
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info,
+            )
+        };
+
+        assert_status_ok!(validate(&TEST_NODE_CONFIG, (1 << 48) - 1));
+        assert_policy_err!(
+            validate(&TEST_NODE_CONFIG, 1 << 48),
+            "validate_commitment_tx: commit_num 281474976710656 exceeds maximum 281474976710655"
+        );
+
+        let mut node_config = TEST_NODE_CONFIG;
+        node_config.maximum_commitment_number = 23;
+        assert_status_ok!(validate(&node_config, 23));
+        assert_policy_err!(
+            validate(&node_config, 24),
+            "validate_commitment_tx: commit_num 24 exceeds maximum 23"
+        );
+    }
+
     // policy-channel-holder-contest-delay-range
     // policy-commitment-to-self-delay-range
     #[test]
@@ -1696,10 +2427,12 @@ mod tests {
         let mut setup = make_test_channel_setup();
         let validator = make_test_validator();
         setup.holder_selected_contest_delay = 5;
-        assert!(validator.validate_ready_channel(&*node, &setup, &vec![]).is_ok());
+        assert!(validator
+            .validate_ready_channel(&*node, &node.node_config, &setup, &vec![])
+            .is_ok());
         setup.holder_selected_contest_delay = 4;
         assert_policy_err!(
-            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            validator.validate_ready_channel(&*node, &node.node_config, &setup, &vec![]),
             "validate_delay: holder_selected_contest_delay too small: 4 < 5"
         );
     }
@@ -1712,10 +2445,12 @@ mod tests {
         let mut setup = make_test_channel_setup();
         let validator = make_test_validator();
         setup.holder_selected_contest_delay = 1440;
-        assert!(validator.validate_ready_channel(&*node, &setup, &vec![]).is_ok());
+        assert!(validator
+            .validate_ready_channel(&*node, &node.node_config, &setup, &vec![])
+            .is_ok());
         setup.holder_selected_contest_delay = 1441;
         assert_policy_err!(
-            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            validator.validate_ready_channel(&*node, &node.node_config, &setup, &vec![]),
             "validate_delay: holder_selected_contest_delay too large: 1441 > 1440"
         );
     }
@@ -1728,10 +2463,12 @@ mod tests {
         let mut setup = make_test_channel_setup();
         let validator = make_test_validator();
         setup.counterparty_selected_contest_delay = 5;
-        assert!(validator.validate_ready_channel(&*node, &setup, &vec![]).is_ok());
+        assert!(validator
+            .validate_ready_channel(&*node, &node.node_config, &setup, &vec![])
+            .is_ok());
         setup.counterparty_selected_contest_delay = 4;
         assert_policy_err!(
-            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            validator.validate_ready_channel(&*node, &node.node_config, &setup, &vec![]),
             "validate_delay: counterparty_selected_contest_delay too small: 4 < 5"
         );
     }
@@ -1744,10 +2481,12 @@ mod tests {
         let mut setup = make_test_channel_setup();
         let validator = make_test_validator();
         setup.counterparty_selected_contest_delay = 1440;
-        assert!(validator.validate_ready_channel(&*node, &setup, &vec![]).is_ok());
+        assert!(validator
+            .validate_ready_channel(&*node, &node.node_config, &setup, &vec![])
+            .is_ok());
         setup.counterparty_selected_contest_delay = 1441;
         assert_policy_err!(
-            validator.validate_ready_channel(&*node, &setup, &vec![]),
+            validator.validate_ready_channel(&*node, &node.node_config, &setup, &vec![]),
             "validate_delay: counterparty_selected_contest_delay too large: 1441 > 1440"
         );
     }
@@ -1765,6 +2504,7 @@ mod tests {
         let info_bad = make_counterparty_info(2_000_000, 1_000_001, delay, vec![], vec![]);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
@@ -1794,6 +2534,7 @@ mod tests {
         let info = make_counterparty_info(2_000_000, 899_000, delay, vec![htlc.clone()], vec![]);
 
         assert_status_ok!(validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1806,6 +2547,7 @@ mod tests {
             make_counterparty_info(2_000_000, 1_000_000, delay, vec![htlc.clone()], vec![]);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
@@ -1831,6 +2573,7 @@ mod tests {
         let info = make_counterparty_info(2_000_000, 800_000, delay, vec![htlc.clone()], vec![]);
 
         let status = validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1841,6 +2584,65 @@ mod tests {
         assert_policy_err!(status, "validate_commitment_tx: initial commitment may not have HTLCS");
     }
 
+    // policy-commitment-fee-range
+    #[test]
+    fn validate_commitment_tx_unaffordable_feerate_test() {
+        let validator = make_test_validator();
+        let mut enforcement_state = EnforcementState::new(0);
+        let commit_num = 23;
+        enforcement_state
+            .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+        enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+        let commit_point = make_test_pubkey(0x12);
+        let cstate = make_test_chain_state();
+        let setup = make_test_channel_setup();
+        let delay = setup.holder_selected_contest_delay;
+        let info_bad = make_counterparty_info_with_feerate(
+            2_000_000,
+            999_000,
+            delay,
+            vec![],
+            vec![],
+            10_000_000,
+        );
+        assert_policy_err!(
+            validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info_bad,
+            ),
+            "validate_commitment_tx: \
+             feerate_per_kw 10000000 implies fee 7240000 exceeding funder balance 2001000"
+        );
+
+        // The largest possible feerate must be rejected cleanly as well
+        let info_bad = make_counterparty_info_with_feerate(
+            2_000_000,
+            999_000,
+            delay,
+            vec![],
+            vec![],
+            u32::MAX,
+        );
+        assert_policy_err!(
+            validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
+                &enforcement_state,
+                commit_num,
+                &commit_point,
+                &setup,
+                &cstate,
+                &info_bad,
+            ),
+            "validate_commitment_tx: \
+             feerate_per_kw 4294967295 implies fee 3109556321 exceeding funder balance 2001000"
+        );
+    }
+
     // policy-commitment-initial-funding-value
     #[test]
     fn validate_commitment_tx_initial_with_bad_fundee_output() {
@@ -1854,6 +2656,7 @@ mod tests {
         let info = make_counterparty_info(2_000_000, 999_000, delay, vec![], vec![]);
 
         let status = validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1881,6 +2684,7 @@ mod tests {
         let info_bad = make_counterparty_info(99_000_000, 900_000, delay, vec![], htlcs);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
@@ -1912,6 +2716,7 @@ mod tests {
         let info_bad = make_counterparty_info(99_000_000, 900_000, delay, vec![], htlcs);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
@@ -1923,6 +2728,28 @@ mod tests {
         );
     }
 
+    // policy-commitment-htlc-inflight-limit
+    #[test]
+    fn validate_commitment_tx_htlc_value_limit_test() {
+        let mut validator = make_test_validator();
+        validator.policy.max_htlc_value_sat = 500_000;
+        let validate = |offered_value_sat: u64, received_value_sat: u64| {
+            validate_counterparty_htlc_values(
+                &validator,
+                offered_value_sat,
+                received_value_sat,
+                7500,
+            )
+        };
+
+        assert_status_ok!(validate(250_000, 249_999));
+        assert_status_ok!(validate(250_000, 250_000));
+        assert_policy_err!(
+            validate(250_001, 250_000),
+            "validate_commitment_tx: sum of HTLC values 500001 too large"
+        );
+    }
+
     #[test]
     fn validate_commitment_tx_htlc_delay_test() {
         let validator = make_test_validator();
@@ -1938,6 +2765,7 @@ mod tests {
         let info_good =
             make_counterparty_info(2_000_000, 990_000, delay, vec![], vec![make_htlc_info2(1005)]);
         assert_validation_ok!(validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1948,6 +2776,7 @@ mod tests {
         let info_good =
             make_counterparty_info(2_000_000, 990_000, delay, vec![], vec![make_htlc_info2(2440)]);
         assert_validation_ok!(validator.validate_commitment_tx(
+            &TEST_NODE_CONFIG,
             &enforcement_state,
             commit_num,
             &commit_point,
@@ -1959,6 +2788,7 @@ mod tests {
             make_counterparty_info(2_000_000, 990_000, delay, vec![], vec![make_htlc_info2(1004)]);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
@@ -1972,6 +2802,7 @@ mod tests {
             make_counterparty_info(2_000_000, 990_000, delay, vec![], vec![make_htlc_info2(2441)]);
         assert_policy_err!(
             validator.validate_commitment_tx(
+                &TEST_NODE_CONFIG,
                 &enforcement_state,
                 commit_num,
                 &commit_point,
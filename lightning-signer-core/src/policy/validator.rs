@@ -3,19 +3,21 @@ extern crate scopeguard;
 use core::cmp::{max, min};
 
 use bitcoin::secp256k1::{PublicKey, SecretKey};
-use bitcoin::{self, Network, Script, SigHash, SigHashType, Transaction};
+use bitcoin::{self, Network, OutPoint, Script, SigHash, SigHashType, Transaction};
 use lightning::chain::keysinterface::InMemorySigner;
 use lightning::ln::chan_utils::{ClosingTransaction, HTLCOutputInCommitment, TxCreationKeys};
 use lightning::ln::PaymentHash;
 use log::debug;
 
 use crate::channel::{ChannelId, ChannelSetup, ChannelSlot};
+use crate::node::NodeConfig;
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::tx::tx::{CommitmentInfo, CommitmentInfo2, HTLCInfo2, PreimageMap};
 use crate::wallet::Wallet;
 
 use super::error::{policy_error, ValidationError};
+use super::rate_limit::RateLimit;
 
 /// A policy checker
 ///
@@ -28,6 +30,7 @@ pub trait Validator {
     fn validate_ready_channel(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
         setup: &ChannelSetup,
         holder_shutdown_key_path: &Vec<u32>,
     ) -> Result<(), ValidationError>;
@@ -43,9 +46,14 @@ pub trait Validator {
     /// * `values_sat` - the amount in satoshi per input
     /// * `opaths` - derivation path for change, one per output,
     ///   empty for non-change or allowlisted outputs
+    /// * `current_height` - the latest block height the node was notified of
+    /// * `funding_policy` - the node's runtime funding policy
     fn validate_onchain_tx(
         &self,
         wallet: &Wallet,
+        node_config: &NodeConfig,
+        current_height: u32,
+        funding_policy: &FundingTxPolicy,
         channels: Vec<Option<Arc<Mutex<ChannelSlot>>>>,
         tx: &Transaction,
         values_sat: &Vec<u64>,
@@ -65,6 +73,7 @@ pub trait Validator {
     /// Validate a counterparty commitment
     fn validate_counterparty_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -76,6 +85,7 @@ pub trait Validator {
     /// Validate a holder commitment
     fn validate_holder_commitment_tx(
         &self,
+        node_config: &NodeConfig,
         estate: &EnforcementState,
         commit_num: u64,
         commitment_point: &PublicKey,
@@ -139,6 +149,57 @@ pub trait Validator {
         holder_wallet_path_hint: &Vec<u32>,
     ) -> Result<(), ValidationError>;
 
+    /// Phase 2 Validation of mutual_close for a channel which never
+    /// had a commitment signed, and therefore has no commitment info
+    fn validate_mutual_close_tx_without_commitments(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        state: &EnforcementState,
+        to_holder_value_sat: u64,
+        to_counterparty_value_sat: u64,
+        holder_script: &Option<Script>,
+        counterparty_script: &Option<Script>,
+        holder_wallet_path_hint: &Vec<u32>,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a splice transaction, which spends the current funding
+    /// output and creates a new funding output of `new_value_sat`.
+    ///
+    /// The holder's balance after the splice is `to_holder_value_sat`,
+    /// and it may be lower than the current balance by at most
+    /// `holder_reduction_sat`, which must be paid to the wallet or to
+    /// allowlisted outputs.  `opaths` are the wallet paths of the outputs.
+    fn validate_splice_tx(
+        &self,
+        wallet: &Wallet,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        tx: &Transaction,
+        opaths: &Vec<Vec<u32>>,
+        funding_redeemscript: &Script,
+        new_funding_vout: u32,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError>;
+
+    /// Validate a counterparty commitment on the new funding output of a
+    /// pending splice.  `setup` has the new funding outpoint and channel value.
+    ///
+    /// This is the current counterparty commitment with the balances
+    /// adjusted for the splice, and the holder's balance may be lower by
+    /// at most `holder_reduction_sat`.
+    fn validate_splice_commitment(
+        &self,
+        setup: &ChannelSetup,
+        estate: &EnforcementState,
+        commit_num: u64,
+        commitment_point: &PublicKey,
+        info2: &CommitmentInfo2,
+        holder_reduction_sat: u64,
+    ) -> Result<(), ValidationError>;
+
     /// Validation of delayed sweep transaction
     fn validate_delayed_sweep(
         &self,
@@ -200,6 +261,11 @@ pub trait Validator {
     /// the funding amount.
     /// The result is in satoshi.
     fn minimum_initial_balance(&self, holder_value_msat: u64) -> u64;
+
+    /// The per-channel limit on commitment signing operations, if any
+    fn signing_rate_limit(&self) -> Option<RateLimit> {
+        None
+    }
 }
 
 /// Blockchain state used by the validator
@@ -215,6 +281,15 @@ pub struct ChainState {
     pub closing_depth: u32,
 }
 
+/// Funding transaction policy which the node operator can change at runtime,
+/// for example to track the fee market
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FundingTxPolicy {
+    /// If set, the minimum feerate of funding transactions in satoshi per vbyte.
+    /// Otherwise only the validator's fee limits apply.
+    pub feerate_floor_per_vbyte: Option<u64>,
+}
+
 /// A factory for validators
 pub trait ValidatorFactory: Send + Sync {
     /// Construct a validator
@@ -224,8 +299,21 @@ pub trait ValidatorFactory: Send + Sync {
         node_id: PublicKey,
         channel_id: Option<ChannelId>,
     ) -> Arc<dyn Validator>;
+
+    /// Called when the node is notified of a new block.
+    ///
+    /// The default implementation does nothing.
+    fn on_new_block(&self, _height: u32, _block_hash: &[u8; 32]) {}
 }
 
+/// The current version of the [EnforcementState] layout, incremented
+/// whenever persisted fields are added
+pub const ENFORCEMENT_STATE_VERSION: u32 = 4;
+
+/// The number of counterparty per-commitment points remembered for
+/// reuse detection
+pub const COUNTERPARTY_POINT_HISTORY_LEN: usize = 16;
+
 /// Enforcement state for a channel
 ///
 /// This keeps track of commitments on both sides and whether the channel
@@ -233,6 +321,8 @@ pub trait ValidatorFactory: Send + Sync {
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
 pub struct EnforcementState {
+    /// The layout version, see [ENFORCEMENT_STATE_VERSION]
+    pub version: u32,
     pub next_holder_commit_num: u64,
     pub next_counterparty_commit_num: u64,
     pub next_counterparty_revoke_num: u64,
@@ -243,6 +333,14 @@ pub struct EnforcementState {
     pub previous_counterparty_commit_info: Option<CommitmentInfo2>,
     pub mutual_close_signed: bool,
     pub initial_holder_value: u64,
+    /// Recently used counterparty points, oldest first, as (commit_num, point)
+    pub counterparty_point_history: Vec<(u64, PublicKey)>,
+    /// The confirmed funding outpoint and its block height, cleared on reorg
+    pub funding_confirmation: Option<(OutPoint, u32)>,
+    /// The cumulative change of the counterparty commitment fee while
+    /// we are the funder, in millisatoshi.  Fee decreases are credited,
+    /// down to zero.
+    pub total_fees_paid_msat: u64,
 }
 
 impl EnforcementState {
@@ -252,6 +350,7 @@ impl EnforcementState {
     /// that we expect the initial commitment to send to us.
     pub fn new(initial_holder_value: u64) -> EnforcementState {
         EnforcementState {
+            version: ENFORCEMENT_STATE_VERSION,
             next_holder_commit_num: 0,
             next_counterparty_commit_num: 0,
             next_counterparty_revoke_num: 0,
@@ -262,7 +361,84 @@ impl EnforcementState {
             previous_counterparty_commit_info: None,
             mutual_close_signed: false,
             initial_holder_value,
+            counterparty_point_history: Vec::new(),
+            funding_confirmation: None,
+            total_fees_paid_msat: 0,
+        }
+    }
+
+    /// Check the internal consistency of the state, for example after
+    /// it was restored from a persister.
+    ///
+    /// The accessors assume these invariants and would otherwise panic
+    /// or misbehave later on.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.version > ENFORCEMENT_STATE_VERSION {
+            return Err(format!(
+                "unsupported version {} > {}",
+                self.version, ENFORCEMENT_STATE_VERSION
+            ));
+        }
+        if self.next_counterparty_revoke_num > self.next_counterparty_commit_num {
+            return Err(format!(
+                "next_counterparty_revoke_num {} > next_counterparty_commit_num {}",
+                self.next_counterparty_revoke_num, self.next_counterparty_commit_num
+            ));
+        }
+        if self.next_counterparty_commit_num > self.next_counterparty_revoke_num + 2 {
+            return Err(format!(
+                "next_counterparty_commit_num {} too far ahead of next_counterparty_revoke_num {}",
+                self.next_counterparty_commit_num, self.next_counterparty_revoke_num
+            ));
+        }
+        if self.next_counterparty_commit_num > 0 {
+            if self.current_counterparty_point.is_none() {
+                return Err(format!(
+                    "missing current_counterparty_point with next_counterparty_commit_num {}",
+                    self.next_counterparty_commit_num
+                ));
+            }
+            if self.current_counterparty_commit_info.is_none() {
+                return Err(format!(
+                    "missing current_counterparty_commit_info with next_counterparty_commit_num {}",
+                    self.next_counterparty_commit_num
+                ));
+            }
+        } else if self.current_counterparty_commit_info.is_some() {
+            return Err("current_counterparty_commit_info set before any commitment".to_string());
+        }
+        if self.next_holder_commit_num > 0 {
+            if self.current_holder_commit_info.is_none() {
+                return Err(format!(
+                    "missing current_holder_commit_info with next_holder_commit_num {}",
+                    self.next_holder_commit_num
+                ));
+            }
+        } else if self.current_holder_commit_info.is_some() {
+            return Err("current_holder_commit_info set before any commitment".to_string());
         }
+        if self.counterparty_point_history.len() > COUNTERPARTY_POINT_HISTORY_LEN {
+            return Err(format!(
+                "counterparty_point_history too long: {}",
+                self.counterparty_point_history.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the funding of the channel was established, i.e. the
+    /// counterparty counter-signed our initial commitment and we validated it
+    pub fn is_funding_established(&self) -> bool {
+        self.next_holder_commit_num > 0
+    }
+
+    /// Whether the current holder or counterparty commitment has any
+    /// offered or received HTLCs
+    pub fn has_pending_htlcs(&self) -> bool {
+        let pending = |info: &Option<CommitmentInfo2>| {
+            info.as_ref().map(|info| !info.htlcs_is_empty()).unwrap_or(false)
+        };
+        pending(&self.current_holder_commit_info) || pending(&self.current_counterparty_commit_info)
     }
 
     /// Returns the minimum amount to_holder from both commitments or
@@ -399,6 +575,7 @@ impl EnforcementState {
             self.previous_counterparty_commit_info = self.current_counterparty_commit_info.take();
             self.current_counterparty_point = Some(current_point);
             self.current_counterparty_commit_info = Some(current_commitment_info);
+            self.remember_counterparty_point(num - 1, current_point);
         } else {
             return policy_err!("invalid progression: {} to {}", current, num);
         }
@@ -408,6 +585,47 @@ impl EnforcementState {
         Ok(())
     }
 
+    /// The change of the fee we pay relative to the current counterparty
+    /// commitment if `info2` is signed as counterparty commitment
+    /// `commit_num`, in millisatoshi.  A fee decrease is negative.
+    ///
+    /// This is the decrease of the balances less the increase of the HTLCs,
+    /// so that HTLCs being added or removed are not counted.  Trimmed HTLCs
+    /// go to fees, so they are not counted with the HTLCs.  Retries, the
+    /// first commitment and channels funded by the counterparty don't change
+    /// fees.
+    pub fn counterparty_fee_increment_msat(
+        &self,
+        commit_num: u64,
+        info2: &CommitmentInfo2,
+        setup: &ChannelSetup,
+    ) -> i64 {
+        if !setup.is_outbound || commit_num != self.next_counterparty_commit_num {
+            return 0;
+        }
+        let balances = |info: &CommitmentInfo2| {
+            (info.to_broadcaster_value_sat + info.to_countersigner_value_sat) as i64
+        };
+        let htlcs = |info: &CommitmentInfo2| {
+            info.untrimmed_htlc_value_sat(setup.option_anchor_outputs()) as i64
+        };
+        match &self.current_counterparty_commit_info {
+            Some(current) =>
+                (balances(current) - balances(info2) - (htlcs(info2) - htlcs(current))) * 1000,
+            None => 0,
+        }
+    }
+
+    /// The total fees paid after a fee change of `increment_msat`, as
+    /// returned by [EnforcementState::counterparty_fee_increment_msat]
+    pub fn total_fees_paid_after_msat(&self, increment_msat: i64) -> u64 {
+        if increment_msat >= 0 {
+            self.total_fees_paid_msat.saturating_add(increment_msat as u64)
+        } else {
+            self.total_fees_paid_msat.saturating_sub((-increment_msat) as u64)
+        }
+    }
+
     /// Previous counterparty commitment point
     pub fn get_previous_counterparty_point(&self, num: u64) -> Result<PublicKey, ValidationError> {
         let point = if num + 1 == self.next_counterparty_commit_num {
@@ -505,6 +723,19 @@ impl EnforcementState {
         self.next_holder_commit_num = num;
     }
 
+    /// Returns the commitment number a recently used counterparty point
+    /// was used for, if any.
+    pub fn get_counterparty_point_commit_num(&self, point: &PublicKey) -> Option<u64> {
+        self.counterparty_point_history.iter().find(|(_, p)| p == point).map(|(num, _)| *num)
+    }
+
+    fn remember_counterparty_point(&mut self, commit_num: u64, point: PublicKey) {
+        if self.counterparty_point_history.len() >= COUNTERPARTY_POINT_HISTORY_LEN {
+            self.counterparty_point_history.remove(0);
+        }
+        self.counterparty_point_history.push((commit_num, point));
+    }
+
     #[allow(missing_docs)]
     #[cfg(feature = "test_utils")]
     pub fn set_next_counterparty_commit_num_for_testing(
@@ -709,6 +940,83 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn counterparty_fee_increment_test() {
+        let setup = make_test_channel_setup();
+        let mut state = EnforcementState::new(0);
+        let mut info = make_test_commitment_info();
+        state.set_next_counterparty_commit_num(1, make_test_pubkey(0x12), info.clone()).unwrap();
+
+        let htlc = |value_sat| HTLCInfo2 {
+            value_sat,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1 << 16,
+        };
+        // An HTLC is not a fee
+        info.to_broadcaster_value_sat -= 10_000;
+        info.offered_htlcs = vec![htlc(10_000)];
+        assert_eq!(state.counterparty_fee_increment_msat(1, &info, &setup), 0);
+        // A fee decrease is negative
+        info.to_countersigner_value_sat += 500;
+        assert_eq!(state.counterparty_fee_increment_msat(1, &info, &setup), -500_000);
+        // An HTLC trimmed at the commitment feerate goes to fees
+        info.to_countersigner_value_sat -= 500;
+        info.offered_htlcs = vec![htlc(10_000), htlc(4000)];
+        info.to_broadcaster_value_sat -= 4000;
+        assert_eq!(state.counterparty_fee_increment_msat(1, &info, &setup), 4_000_000);
+        // Only the next commitment of a channel we funded changes fees
+        assert_eq!(state.counterparty_fee_increment_msat(0, &info, &setup), 0);
+        let inbound_setup = ChannelSetup { is_outbound: false, ..setup };
+        assert_eq!(state.counterparty_fee_increment_msat(1, &info, &inbound_setup), 0);
+
+        state.total_fees_paid_msat = 1000;
+        assert_eq!(state.total_fees_paid_after_msat(-2000), 0);
+        assert_eq!(state.total_fees_paid_after_msat(2000), 3000);
+        state.total_fees_paid_msat = u64::MAX;
+        assert_eq!(state.total_fees_paid_after_msat(1), u64::MAX);
+    }
+
+    #[test]
+    fn enforcement_state_check_invariants_test() {
+        let mut state = EnforcementState::new(0);
+        assert!(state.check_invariants().is_ok());
+
+        let point0 = make_test_pubkey(0x12);
+        let commit_info = make_test_commitment_info();
+        state.set_next_counterparty_commit_num(1, point0, commit_info.clone()).unwrap();
+        assert!(state.check_invariants().is_ok());
+
+        let mut bad_state = state.clone();
+        bad_state.next_counterparty_revoke_num = 2;
+        assert_eq!(
+            bad_state.check_invariants(),
+            Err("next_counterparty_revoke_num 2 > next_counterparty_commit_num 1".to_string())
+        );
+
+        let mut bad_state = state.clone();
+        bad_state.next_counterparty_commit_num = 3;
+        assert_eq!(
+            bad_state.check_invariants(),
+            Err("next_counterparty_commit_num 3 too far ahead of next_counterparty_revoke_num 0"
+                .to_string())
+        );
+
+        let mut bad_state = state.clone();
+        bad_state.current_counterparty_commit_info = None;
+        assert_eq!(
+            bad_state.check_invariants(),
+            Err("missing current_counterparty_commit_info with next_counterparty_commit_num 1"
+                .to_string())
+        );
+
+        let mut bad_state = state.clone();
+        bad_state.next_holder_commit_num = 1;
+        assert_eq!(
+            bad_state.check_invariants(),
+            Err("missing current_holder_commit_info with next_holder_commit_num 1".to_string())
+        );
+    }
+
     #[test]
     fn enforcement_state_previous_counterparty_point_test() {
         let mut state = EnforcementState::new(0);
@@ -2,14 +2,20 @@
 mod tests {
     use bitcoin;
     use bitcoin::hashes::hex::{FromHex, ToHex};
+    use bitcoin::secp256k1::PublicKey;
     use bitcoin::secp256k1::SecretKey;
-    use bitcoin::Script;
-    use lightning::ln::chan_utils::ChannelPublicKeys;
+    use bitcoin::{Network, Script};
+    use lightning::chain::keysinterface::BaseSign;
+    use lightning::ln::chan_utils::{make_funding_redeemscript, ChannelPublicKeys};
     use test_log::test;
 
-    use crate::channel::channel_nonce_to_id;
+    use crate::channel::{channel_nonce_to_id, CommitmentType};
+    use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use crate::sync::Arc;
+    use crate::util::key_utils::make_test_pubkey;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
+    use crate::wallet::Wallet;
 
     macro_rules! hex (($hex:expr) => (Vec::from_hex($hex).unwrap()));
     macro_rules! hex_script (($hex:expr) => (Script::from(hex!($hex))));
@@ -144,6 +150,9 @@ mod tests {
                 "policy failure: channel stub cannot release commitment secret"
             );
 
+            // A stub doesn't have channel parameters yet.
+            assert!(base.get_channel_parameters().is_none());
+
             Ok(())
         });
 
@@ -186,6 +195,61 @@ mod tests {
         assert_eq!(err.message(), format!("channel already exists: {}", TEST_CHANNEL_ID[0]));
     }
 
+    #[test]
+    fn ready_channel_simple_taproot_test() {
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::SimpleTaprootChannels;
+        assert!(setup.option_simple_taproot_channels());
+        assert!(setup.option_anchor_outputs());
+        let (node, channel_id) = init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup);
+
+        let result: Result<_, Status> = node.with_ready_channel(&channel_id, |chan| {
+            assert!(chan.make_channel_parameters().opt_anchors.is_some());
+            let result = chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10),
+                0,
+                0,
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            );
+            assert_failed_precondition_err!(
+                result.clone(),
+                "policy failure: validate_counterparty_commitment_tx: validate_commitment_tx: \
+                 simple taproot channel commitments not supported yet"
+            );
+            Ok(())
+        });
+        result.unwrap();
+    }
+
+    #[test]
+    fn get_channel_parameters_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let params = node
+            .with_channel_base(&channel_id, |base| Ok(base.get_channel_parameters()))
+            .unwrap()
+            .expect("ready channel has parameters");
+        let basepoints =
+            node.with_channel_base(&channel_id, |base| Ok(base.get_channel_basepoints())).unwrap();
+        assert!(params.holder_pubkeys == basepoints);
+        assert_eq!(params.holder_selected_contest_delay, setup.holder_selected_contest_delay);
+        assert_eq!(params.is_outbound_from_holder, setup.is_outbound);
+        let funding_outpoint = params.funding_outpoint.unwrap();
+        assert_eq!(funding_outpoint.txid, setup.funding_outpoint.txid);
+        assert_eq!(funding_outpoint.index as u32, setup.funding_outpoint.vout);
+        let counterparty_parameters = params.counterparty_parameters.unwrap();
+        assert!(counterparty_parameters.pubkeys == setup.counterparty_points);
+        assert_eq!(
+            counterparty_parameters.selected_contest_delay,
+            setup.counterparty_selected_contest_delay
+        );
+    }
+
     #[test]
     fn ready_channel_already_ready_test() {
         let (node, channel_id) =
@@ -253,4 +317,157 @@ mod tests {
             &holder_shutdown_key_path
         ));
     }
+
+    #[test]
+    fn ready_channel_derived_holder_shutdown_script() {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        let holder_shutdown_key_path = vec![7];
+        let script = node.holder_shutdown_script(&holder_shutdown_key_path).unwrap();
+        assert_eq!(script, hex_script!("0014b76dd61e41b5ef052af21cda3260888c070bb9af"));
+        assert!(node.can_spend(&holder_shutdown_key_path, &script).unwrap());
+        assert_invalid_argument_err!(node.holder_shutdown_script(&[]), "empty child path");
+
+        let mut setup = make_test_channel_setup();
+        setup.holder_shutdown_script = Some(script);
+        assert_status_ok!(node.ready_channel(
+            channel_id,
+            None,
+            setup.clone(),
+            &holder_shutdown_key_path
+        ));
+    }
+
+    fn ready_legacy_channel(require_static_remote_key: bool) -> Result<(), Status> {
+        let mut config = TEST_NODE_CONFIG;
+        config.require_static_remote_key = require_static_remote_key;
+        let node = init_node(config, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        let mut setup = make_test_channel_setup();
+        setup.commitment_type = CommitmentType::Legacy;
+        node.ready_channel(channel_id, None, setup, &vec![]).map(|_| ())
+    }
+
+    // policy-channel-static-remote-key-required
+    #[test]
+    fn ready_channel_legacy_with_require_static_remote_key() {
+        assert_failed_precondition_err!(
+            ready_legacy_channel(true),
+            "policy failure: validate_ready_channel: legacy channels not allowed by node config"
+        );
+    }
+
+    #[test]
+    fn ready_channel_legacy_without_require_static_remote_key() {
+        assert_status_ok!(ready_legacy_channel(false));
+    }
+
+    fn ready_channel_with_delays(holder_delay: u16, counterparty_delay: u16) -> Result<(), Status> {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        let mut setup = make_test_channel_setup();
+        setup.holder_selected_contest_delay = holder_delay;
+        setup.counterparty_selected_contest_delay = counterparty_delay;
+        node.ready_channel(channel_id, None, setup, &vec![]).map(|_| ())
+    }
+
+    // policy-channel-counterparty-contest-delay-range
+    #[test]
+    fn ready_channel_zero_counterparty_contest_delay() {
+        assert_failed_precondition_err!(
+            ready_channel_with_delays(6, 0),
+            "policy failure: validate_delay: counterparty_selected_contest_delay too small: 0 < 4"
+        );
+    }
+
+    // policy-channel-holder-contest-delay-range
+    #[test]
+    fn ready_channel_absurd_holder_contest_delay() {
+        assert_failed_precondition_err!(
+            ready_channel_with_delays(u16::MAX, 7),
+            "policy failure: validate_delay: holder_selected_contest_delay too large: 65535 > 2016"
+        );
+    }
+
+    fn ready_channel_with_allowed_peers(
+        allowed_peer_ids: Vec<PublicKey>,
+        counterparty_node_id: Option<PublicKey>,
+    ) -> Result<(), Status> {
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[1]);
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.allowed_peer_ids = Some(allowed_peer_ids);
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        let channel_nonce = "nonce1".as_bytes().to_vec();
+        let channel_id = channel_nonce_to_id(&channel_nonce);
+        node.new_channel(Some(channel_id), Some(channel_nonce), &node).expect("new_channel");
+        let mut setup = make_test_channel_setup();
+        setup.counterparty_node_id = counterparty_node_id;
+        node.ready_channel(channel_id, None, setup, &vec![]).map(|_| ())
+    }
+
+    // policy-channel-counterparty-allowed
+    #[test]
+    fn ready_channel_allowed_peer_test() {
+        let peer = make_test_pubkey(43);
+        assert_status_ok!(ready_channel_with_allowed_peers(
+            vec![make_test_pubkey(42), peer],
+            Some(peer)
+        ));
+        // an empty list allows any peer
+        assert_status_ok!(ready_channel_with_allowed_peers(vec![], None));
+    }
+
+    // policy-channel-counterparty-allowed
+    #[test]
+    fn ready_channel_unlisted_peer_test() {
+        let peer = make_test_pubkey(43);
+        assert_failed_precondition_err!(
+            ready_channel_with_allowed_peers(vec![make_test_pubkey(42)], Some(peer)),
+            format!(
+                "policy failure: validate_ready_channel: counterparty {} is not an allowed peer",
+                peer
+            )
+        );
+        // The funding pubkey does not identify the peer
+        let funding_pubkey = make_test_channel_setup().counterparty_points.funding_pubkey;
+        assert_failed_precondition_err!(
+            ready_channel_with_allowed_peers(vec![funding_pubkey], None),
+            "policy failure: validate_ready_channel: counterparty node id is unknown"
+        );
+    }
+
+    #[test]
+    fn funding_script_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        node.with_ready_channel(&channel_id, |chan| {
+            let redeemscript = make_funding_redeemscript(
+                &chan.keys.pubkeys().funding_pubkey,
+                &setup.counterparty_points.funding_pubkey,
+            );
+            assert_eq!(chan.funding_redeemscript(), redeemscript);
+            assert_eq!(
+                chan.funding_script_pubkey(),
+                Script::new_v0_wsh(&redeemscript.wscript_hash())
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn channel_setup_csv_delays_test() {
+        let setup = make_test_channel_setup();
+        assert_eq!(setup.local_csv(), setup.holder_selected_contest_delay);
+        assert_eq!(setup.remote_csv(), setup.counterparty_selected_contest_delay);
+        assert_eq!(setup.local_csv(), 6);
+        assert_eq!(setup.remote_csv(), 7);
+    }
 }
@@ -1,10 +1,13 @@
 #[cfg(test)]
 mod tests {
+    use core::time::Duration;
+
     use bitcoin;
     use bitcoin::hashes::hex::ToHex;
     use bitcoin::hashes::Hash;
     use bitcoin::secp256k1::PublicKey;
     use bitcoin::util::psbt::serialize::Serialize;
+    use bitcoin::Network;
     use lightning::chain::keysinterface::BaseSign;
     use lightning::ln::chan_utils::{
         make_funding_redeemscript, BuiltCommitmentTransaction, TxCreationKeys,
@@ -12,11 +15,15 @@ mod tests {
     use lightning::ln::PaymentHash;
     use test_log::test;
 
-    use crate::channel::{Channel, ChannelSetup, CommitmentType, TypedSignature};
+    use crate::channel::{Channel, ChannelSetup, CommitmentParams, CommitmentType, TypedSignature};
+    use crate::policy::rate_limit::RateLimit;
+    use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
     use crate::policy::validator::{ChainState, EnforcementState};
+    use crate::sync::Arc;
     use crate::tx::script::get_to_countersignatory_with_anchors_redeemscript;
     use crate::tx::tx::HTLCInfo2;
-    use crate::util::crypto_utils::payload_for_p2wpkh;
+    use crate::util::clock::ManualClock;
+    use crate::util::crypto_utils::{payload_for_p2wpkh, signature_to_bitcoin_vec};
     use crate::util::key_utils::*;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
@@ -59,6 +66,7 @@ mod tests {
                     make_test_pubkey(0x10),
                 );
                 chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
 
                 let commitment_tx = chan.make_counterparty_commitment_tx(
                     &remote_percommitment_point,
@@ -105,7 +113,7 @@ mod tests {
             "d167e8e687f93170e787d210bac57538910050138b7d088684fe7fdcf735bf6d"
         );
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
 
@@ -119,6 +127,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn recompose_and_compare_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut params = CommitmentParams {
+            counterparty_point: Some(make_test_pubkey(10)),
+            commitment_number: 23,
+            feerate_per_kw: 0,
+            to_holder_value_sat: 1_999_000,
+            to_counterparty_value_sat: 1_000_000,
+            offered_htlcs: vec![],
+            received_htlcs: vec![],
+        };
+        node.with_ready_channel(&channel_id, |chan| {
+            let state_before = format!("{:?}", chan.enforcement_state);
+            let tx = chan
+                .make_counterparty_commitment_tx(
+                    &params.counterparty_point.unwrap(),
+                    params.commitment_number,
+                    params.feerate_per_kw,
+                    params.to_holder_value_sat,
+                    params.to_counterparty_value_sat,
+                    vec![],
+                )
+                .trust()
+                .built_transaction()
+                .transaction
+                .clone();
+            assert_status_ok!(chan.recompose_and_compare(&tx, &params));
+
+            let mut tampered_tx = tx.clone();
+            tampered_tx.lock_time = 42;
+            assert_failed_precondition_err!(
+                chan.recompose_and_compare(&tampered_tx, &params),
+                "policy failure: recomposed tx mismatch: bad commitment locktime: 42"
+            );
+
+            let mut tampered_tx = tx.clone();
+            tampered_tx.output[0].value -= 1;
+            assert_failed_precondition_err!(
+                chan.recompose_and_compare(&tampered_tx, &params),
+                "policy failure: recomposed tx mismatch"
+            );
+
+            params.to_holder_value_sat -= 1;
+            assert_failed_precondition_err!(
+                chan.recompose_and_compare(&tx, &params),
+                "policy failure: recomposed tx mismatch"
+            );
+
+            // a holder commitment with the same values is different
+            params.to_holder_value_sat += 1;
+            params.counterparty_point = None;
+            assert_failed_precondition_err!(
+                chan.recompose_and_compare(&tx, &params),
+                "policy failure: recomposed tx mismatch"
+            );
+            assert_eq!(format!("{:?}", chan.enforcement_state), state_before);
+            Ok(())
+        })
+        .expect("channel");
+    }
+
     #[test]
     fn sign_counterparty_commitment_tx_with_htlc_static_test() {
         let setup = make_test_channel_setup();
@@ -183,6 +254,7 @@ mod tests {
                     make_test_pubkey(0x10),
                 );
                 chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
 
                 let commitment_tx = chan.make_counterparty_commitment_tx(
                     &remote_percommitment_point,
@@ -217,7 +289,7 @@ mod tests {
             "98fe7f855e1cc99ca29a7c18caf1b8c6ac81fcdc44a854c60bf1b28d390323c4"
         );
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
 
@@ -249,7 +321,7 @@ mod tests {
             init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
 
         let remote_percommitment_point = make_test_pubkey(10);
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
 
         // fee = 1000
         let commit_num = 23;
@@ -264,6 +336,7 @@ mod tests {
                     make_test_pubkey(0x10),
                 );
                 chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
 
                 let commitment_tx = chan.make_counterparty_commitment_tx(
                     &remote_percommitment_point,
@@ -308,6 +381,490 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sign_counterparty_commitment_tx_v2_test() {
+        let setup = make_test_channel_setup();
+        let remote_percommitment_point = make_test_pubkey(10);
+        let commit_num = 23;
+        let htlc = HTLCInfo2 {
+            value_sat: 10_000,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1 << 16,
+        };
+
+        let sign = |use_v2: bool| {
+            let (node, channel_id) =
+                init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+                if use_v2 {
+                    let info = chan.build_counterparty_commitment_info(
+                        &remote_percommitment_point,
+                        1_000_000,
+                        1_979_000,
+                        vec![htlc.clone()],
+                        vec![],
+                        7500,
+                    )?;
+                    let mut wrong_point_info = info.clone();
+                    wrong_point_info.revocation_pubkey = make_test_pubkey(11);
+                    assert_invalid_argument_err!(
+                        chan.sign_counterparty_commitment_tx_v2(
+                            &remote_percommitment_point,
+                            commit_num,
+                            &wrong_point_info,
+                        ),
+                        format!(
+                            "commitment info does not match per_commitment_point {}",
+                            remote_percommitment_point
+                        )
+                    );
+                    chan.sign_counterparty_commitment_tx_v2(
+                        &remote_percommitment_point,
+                        commit_num,
+                        &info,
+                    )
+                } else {
+                    let (sig, htlc_sigs) = chan.sign_counterparty_commitment_tx_phase2(
+                        &remote_percommitment_point,
+                        commit_num,
+                        7500,
+                        1_000_000,
+                        1_979_000,
+                        vec![htlc.clone()],
+                        vec![],
+                    )?;
+                    Ok((
+                        signature_to_bitcoin_vec(sig),
+                        htlc_sigs.into_iter().map(signature_to_bitcoin_vec).collect(),
+                    ))
+                }
+            })
+            .expect("sign")
+        };
+
+        let (sig, htlc_sigs) = sign(true);
+        assert_eq!(htlc_sigs.len(), 1);
+        assert_eq!((sig, htlc_sigs), sign(false));
+    }
+
+    // Guards against a regression to randomized nonces
+    #[test]
+    fn sign_counterparty_commitment_tx_deterministic_test() {
+        let setup = make_test_channel_setup();
+        let remote_percommitment_point = make_test_pubkey(10);
+        let commit_num = 23;
+        let htlc = HTLCInfo2 {
+            value_sat: 10_000,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1 << 16,
+        };
+
+        let sign_twice = || {
+            let (node, channel_id) =
+                init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+                let mut sign = || {
+                    chan.sign_counterparty_commitment_tx_phase2(
+                        &remote_percommitment_point,
+                        commit_num,
+                        7500,
+                        1_000_000,
+                        1_979_000,
+                        vec![htlc.clone()],
+                        vec![],
+                    )
+                };
+                let first = sign()?;
+                // a retry of the same commitment
+                let second = sign()?;
+                Ok((first, second))
+            })
+            .expect("sign")
+        };
+
+        let (first, second) = sign_twice();
+        assert_eq!(first.1.len(), 1);
+        assert_eq!(first.0.serialize_der().to_vec(), second.0.serialize_der().to_vec());
+        assert_eq!(first, second);
+        // A separately constructed node with the same seed signs identically
+        assert_eq!(sign_twice().0, first);
+    }
+
+    #[test]
+    fn sign_counterparty_commitment_tx_low_r_test() {
+        let setup = make_test_channel_setup();
+        let commit_num = 23;
+        let htlc = HTLCInfo2 {
+            value_sat: 10_000,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1 << 16,
+        };
+
+        let sign = |low_r: bool, point_idx: u8| {
+            let mut node_config = TEST_NODE_CONFIG;
+            node_config.low_r_grinding = low_r;
+            let (node, channel_id) =
+                init_node_and_channel(node_config, TEST_SEED[1], setup.clone());
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(point_idx),
+                    commit_num,
+                    7500,
+                    1_000_000,
+                    1_979_000,
+                    vec![htlc.clone()],
+                    vec![],
+                )
+            })
+            .expect("sign")
+        };
+
+        assert_low_r(|i| {
+            let (sig, htlc_sigs) = sign(true, i);
+            let (plain_sig, plain_htlc_sigs) = sign(false, i);
+            assert_eq!(htlc_sigs.len(), 1);
+
+            // Grinding starts from the plain nonce, so a plain signature that
+            // already has a low R must come out unchanged
+            if plain_sig.serialize_compact()[0] < 0x80 {
+                assert_eq!(sig, plain_sig);
+            }
+            if plain_htlc_sigs[0].serialize_compact()[0] < 0x80 {
+                assert_eq!(htlc_sigs[0], plain_htlc_sigs[0]);
+            }
+            vec![sig, htlc_sigs[0]]
+        });
+    }
+
+    // policy-commitment-signing-rate
+    #[test]
+    fn sign_counterparty_commitment_tx_rate_limit_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let clock = Arc::new(ManualClock::new(Duration::from_secs(1_000_000)));
+        node.set_clock(clock.clone());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_signs_per_interval =
+            Some(RateLimit { max_signs: 2, interval: Duration::from_secs(60) });
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let commit_num = 23;
+        let sign = || {
+            node.with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
+                    commit_num,
+                    make_test_pubkey(0x10),
+                );
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+                chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(10),
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_999_000,
+                    vec![],
+                    vec![],
+                )
+            })
+        };
+
+        assert_status_ok!(sign());
+        assert_status_ok!(sign());
+        assert_failed_precondition_err!(
+            sign(),
+            format!(
+                "policy failure: signing rate limit exceeded for channel {}: 2 per 60s",
+                channel_id
+            )
+        );
+
+        // A token is replenished every 30 seconds
+        clock.advance(Duration::from_secs(30));
+        assert_status_ok!(sign());
+        assert!(sign().is_err());
+    }
+
+    // policy-commitment-point-not-reused
+    #[test]
+    fn sign_counterparty_commitment_tx_reused_point_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+
+        let remote_percommitment_point = make_test_pubkey(10);
+        let commit_num = 23;
+        let to_holder_value_sat = 1_000_000;
+        let to_counterparty_value_sat = 1_999_000;
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+
+            chan.sign_counterparty_commitment_tx_phase2(
+                &remote_percommitment_point,
+                commit_num,
+                0,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                vec![],
+                vec![],
+            )?;
+
+            // Revoke the prior commitment so the next one may be signed.
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num);
+
+            assert_failed_precondition_err!(
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &remote_percommitment_point,
+                    commit_num + 1,
+                    0,
+                    to_holder_value_sat,
+                    to_counterparty_value_sat,
+                    vec![],
+                    vec![],
+                ),
+                "policy failure: validate_counterparty_commitment_tx: \
+                 counterparty point for commit_num 24 was already used for commit_num 23"
+            );
+            Ok(())
+        })
+        .expect("reused point");
+    }
+
+    // policy-commitment-funding-confirmed
+    #[test]
+    fn sign_counterparty_commitment_tx_funding_confirmation_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_funding_confirmed = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let commit_num = 23;
+        let sign = |chan: &mut Channel| {
+            chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10),
+                commit_num,
+                0,
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )
+        };
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+
+            assert_failed_precondition_err!(
+                sign(chan),
+                "policy failure: validate_counterparty_commitment_tx: \
+                 validate_commitment_tx: funding not confirmed"
+            );
+
+            chan.confirm_funding(setup.funding_outpoint, 100)?;
+            assert_status_ok!(sign(chan));
+
+            // After a reorg even a retry is refused
+            chan.handle_funding_reorg()?;
+            assert_failed_precondition_err!(
+                sign(chan),
+                "policy failure: validate_counterparty_commitment_tx: \
+                 validate_commitment_tx: funding not confirmed"
+            );
+            Ok(())
+        })
+        .expect("funding confirmation");
+    }
+
+    #[test]
+    fn sign_commitment_tx_before_funding_established_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+
+        node.with_ready_channel(&channel_id, |chan| {
+            assert!(!chan.enforcement_state.is_funding_established());
+            let sign = |chan: &mut Channel, commit_num: u64| {
+                chan.sign_counterparty_commitment_tx_phase2(
+                    &make_test_pubkey(10 + commit_num as u8),
+                    commit_num,
+                    0,
+                    1_000_000,
+                    1_999_000,
+                    vec![],
+                    vec![],
+                )
+            };
+
+            // The initial commitment is signed before the funding is established
+            assert_status_ok!(sign(chan, 0));
+
+            assert_failed_precondition_err!(
+                sign(chan, 1),
+                "policy failure: funding not established: \
+                 initial holder commitment not validated before signing commitment 1"
+            );
+            assert_failed_precondition_err!(
+                chan.sign_holder_commitment_tx_phase2_redundant(
+                    1,
+                    0,
+                    1_000_000,
+                    1_999_000,
+                    vec![],
+                    vec![],
+                ),
+                "policy failure: funding not established: \
+                 initial holder commitment not validated before signing commitment 1"
+            );
+            assert_eq!(chan.enforcement_state.next_counterparty_commit_num, 1);
+            Ok(())
+        })
+        .expect("before funding established");
+    }
+
+    // policy-commitment-total-fees
+    #[test]
+    fn sign_counterparty_commitment_tx_total_fees_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.max_total_fees_msat = Some(3_000_000);
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        // We are the funder, so the fee comes out of our balance
+        let sign_with_htlcs = |chan: &mut Channel,
+                               commit_num: u64,
+                               fee_sat: u64,
+                               offered_htlcs: Vec<HTLCInfo2>|
+         -> Result<u64, Status> {
+            let htlc_value_sat: u64 = offered_htlcs.iter().map(|h| h.value_sat).sum();
+            chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10 + commit_num as u8),
+                commit_num,
+                0,
+                1_000_000 - fee_sat,
+                2_000_000 - htlc_value_sat,
+                offered_htlcs,
+                vec![],
+            )?;
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num);
+            Ok(chan.enforcement_state.total_fees_paid_msat)
+        };
+        let sign = |chan: &mut Channel, commit_num: u64, fee_sat: u64| {
+            sign_with_htlcs(chan, commit_num, fee_sat, vec![])
+        };
+
+        node.with_ready_channel(&channel_id, |chan| {
+            let commit_num = 23;
+            chan.enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+
+            // There is no prior commitment to compare the first one to
+            assert_eq!(sign(chan, 23, 1000)?, 0);
+            assert_eq!(sign(chan, 24, 2000)?, 1_000_000);
+            // A fee decrease is credited
+            assert_eq!(sign(chan, 25, 1500)?, 500_000);
+            assert_eq!(sign(chan, 26, 3000)?, 2_000_000);
+
+            assert_failed_precondition_err!(
+                sign(chan, 27, 4500),
+                "policy failure: validate_counterparty_commitment_tx: \
+                 total fees paid 3500000 msat exceeds maximum 3000000 msat"
+            );
+            assert_eq!(chan.enforcement_state.total_fees_paid_msat, 2_000_000);
+            assert_eq!(sign(chan, 27, 4000)?, 3_000_000);
+
+            // An HTLC taken out of a balance is not a fee
+            let htlc = HTLCInfo2 {
+                value_sat: 10_000,
+                payment_hash: PaymentHash([1; 32]),
+                cltv_expiry: 1 << 16,
+            };
+            assert_eq!(sign_with_htlcs(chan, 28, 4000, vec![htlc])?, 3_000_000);
+            Ok(())
+        })
+        .expect("total fees");
+    }
+
+    // policy-commitment-htlc-cltv-range
+    #[test]
+    fn sign_counterparty_commitment_tx_expired_htlc_after_new_block_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.use_chain_state = true;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+
+        let offered_htlc =
+            HTLCInfo2 { value_sat: 5_000, payment_hash: PaymentHash([1; 32]), cltv_expiry: 1000 };
+        let commit_num = 23;
+        let sign = |chan: &mut Channel, commit_num: u64| {
+            chan.sign_counterparty_commitment_tx_phase2(
+                &make_test_pubkey(10 + commit_num as u8),
+                commit_num,
+                0,
+                1_000_000,
+                1_994_000,
+                vec![offered_htlc.clone()],
+                vec![],
+            )
+        };
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.enforcement_state
+                .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
+            assert_status_ok!(sign(chan, commit_num));
+            chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num);
+            Ok(())
+        })
+        .expect("before expiry");
+
+        node.notify_new_block(997, [0; 32]).unwrap();
+        assert_eq!(node.get_block_height(), 997);
+
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_failed_precondition_err!(
+                sign(chan, commit_num + 1),
+                "policy failure: validate_counterparty_commitment_tx: \
+                 validate_expiry: offered HTLC expiry too early: 1000 < 1001"
+            );
+            Ok(())
+        })
+        .expect("after expiry");
+    }
+
     #[allow(dead_code)]
     struct TxMutationState<'a> {
         opt_anchors: bool,
@@ -344,6 +901,7 @@ mod tests {
             chan.enforcement_state
                 .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
             chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
 
             // Mutate the signer state.
             statemut(&mut chan.enforcement_state);
@@ -417,7 +975,7 @@ mod tests {
             Ok((sig, tx.transaction.clone()))
         })?;
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &setup.counterparty_points.funding_pubkey);
 
@@ -627,7 +1185,7 @@ mod tests {
         |tms| {
             tms.tx.transaction.lock_time = 42;
         },
-        |_| "policy failure: recomposed tx mismatch"
+        |_| "policy failure: recomposed tx mismatch: bad commitment locktime: 42"
     );
 
     // policy-commitment-sequence
@@ -636,7 +1194,7 @@ mod tests {
         |tms| {
             tms.tx.transaction.input[0].sequence = 42;
         },
-        |_| "policy failure: recomposed tx mismatch"
+        |_| "policy failure: recomposed tx mismatch: bad commitment sequence: 42"
     );
 
     // policy-commitment-input-single
@@ -786,6 +1344,7 @@ mod tests {
             chan.enforcement_state
                 .set_next_counterparty_commit_num_for_testing(commit_num, make_test_pubkey(0x10));
             chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(commit_num - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
 
             let parameters = channel_parameters.as_counterparty_broadcastable();
             let keys = chan.make_counterparty_tx_keys(&remote_percommitment_point)?;
@@ -471,4 +471,21 @@ mod tests {
             },
         ));
     }
+
+    #[test]
+    fn derive_htlc_signing_key_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let secp_ctx = Secp256k1::signing_only();
+        assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
+            let per_commitment_point = make_test_pubkey(10);
+            let key = chan.derive_htlc_signing_key(&per_commitment_point)?;
+            let pubkey = PublicKey::from_secret_key(&secp_ctx, &key);
+            let counterparty_keys = chan.make_counterparty_tx_keys(&per_commitment_point)?;
+            assert_eq!(pubkey, counterparty_keys.countersignatory_htlc_key);
+            let holder_keys = chan.make_holder_tx_keys(&per_commitment_point)?;
+            assert_eq!(pubkey, holder_keys.broadcaster_htlc_key);
+            Ok(())
+        }));
+    }
 }
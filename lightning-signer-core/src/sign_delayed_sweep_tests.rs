@@ -316,4 +316,106 @@ mod tests {
              fee above maximum: 1978997 > 200000"
         );
     }
+
+    #[test]
+    fn sign_closing_sweep_tx_test() {
+        let (node_ctx, chan_ctx) =
+            setup_funded_channel(HOLD_COMMIT_NUM, HOLD_COMMIT_NUM + 1, HOLD_COMMIT_NUM);
+        let commit_tx_ctx = setup_validated_holder_commitment(
+            &node_ctx,
+            &chan_ctx,
+            HOLD_COMMIT_NUM,
+            |_commit_tx_ctx| {},
+            |_keys| {},
+        )
+        .expect("holder commitment");
+        let built_commit = commit_tx_ctx.tx.as_ref().unwrap().trust().built_transaction().clone();
+        let (keys, contest_delay) = node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                let per_commitment_point = chan.get_per_commitment_point(HOLD_COMMIT_NUM)?;
+                let keys = chan.make_holder_tx_keys(&per_commitment_point).unwrap();
+                Ok((keys, chan.setup.counterparty_selected_contest_delay))
+            })
+            .unwrap();
+        let to_local_outndx = 4;
+        let amount_sat = built_commit.transaction.output[to_local_outndx].value;
+        let (script_pubkey, wallet_path) = make_test_wallet_dest(&node_ctx, 19, P2wpkh);
+        let tx = make_test_delayed_sweep_tx(
+            built_commit.txid,
+            to_local_outndx as u32,
+            contest_delay,
+            script_pubkey,
+            amount_sat - 1_000,
+        );
+
+        let sig = node_ctx
+            .node
+            .sign_closing_sweep_tx(
+                &chan_ctx.channel_id,
+                &tx,
+                0,
+                HOLD_COMMIT_NUM,
+                &keys.revocation_key,
+                contest_delay,
+                amount_sat,
+                &wallet_path,
+            )
+            .expect("sign_closing_sweep_tx");
+        let redeemscript = get_revokeable_redeemscript(
+            &keys.revocation_key,
+            contest_delay,
+            &keys.broadcaster_delayed_payment_key,
+        );
+        check_signature(
+            &tx,
+            0,
+            TypedSignature::all(sig),
+            &keys.broadcaster_delayed_payment_key,
+            amount_sat,
+            &redeemscript,
+        );
+
+        // The sequence of the swept input is checked, not that of the first input
+        let mut multi_tx = tx.clone();
+        let mut other_input = tx.input[0].clone();
+        other_input.previous_output.vout = 0;
+        other_input.sequence = 0xffff_ffff;
+        multi_tx.input.insert(0, other_input);
+        let sig = node_ctx
+            .node
+            .sign_closing_sweep_tx(
+                &chan_ctx.channel_id,
+                &multi_tx,
+                1,
+                HOLD_COMMIT_NUM,
+                &keys.revocation_key,
+                contest_delay,
+                amount_sat,
+                &wallet_path,
+            )
+            .expect("sign_closing_sweep_tx second input");
+        check_signature(
+            &multi_tx,
+            1,
+            TypedSignature::all(sig),
+            &keys.broadcaster_delayed_payment_key,
+            amount_sat,
+            &redeemscript,
+        );
+
+        assert_invalid_argument_err!(
+            node_ctx.node.sign_closing_sweep_tx(
+                &chan_ctx.channel_id,
+                &tx,
+                0,
+                HOLD_COMMIT_NUM,
+                &keys.revocation_key,
+                contest_delay + 1,
+                amount_sat,
+                &wallet_path,
+            ),
+            "sign_closing_sweep_tx: bad to_self_delay: 8 != 7"
+        );
+    }
 }
@@ -1,10 +1,13 @@
 #[cfg(test)]
 mod tests {
     use bitcoin::hashes::hex::ToHex;
+    use bitcoin::hashes::sha256::Hash as Sha256Hash;
+    use bitcoin::hashes::Hash;
     use bitcoin::{self, Script, Transaction};
     use lightning::ln::chan_utils::{
         build_htlc_transaction, get_htlc_redeemscript, make_funding_redeemscript,
     };
+    use lightning::ln::{PaymentHash, PaymentPreimage};
 
     use test_log::test;
 
@@ -79,7 +82,7 @@ mod tests {
             }
         );
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &setup.counterparty_points.funding_pubkey);
 
@@ -93,6 +96,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn success_redundant_low_r() {
+        let setup = make_test_channel_setup();
+        let mut node_config = TEST_NODE_CONFIG;
+        node_config.low_r_grinding = true;
+        let (node, channel_id) = init_node_and_channel(node_config, TEST_SEED[1], setup.clone());
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
+        let channel_funding_redeemscript =
+            make_funding_redeemscript(&funding_pubkey, &setup.counterparty_points.funding_pubkey);
+
+        assert_low_r(|i| {
+            let commit_num = i as u64;
+            let (tx, signature) = node
+                .with_ready_channel(&channel_id, |chan| {
+                    chan.enforcement_state.set_next_holder_commit_num_for_testing(commit_num);
+                    let commitment_tx = chan.make_holder_commitment_tx(
+                        commit_num,
+                        0,
+                        1_000_000,
+                        1_999_000,
+                        vec![],
+                    )?;
+                    let (signature, _) = chan.sign_holder_commitment_tx_phase2_redundant(
+                        commit_num,
+                        0, // feerate not used
+                        1_000_000,
+                        1_999_000,
+                        vec![],
+                        vec![],
+                    )?;
+                    Ok((commitment_tx.trust().built_transaction().transaction.clone(), signature))
+                })
+                .expect("sign");
+
+            check_signature(
+                &tx,
+                0,
+                TypedSignature::all(signature),
+                &funding_pubkey,
+                setup.channel_value_sat,
+                &channel_funding_redeemscript,
+            );
+            vec![signature]
+        });
+    }
+
     const HOLD_COMMIT_NUM: u64 = 23;
 
     #[allow(dead_code)]
@@ -270,7 +319,8 @@ mod tests {
             }
         );
 
-        let funding_pubkey = get_channel_funding_pubkey(&node_ctx.node, &chan_ctx.channel_id);
+        let funding_pubkey =
+            node_ctx.node.get_funding_pubkey(&chan_ctx.channel_id).expect("funding pubkey");
         let channel_funding_redeemscript = make_funding_redeemscript(
             &funding_pubkey,
             &chan_ctx.setup.counterparty_points.funding_pubkey,
@@ -302,6 +352,190 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_all_holder_htlc_txs_test() {
+        let (node_ctx, chan_ctx) =
+            setup_funded_channel(HOLD_COMMIT_NUM, HOLD_COMMIT_NUM + 1, HOLD_COMMIT_NUM);
+
+        let commit_tx_ctx = setup_validated_holder_commitment(
+            &node_ctx,
+            &chan_ctx,
+            HOLD_COMMIT_NUM,
+            |commit_tx_ctx| {
+                // Leave one offered and one received HTLC.
+                let htlc = commit_tx_ctx.received_htlcs.pop().unwrap();
+                commit_tx_ctx.to_countersignatory += htlc.value_sat;
+            },
+            |_keys| {},
+        )
+        .expect("validated holder commitment");
+
+        let (sigs, keys, per_commitment_point) = node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                let sigs = chan.sign_all_holder_htlc_txs(commit_tx_ctx.commit_num)?;
+                let per_commitment_point =
+                    chan.get_per_commitment_point(commit_tx_ctx.commit_num)?;
+                let keys = chan.make_holder_tx_keys(&per_commitment_point)?;
+                Ok((sigs, keys, per_commitment_point))
+            })
+            .expect("sign");
+        assert_eq!(sigs.len(), 2);
+
+        let commitment_txid = commit_tx_ctx.tx.as_ref().unwrap().trust().txid();
+        let htlc_pubkey =
+            get_channel_htlc_pubkey(&node_ctx.node, &chan_ctx.channel_id, &per_commitment_point);
+        let option_anchor_outputs = chan_ctx.setup.option_anchor_outputs();
+
+        for (htlc, sig) in sigs {
+            let htlc_tx = build_htlc_transaction(
+                &commitment_txid,
+                commit_tx_ctx.feerate_per_kw,
+                chan_ctx.setup.counterparty_selected_contest_delay,
+                &htlc,
+                option_anchor_outputs,
+                &keys.broadcaster_delayed_payment_key,
+                &keys.revocation_key,
+            );
+            let htlc_redeemscript = get_htlc_redeemscript(&htlc, option_anchor_outputs, &keys);
+            check_signature(
+                &htlc_tx,
+                0,
+                TypedSignature::all(sig),
+                &htlc_pubkey,
+                htlc.amount_msat / 1000,
+                &htlc_redeemscript,
+            );
+        }
+    }
+
+    #[test]
+    fn check_counterparty_htlc_sig_test() {
+        let (node_ctx, chan_ctx) =
+            setup_funded_channel(HOLD_COMMIT_NUM, HOLD_COMMIT_NUM + 1, HOLD_COMMIT_NUM);
+
+        let mut commit_tx_ctx = setup_validated_holder_commitment(
+            &node_ctx,
+            &chan_ctx,
+            HOLD_COMMIT_NUM,
+            |_commit_tx_ctx| {},
+            |_keys| {},
+        )
+        .expect("validated holder commitment");
+
+        let (_, htlc_sigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        let htlcs = commit_tx_ctx.tx.as_ref().unwrap().htlcs().clone();
+        assert_eq!(htlcs.len(), 3);
+
+        node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                let per_commitment_point =
+                    chan.get_per_commitment_point(commit_tx_ctx.commit_num)?;
+                for (htlc, sig) in htlcs.iter().zip(htlc_sigs.iter()) {
+                    chan.check_counterparty_htlc_sig(
+                        htlc,
+                        sig,
+                        &per_commitment_point,
+                        commit_tx_ctx.commit_num,
+                    )?;
+                }
+
+                // A signature for a different HTLC must be rejected
+                assert_failed_precondition_err!(
+                    chan.check_counterparty_htlc_sig(
+                        &htlcs[0],
+                        &htlc_sigs[1],
+                        &per_commitment_point,
+                        commit_tx_ctx.commit_num,
+                    ),
+                    "policy failure: htlc sig verify failed: secp: signature failed verification"
+                );
+                Ok(())
+            })
+            .expect("check sigs");
+    }
+
+    #[test]
+    fn complete_inbound_htlc_test() {
+        let (node_ctx, chan_ctx) =
+            setup_funded_channel(HOLD_COMMIT_NUM, HOLD_COMMIT_NUM + 1, HOLD_COMMIT_NUM);
+
+        let preimage = PaymentPreimage([7; 32]);
+        let payment_hash = PaymentHash(Sha256Hash::hash(&preimage.0).into_inner());
+
+        let mut commit_tx_ctx = setup_validated_holder_commitment(
+            &node_ctx,
+            &chan_ctx,
+            HOLD_COMMIT_NUM,
+            |commit_tx_ctx| {
+                commit_tx_ctx.received_htlcs[0].payment_hash = payment_hash;
+            },
+            |_keys| {},
+        )
+        .expect("validated holder commitment");
+
+        let (_, htlc_sigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        let commitment_tx = commit_tx_ctx.tx.as_ref().unwrap();
+        let ndx = commitment_tx
+            .htlcs()
+            .iter()
+            .position(|htlc| !htlc.offered && htlc.payment_hash == payment_hash)
+            .expect("inbound htlc");
+        let htlc = commitment_tx.htlcs()[ndx].clone();
+
+        node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                let per_commitment_point =
+                    chan.get_per_commitment_point(commit_tx_ctx.commit_num)?;
+                let txkeys = chan.make_holder_tx_keys(&per_commitment_point)?;
+                let htlc_tx = build_htlc_transaction(
+                    &commitment_tx.trust().txid(),
+                    commit_tx_ctx.feerate_per_kw,
+                    chan.setup.counterparty_selected_contest_delay,
+                    &htlc,
+                    chan.setup.option_anchor_outputs(),
+                    &txkeys.broadcaster_delayed_payment_key,
+                    &txkeys.revocation_key,
+                );
+
+                let signed_tx = chan.complete_inbound_htlc(
+                    &htlc,
+                    &preimage,
+                    &htlc_tx,
+                    commit_tx_ctx.commit_num,
+                    &htlc_sigs[ndx],
+                )?;
+                let witness = &signed_tx.input[0].witness;
+                assert_eq!(witness.len(), 5);
+                assert_eq!(witness[3], preimage.0.to_vec());
+                assert_eq!(
+                    witness[4],
+                    get_htlc_redeemscript(&htlc, chan.setup.option_anchor_outputs(), &txkeys)
+                        .into_bytes()
+                );
+
+                assert_invalid_argument_err!(
+                    chan.complete_inbound_htlc(
+                        &htlc,
+                        &PaymentPreimage([8; 32]),
+                        &htlc_tx,
+                        commit_tx_ctx.commit_num,
+                        &htlc_sigs[ndx],
+                    ),
+                    format!(
+                        "complete_inbound_htlc: preimage does not match payment hash {}",
+                        payment_hash.0.to_hex()
+                    )
+                );
+                Ok(())
+            })
+            .expect("complete inbound htlc");
+    }
+
     macro_rules! generate_status_ok_variations {
         ($name: ident, $sms: expr) => {
             paste! {
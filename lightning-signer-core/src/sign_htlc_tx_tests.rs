@@ -113,6 +113,71 @@ mod tests {
         check_signature(&htlc_tx, 0, sig1, &htlc_pubkey, htlc_amount_sat, &htlc_redeemscript);
     }
 
+    #[test]
+    fn sign_local_htlc_tx_low_r_test() {
+        let mut node_config = TEST_NODE_CONFIG;
+        node_config.low_r_grinding = true;
+        let setup = make_test_channel_setup();
+        let (node, channel_id) = init_node_and_channel(node_config, TEST_SEED[1], setup.clone());
+
+        let htlc_amount_sat = 10 * 1000;
+        let commitment_txid = bitcoin::Txid::from_slice(&[2u8; 32]).unwrap();
+        let htlc = HTLCOutputInCommitment {
+            offered: true,
+            amount_msat: htlc_amount_sat * 1000,
+            cltv_expiry: 2 << 16,
+            payment_hash: PaymentHash([1; 32]),
+            transaction_output_index: Some(0),
+        };
+
+        assert_low_r(|i| {
+            let per_commitment_point = make_test_pubkey(i);
+            let (txkeys, to_self_delay) = node
+                .with_ready_channel(&channel_id, |chan| {
+                    let txkeys = chan.make_holder_tx_keys(&per_commitment_point)?;
+                    let to_self_delay =
+                        chan.make_channel_parameters().as_holder_broadcastable().contest_delay();
+                    Ok((txkeys, to_self_delay))
+                })
+                .expect("txkeys");
+
+            let htlc_tx = build_htlc_transaction(
+                &commitment_txid,
+                1000,
+                to_self_delay,
+                &htlc,
+                setup.option_anchor_outputs(),
+                &txkeys.broadcaster_delayed_payment_key,
+                &txkeys.revocation_key,
+            );
+            let htlc_redeemscript =
+                get_htlc_redeemscript(&htlc, setup.option_anchor_outputs(), &txkeys);
+            let output_witscript = get_revokeable_redeemscript(
+                &txkeys.revocation_key,
+                to_self_delay,
+                &txkeys.broadcaster_delayed_payment_key,
+            );
+
+            let sig = node
+                .with_ready_channel(&channel_id, |chan| {
+                    chan.sign_holder_htlc_tx(
+                        &htlc_tx,
+                        999,
+                        Some(per_commitment_point),
+                        &htlc_redeemscript,
+                        htlc_amount_sat,
+                        &output_witscript,
+                    )
+                })
+                .expect("sign");
+
+            let signature = sig.sig;
+            let htlc_pubkey = get_channel_htlc_pubkey(&node, &channel_id, &per_commitment_point);
+            check_signature(&htlc_tx, 0, sig, &htlc_pubkey, htlc_amount_sat, &htlc_redeemscript);
+            vec![signature]
+        });
+    }
+
     #[allow(dead_code)]
     struct ChanParamMutationState<'a> {
         is_counterparty: bool,
@@ -644,6 +709,13 @@ mod tests {
         }
     );
 
+    // policy-htlc-fee-range
+    generate_failed_precondition_error_with_mutated_tx!(
+        negative_fee,
+        |tms| tms.tx.output[0].value = 1_000_001, // htlc_amount_sat is 1_000_000
+        |_| "policy failure: HTLC fee underflow: 1000000 - 1000001"
+    );
+
     // policy-htlc-fee-range
     generate_failed_precondition_error_with_mutated_tx!(
         high_feerate,
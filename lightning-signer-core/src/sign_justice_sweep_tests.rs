@@ -1,13 +1,18 @@
 #[cfg(test)]
 mod tests {
+    use bitcoin::hashes::Hash;
     use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
     use bitcoin::{self, OutPoint, Script, Transaction, TxIn, TxOut, Txid};
-    use lightning::ln::chan_utils::get_revokeable_redeemscript;
+    use lightning::ln::chan_utils::{
+        get_htlc_redeemscript, get_revokeable_redeemscript, HTLCOutputInCommitment, TxCreationKeys,
+    };
+    use lightning::ln::PaymentHash;
     use test_log::test;
 
-    use crate::channel::{Channel, ChannelBase, CommitmentType, TypedSignature};
+    use crate::channel::{Channel, ChannelBase, ChannelId, CommitmentType, TypedSignature};
+    use crate::node::Node;
     use crate::node::SpendType::{P2shP2wpkh, P2wpkh};
-    use crate::policy::validator::ChainState;
+    use crate::policy::validator::{ChainState, COUNTERPARTY_POINT_HISTORY_LEN};
     use crate::util::crypto_utils::{
         derive_private_revocation_key, derive_public_key, derive_revocation_pubkey,
     };
@@ -358,4 +363,191 @@ mod tests {
              fee above maximum: 1978997 > 200000"
         );
     }
+
+    fn make_test_revoked_scripts(
+        node: &Node,
+        channel_id: &ChannelId,
+        revoked_point: &PublicKey,
+        to_self_delay: u16,
+    ) -> (Script, Script) {
+        let revocation_pubkey = get_channel_revocation_pubkey(node, channel_id, revoked_point);
+        let to_local_script =
+            get_revokeable_redeemscript(&revocation_pubkey, to_self_delay, &make_test_pubkey(41));
+        let keys = TxCreationKeys {
+            per_commitment_point: *revoked_point,
+            revocation_key: revocation_pubkey,
+            broadcaster_htlc_key: make_test_pubkey(42),
+            countersignatory_htlc_key: make_test_pubkey(43),
+            broadcaster_delayed_payment_key: make_test_pubkey(41),
+        };
+        let htlc = HTLCOutputInCommitment {
+            offered: true,
+            amount_msat: 1_000_000_000,
+            cltv_expiry: 0,
+            payment_hash: PaymentHash([1; 32]),
+            transaction_output_index: Some(1),
+        };
+        (to_local_script, get_htlc_redeemscript(&htlc, false, &keys))
+    }
+
+    #[test]
+    fn sign_justice_tx_all_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let node_ctx = TestNodeContext { node: node.clone(), secp_ctx: Secp256k1::signing_only() };
+        let (dest_script, wallet_path) = make_test_wallet_dest(&node_ctx, 19, P2wpkh);
+
+        let (revoked_point, revoked_secret) = make_test_key(30);
+        let (current_point, current_secret) = make_test_key(31);
+        let (unknown_point, unknown_secret) = make_test_key(32);
+
+        let txid = Txid::from_slice(&[3; 32]).unwrap();
+        let mut tx = make_test_justice_sweep_tx(txid, 4, dest_script, 2_900_000);
+        tx.input.push(TxIn {
+            previous_output: OutPoint { txid, vout: 2 },
+            script_sig: Script::new(),
+            sequence: 0x_ffff_ffff_u32,
+            witness: vec![],
+        });
+        let (to_local_script, htlc_script) = make_test_revoked_scripts(
+            &node,
+            &channel_id,
+            &revoked_point,
+            setup.holder_selected_contest_delay,
+        );
+        let per_output = vec![(0, to_local_script, 1_979_997), (1, htlc_script, 1_000_000)];
+        let unknown_pubkey = get_channel_revocation_pubkey(&node, &channel_id, &unknown_point);
+
+        let sigs = node
+            .with_ready_channel(&channel_id, |chan| {
+                chan.enforcement_state.counterparty_point_history =
+                    vec![(22, revoked_point), (23, current_point)];
+                chan.enforcement_state
+                    .set_next_counterparty_commit_num_for_testing(24, current_point);
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(23);
+
+                // commitment 23 is not revoked yet
+                assert_failed_precondition_err!(
+                    chan.sign_justice_tx_all(&tx, 23, &current_secret, &per_output, &wallet_path),
+                    "policy failure: sign_justice_tx_all: counterparty commitment 23 \
+                     is not revoked, next revoke num is 23"
+                );
+                assert_failed_precondition_err!(
+                    chan.sign_justice_tx_all(&tx, 21, &revoked_secret, &per_output, &wallet_path),
+                    format!(
+                        "policy failure: sign_justice_tx_all: revocation secret for point {} \
+                         is for commitment 22, not 21",
+                        revoked_point
+                    )
+                );
+                assert_failed_precondition_err!(
+                    chan.sign_justice_tx_all(&tx, 22, &unknown_secret, &per_output, &wallet_path),
+                    format!(
+                        "policy failure: sign_justice_tx_all: redeemscript for input 0 \
+                         does not pay to revocation pubkey {}",
+                        unknown_pubkey
+                    )
+                );
+                assert_invalid_argument_err!(
+                    chan.sign_justice_tx_all(
+                        &tx,
+                        22,
+                        &revoked_secret,
+                        &[(2, Script::new(), 1_000)],
+                        &wallet_path
+                    ),
+                    "sign_justice_tx_all: bad input index: 2 >= 2"
+                );
+
+                chan.sign_justice_tx_all(&tx, 22, &revoked_secret, &per_output, &wallet_path)
+            })
+            .expect("sign justice tx");
+
+        assert_eq!(sigs.len(), 2);
+        let pubkey = get_channel_revocation_pubkey(&node, &channel_id, &revoked_point);
+        for ((input, redeemscript, amount_sat), sig) in per_output.iter().zip(sigs) {
+            check_signature(
+                &tx,
+                *input,
+                TypedSignature::all(sig),
+                &pubkey,
+                *amount_sat,
+                redeemscript,
+            );
+        }
+    }
+
+    #[test]
+    fn sign_justice_tx_all_old_state_test() {
+        let setup = make_test_channel_setup();
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], setup.clone());
+        let node_ctx = TestNodeContext { node: node.clone(), secp_ctx: Secp256k1::signing_only() };
+        let (dest_script, wallet_path) = make_test_wallet_dest(&node_ctx, 19, P2wpkh);
+
+        let (revoked_point, revoked_secret) = make_test_key(30);
+        let (current_point, _) = make_test_key(31);
+
+        let txid = Txid::from_slice(&[3; 32]).unwrap();
+        let mut tx = make_test_justice_sweep_tx(txid, 4, dest_script, 2_900_000);
+        tx.input.push(TxIn {
+            previous_output: OutPoint { txid, vout: 2 },
+            script_sig: Script::new(),
+            sequence: 0x_ffff_ffff_u32,
+            witness: vec![],
+        });
+        let (to_local_script, htlc_script) = make_test_revoked_scripts(
+            &node,
+            &channel_id,
+            &revoked_point,
+            setup.holder_selected_contest_delay,
+        );
+        let per_output = vec![(0, to_local_script, 1_979_997), (1, htlc_script, 1_000_000)];
+
+        // Commitment 3 is far older than the point history reaches
+        let sigs = node
+            .with_ready_channel(&channel_id, |chan| {
+                let history_len = COUNTERPARTY_POINT_HISTORY_LEN as u64;
+                chan.enforcement_state.counterparty_point_history = (100 - history_len..100)
+                    .map(|num| (num, make_test_pubkey(60 + num as u8)))
+                    .collect();
+                chan.enforcement_state
+                    .set_next_counterparty_commit_num_for_testing(101, current_point);
+                chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(100);
+                assert!(chan
+                    .enforcement_state
+                    .get_counterparty_point_commit_num(&revoked_point)
+                    .is_none());
+                chan.sign_justice_tx_all(&tx, 3, &revoked_secret, &per_output, &wallet_path)
+            })
+            .expect("sign justice tx");
+
+        let pubkey = get_channel_revocation_pubkey(&node, &channel_id, &revoked_point);
+        for ((input, redeemscript, amount_sat), sig) in per_output.iter().zip(sigs) {
+            check_signature(
+                &tx,
+                *input,
+                TypedSignature::all(sig),
+                &pubkey,
+                *amount_sat,
+                redeemscript,
+            );
+        }
+    }
+
+    #[test]
+    fn compute_revocation_private_key_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let secp_ctx = Secp256k1::new();
+        let (per_commitment_point, per_commitment_secret) = make_test_key(42);
+        node.with_ready_channel(&channel_id, |chan| {
+            let privkey = chan.compute_revocation_private_key(&per_commitment_secret)?;
+            let expected_pubkey = chan.get_revocation_pubkey(&per_commitment_point)?;
+            assert_eq!(PublicKey::from_secret_key(&secp_ctx, &privkey), expected_pubkey);
+            Ok(())
+        })
+        .expect("channel");
+    }
 }
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
     use std::mem;
+    use std::str::FromStr;
 
     use bitcoin::hashes::hex::FromHex;
     use bitcoin::secp256k1;
@@ -14,7 +15,7 @@ mod tests {
     use test_log::test;
 
     use crate::channel::{Channel, ChannelBase, ChannelId, ChannelSetup, TypedSignature};
-    use crate::node::Node;
+    use crate::node::{ChannelStateCounts, Node};
     use crate::sync::Arc;
     use crate::tx::tx::{CommitmentInfo2, HTLCInfo2};
     use crate::util::key_utils::*;
@@ -198,7 +199,7 @@ mod tests {
             Ok((tx.clone(), sig))
         })?;
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
 
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
@@ -330,7 +331,7 @@ mod tests {
         let trusted = closing_tx.trust();
         let tx = trusted.built_transaction();
 
-        let funding_pubkey = get_channel_funding_pubkey(&node, &channel_id);
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
 
         let channel_funding_redeemscript =
             make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
@@ -633,6 +634,224 @@ mod tests {
         ));
     }
 
+    // policy-mutual-destination-allowlisted
+    #[test]
+    fn sign_mutual_close_tx_phase2_to_allowlisted_p2wsh_success() {
+        assert_status_ok!(sign_mutual_close_tx_phase2_with_mutators_outbound!(
+            |_chan,
+             _to_holder,
+             _to_counterparty,
+             holder_script,
+             _counter_script,
+             _outpoint,
+             wallet_path,
+             allowlist| {
+                // Close to a 2-of-2 cold storage multisig
+                let multisig =
+                    make_funding_redeemscript(&make_test_pubkey(1), &make_test_pubkey(2));
+                let address = Address::p2wsh(&multisig, Network::Testnet);
+                *holder_script = address.script_pubkey();
+                *wallet_path = vec![];
+                allowlist.push(address.to_string());
+            },
+            |chan| {
+                // Channel should be marked closed
+                assert_eq!(chan.enforcement_state.mutual_close_signed, true);
+                let node = chan.node.upgrade().unwrap();
+                let multisig =
+                    make_funding_redeemscript(&make_test_pubkey(1), &make_test_pubkey(2));
+                let address = Address::p2wsh(&multisig, Network::Testnet);
+                assert_eq!(node.allowlist().unwrap(), vec![format!("address:{}", address)]);
+            }
+        ));
+    }
+
+    // policy-mutual-destination-allowlisted
+    #[test]
+    fn sign_mutual_close_tx_to_transient_script_success() {
+        let (
+            _secp_ctx,
+            _setup,
+            node,
+            channel_id,
+            _holder_commit_num,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            _holder_wallet_path_hint,
+            _counterparty_points,
+        ) = setup_mutual_close_tx(true).expect("setup");
+
+        let holder_script = Address::from_str("tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07")
+            .expect("address")
+            .script_pubkey();
+        let counterparty_script = Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+            .expect("script_pubkey");
+
+        assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_mutual_close_tx_to(
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                &Some(holder_script.clone()),
+                &Some(counterparty_script.clone()),
+                &vec![],
+                &[holder_script.clone()],
+            )?;
+            assert_eq!(chan.enforcement_state.mutual_close_signed, true);
+            Ok(())
+        }));
+
+        // The transient script was not added to the allowlist.
+        assert!(node.allowlist().expect("allowlist").is_empty());
+    }
+
+    #[test]
+    fn build_mutual_close_tx_then_sign_success() {
+        let (
+            secp_ctx,
+            setup,
+            node,
+            channel_id,
+            _holder_commit_num,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_wallet_path_hint,
+            counterparty_points,
+        ) = setup_mutual_close_tx(true).expect("setup");
+
+        let holder_script = Address::p2wpkh(
+            &node.get_wallet_pubkey(&secp_ctx, &holder_wallet_path_hint).unwrap(),
+            Network::Testnet,
+        )
+        .expect("Address")
+        .script_pubkey();
+        let counterparty_script = Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+            .expect("script_pubkey");
+
+        let (tx, sig) = node
+            .with_ready_channel(&channel_id, |chan| {
+                let tx = chan.build_mutual_close_tx(
+                    to_holder_value_sat,
+                    to_counterparty_value_sat,
+                    &Some(holder_script.clone()),
+                    &Some(counterparty_script.clone()),
+                    &holder_wallet_path_hint,
+                )?;
+                let opaths = tx
+                    .output
+                    .iter()
+                    .map(|out| {
+                        if out.script_pubkey == holder_script {
+                            holder_wallet_path_hint.clone()
+                        } else {
+                            vec![]
+                        }
+                    })
+                    .collect();
+                let sig = chan.sign_mutual_close_tx(&tx, &opaths)?;
+                Ok((tx, sig))
+            })
+            .expect("build and sign");
+
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, setup.funding_outpoint);
+
+        let funding_pubkey = node.get_funding_pubkey(&channel_id).expect("funding pubkey");
+        let channel_funding_redeemscript =
+            make_funding_redeemscript(&funding_pubkey, &counterparty_points.funding_pubkey);
+        check_signature(
+            &tx,
+            0,
+            TypedSignature::all(sig),
+            &funding_pubkey,
+            setup.channel_value_sat,
+            &channel_funding_redeemscript,
+        );
+    }
+
+    #[test]
+    fn build_mutual_close_tx_with_fee_too_large() {
+        let (
+            _secp_ctx,
+            _setup,
+            node,
+            channel_id,
+            _holder_commit_num,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            _holder_wallet_path_hint,
+            _counterparty_points,
+        ) = setup_mutual_close_tx(true).expect("setup");
+
+        let holder_script = Address::from_str("tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07")
+            .expect("address")
+            .script_pubkey();
+        node.add_allowlist(&vec!["tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07".to_string()])
+            .expect("add_allowlist");
+        let counterparty_script = Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+            .expect("script_pubkey");
+
+        let rv = node.with_ready_channel(&channel_id, |chan| {
+            chan.build_mutual_close_tx(
+                to_holder_value_sat - 50_000,
+                to_counterparty_value_sat,
+                &Some(holder_script.clone()),
+                &Some(counterparty_script.clone()),
+                &vec![],
+            )
+        });
+        assert!(rv.is_err());
+        assert_eq!(rv.unwrap_err().code(), Code::FailedPrecondition);
+    }
+
+    #[test]
+    fn sign_closing_tx_from_shutdown_scripts_success() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let holder_addr = "tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07";
+        node.add_allowlist(&vec![holder_addr.to_string()]).expect("add_allowlist");
+        let holder_script = Address::from_str(holder_addr).expect("address").script_pubkey();
+        let counterparty_script = Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+            .expect("script_pubkey");
+
+        assert_status_ok!(node.sign_closing_tx_from_shutdown_scripts(
+            &channel_id,
+            2_000_000,
+            999_000,
+            &Some(holder_script),
+            &Some(counterparty_script),
+            &vec![],
+        ));
+        node.with_ready_channel(&channel_id, |chan| {
+            assert_eq!(chan.enforcement_state.mutual_close_signed, true);
+            Ok(())
+        })
+        .expect("channel");
+    }
+
+    #[test]
+    fn sign_closing_tx_from_shutdown_scripts_no_fee() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let holder_addr = "tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07";
+        node.add_allowlist(&vec![holder_addr.to_string()]).expect("add_allowlist");
+        let holder_script = Address::from_str(holder_addr).expect("address").script_pubkey();
+        let counterparty_script = Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+            .expect("script_pubkey");
+
+        assert_failed_precondition_err!(
+            node.sign_closing_tx_from_shutdown_scripts(
+                &channel_id,
+                2_000_000,
+                1_000_000,
+                &Some(holder_script.clone()),
+                &Some(counterparty_script.clone()),
+                &vec![],
+            ),
+            "policy failure: validate_mutual_close_tx_without_commitments: \
+             outputs 3000000 exceed channel value 3000000 minus minimum fee 100"
+        );
+    }
+
     // policy-mutual-destination-allowlisted
     #[test]
     fn sign_mutual_close_tx_phase2_no_wallet_path_or_allowlist() {
@@ -738,6 +957,31 @@ mod tests {
         );
     }
 
+    // policy-mutual-fee-range
+    #[test]
+    fn sign_mutual_close_tx_phase2_with_outputs_exceeding_channel_value() {
+        assert_failed_precondition_err!(
+            sign_mutual_close_tx_phase2_with_mutators_outbound!(
+                |_chan,
+                 to_holder,
+                 _to_counterparty,
+                 _holder_script,
+                 _counter_script,
+                 _outpoint,
+                 _wallet_path,
+                 _allowlist| {
+                    // The outputs sum to more than the channel value
+                    *to_holder += 10_000;
+                },
+                |chan| {
+                    // Channel should not be marked closed
+                    assert_eq!(chan.enforcement_state.mutual_close_signed, false);
+                }
+            ),
+            "policy failure: validate_mutual_close_tx: fee underflow: 3000000 - 3008000"
+        );
+    }
+
     #[test]
     fn sign_mutual_close_tx_with_bad_num_txout() {
         assert_failed_precondition_err!(
@@ -915,6 +1159,106 @@ mod tests {
         );
     }
 
+    #[test]
+    fn count_channels_by_state_test() {
+        let (
+            secp_ctx,
+            setup,
+            node,
+            channel_id,
+            _,
+            to_holder_value_sat,
+            to_counterparty_value_sat,
+            holder_wallet_path_hint,
+            _,
+        ) = setup_mutual_close_tx(true).unwrap();
+        let counts = |stub_count, ready_count, mutual_close_signed_count| ChannelStateCounts {
+            stub_count,
+            ready_count,
+            mutual_close_signed_count,
+        };
+        assert_eq!(node.count_channels_by_state(), counts(0, 1, 0));
+
+        let (stub_id, _) = node.new_channel(None, None, &node).unwrap();
+        assert_eq!(node.count_channels_by_state(), counts(1, 1, 0));
+
+        // An alternate ID doesn't count twice
+        let mut setup2 = setup.clone();
+        setup2.funding_outpoint.vout = 1;
+        node.ready_channel(stub_id, Some(ChannelId([42; 32])), setup2, &vec![]).unwrap();
+        assert_eq!(node.count_channels_by_state(), counts(0, 2, 0));
+
+        node.with_ready_channel(&channel_id, |chan| {
+            let holder_shutdown_script = Address::p2wpkh(
+                &node.get_wallet_pubkey(&secp_ctx, &holder_wallet_path_hint).unwrap(),
+                Network::Testnet,
+            )
+            .expect("Address")
+            .script_pubkey();
+            let counterparty_shutdown_script =
+                Script::from_hex("0014be56df7de366ad8ee9ccdad54e9a9993e99ef565")
+                    .expect("script_pubkey");
+            let closing_tx = ClosingTransaction::new(
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                holder_shutdown_script,
+                counterparty_shutdown_script,
+                setup.funding_outpoint,
+            );
+            let tx = closing_tx.trust().built_transaction().clone();
+            chan.sign_mutual_close_tx(&tx, &vec![vec![], holder_wallet_path_hint.clone()])
+        })
+        .expect("sign mutual close");
+        assert_eq!(node.count_channels_by_state(), counts(0, 2, 1));
+    }
+
+    #[test]
+    fn has_pending_htlcs_test() {
+        let (_, _, node, channel_id, _, _, _, _, _) = setup_mutual_close_tx(true).unwrap();
+        let htlc =
+            HTLCInfo2 { value_sat: 1, payment_hash: PaymentHash([1; 32]), cltv_expiry: 2 << 16 };
+        node.with_ready_channel(&channel_id, |chan| {
+            assert!(!chan.has_pending_htlcs());
+            let estate = &mut chan.enforcement_state;
+            let holder_info = estate.current_holder_commit_info.clone();
+            let counterparty_info = estate.current_counterparty_commit_info.clone();
+
+            // holder commitment, offered
+            estate.current_holder_commit_info.as_mut().unwrap().offered_htlcs.push(htlc.clone());
+            assert!(chan.has_pending_htlcs());
+            chan.enforcement_state.current_holder_commit_info = holder_info.clone();
+
+            // holder commitment, received
+            let estate = &mut chan.enforcement_state;
+            estate.current_holder_commit_info.as_mut().unwrap().received_htlcs.push(htlc.clone());
+            assert!(chan.has_pending_htlcs());
+            chan.enforcement_state.current_holder_commit_info = holder_info.clone();
+
+            // counterparty commitment, offered
+            let estate = &mut chan.enforcement_state;
+            let cinfo = estate.current_counterparty_commit_info.as_mut().unwrap();
+            cinfo.offered_htlcs.push(htlc.clone());
+            assert!(chan.has_pending_htlcs());
+            chan.enforcement_state.current_counterparty_commit_info = counterparty_info.clone();
+
+            // counterparty commitment, received
+            let estate = &mut chan.enforcement_state;
+            let cinfo = estate.current_counterparty_commit_info.as_mut().unwrap();
+            cinfo.received_htlcs.push(htlc.clone());
+            assert!(chan.has_pending_htlcs());
+            chan.enforcement_state.current_counterparty_commit_info = counterparty_info.clone();
+
+            assert!(!chan.has_pending_htlcs());
+
+            // missing commitment info means nothing is known to be pending
+            chan.enforcement_state.current_holder_commit_info = None;
+            chan.enforcement_state.current_counterparty_commit_info = None;
+            assert!(!chan.has_pending_htlcs());
+            Ok(())
+        })
+        .expect("pending htlcs");
+    }
+
     // policy-mutual-no-pending-htlcs
     #[test]
     fn sign_mutual_close_tx_with_holder_offered_htlcs() {
@@ -12,6 +12,10 @@ mod tests {
 
     use crate::channel::CommitmentType;
     use crate::node::SpendType;
+    use crate::policy::simple_validator::{make_simple_policy, SimpleValidatorFactory};
+    use crate::policy::validator::FundingTxPolicy;
+    use crate::sync::Arc;
+    use crate::util::key_utils::make_test_pubkey;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
 
@@ -31,14 +35,14 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid: Default::default(), vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
         let input2 = TxIn {
             previous_output: OutPoint { txid: Default::default(), vout: 1 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
         let (opath, mut tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1, input2], chanamt);
@@ -82,7 +86,7 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid, vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
@@ -127,7 +131,7 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid, vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
@@ -149,6 +153,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sign_funding_tx_mismatched_lengths() {
+        let secp_ctx = Secp256k1::signing_only();
+        let node = init_node(TEST_NODE_CONFIG, TEST_SEED[0]);
+        let input1 = TxIn {
+            previous_output: OutPoint { txid: Default::default(), vout: 0 },
+            script_sig: Script::new(),
+            sequence: u32::MAX,
+            witness: vec![],
+        };
+        let (opath, tx) = make_test_funding_tx(&secp_ctx, &node, vec![input1], 100);
+        let ipaths = vec![vec![0u32]];
+        let values_sat = vec![200u64];
+        let spendtypes = vec![SpendType::P2wpkh];
+        let uniclosekeys = vec![None];
+        let opaths = vec![opath];
+
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &vec![],
+                &values_sat,
+                &spendtypes,
+                uniclosekeys.clone(),
+                &opaths
+            ),
+            "sign_onchain_tx: bad ipaths len 0 with tx.input len 1"
+        );
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &vec![200, 300],
+                &spendtypes,
+                uniclosekeys.clone(),
+                &opaths
+            ),
+            "sign_onchain_tx: bad values_sat len 2 with tx.input len 1"
+        );
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(&tx, &ipaths, &values_sat, &vec![], uniclosekeys.clone(), &opaths),
+            "sign_onchain_tx: bad spendtypes len 0 with tx.input len 1"
+        );
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(&tx, &ipaths, &values_sat, &spendtypes, vec![], &opaths),
+            "sign_onchain_tx: bad uniclosekeys len 0 with tx.input len 1"
+        );
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys.clone(),
+                &vec![]
+            ),
+            "sign_onchain_tx: bad opath len 0 with tx.output len 1"
+        );
+        assert_invalid_argument_err!(
+            node.sign_onchain_tx(
+                &tx,
+                &ipaths,
+                &values_sat,
+                &spendtypes,
+                uniclosekeys.clone(),
+                &vec![opaths[0].clone(), vec![]]
+            ),
+            "sign_onchain_tx: bad opath len 2 with tx.output len 1"
+        );
+    }
+
     #[test]
     fn sign_funding_tx_unilateral_close_info_test() -> Result<(), ()> {
         let secp_ctx = Secp256k1::signing_only();
@@ -162,7 +237,7 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid, vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
@@ -212,7 +287,7 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid, vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
@@ -255,7 +330,7 @@ mod tests {
         let input1 = TxIn {
             previous_output: OutPoint { txid, vout: 0 },
             script_sig: Script::new(),
-            sequence: 0,
+            sequence: u32::MAX,
             witness: vec![],
         };
 
@@ -317,19 +392,19 @@ mod tests {
             TxIn {
                 previous_output: OutPoint { txid: txids[0], vout: 0 },
                 script_sig: Script::new(),
-                sequence: 0,
+                sequence: u32::MAX,
                 witness: vec![],
             },
             TxIn {
                 previous_output: OutPoint { txid: txids[1], vout: 0 },
                 script_sig: Script::new(),
-                sequence: 0,
+                sequence: u32::MAX,
                 witness: vec![],
             },
             TxIn {
                 previous_output: OutPoint { txid: txids[2], vout: 0 },
                 script_sig: Script::new(),
-                sequence: 0,
+                sequence: u32::MAX,
                 witness: vec![],
             },
         ];
@@ -361,6 +436,7 @@ mod tests {
 
     #[allow(dead_code)]
     struct FundingTxMutationState<'a> {
+        node_ctx: &'a TestNodeContext,
         chan_ctx: &'a mut TestChannelContext,
         tx_ctx: &'a mut TestFundingTxContext,
         tx: &'a mut Transaction,
@@ -397,6 +473,7 @@ mod tests {
         let mut tx = funding_tx_from_ctx(&tx_ctx);
 
         mutate_funding_tx(&mut FundingTxMutationState {
+            node_ctx: &node_ctx,
             chan_ctx: &mut chan_ctx,
             tx_ctx: &mut tx_ctx,
             tx: &mut tx,
@@ -455,6 +532,94 @@ mod tests {
         );
     }
 
+    // policy-onchain-funding-non-rbf
+    #[test]
+    fn rbf_sequence_rejected() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                fms.tx.input[1].sequence = 0xffff_fffd;
+            }),
+            "policy failure: validate_onchain_tx: funding input[1] signals RBF: sequence 0xfffffffd"
+        );
+    }
+
+    #[test]
+    fn final_sequence_with_locktime() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            fms.tx.input[0].sequence = 0xffff_fffe;
+        }));
+    }
+
+    fn enable_anti_fee_sniping(fms: &mut FundingTxMutationState, height: u32) {
+        let mut policy = make_simple_policy(Network::Testnet);
+        policy.require_funding_anti_fee_sniping = true;
+        let node = &fms.node_ctx.node;
+        node.set_validator_factory(Arc::new(SimpleValidatorFactory::new_with_policy(policy)));
+        node.notify_new_block(height, [0; 32]).unwrap();
+    }
+
+    // policy-onchain-funding-anti-fee-sniping
+    #[test]
+    fn anti_fee_sniping_zero_locktime() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                enable_anti_fee_sniping(fms, 700_000);
+            }),
+            "policy failure: validate_onchain_tx: funding locktime is zero at height 700000"
+        );
+    }
+
+    // policy-onchain-funding-anti-fee-sniping
+    #[test]
+    fn anti_fee_sniping_stale_locktime() {
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                enable_anti_fee_sniping(fms, 700_000);
+                fms.tx.lock_time = 699_899;
+            }),
+            "policy failure: validate_onchain_tx: funding locktime 699899 too far from height 700000"
+        );
+    }
+
+    #[test]
+    fn anti_fee_sniping_current_locktime() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            enable_anti_fee_sniping(fms, 700_000);
+            fms.tx.lock_time = 699_900;
+        }));
+    }
+
+    #[test]
+    fn anti_fee_sniping_disabled_by_default() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            fms.node_ctx.node.notify_new_block(700_000, [0; 32]).unwrap();
+        }));
+    }
+
+    fn set_feerate_floor(fms: &mut FundingTxMutationState, floor: u64) {
+        let policy = FundingTxPolicy { feerate_floor_per_vbyte: Some(floor) };
+        fms.node_ctx.node.set_funding_tx_policy(policy);
+    }
+
+    // policy-onchain-funding-feerate-floor
+    #[test]
+    fn feerate_below_floor() {
+        // The 1000 sat fee is about 3.5 sat/vbyte
+        assert_failed_precondition_err!(
+            sign_funding_tx_with_mutator(|fms| {
+                set_feerate_floor(fms, 4);
+            }),
+            "policy failure: validate_onchain_tx: funding feerate 3 sat/vbyte is below floor 4"
+        );
+    }
+
+    #[test]
+    fn feerate_above_floor() {
+        assert_status_ok!(sign_funding_tx_with_mutator(|fms| {
+            set_feerate_floor(fms, 3);
+        }));
+    }
+
     #[test]
     fn wallet_cannot_spend() {
         assert_failed_precondition_err!(
@@ -734,6 +899,59 @@ mod tests {
         funding_tx_validate_sig(&node_ctx, &tx_ctx, &mut tx, &witvec);
     }
 
+    #[test]
+    fn sign_funding_tx_concurrent_with_all_ready_channels() {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+
+        let incoming = 10_000_000;
+        let channel_amount = 3_000_000;
+        let fee = 1000;
+        let change = incoming - 2 * channel_amount - fee;
+
+        // Fund the channels in the reverse of channel ID order, so that
+        // sign_onchain_tx locks them in a different order than
+        // with_all_ready_channels does.
+        let mut chan_ctxs = vec![
+            test_chan_ctx(&node_ctx, 1, channel_amount),
+            test_chan_ctx(&node_ctx, 2, channel_amount),
+        ];
+        chan_ctxs.sort_by(|a, b| b.channel_id.cmp(&a.channel_id));
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndxs: Vec<u32> = chan_ctxs
+            .iter()
+            .map(|chan_ctx| {
+                funding_tx_add_channel_outpoint(&node_ctx, chan_ctx, &mut tx_ctx, channel_amount)
+            })
+            .collect();
+
+        let tx = funding_tx_from_ctx(&tx_ctx);
+
+        for (chan_ctx, outpoint_ndx) in chan_ctxs.iter_mut().zip(outpoint_ndxs) {
+            funding_tx_ready_channel(&node_ctx, chan_ctx, &tx, outpoint_ndx);
+            let mut commit_tx_ctx = channel_initial_holder_commitment(&node_ctx, chan_ctx);
+            let (csig, hsigs) =
+                counterparty_sign_holder_commitment(&node_ctx, chan_ctx, &mut commit_tx_ctx);
+            validate_holder_commitment(&node_ctx, chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+                .expect("valid holder commitment");
+        }
+
+        let node = Arc::clone(&node_ctx.node);
+        let thread = std::thread::spawn(move || {
+            for _ in 0..100 {
+                let results = node.with_all_ready_channels(|_chan| Ok(()));
+                assert_eq!(results.len(), 2);
+            }
+        });
+        for _ in 0..100 {
+            funding_tx_sign(&node_ctx, &tx_ctx, &tx).expect("witvec");
+        }
+        thread.join().unwrap();
+    }
+
     // policy-onchain-initial-commitment-countersigned
     #[test]
     fn sign_funding_tx_with_missing_initial_commitment_validation() {
@@ -810,14 +1028,60 @@ mod tests {
         validate_holder_commitment(&node_ctx, &chan_ctx, &commit_tx_ctx, &csig, &hsigs)
             .expect("valid holder commitment");
 
-        // Because the channel isn't found the output is considered non-beneficial.
         assert_failed_precondition_err!(
             funding_tx_sign(&node_ctx, &tx_ctx, &tx),
-            "policy failure: validate_onchain_tx: \
-             validate_beneficial_value: non-beneficial value above maximum: 501000 > 200000"
+            "policy failure: validate_onchain_tx: change output[1] not to wallet or allowlist"
         );
     }
 
+    // policy-onchain-change-to-wallet
+    #[test]
+    fn sign_funding_tx_with_third_party_change() {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+
+        let incoming = 5_000_000;
+        let channel_amount = 3_000_000;
+        // Small enough to pass as non-beneficial value
+        let third_party = 10_000;
+        let fee = 1000;
+        let change = incoming - channel_amount - third_party - fee;
+
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, channel_amount);
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndx =
+            funding_tx_add_channel_outpoint(&node_ctx, &chan_ctx, &mut tx_ctx, channel_amount);
+        let address = Address::p2wpkh(
+            &bitcoin::PublicKey { compressed: true, key: make_test_pubkey(42) },
+            Network::Testnet,
+        )
+        .unwrap();
+        tx_ctx.outputs.push(TxOut { value: third_party, script_pubkey: address.script_pubkey() });
+        tx_ctx.opaths.push(vec![]);
+
+        let tx = funding_tx_from_ctx(&tx_ctx);
+
+        funding_tx_ready_channel(&node_ctx, &mut chan_ctx, &tx, outpoint_ndx);
+
+        let mut commit_tx_ctx = channel_initial_holder_commitment(&node_ctx, &chan_ctx);
+        let (csig, hsigs) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        validate_holder_commitment(&node_ctx, &chan_ctx, &commit_tx_ctx, &csig, &hsigs)
+            .expect("valid holder commitment");
+
+        assert_failed_precondition_err!(
+            funding_tx_sign(&node_ctx, &tx_ctx, &tx),
+            "policy failure: validate_onchain_tx: change output[2] not to wallet or allowlist"
+        );
+
+        // Allowlisting the destination makes it acceptable change
+        node_ctx.node.add_allowlist(&vec![address.to_string()]).expect("add_allowlist");
+        assert_status_ok!(funding_tx_sign(&node_ctx, &tx_ctx, &tx));
+    }
+
     #[test]
     fn sign_funding_tx_with_bad_input_path() {
         let is_p2sh = false;
@@ -1023,6 +1287,39 @@ mod tests {
         );
     }
 
+    // policy-onchain-output-scriptpubkey
+    #[test]
+    fn sign_funding_tx_with_wrong_counterparty_funding_pubkey() {
+        let is_p2sh = false;
+        let node_ctx = test_node_ctx(1);
+
+        let incoming = 5_000_000;
+        let channel_amount = 3_000_000;
+        let fee = 1000;
+        let change = incoming - channel_amount - fee;
+
+        let mut chan_ctx = test_chan_ctx(&node_ctx, 1, channel_amount);
+        let mut tx_ctx = test_funding_tx_ctx();
+
+        funding_tx_add_wallet_input(&mut tx_ctx, is_p2sh, 1, incoming);
+        funding_tx_add_wallet_output(&node_ctx, &mut tx_ctx, is_p2sh, 1, change);
+        let outpoint_ndx =
+            funding_tx_add_channel_outpoint(&node_ctx, &chan_ctx, &mut tx_ctx, channel_amount);
+
+        let tx = funding_tx_from_ctx(&tx_ctx);
+
+        // The funding output was built for the real counterparty funding
+        // pubkey, but the channel is readied with a different one
+        chan_ctx.setup.counterparty_points.funding_pubkey = make_test_pubkey(99);
+        funding_tx_ready_channel(&node_ctx, &mut chan_ctx, &tx, outpoint_ndx);
+
+        let err = funding_tx_sign(&node_ctx, &tx_ctx, &tx).unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(err.message().starts_with(
+            "policy failure: validate_onchain_tx: funding script_pubkey mismatch w/ channel"
+        ));
+    }
+
     #[test]
     fn sign_funding_tx_with_bad_push_val() {
         let is_p2sh = false;
@@ -0,0 +1,327 @@
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::{Address, OutPoint, Script, Transaction, TxIn, TxOut};
+    use lightning::ln::chan_utils::make_funding_redeemscript;
+    use test_log::test;
+
+    use crate::channel::{Channel, ChannelId};
+    use crate::node::Node;
+    use crate::sync::Arc;
+    use crate::util::key_utils::make_test_pubkey;
+    use crate::util::status::{Code, Status};
+    use crate::util::test_utils::*;
+    use crate::wallet::Wallet;
+
+    fn make_splice_tx(node: &Node, channel_id: &ChannelId, new_value_sat: u64) -> Transaction {
+        let setup = make_test_channel_setup();
+        let funding_pubkey = node.get_funding_pubkey(channel_id).expect("funding pubkey");
+        let redeemscript =
+            make_funding_redeemscript(&funding_pubkey, &setup.counterparty_points.funding_pubkey);
+        let wallet_script = node.get_native_address(&vec![1]).expect("address").script_pubkey();
+        make_test_funding_tx_with_ins_outs(
+            vec![TxIn {
+                previous_output: setup.funding_outpoint,
+                script_sig: Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            vec![
+                TxOut { value: new_value_sat, script_pubkey: redeemscript.to_v0_p2wsh() },
+                TxOut { value: 1_000_000, script_pubkey: wallet_script },
+            ],
+        )
+    }
+
+    fn wallet_opaths() -> Vec<Vec<u32>> {
+        vec![vec![], vec![1]]
+    }
+
+    fn sign_splice_with_mutator<F>(
+        opaths: Vec<Vec<u32>>,
+        new_value_sat: u64,
+        to_holder_value_sat: u64,
+        holder_reduction_sat: u64,
+        mutate_tx: F,
+    ) -> Result<(Arc<Node>, ChannelId, Transaction), Status>
+    where
+        F: Fn(&mut Transaction),
+    {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut tx = make_splice_tx(&node, &channel_id, new_value_sat);
+        mutate_tx(&mut tx);
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_splice_tx(
+                &tx,
+                &opaths,
+                0,
+                new_value_sat,
+                to_holder_value_sat,
+                holder_reduction_sat,
+            )
+        })?;
+        Ok((node, channel_id, tx))
+    }
+
+    #[test]
+    fn sign_splice_tx_splice_in_test() {
+        let (node, channel_id, tx) =
+            sign_splice_with_mutator(wallet_opaths(), 4_000_000, 4_000_000, 0, |_tx| {})
+                .expect("splice");
+        let new_outpoint = OutPoint { txid: tx.txid(), vout: 0 };
+        node.with_ready_channel(&channel_id, |chan| {
+            // The channel keeps the current funding output until the splice confirms
+            assert_eq!(chan.setup.funding_outpoint, make_test_channel_setup().funding_outpoint);
+            assert_eq!(chan.setup.channel_value_sat, 3_000_000);
+            assert_eq!(chan.setup.splice_funding, Some((new_outpoint, 4_000_000)));
+            assert_eq!(chan.monitor.get_state().splice_outpoint, Some(new_outpoint));
+
+            chan.confirm_splice(100)?;
+            assert_eq!(chan.setup.funding_outpoint, new_outpoint);
+            assert_eq!(chan.setup.channel_value_sat, 4_000_000);
+            assert_eq!(chan.setup.splice_funding, None);
+            assert_eq!(chan.enforcement_state.funding_confirmation, Some((new_outpoint, 100)));
+            Ok(())
+        })
+        .expect("channel");
+    }
+
+    #[test]
+    fn sign_splice_tx_low_r_test() {
+        let mut node_config = TEST_NODE_CONFIG;
+        node_config.low_r_grinding = true;
+        assert_low_r(|i| {
+            let (node, channel_id) =
+                init_node_and_channel(node_config, TEST_SEED[1], make_test_channel_setup());
+            let new_value_sat = 4_000_000 + i as u64;
+            let tx = make_splice_tx(&node, &channel_id, new_value_sat);
+            let sig = node
+                .with_ready_channel(&channel_id, |chan| {
+                    chan.sign_splice_tx(&tx, &wallet_opaths(), 0, new_value_sat, new_value_sat, 0)
+                })
+                .expect("splice");
+            vec![sig]
+        });
+    }
+
+    #[test]
+    fn sign_splice_tx_abandon_test() {
+        let (node, channel_id, _tx) =
+            sign_splice_with_mutator(wallet_opaths(), 4_000_000, 4_000_000, 0, |_tx| {})
+                .expect("splice");
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.abandon_splice()?;
+            assert_eq!(chan.setup.splice_funding, None);
+            assert_eq!(chan.monitor.get_state().splice_outpoint, None);
+            assert_invalid_argument_err!(chan.confirm_splice(100), "no splice was signed");
+            assert_eq!(chan.setup.funding_outpoint, make_test_channel_setup().funding_outpoint);
+            Ok(())
+        })
+        .expect("channel");
+    }
+
+    #[test]
+    fn sign_splice_tx_bad_opaths_test() {
+        assert_invalid_argument_err!(
+            sign_splice_with_mutator(vec![vec![]], 4_000_000, 4_000_000, 0, |_tx| {}),
+            "sign_splice_tx: bad opath len 1 with tx.output len 2"
+        );
+    }
+
+    // policy-splice-amount-agreed
+    #[test]
+    fn sign_splice_tx_pending_amount_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.set_splice_pending_amount(Some(4_000_000))?;
+            let tx = make_splice_tx(&node, &channel_id, 4_500_000);
+            assert_failed_precondition_err!(
+                chan.sign_splice_tx(&tx, &wallet_opaths(), 0, 4_500_000, 4_500_000, 0),
+                "policy failure: validate_splice_tx: \
+                 new channel value 4500000 != pending splice amount 4000000"
+            );
+            let tx = make_splice_tx(&node, &channel_id, 4_000_000);
+            chan.sign_splice_tx(&tx, &wallet_opaths(), 0, 4_000_000, 4_000_000, 0)?;
+            assert_eq!(chan.setup.splice_pending_amount, None);
+            Ok(())
+        })
+        .expect("channel");
+    }
+
+    #[test]
+    fn sign_splice_commitment_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let point = make_test_pubkey(10);
+        let new_outpoint =
+            OutPoint { txid: make_splice_tx(&node, &channel_id, 4_000_000).txid(), vout: 0 };
+        let sign = |chan: &mut Channel,
+                    new_value_sat: u64,
+                    to_holder_value_sat: u64,
+                    to_counterparty_value_sat: u64,
+                    holder_reduction_sat: u64| {
+            chan.sign_splice_commitment(
+                new_outpoint,
+                new_value_sat,
+                &point,
+                0,
+                0,
+                to_holder_value_sat,
+                to_counterparty_value_sat,
+                vec![],
+                vec![],
+                holder_reduction_sat,
+            )
+        };
+
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_counterparty_commitment_tx_phase2(
+                &point,
+                0,
+                0,
+                1_000_000,
+                1_999_000,
+                vec![],
+                vec![],
+            )?;
+
+            assert_failed_precondition_err!(
+                sign(chan, 4_000_000, 1_000_000, 2_999_000, 0),
+                "policy failure: validate_splice_commitment: no splice pending"
+            );
+
+            chan.set_splice_pending_amount(Some(4_000_000))?;
+            assert_failed_precondition_err!(
+                sign(chan, 3_500_000, 1_000_000, 2_499_000, 0),
+                "policy failure: validate_splice_commitment: \
+                 new channel value 3500000 != pending splice amount 4000000"
+            );
+            // policy-splice-holder-balance-consented
+            assert_failed_precondition_err!(
+                sign(chan, 4_000_000, 500_000, 3_499_000, 0),
+                "policy failure: validate_splice_commitment: \
+                 holder balance reduced from 1000000 to 500000 with only 0 consented"
+            );
+            assert_failed_precondition_err!(
+                chan.sign_splice_commitment(
+                    new_outpoint,
+                    4_000_000,
+                    &make_test_pubkey(11),
+                    0,
+                    0,
+                    1_000_000,
+                    2_999_000,
+                    vec![],
+                    vec![],
+                    0,
+                ),
+                "policy failure: validate_splice_commitment: \
+                 splice commitment 0 is not the current commitment"
+            );
+
+            // The counterparty splices in
+            assert_status_ok!(sign(chan, 4_000_000, 1_000_000, 2_999_000, 0));
+            // We splice out with consent
+            assert_status_ok!(sign(chan, 4_000_000, 500_000, 3_499_000, 500_000));
+
+            // The channel still uses the current funding output and state
+            assert_eq!(chan.setup.channel_value_sat, 3_000_000);
+            assert_eq!(chan.setup.funding_outpoint, make_test_channel_setup().funding_outpoint);
+            assert_eq!(chan.setup.splice_pending_amount, Some(4_000_000));
+            assert_eq!(chan.enforcement_state.next_counterparty_commit_num, 1);
+            Ok(())
+        })
+        .expect("channel");
+    }
+
+    #[test]
+    fn sign_splice_tx_splice_out_with_consent_test() {
+        assert_status_ok!(sign_splice_with_mutator(
+            wallet_opaths(),
+            2_000_000,
+            2_000_000,
+            1_000_000,
+            |_tx| {}
+        ));
+    }
+
+    // policy-splice-destination-allowlisted
+    #[test]
+    fn sign_splice_tx_splice_out_to_allowlisted_test() {
+        let (node, channel_id) =
+            init_node_and_channel(TEST_NODE_CONFIG, TEST_SEED[1], make_test_channel_setup());
+        let mut tx = make_splice_tx(&node, &channel_id, 2_000_000);
+        let address = "tb1qkakav8jpkhhs22hjrndrycyg3srshwd09gax07";
+        node.add_allowlist(&vec![address.to_string()]).expect("add_allowlist");
+        tx.output[1].script_pubkey = Address::from_str(address).expect("address").script_pubkey();
+        node.with_ready_channel(&channel_id, |chan| {
+            chan.sign_splice_tx(&tx, &vec![vec![], vec![]], 0, 2_000_000, 2_000_000, 1_000_000)
+        })
+        .expect("splice");
+    }
+
+    // policy-splice-destination-allowlisted
+    #[test]
+    fn sign_splice_tx_splice_out_to_unknown_destination_test() {
+        assert_failed_precondition_err!(
+            sign_splice_with_mutator(
+                vec![vec![], vec![]],
+                2_000_000,
+                2_000_000,
+                1_000_000,
+                |_tx| {}
+            ),
+            "policy failure: validate_splice_tx: \
+             spliced-out value 0 to wallet or allowlist \
+             is less than the holder reduction 1000000"
+        );
+    }
+
+    // policy-splice-destination-allowlisted
+    #[test]
+    fn sign_splice_tx_splice_out_short_to_wallet_test() {
+        assert_failed_precondition_err!(
+            sign_splice_with_mutator(wallet_opaths(), 2_000_000, 2_000_000, 1_000_000, |tx| {
+                tx.output[1].value = 900_000;
+            }),
+            "policy failure: validate_splice_tx: \
+             spliced-out value 900000 to wallet or allowlist \
+             is less than the holder reduction 1000000"
+        );
+    }
+
+    // policy-splice-holder-balance-consented
+    #[test]
+    fn sign_splice_tx_splice_out_without_consent_test() {
+        assert_failed_precondition_err!(
+            sign_splice_with_mutator(wallet_opaths(), 2_000_000, 2_000_000, 0, |_tx| {}),
+            "policy failure: validate_splice_tx: \
+             holder balance reduced from 3000000 to 2000000 with only 0 consented"
+        );
+    }
+
+    #[test]
+    fn sign_splice_tx_bad_funding_script_test() {
+        assert_failed_precondition_err!(
+            sign_splice_with_mutator(wallet_opaths(), 4_000_000, 4_000_000, 0, |tx| {
+                tx.output[0].script_pubkey = tx.output[1].script_pubkey.clone();
+            }),
+            "policy failure: validate_splice_tx: \
+             new funding output does not pay to the funding script"
+        );
+    }
+
+    #[test]
+    fn sign_splice_tx_not_spending_funding_test() {
+        assert_failed_precondition_err!(
+            sign_splice_with_mutator(wallet_opaths(), 4_000_000, 4_000_000, 0, |tx| {
+                tx.input[0].previous_output.vout += 1;
+            }),
+            "policy failure: validate_splice_tx: \
+             splice must spend the funding outpoint exactly once"
+        );
+    }
+}
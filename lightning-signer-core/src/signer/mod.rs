@@ -1,5 +1,7 @@
 /// An implementation of KeysInterface
 pub mod my_keys_manager;
+/// Abstraction over node key operations
+pub mod node_key_signer;
 /// A multi-node signer
 #[macro_use]
 pub mod multi_signer;
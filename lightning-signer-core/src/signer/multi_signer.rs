@@ -36,7 +36,8 @@ impl MultiSigner {
             true,
             vec![],
             validator_factory,
-        );
+        )
+        .expect("nothing to restore");
         info!("new MultiSigner");
         signer
     }
@@ -48,26 +49,29 @@ impl MultiSigner {
             true,
             vec![],
             validator_factory,
-        );
+        )
+        .expect("nothing to restore");
         info!("new MultiSigner");
         signer
     }
 
-    /// Construct
+    /// Construct, restoring the nodes in `persister`.
+    ///
+    /// Fails if a node can't be restored, see [Node::restore_node].
     pub fn new_with_persister(
         persister: Arc<dyn Persist>,
         test_mode: bool,
         initial_allowlist: Vec<String>,
         validator_factory: Arc<dyn ValidatorFactory>,
-    ) -> MultiSigner {
-        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone());
-        MultiSigner {
+    ) -> Result<MultiSigner, Status> {
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone())?;
+        Ok(MultiSigner {
             nodes: Mutex::new(nodes),
             persister,
             test_mode,
             initial_allowlist,
             validator_factory,
-        }
+        })
     }
 
     /// Create a node with a random seed
@@ -21,6 +21,7 @@ use lightning::chain::keysinterface::{
 };
 use lightning::ln::msgs::DecodeError;
 use lightning::ln::script::ShutdownScript;
+use lightning::ln::{PaymentPreimage, PaymentSecret};
 
 use crate::channel::ChannelId;
 use crate::util::crypto_utils::{
@@ -95,6 +96,8 @@ pub struct MyKeysManager {
     inbound_payment_key: KeyMaterial,
     channel_seed_base: [u8; 32],
     account_extended_key: ExtendedPrivKey,
+    // From the hardened m/1' key, not the wallet key of
+    // Node::get_destination_script_with_path
     destination_script: Script,
     ldk_shutdown_pubkey: PublicKey,
     #[allow(dead_code)]
@@ -111,9 +114,39 @@ pub struct MyKeysManager {
     unique_start: Sha256State,
 
     id_to_nonce: Mutex<OrderedMap<ChannelId, Vec<u8>>>,
+
+    session_key_counter: Mutex<u64>,
 }
 
 impl MyKeysManager {
+    fn derive_node_keys(
+        secp_ctx: &Secp256k1<All>,
+        key_derivation_style: KeyDerivationStyle,
+        seed: &[u8],
+        network: Network,
+        master_key: ExtendedPrivKey,
+    ) -> (PublicKey, SecretKey) {
+        match key_derivation_style {
+            KeyDerivationStyle::Native => node_keys_native(secp_ctx, seed),
+            KeyDerivationStyle::Lnd => node_keys_lnd(secp_ctx, network, master_key),
+        }
+    }
+
+    /// Derive the node public key from the seed, without constructing
+    /// the rest of the keys manager
+    pub fn node_pubkey_from_seed(
+        key_derivation_style: KeyDerivationStyle,
+        seed: &[u8],
+        network: Network,
+    ) -> PublicKey {
+        let secp_ctx = Secp256k1::new();
+        let master_key =
+            ExtendedPrivKey::new_master(network.clone(), seed).expect("your RNG is busted");
+        let (node_id, _) =
+            Self::derive_node_keys(&secp_ctx, key_derivation_style, seed, network, master_key);
+        node_id
+    }
+
     /// Construct
     pub fn new(
         key_derivation_style: KeyDerivationStyle,
@@ -125,10 +158,8 @@ impl MyKeysManager {
         let secp_ctx = Secp256k1::new();
         let master_key =
             ExtendedPrivKey::new_master(network.clone(), seed).expect("your RNG is busted");
-        let (_, node_secret) = match key_derivation_style {
-            KeyDerivationStyle::Native => node_keys_native(&secp_ctx, seed),
-            KeyDerivationStyle::Lnd => node_keys_lnd(&secp_ctx, network.clone(), master_key),
-        };
+        let (_, node_secret) =
+            Self::derive_node_keys(&secp_ctx, key_derivation_style, seed, network, master_key);
         let destination_script =
             match master_key.ckd_priv(&secp_ctx, ChildNumber::from_hardened_idx(1).unwrap()) {
                 Ok(destination_key) => {
@@ -210,6 +241,7 @@ impl MyKeysManager {
             lnd_basepoint_index: AtomicU32::new(0),
             unique_start,
             id_to_nonce: Mutex::new(OrderedMap::new()),
+            session_key_counter: Mutex::new(0),
         };
 
         let secp_seed = res.get_secure_random_bytes();
@@ -419,6 +451,55 @@ impl MyKeysManager {
         self.channel_id_child_index.fetch_add(1, Ordering::AcqRel)
     }
 
+    /// Derive a fresh onion session key.
+    ///
+    /// Secure random bytes are mixed with a counter, which should be
+    /// persisted and restored with [MyKeysManager::set_session_key_counter]
+    /// so that keys remain unique across restarts.
+    pub fn rotate_session_key(&self) -> SecretKey {
+        let counter = {
+            let mut counter = self.session_key_counter.lock().unwrap();
+            *counter += 1;
+            *counter
+        };
+        let mut sha = Sha256::engine();
+        sha.input(&self.get_secure_random_bytes());
+        sha.input(&byte_utils::be64_to_array(counter));
+        sha.input(b"Onion Session Key Salt");
+        SecretKey::from_slice(&Sha256::from_engine(sha).into_inner()).expect("valid session key")
+    }
+
+    /// The number of session keys derived, including before a restart
+    pub fn session_key_counter(&self) -> u64 {
+        *self.session_key_counter.lock().unwrap()
+    }
+
+    /// Restore the session key counter, for example from the persister
+    pub fn set_session_key_counter(&self, counter: u64) {
+        *self.session_key_counter.lock().unwrap() = counter;
+    }
+
+    /// Derive the preimage of a keysend payment from `payment_nonce`.
+    ///
+    /// The preimage is deterministic, so it can be derived again
+    /// when the payment arrives.
+    pub fn derive_keysend_preimage(&self, payment_nonce: &[u8]) -> PaymentPreimage {
+        PaymentPreimage(hkdf_sha256(
+            &self.inbound_payment_key.0,
+            "keysend preimage".as_bytes(),
+            payment_nonce,
+        ))
+    }
+
+    /// Derive the payment secret of a keysend payment from `payment_nonce`
+    pub fn derive_keysend_payment_secret(&self, payment_nonce: &[u8]) -> PaymentSecret {
+        PaymentSecret(hkdf_sha256(
+            &self.inbound_payment_key.0,
+            "keysend payment secret".as_bytes(),
+            payment_nonce,
+        ))
+    }
+
     /// Creates a Transaction which spends the given descriptors to the given outputs, plus an
     /// output to the given change destination (if sufficient change value remains). The
     /// transaction will have a feerate, at least, of the given value.
@@ -664,6 +745,25 @@ mod tests {
     use crate::util::test_utils::hex_encode;
     use test_log::test;
 
+    #[test]
+    fn rotate_session_key_test() {
+        let manager =
+            MyKeysManager::new(KeyDerivationStyle::Native, &[0u8; 32], Network::Testnet, 0, 0);
+        let key0 = manager.rotate_session_key();
+        let key1 = manager.rotate_session_key();
+        assert_ne!(key0, key1);
+        assert_eq!(manager.session_key_counter(), 2);
+
+        // A restart with the same starting time repeats the random bytes,
+        // the restored counter keeps the keys unique
+        let restarted =
+            MyKeysManager::new(KeyDerivationStyle::Native, &[0u8; 32], Network::Testnet, 0, 0);
+        restarted.set_session_key_counter(manager.session_key_counter());
+        let key2 = restarted.rotate_session_key();
+        assert_ne!(key2, key0);
+        assert_ne!(key2, key1);
+    }
+
     #[test]
     fn keys_test_native() -> Result<(), ()> {
         let manager =
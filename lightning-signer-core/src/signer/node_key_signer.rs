@@ -0,0 +1,59 @@
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::recovery::RecoverableSignature;
+use bitcoin::secp256k1::{self, Message, PublicKey, Secp256k1, SecretKey, Signature};
+
+use crate::prelude::*;
+use crate::util::status::Status;
+
+/// Operations that require the node secret key.
+///
+/// The node only hands message digests and public keys to this trait,
+/// so an implementation may keep the node secret in external hardware.
+pub trait NodeKeySigner: Send + Sync {
+    /// The node public key
+    fn node_id(&self) -> PublicKey;
+
+    /// ECDH between the node key and `other_key`, returning the 32 byte shared secret
+    fn ecdh(&self, other_key: &PublicKey) -> Result<Vec<u8>, Status>;
+
+    /// Sign a 32 byte digest with the node key
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, Status>;
+
+    /// Sign a 32 byte digest with the node key, producing a recoverable signature
+    fn sign_digest_recoverable(&self, digest: &Message) -> Result<RecoverableSignature, Status>;
+}
+
+/// A [NodeKeySigner] holding the node secret in memory
+pub struct InMemoryNodeKeySigner {
+    secp_ctx: Secp256k1<secp256k1::All>,
+    node_secret: SecretKey,
+    node_id: PublicKey,
+}
+
+impl InMemoryNodeKeySigner {
+    /// Construct from the node secret
+    pub fn new(node_secret: SecretKey) -> Self {
+        let secp_ctx = Secp256k1::new();
+        let node_id = PublicKey::from_secret_key(&secp_ctx, &node_secret);
+        InMemoryNodeKeySigner { secp_ctx, node_secret, node_id }
+    }
+}
+
+impl NodeKeySigner for InMemoryNodeKeySigner {
+    fn node_id(&self) -> PublicKey {
+        self.node_id
+    }
+
+    fn ecdh(&self, other_key: &PublicKey) -> Result<Vec<u8>, Status> {
+        let ss = SharedSecret::new(other_key, &self.node_secret);
+        Ok(ss[..].to_vec())
+    }
+
+    fn sign_digest(&self, digest: &Message) -> Result<Signature, Status> {
+        Ok(self.secp_ctx.sign(digest, &self.node_secret))
+    }
+
+    fn sign_digest_recoverable(&self, digest: &Message) -> Result<RecoverableSignature, Status> {
+        Ok(self.secp_ctx.sign_recoverable(digest, &self.node_secret))
+    }
+}
@@ -16,7 +16,8 @@ use bitcoin::{OutPoint, Script, Transaction, TxIn, TxOut};
 use lightning::chain::keysinterface::{BaseSign, InMemorySigner};
 use lightning::ln::chan_utils;
 use lightning::ln::chan_utils::{
-    get_anchor_redeemscript, get_revokeable_redeemscript, HTLCOutputInCommitment, TxCreationKeys,
+    get_anchor_redeemscript, get_revokeable_redeemscript, htlc_success_tx_weight,
+    htlc_timeout_tx_weight, HTLCOutputInCommitment, TxCreationKeys,
 };
 use lightning::ln::PaymentHash;
 
@@ -29,6 +30,7 @@ use crate::tx::script::{
 };
 use crate::util::crypto_utils::payload_for_p2wpkh;
 use crate::util::debug_utils::DebugPayload;
+use crate::util::transaction_utils::MIN_DUST_LIMIT_SATOSHIS;
 use crate::util::AddedItemsIter;
 use bitcoin::hashes::hex::ToHex;
 
@@ -36,6 +38,39 @@ const MAX_DELAY: i64 = 1000;
 /// Value for anchor outputs
 pub(crate) const ANCHOR_SAT: u64 = 330;
 
+/// The expected version, locktime and sequence encoding of a commitment
+/// transaction, selected by [CommitmentType](crate::channel::CommitmentType)
+///
+/// The 48-bit obscured commitment number is split between the locktime
+/// (lower 24 bits) and the funding input sequence (upper 24 bits).  The
+/// upper byte of each field is a fixed marker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitmentFormat {
+    /// The transaction version
+    pub version: i32,
+    /// The upper byte of the locktime
+    pub locktime_marker: u8,
+    /// The upper byte of the funding input sequence
+    pub sequence_marker: u8,
+}
+
+impl CommitmentFormat {
+    /// The format specified by BOLT-3
+    pub const BOLT3: CommitmentFormat =
+        CommitmentFormat { version: 2, locktime_marker: 0x20, sequence_marker: 0x80 };
+
+    /// The locktime for an obscured commitment number
+    pub fn lock_time(&self, obscured_commitment_number: u64) -> u32 {
+        ((self.locktime_marker as u32) << 8 * 3) | (obscured_commitment_number & 0xff_ffff) as u32
+    }
+
+    /// The funding input sequence for an obscured commitment number
+    pub fn sequence(&self, obscured_commitment_number: u64) -> u32 {
+        ((self.sequence_marker as u32) << 8 * 3)
+            | ((obscured_commitment_number >> 3 * 8) & 0xff_ffff) as u32
+    }
+}
+
 pub(crate) fn get_commitment_transaction_number_obscure_factor(
     local_payment_basepoint: &PublicKey,
     counterparty_payment_basepoint: &PublicKey,
@@ -66,6 +101,7 @@ pub(crate) fn build_commitment_tx(
     info: &CommitmentInfo2,
     obscured_commitment_transaction_number: u64,
     outpoint: OutPoint,
+    format: &CommitmentFormat,
     option_anchor_outputs: bool,
     workaround_local_funding_pubkey: &PublicKey,
     workaround_remote_funding_pubkey: &PublicKey,
@@ -75,8 +111,7 @@ pub(crate) fn build_commitment_tx(
         ins.push(TxIn {
             previous_output: outpoint,
             script_sig: Script::new(),
-            sequence: ((0x80 as u32) << 8 * 3)
-                | ((obscured_commitment_transaction_number >> 3 * 8) as u32),
+            sequence: format.sequence(obscured_commitment_transaction_number),
             witness: Vec::new(),
         });
         ins
@@ -183,9 +218,8 @@ pub(crate) fn build_commitment_tx(
 
     (
         Transaction {
-            version: 2,
-            lock_time: ((0x20 as u32) << 8 * 3)
-                | ((obscured_commitment_transaction_number & 0xffffffu64) as u32),
+            version: format.version,
+            lock_time: format.lock_time(obscured_commitment_transaction_number),
             input: txins,
             output: outputs,
         },
@@ -364,6 +398,18 @@ impl CommitmentInfo2 {
             + self.received_htlcs.iter().map(|h| h.value_sat).sum::<u64>()
     }
 
+    /// The total value of the HTLCs which are not trimmed at the commitment
+    /// feerate, assuming the minimum dust limit.
+    pub fn untrimmed_htlc_value_sat(&self, option_anchor_outputs: bool) -> u64 {
+        let threshold =
+            |weight: u64| MIN_DUST_LIMIT_SATOSHIS + self.feerate_per_kw as u64 * weight / 1000;
+        let offered_threshold = threshold(htlc_timeout_tx_weight(option_anchor_outputs));
+        let received_threshold = threshold(htlc_success_tx_weight(option_anchor_outputs));
+        let offered = self.offered_htlcs.iter().filter(|h| h.value_sat >= offered_threshold);
+        let received = self.received_htlcs.iter().filter(|h| h.value_sat >= received_threshold);
+        offered.chain(received).map(|h| h.value_sat).sum()
+    }
+
     /// Compute claimable balance in sat, defined as the sum of:
     /// - the output to us
     /// - HTLCs offered to us for which the preimage is known
@@ -871,6 +917,33 @@ mod tests {
 
     use test_log::test;
 
+    #[test]
+    fn commitment_format_bolt3_test() {
+        // BOLT-3 Appendix C: commitment number 42, obscuring factor 0x2bb038521914
+        let obscured = 0x2bb038521914 ^ 42;
+        let format = CommitmentFormat::BOLT3;
+        assert_eq!(format.version, 2);
+        assert_eq!(format.lock_time(obscured), 542251326);
+        assert_eq!(format.sequence(obscured), 2150346808);
+    }
+
+    #[test]
+    fn commitment_format_by_type_test() {
+        for commitment_type in &[
+            CommitmentType::Legacy,
+            CommitmentType::StaticRemoteKey,
+            CommitmentType::Anchors,
+            CommitmentType::SimpleTaprootChannels,
+        ] {
+            assert_eq!(
+                commitment_type.commitment_format(),
+                CommitmentFormat::BOLT3,
+                "{:?}",
+                commitment_type
+            );
+        }
+    }
+
     #[test]
     fn htlc2_sorting() {
         // Defined in order ...
@@ -944,6 +1017,17 @@ mod tests {
         assert_eq!(res.unwrap_err(), mismatch_error(format!("anchor wrong size: {}", out.value)));
     }
 
+    #[test]
+    fn handle_anchor_too_large_test() {
+        let mut info = CommitmentInfo::new_for_holder();
+        let keys = make_test_channel_keys();
+        let out = TxOut { value: ANCHOR_SAT + 1, script_pubkey: Default::default() };
+        let to_pubkey_data = keys.pubkeys().funding_pubkey.serialize().to_vec();
+        let res = info.handle_anchor_output(&keys, &out, to_pubkey_data);
+        assert_eq!(res.unwrap_err(), mismatch_error(format!("anchor wrong size: {}", out.value)));
+        assert_eq!(info.to_broadcaster_anchor_count, 0);
+    }
+
     #[test]
     fn handle_anchor_not_local_or_remote_test() {
         let mut info = CommitmentInfo::new_for_holder();
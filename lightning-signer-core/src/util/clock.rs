@@ -0,0 +1,61 @@
+use core::time::Duration;
+
+use crate::prelude::*;
+use crate::sync::Arc;
+
+/// A source of wall-clock time
+pub trait Clock: Send + Sync {
+    /// The time since the Unix epoch
+    fn now(&self) -> Duration;
+}
+
+/// A [Clock] backed by the system time
+#[cfg(feature = "std")]
+pub struct StandardClock();
+
+#[cfg(feature = "std")]
+impl Clock for StandardClock {
+    fn now(&self) -> Duration {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("time went backwards")
+    }
+}
+
+/// A [Clock] that only moves when it is explicitly set.
+///
+/// Useful for tests, and in environments without a system clock.
+pub struct ManualClock(Mutex<Duration>);
+
+impl ManualClock {
+    /// Construct, starting at `now`
+    pub fn new(now: Duration) -> Self {
+        ManualClock(Mutex::new(now))
+    }
+
+    /// Set the current time
+    pub fn set(&self, now: Duration) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    /// Move the current time forward
+    pub fn advance(&self, delta: Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += delta;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// The default clock - the system time if available, otherwise
+/// a [ManualClock] starting at the epoch
+pub fn default_clock() -> Arc<dyn Clock> {
+    #[cfg(feature = "std")]
+    return Arc::new(StandardClock());
+    #[cfg(not(feature = "std"))]
+    return Arc::new(ManualClock::new(Duration::from_secs(0)));
+}
@@ -8,6 +8,7 @@ use bitcoin::util::address::Payload;
 use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey};
 use bitcoin::Network;
 use bitcoin::{bech32, Script, SigHashType};
+use secp256k1_xonly::XOnlyPublicKey;
 
 fn hkdf_extract_expand(salt: &[u8], secret: &[u8], info: &[u8], output: &mut [u8]) {
     let mut hmac = HmacEngine::<BitcoinSha256>::new(salt);
@@ -219,6 +220,33 @@ pub(crate) fn payload_for_p2wpkh(key: &PublicKey) -> Payload {
     }
 }
 
+/// The BIP-86 output key for a key path only spend by `internal_key`
+pub(crate) fn taproot_output_key<C: secp256k1::Verification>(
+    secp_ctx: &Secp256k1<C>,
+    internal_key: &XOnlyPublicKey,
+) -> XOnlyPublicKey {
+    let tag_hash = BitcoinSha256::hash("TapTweak".as_bytes()).into_inner();
+    let mut sha = BitcoinSha256::engine();
+    sha.input(&tag_hash);
+    sha.input(&tag_hash);
+    sha.input(&internal_key.serialize());
+    let tweak = BitcoinSha256::from_engine(sha).into_inner();
+    let mut output_key = *internal_key;
+    output_key.tweak_add_assign(secp_ctx, &tweak).expect("tweak is a hash");
+    output_key
+}
+
+pub(crate) fn payload_for_p2tr<C: secp256k1::Verification>(
+    secp_ctx: &Secp256k1<C>,
+    key: &PublicKey,
+) -> Payload {
+    let output_key = taproot_output_key(secp_ctx, &XOnlyPublicKey::from(*key));
+    Payload::WitnessProgram {
+        version: bech32::u5::try_from_u8(1).expect("1<32"),
+        program: output_key.serialize().to_vec(),
+    }
+}
+
 pub(crate) fn payload_for_p2wsh(script: &Script) -> Payload {
     let mut hash_engine = BitcoinSha256::engine();
     hash_engine.input(&script[..]);
@@ -228,6 +256,54 @@ pub(crate) fn payload_for_p2wsh(script: &Script) -> Payload {
     }
 }
 
+/// Sign a message, grinding the nonce until R is low if `low_r` is set.
+///
+/// A low-R signature is at most 71 bytes DER-encoded, one byte
+/// shorter than the worst case.
+pub fn sign_maybe_low_r<C: secp256k1::Signing>(
+    secp_ctx: &Secp256k1<C>,
+    message: &secp256k1::Message,
+    key: &SecretKey,
+    low_r: bool,
+) -> Signature {
+    if low_r {
+        secp_ctx.sign_low_r(message, key)
+    } else {
+        secp_ctx.sign(message, key)
+    }
+}
+
+/// Compute the RFC6979 nonce that libsecp256k1 uses when signing `message`
+/// with `key` and no extra entropy.
+///
+/// This allows tests to check that signing is deterministic.
+#[cfg(feature = "test_utils")]
+pub fn rfc6979_nonce(key: &SecretKey, message: &secp256k1::Message) -> SecretKey {
+    fn hmac(k: &[u8; 32], parts: &[&[u8]]) -> [u8; 32] {
+        let mut engine = HmacEngine::<BitcoinSha256>::new(k);
+        for part in parts {
+            engine.input(part);
+        }
+        Hmac::from_engine(engine).into_inner()
+    }
+
+    let mut k = [0u8; 32];
+    let mut v = [1u8; 32];
+    k = hmac(&k, &[&v, &[0], &key[..], &message[..]]);
+    v = hmac(&k, &[&v]);
+    k = hmac(&k, &[&v, &[1], &key[..], &message[..]]);
+    v = hmac(&k, &[&v]);
+    loop {
+        v = hmac(&k, &[&v]);
+        // Candidates outside [1, n) are rejected and the generator advanced
+        if let Ok(nonce) = SecretKey::from_slice(&v) {
+            return nonce;
+        }
+        k = hmac(&k, &[&v, &[0]]);
+        v = hmac(&k, &[&v]);
+    }
+}
+
 /// Convert a [Signature] to Bitcoin signature bytes, with SIGHASH_ALL
 pub fn signature_to_bitcoin_vec(sig: Signature) -> Vec<u8> {
     let mut sigvec = sig.serialize_der().to_vec();
@@ -255,11 +331,10 @@ pub fn bitcoin_vec_to_signature(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bitcoin::hashes::hex::ToHex;
+    use bitcoin::hashes::hex::{FromHex, ToHex};
     use bitcoin::schnorr::KeyPair;
     use bitcoin::secp256k1::Message;
     use bitcoin::Network::Testnet;
-    use secp256k1_xonly::XOnlyPublicKey;
 
     #[test]
     fn node_keys_native_test() -> Result<(), ()> {
@@ -321,6 +396,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn taproot_output_key_test() {
+        // BIP-86 test vector, first receiving address of the first account
+        let secp = Secp256k1::verification_only();
+        let internal_key = XOnlyPublicKey::from_slice(
+            &Vec::from_hex("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            taproot_output_key(&secp, &internal_key).serialize().to_hex(),
+            "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+        );
+    }
+
     #[test]
     fn test_xonly() {
         let secp = Secp256k1::new();
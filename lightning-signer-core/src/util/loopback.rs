@@ -2,8 +2,6 @@
 use std::convert::TryInto;
 
 use bitcoin::bech32::u5;
-use bitcoin::hash_types::WPubkeyHash;
-use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::recovery::RecoverableSignature;
 use bitcoin::secp256k1::{All, PublicKey, Secp256k1, SecretKey, Signature};
 use bitcoin::util::psbt::serialize::Serialize;
@@ -185,8 +183,10 @@ impl LoopbackChannelSigner {
         (offered_htlcs, received_htlcs)
     }
 
-    fn dest_wallet_path() -> Vec<u32> {
-        vec![1]
+    fn dest_wallet_path(&self) -> Vec<u32> {
+        let node = self.signer.get_node(&self.node_id).expect("our node is missing");
+        // Sweeps pay to the script our KeysInterface supplies
+        node.get_destination_script_with_path().1
     }
 
     fn option_anchor_outputs(&self) -> bool {
@@ -315,7 +315,8 @@ impl BaseSign for LoopbackChannelSigner {
         let forward_idx = INITIAL_COMMITMENT_NUMBER - idx;
         self.signer
             .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
-                chan.validate_counterparty_revocation(forward_idx, secret)
+                chan.validate_counterparty_revocation(forward_idx, secret)?;
+                Ok(())
             })
             .map_err(|s| self.bad_status(s))?;
 
@@ -369,7 +370,7 @@ impl BaseSign for LoopbackChannelSigner {
             &delayed_payment_key,
         );
 
-        let wallet_path = LoopbackChannelSigner::dest_wallet_path();
+        let wallet_path = self.dest_wallet_path();
 
         // TODO phase 2
         let sig = self
@@ -402,7 +403,7 @@ impl BaseSign for LoopbackChannelSigner {
         let tx_keys = self.make_counterparty_tx_keys(&per_commitment_point, secp_ctx)?;
         let redeem_script =
             chan_utils::get_htlc_redeemscript(&htlc, self.option_anchor_outputs(), &tx_keys);
-        let wallet_path = LoopbackChannelSigner::dest_wallet_path();
+        let wallet_path = self.dest_wallet_path();
 
         // TODO phase 2
         let sig = self
@@ -434,7 +435,7 @@ impl BaseSign for LoopbackChannelSigner {
         let chan_keys = self.make_counterparty_tx_keys(per_commitment_point, secp_ctx)?;
         let redeem_script =
             chan_utils::get_htlc_redeemscript(htlc, self.option_anchor_outputs(), &chan_keys);
-        let wallet_path = LoopbackChannelSigner::dest_wallet_path();
+        let wallet_path = self.dest_wallet_path();
 
         // TODO phase 2
         let sig = self
@@ -489,7 +490,7 @@ impl BaseSign for LoopbackChannelSigner {
         let (nsig, bsig) = self
             .signer
             .with_ready_channel(&self.node_id, &self.channel_id, |chan| {
-                Ok(chan.sign_channel_announcement(&msg.encode()))
+                chan.sign_channel_announcement(&msg.encode())
             })
             .map_err(|s| self.bad_status(s))?;
         Ok((nsig, bsig))
@@ -509,9 +510,12 @@ impl BaseSign for LoopbackChannelSigner {
             holder_selected_contest_delay: parameters.holder_selected_contest_delay,
             holder_shutdown_script: None, // use the signer's shutdown script
             counterparty_points: counterparty_parameters.pubkeys.clone(),
+            counterparty_node_id: None,
             counterparty_selected_contest_delay: counterparty_parameters.selected_contest_delay,
             counterparty_shutdown_script: None, // TODO
             commitment_type: CommitmentType::StaticRemoteKey, // TODO
+            splice_pending_amount: None,
+            splice_funding: None,
         };
         let node = self.signer.get_node(&self.node_id).expect("no such node");
 
@@ -529,16 +533,13 @@ impl KeysInterface for LoopbackSignerKeysInterface {
     // TODO secret key leaking
     fn get_node_secret(&self, recipient: Recipient) -> Result<SecretKey, ()> {
         match recipient {
-            Recipient::Node => Ok(self.get_node().get_node_secret()),
+            Recipient::Node => Ok(self.get_node().node_secret()),
             Recipient::PhantomNode => Err(()),
         }
     }
 
     fn get_destination_script(&self) -> Script {
-        let secp_ctx = Secp256k1::signing_only();
-        let wallet_path = LoopbackChannelSigner::dest_wallet_path();
-        let pubkey = self.get_node().get_wallet_pubkey(&secp_ctx, &wallet_path).expect("pubkey");
-        Script::new_v0_wpkh(&WPubkeyHash::hash(&pubkey.serialize()))
+        self.get_node().get_destination_script_with_path().0
     }
 
     fn get_shutdown_scriptpubkey(&self) -> ShutdownScript {
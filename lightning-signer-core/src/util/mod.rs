@@ -1,5 +1,7 @@
 /// Byte to integer conversion
 pub mod byte_utils;
+/// Wall-clock time sources
+pub mod clock;
 /// Cryptographic utilities
 pub mod crypto_utils;
 /// Logging macros
@@ -35,6 +37,10 @@ pub mod transaction_utils;
 /// The initial commitment number when counting backwards
 pub const INITIAL_COMMITMENT_NUMBER: u64 = (1 << 48) - 1;
 
+/// The default minimum `min_final_cltv_expiry` of invoices we sign,
+/// the BOLT-11 default
+pub const DEFAULT_MIN_INVOICE_CLTV_EXPIRY: u32 = 9;
+
 use crate::prelude::*;
 use core::slice::Iter;
 use itertools::{put_back, PutBack};
@@ -25,6 +25,9 @@ pub enum Code {
     /// Client specified an invalid argument.
     InvalidArgument = 3,
 
+    /// The caller does not have permission to execute the specified operation.
+    PermissionDenied = 7,
+
     /// The system is not in a state required for the operation’s execution.
     FailedPrecondition = 9,
 
@@ -58,6 +61,11 @@ impl Status {
         Self::new(Code::FailedPrecondition, message)
     }
 
+    /// Construct a permission denied status
+    pub fn permission_denied(message: impl Into<String>) -> Status {
+        Self::new(Code::PermissionDenied, message)
+    }
+
     /// Construct an internal error status
     pub fn internal(message: impl Into<String>) -> Status {
         Self::new(Code::Internal, message)
@@ -108,6 +116,12 @@ pub fn invalid_argument(msg: impl Into<String>) -> Status {
     Status::invalid_argument(s)
 }
 
+pub(crate) fn permission_denied(msg: impl Into<String>) -> Status {
+    let s = msg.into();
+    error!("PERMISSION DENIED: {}", &s);
+    Status::permission_denied(s)
+}
+
 pub(crate) fn internal_error(msg: impl Into<String>) -> Status {
     let s = msg.into();
     error!("INTERNAL ERROR: {}", &s);
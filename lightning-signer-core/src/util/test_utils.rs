@@ -40,7 +40,7 @@ use crate::channel::{
     CommitmentType, TypedSignature,
 };
 use crate::node::SpendType;
-use crate::node::{Node, NodeConfig};
+use crate::node::{Capabilities, Node, NodeConfig};
 use crate::persist::{DummyPersister, Persist};
 use crate::policy::simple_validator::SimpleValidatorFactory;
 use crate::policy::validator::ChainState;
@@ -51,11 +51,10 @@ use crate::tx::script::{
     ANCHOR_OUTPUT_VALUE_SATOSHI,
 };
 use crate::tx::tx::{sort_outputs, CommitmentInfo2, HTLCInfo2};
-use crate::util::crypto_utils::{
-    derive_public_key, derive_revocation_pubkey, payload_for_p2wpkh, payload_for_p2wsh,
-};
+use crate::util::crypto_utils::{derive_public_key, payload_for_p2wpkh, payload_for_p2wsh};
 use crate::util::loopback::LoopbackChannelSigner;
 use crate::util::status::Status;
+use crate::util::{DEFAULT_MIN_INVOICE_CLTV_EXPIRY, INITIAL_COMMITMENT_NUMBER};
 use crate::wallet::Wallet;
 use crate::Arc;
 
@@ -263,9 +262,12 @@ pub fn make_test_channel_setup() -> ChannelSetup {
         holder_selected_contest_delay: 6,
         holder_shutdown_script: None,
         counterparty_points: make_test_counterparty_points(),
+        counterparty_node_id: None,
         counterparty_selected_contest_delay: 7,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        splice_pending_amount: None,
+        splice_funding: None,
     }
 }
 
@@ -355,7 +357,7 @@ pub fn make_test_funding_wallet_input() -> TxIn {
     TxIn {
         previous_output: bitcoin::OutPoint { txid: Default::default(), vout: 0 },
         script_sig: Script::new(),
-        sequence: 0,
+        sequence: u32::MAX,
         witness: vec![],
     }
 }
@@ -546,9 +548,12 @@ pub fn test_chan_ctx_with_push_val(
         holder_selected_contest_delay: 6,
         holder_shutdown_script: None,
         counterparty_points: make_test_counterparty_points(),
+        counterparty_node_id: None,
         counterparty_selected_contest_delay: 7,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::StaticRemoteKey,
+        splice_pending_amount: None,
+        splice_funding: None,
     };
 
     node_ctx
@@ -1017,9 +1022,6 @@ pub fn validate_holder_commitment(
         commit_tx_ctx.received_htlcs.clone(),
     );
     node_ctx.node.with_ready_channel(&chan_ctx.channel_id, |chan| {
-        let channel_parameters = chan.make_channel_parameters();
-        let parameters = channel_parameters.as_holder_broadcastable();
-
         // NOTE - the unit tests calling this method may be
         // setting up a commitment with a bogus
         // commitment_number on purpose.  To allow this we
@@ -1031,19 +1033,8 @@ pub fn validate_holder_commitment(
         let per_commitment_point = chan.get_per_commitment_point(commit_tx_ctx.commit_num)?;
         chan.enforcement_state.set_next_holder_commit_num_for_testing(save_commit_num);
 
-        let keys = chan.make_holder_tx_keys(&per_commitment_point).unwrap();
-
-        let redeem_scripts = build_tx_scripts(
-            &keys,
-            commit_tx_ctx.to_broadcaster,
-            commit_tx_ctx.to_countersignatory,
-            &htlcs,
-            &parameters,
-            &chan.keys.pubkeys().funding_pubkey,
-            &chan.setup.counterparty_points.funding_pubkey,
-        )
-        .expect("scripts");
-        let output_witscripts = redeem_scripts.iter().map(|s| s.serialize()).collect();
+        let output_witscripts =
+            holder_commitment_witscripts(chan, commit_tx_ctx, &per_commitment_point, &htlcs);
 
         chan.validate_holder_commitment_tx(
             &commit_tx_ctx.tx.as_ref().unwrap().trust().built_transaction().transaction,
@@ -1058,6 +1049,29 @@ pub fn validate_holder_commitment(
     })
 }
 
+pub fn holder_commitment_witscripts(
+    chan: &Channel,
+    commit_tx_ctx: &TestCommitmentTxContext,
+    per_commitment_point: &PublicKey,
+    htlcs: &Vec<HTLCOutputInCommitment>,
+) -> Vec<Vec<u8>> {
+    let channel_parameters = chan.make_channel_parameters();
+    let parameters = channel_parameters.as_holder_broadcastable();
+    let keys = chan.make_holder_tx_keys(per_commitment_point).unwrap();
+
+    let redeem_scripts = build_tx_scripts(
+        &keys,
+        commit_tx_ctx.to_broadcaster,
+        commit_tx_ctx.to_countersignatory,
+        htlcs,
+        &parameters,
+        &chan.keys.pubkeys().funding_pubkey,
+        &chan.setup.counterparty_points.funding_pubkey,
+    )
+    .expect("scripts");
+    redeem_scripts.iter().map(|s| s.serialize()).collect()
+}
+
 pub fn sign_holder_commitment(
     node_ctx: &TestNodeContext,
     chan_ctx: &TestChannelContext,
@@ -1140,11 +1154,31 @@ pub fn make_test_commitment_info() -> CommitmentInfo2 {
     )
 }
 
-pub const TEST_NODE_CONFIG: NodeConfig =
-    NodeConfig { network: Network::Testnet, key_derivation_style: KeyDerivationStyle::Native };
+pub const TEST_NODE_CONFIG: NodeConfig = NodeConfig {
+    network: Network::Testnet,
+    key_derivation_style: KeyDerivationStyle::Native,
+    require_static_remote_key: false,
+    low_r_grinding: false,
+    allow_rbf_funding: false,
+    maximum_commitment_number: INITIAL_COMMITMENT_NUMBER,
+    export_allowed: false,
+    allowlist_match_derivable: false,
+    min_invoice_cltv_expiry: DEFAULT_MIN_INVOICE_CLTV_EXPIRY,
+    capabilities: Capabilities::ALL,
+};
 
-pub const REGTEST_NODE_CONFIG: NodeConfig =
-    NodeConfig { network: Network::Regtest, key_derivation_style: KeyDerivationStyle::Native };
+pub const REGTEST_NODE_CONFIG: NodeConfig = NodeConfig {
+    network: Network::Regtest,
+    key_derivation_style: KeyDerivationStyle::Native,
+    require_static_remote_key: false,
+    low_r_grinding: false,
+    allow_rbf_funding: false,
+    maximum_commitment_number: INITIAL_COMMITMENT_NUMBER,
+    export_allowed: false,
+    allowlist_match_derivable: false,
+    min_invoice_cltv_expiry: DEFAULT_MIN_INVOICE_CLTV_EXPIRY,
+    capabilities: Capabilities::ALL,
+};
 
 pub const TEST_SEED: &[&str] = &[
     "6c696768746e696e672d31000000000000000000000000000000000000000000",
@@ -1254,12 +1288,6 @@ pub fn build_tx_scripts(
     Ok(scripts)
 }
 
-pub fn get_channel_funding_pubkey(node: &Node, channel_id: &ChannelId) -> PublicKey {
-    let res: Result<PublicKey, Status> =
-        node.with_ready_channel(&channel_id, |chan| Ok(chan.keys.pubkeys().funding_pubkey));
-    res.unwrap()
-}
-
 pub fn get_channel_htlc_pubkey(
     node: &Node,
     channel_id: &ChannelId,
@@ -1301,17 +1329,22 @@ pub fn get_channel_revocation_pubkey(
     channel_id: &ChannelId,
     revocation_point: &PublicKey,
 ) -> PublicKey {
-    let res: Result<PublicKey, Status> = node.with_ready_channel(&channel_id, |chan| {
-        let secp_ctx = &chan.secp_ctx;
-        let pubkey = derive_revocation_pubkey(
-            secp_ctx,
-            revocation_point, // matches revocation_secret
-            &chan.keys.pubkeys().revocation_basepoint,
-        )
-        .unwrap();
-        Ok(pubkey)
-    });
-    res.unwrap()
+    node.with_ready_channel(&channel_id, |chan| chan.get_revocation_pubkey(revocation_point))
+        .unwrap()
+}
+
+/// Check that the signatures made by `sign` have a low R.
+///
+/// A high-R signature is equally likely as a low-R one without grinding,
+/// so `sign` is called with indices 1 to 15 to make a number of them.
+pub fn assert_low_r<F: Fn(u8) -> Vec<Signature>>(sign: F) {
+    for i in 1..16 {
+        for sig in sign(i) {
+            // R is under 2^255
+            assert!(sig.serialize_compact()[0] < 0x80);
+            assert!(sig.serialize_der().len() <= 70);
+        }
+    }
 }
 
 pub fn check_signature(
@@ -1611,9 +1644,12 @@ pub fn create_test_channel_setup(dummy_pubkey: PublicKey) -> ChannelSetup {
             delayed_payment_basepoint: dummy_pubkey,
             htlc_basepoint: dummy_pubkey,
         },
+        counterparty_node_id: Some(dummy_pubkey),
         counterparty_selected_contest_delay: 11,
         counterparty_shutdown_script: None,
         commitment_type: CommitmentType::Legacy,
+        splice_pending_amount: None,
+        splice_funding: None,
     }
 }
 
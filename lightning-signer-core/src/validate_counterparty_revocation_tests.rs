@@ -8,7 +8,7 @@ mod tests {
 
     use test_log::test;
 
-    use crate::channel::{Channel, CommitmentType};
+    use crate::channel::{Channel, CommitmentType, CounterpartyRevocation};
     use crate::util::key_utils::*;
     use crate::util::status::{Code, Status};
     use crate::util::test_utils::*;
@@ -41,6 +41,7 @@ mod tests {
             let to_countersignatory = 1_000_000;
 
             chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(REV_COMMIT_NUM - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
             chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
                 REV_COMMIT_NUM,
                 make_test_pubkey(0x10),
@@ -234,6 +235,7 @@ mod tests {
         // Setup enforcement state
         assert_status_ok!(node.with_ready_channel(&channel_id, |chan| {
             chan.enforcement_state.set_next_counterparty_revoke_num_for_testing(REV_COMMIT_NUM - 1);
+            chan.enforcement_state.set_next_holder_commit_num_for_testing(1);
             chan.enforcement_state.set_next_counterparty_commit_num_for_testing(
                 REV_COMMIT_NUM,
                 make_test_pubkey((REV_COMMIT_NUM - 1) as u8),
@@ -402,20 +404,33 @@ mod tests {
             assert!(chan.enforcement_state.previous_counterparty_commit_info.is_some());
 
             // can revoke correctly
-            assert_status_ok!(chan.validate_counterparty_revocation(
-                REV_COMMIT_NUM,
-                &make_test_privkey(REV_COMMIT_NUM as u8)
-            ));
+            let revocation = chan
+                .validate_counterparty_revocation(
+                    REV_COMMIT_NUM,
+                    &make_test_privkey(REV_COMMIT_NUM as u8),
+                )
+                .expect("revocation");
+            assert_eq!(
+                revocation,
+                CounterpartyRevocation {
+                    revoked_commit_num: REV_COMMIT_NUM,
+                    previous_commit_info_released: true
+                }
+            );
 
             // state is modified
             assert_eq!(chan.enforcement_state.next_counterparty_revoke_num, REV_COMMIT_NUM + 1);
             assert!(chan.enforcement_state.previous_counterparty_commit_info.is_none());
 
             // Retry is ok
-            assert_status_ok!(chan.validate_counterparty_revocation(
-                REV_COMMIT_NUM,
-                &make_test_privkey(REV_COMMIT_NUM as u8)
-            ));
+            assert_eq!(
+                chan.validate_counterparty_revocation(
+                    REV_COMMIT_NUM,
+                    &make_test_privkey(REV_COMMIT_NUM as u8)
+                )
+                .expect("retry"),
+                revocation
+            );
 
             // state is unchanged
             assert_eq!(chan.enforcement_state.next_counterparty_revoke_num, REV_COMMIT_NUM + 1);
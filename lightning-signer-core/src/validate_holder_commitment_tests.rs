@@ -83,6 +83,98 @@ mod tests {
             .expect("valid holder commitment");
     }
 
+    #[test]
+    fn verify_counterparty_commitment_signature_test() {
+        let node_ctx = test_node_ctx(1);
+
+        let channel_amount = 3_000_000;
+        let chan_ctx = fund_test_channel(&node_ctx, channel_amount);
+        let offered_htlcs = vec![HTLCInfo2 {
+            value_sat: 10_000,
+            payment_hash: PaymentHash([1; 32]),
+            cltv_expiry: 1 << 16,
+        }];
+
+        let commit_num = 1;
+        let feerate_per_kw = 1100;
+        let to_broadcaster = 1_000_000;
+        let to_countersignatory = channel_amount - to_broadcaster - 10_000 - 20_000;
+
+        let mut commit_tx_ctx = channel_commitment(
+            &node_ctx,
+            &chan_ctx,
+            commit_num,
+            feerate_per_kw,
+            to_broadcaster,
+            to_countersignatory,
+            offered_htlcs.clone(),
+            vec![],
+        );
+        let (csig, _) =
+            counterparty_sign_holder_commitment(&node_ctx, &chan_ctx, &mut commit_tx_ctx);
+        let htlcs = Channel::htlcs_info2_to_oic(offered_htlcs, vec![]);
+
+        node_ctx
+            .node
+            .with_ready_channel(&chan_ctx.channel_id, |chan| {
+                let tx = commit_tx_ctx
+                    .tx
+                    .as_ref()
+                    .unwrap()
+                    .trust()
+                    .built_transaction()
+                    .transaction
+                    .clone();
+                let point = chan.get_per_commitment_point(commit_num)?;
+                let witscripts = holder_commitment_witscripts(chan, &commit_tx_ctx, &point, &htlcs);
+                chan.verify_counterparty_commitment_signature(
+                    &tx,
+                    &witscripts,
+                    &csig,
+                    &point,
+                    commit_num,
+                )?;
+
+                assert_invalid_argument_err!(
+                    chan.verify_counterparty_commitment_signature(
+                        &tx,
+                        &witscripts,
+                        &csig,
+                        &point,
+                        commit_num + 1,
+                    ),
+                    "commitment tx is not for commit_num 2"
+                );
+
+                let other_point = make_test_pubkey(42);
+                assert_invalid_argument_err!(
+                    chan.verify_counterparty_commitment_signature(
+                        &tx,
+                        &witscripts,
+                        &csig,
+                        &other_point,
+                        commit_num,
+                    ),
+                    format!("commitment tx does not match per_commitment_point {}", other_point)
+                );
+
+                let mut bad_tx = tx.clone();
+                bad_tx.output[0].value -= 1;
+                assert_failed_precondition_err!(
+                    chan.verify_counterparty_commitment_signature(
+                        &bad_tx,
+                        &witscripts,
+                        &csig,
+                        &point,
+                        commit_num,
+                    ),
+                    "policy failure: commit sig verify failed: secp: signature failed verification"
+                );
+                Ok(())
+            })
+            .expect("verify");
+    }
+
     // policy-revoke-new-commitment-signed
     #[test]
     fn validate_holder_commitment_with_bad_commit_num() {
@@ -701,6 +793,32 @@ mod tests {
              commit sig verify failed for htlc 0: secp: signature failed verification"
     );
 
+    generate_failed_precondition_error_with_mutated_validation_input!(
+        bad_last_htlc_sig,
+        |vms| {
+            let ndx = vms.htlc_sigs.len() - 1;
+            vms.htlc_sigs[ndx] = vms.htlc_sigs[0].clone();
+        },
+        |vs| {
+            // Channel state should not advance.
+            assert_eq!(vs.chan.enforcement_state.next_holder_commit_num, HOLD_COMMIT_NUM);
+        },
+        |_| "policy failure: \
+             commit sig verify failed for htlc 2: secp: signature failed verification"
+    );
+
+    generate_failed_precondition_error_with_mutated_validation_input!(
+        missing_htlc_sig,
+        |vms| {
+            vms.htlc_sigs.pop();
+        },
+        |vs| {
+            // Channel state should not advance.
+            assert_eq!(vs.chan.enforcement_state.next_holder_commit_num, HOLD_COMMIT_NUM);
+        },
+        |_| "policy failure: htlc sig count mismatch: 2 != 3"
+    );
+
     generate_failed_precondition_error_with_mutated_validation_input!(
         not_ahead,
         |vms| {
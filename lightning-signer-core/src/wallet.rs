@@ -20,4 +20,42 @@ pub trait Wallet {
 
     /// Returns the wrapped segwit address at path
     fn get_wrapped_address(&self, child_path: &Vec<u32>) -> Result<Address, Status>;
+
+    /// Returns the derivation path of the wallet key which can spend the
+    /// given output, searching the first `search_range` indices
+    fn find_wallet_path(&self, script_pubkey: &Script, search_range: u32) -> Option<Vec<u32>> {
+        (0..search_range)
+            .map(|index| vec![index])
+            .find(|path| self.can_spend(path, script_pubkey).unwrap_or(false))
+    }
+}
+
+/// A wallet which additionally accepts a set of transiently authorized
+/// destination scripts.  The extra scripts are never persisted.
+pub(crate) struct AuthorizedScriptsWallet<'a> {
+    pub(crate) wallet: &'a dyn Wallet,
+    pub(crate) authorized_scripts: &'a [Script],
+}
+
+impl<'a> Wallet for AuthorizedScriptsWallet<'a> {
+    fn can_spend(&self, child_path: &Vec<u32>, script_pubkey: &Script) -> Result<bool, Status> {
+        self.wallet.can_spend(child_path, script_pubkey)
+    }
+
+    fn allowlist_contains(&self, script_pubkey: &Script) -> bool {
+        self.authorized_scripts.contains(script_pubkey)
+            || self.wallet.allowlist_contains(script_pubkey)
+    }
+
+    fn network(&self) -> Network {
+        self.wallet.network()
+    }
+
+    fn get_native_address(&self, child_path: &Vec<u32>) -> Result<Address, Status> {
+        self.wallet.get_native_address(child_path)
+    }
+
+    fn get_wrapped_address(&self, child_path: &Vec<u32>) -> Result<Address, Status> {
+        self.wallet.get_wrapped_address(child_path)
+    }
 }
@@ -4,6 +4,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::iter::FromIterator;
 
+use crate::lightning::ln::chan_utils::ChannelPublicKeys;
 use bitcoin::consensus::{deserialize, serialize};
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{Network, OutPoint};
@@ -13,18 +14,21 @@ use serde::{Deserialize, Serialize};
 use serde_with::hex::Hex;
 use serde_with::serde_as;
 
+use lightning_signer::channel::Channel;
 use lightning_signer::channel::ChannelId;
 use lightning_signer::channel::ChannelSetup;
 use lightning_signer::monitor::ChainMonitor;
 use lightning_signer::monitor::State as ChainMonitorState;
+use lightning_signer::node::Capabilities;
 use lightning_signer::persist::model::{
     ChannelEntry as CoreChannelEntry, NodeEntry as CoreNodeEntry,
 };
 use lightning_signer::policy::validator::EnforcementState;
+use lightning_signer::util::{DEFAULT_MIN_INVOICE_CLTV_EXPIRY, INITIAL_COMMITMENT_NUMBER};
 
 use super::ser_util::{
-    ChainMonitorStateDef, ChannelIdHandler, ChannelSetupDef, EnforcementStateDef, ListenSlotDef,
-    OutPointDef,
+    ChainMonitorStateDef, ChannelIdHandler, ChannelPublicKeysDef, ChannelSetupDef,
+    EnforcementStateDef, ListenSlotDef, OutPointDef,
 };
 
 #[serde_as]
@@ -34,6 +38,40 @@ pub struct NodeEntry {
     pub seed: Vec<u8>,
     pub key_derivation_style: u8,
     pub network: String,
+    #[serde(default)]
+    pub require_static_remote_key: bool,
+    #[serde(default)]
+    pub low_r_grinding: bool,
+    // Nodes persisted before the RBF check existed accepted RBF funding
+    #[serde(default = "default_allow_rbf_funding")]
+    pub allow_rbf_funding: bool,
+    #[serde(default = "default_maximum_commitment_number")]
+    pub maximum_commitment_number: u64,
+    #[serde(default)]
+    pub export_allowed: bool,
+    #[serde(default)]
+    pub allowlist_match_derivable: bool,
+    #[serde(default = "default_min_invoice_cltv_expiry")]
+    pub min_invoice_cltv_expiry: u32,
+    // Nodes persisted before capabilities existed could do everything
+    #[serde(default = "default_capabilities")]
+    pub capabilities: u32,
+}
+
+fn default_allow_rbf_funding() -> bool {
+    true
+}
+
+fn default_maximum_commitment_number() -> u64 {
+    INITIAL_COMMITMENT_NUMBER
+}
+
+fn default_min_invoice_cltv_expiry() -> u32 {
+    DEFAULT_MIN_INVOICE_CLTV_EXPIRY
+}
+
+fn default_capabilities() -> u32 {
+    Capabilities::ALL.bits()
 }
 
 impl From<NodeEntry> for CoreNodeEntry {
@@ -42,6 +80,14 @@ impl From<NodeEntry> for CoreNodeEntry {
             seed: e.seed,
             key_derivation_style: e.key_derivation_style,
             network: e.network,
+            require_static_remote_key: e.require_static_remote_key,
+            low_r_grinding: e.low_r_grinding,
+            allow_rbf_funding: e.allow_rbf_funding,
+            maximum_commitment_number: e.maximum_commitment_number,
+            export_allowed: e.export_allowed,
+            allowlist_match_derivable: e.allowlist_match_derivable,
+            min_invoice_cltv_expiry: e.min_invoice_cltv_expiry,
+            capabilities: e.capabilities,
         }
     }
 }
@@ -59,6 +105,23 @@ pub struct ChannelEntry {
     pub id: Option<ChannelId>,
     #[serde_as(as = "EnforcementStateDef")]
     pub enforcement_state: EnforcementState,
+    // Counterparty points supplied to a stub before it was readied
+    #[serde(default)]
+    #[serde_as(as = "Option<ChannelPublicKeysDef>")]
+    pub counterparty_points: Option<ChannelPublicKeys>,
+}
+
+impl From<&Channel> for ChannelEntry {
+    fn from(channel: &Channel) -> Self {
+        ChannelEntry {
+            nonce: channel.nonce.clone(),
+            channel_value_satoshis: channel.setup.channel_value_sat,
+            channel_setup: Some(channel.setup.clone()),
+            id: channel.id,
+            enforcement_state: channel.enforcement_state.clone(),
+            counterparty_points: None,
+        }
+    }
 }
 
 impl From<ChannelEntry> for CoreChannelEntry {
@@ -69,6 +132,7 @@ impl From<ChannelEntry> for CoreChannelEntry {
             channel_setup: e.channel_setup,
             id: e.id,
             enforcement_state: e.enforcement_state,
+            counterparty_points: e.counterparty_points,
         }
     }
 }
@@ -79,6 +143,16 @@ pub struct AllowlistItemEntry {
     pub allowlist: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionKeyCounterEntry {
+    pub counter: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockHeightEntry {
+    pub height: u32,
+}
+
 /// Fully qualified channel ID
 #[derive(Clone)]
 pub struct NodeChannelId(Vec<u8>);
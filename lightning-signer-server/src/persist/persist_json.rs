@@ -1,5 +1,6 @@
 use kv::{Bucket, Config, Json, Store, TransactionError};
 
+use crate::lightning::ln::chan_utils::ChannelPublicKeys;
 use bitcoin::secp256k1::PublicKey;
 use lightning_signer::chain::tracker::ChainTracker;
 
@@ -15,7 +16,9 @@ use log::error;
 
 use crate::persist::model::ChainTrackerEntry;
 use crate::persist::model::NodeChannelId;
-use crate::persist::model::{AllowlistItemEntry, ChannelEntry, NodeEntry};
+use crate::persist::model::{
+    AllowlistItemEntry, BlockHeightEntry, ChannelEntry, NodeEntry, SessionKeyCounterEntry,
+};
 
 /// A persister that uses the kv crate and JSON serialization for values.
 pub struct KVJsonPersister<'a> {
@@ -23,6 +26,8 @@ pub struct KVJsonPersister<'a> {
     pub channel_bucket: Bucket<'a, NodeChannelId, Json<ChannelEntry>>,
     pub allowlist_bucket: Bucket<'a, Vec<u8>, Json<AllowlistItemEntry>>,
     pub chain_tracker_bucket: Bucket<'a, Vec<u8>, Json<ChainTrackerEntry>>,
+    pub session_key_counter_bucket: Bucket<'a, Vec<u8>, Json<SessionKeyCounterEntry>>,
+    pub block_height_bucket: Bucket<'a, Vec<u8>, Json<BlockHeightEntry>>,
 }
 
 impl KVJsonPersister<'_> {
@@ -34,7 +39,43 @@ impl KVJsonPersister<'_> {
         let allowlist_bucket = store.bucket(Some("allowlists")).expect("create allowlist bucket");
         let chain_tracker_bucket =
             store.bucket(Some("chain_tracker")).expect("create chain tracker bucket");
-        Self { node_bucket, channel_bucket, allowlist_bucket, chain_tracker_bucket }
+        let session_key_counter_bucket =
+            store.bucket(Some("session_key_counters")).expect("create session key counter bucket");
+        let block_height_bucket =
+            store.bucket(Some("block_heights")).expect("create block height bucket");
+        Self {
+            node_bucket,
+            channel_bucket,
+            allowlist_bucket,
+            chain_tracker_bucket,
+            session_key_counter_bucket,
+            block_height_bucket,
+        }
+    }
+}
+
+impl KVJsonPersister<'_> {
+    /// Set the export permission of every persisted node.
+    ///
+    /// The permission is a server setting rather than a property of the
+    /// node, and entries written before it was persisted restore with export
+    /// disabled.  Call this before restoring the nodes.
+    pub fn set_export_allowed(&self, export_allowed: bool) {
+        let mut updates = Vec::new();
+        for item_res in self.node_bucket.iter() {
+            let item = item_res.unwrap();
+            let key: Vec<u8> = item.key().unwrap();
+            let value: Json<NodeEntry> = item.value().unwrap();
+            let mut entry = value.0;
+            if entry.export_allowed != export_allowed {
+                entry.export_allowed = export_allowed;
+                updates.push((key, entry));
+            }
+        }
+        for (key, entry) in updates {
+            self.node_bucket.set(key, Json(entry)).expect("update node");
+        }
+        self.node_bucket.flush().expect("flush");
     }
 }
 
@@ -46,6 +87,14 @@ impl<'a> Persist for KVJsonPersister<'a> {
             seed: seed.to_vec(),
             key_derivation_style: config.key_derivation_style as u8,
             network: config.network.to_string(),
+            require_static_remote_key: config.require_static_remote_key,
+            low_r_grinding: config.low_r_grinding,
+            allow_rbf_funding: config.allow_rbf_funding,
+            maximum_commitment_number: config.maximum_commitment_number,
+            export_allowed: config.export_allowed,
+            allowlist_match_derivable: config.allowlist_match_derivable,
+            min_invoice_cltv_expiry: config.min_invoice_cltv_expiry,
+            capabilities: config.capabilities.bits(),
         };
         self.node_bucket.set(key, Json(entry)).expect("insert node");
         self.node_bucket.flush().expect("flush");
@@ -58,7 +107,9 @@ impl<'a> Persist for KVJsonPersister<'a> {
         }
         let key = node_id.serialize().to_vec();
         self.node_bucket.remove(key.clone()).unwrap();
-        self.chain_tracker_bucket.remove(key).unwrap();
+        self.chain_tracker_bucket.remove(key.clone()).unwrap();
+        self.session_key_counter_bucket.remove(key.clone()).unwrap();
+        self.block_height_bucket.remove(key).unwrap();
     }
 
     fn new_channel(&self, node_id: &PublicKey, stub: &ChannelStub) -> Result<(), ()> {
@@ -73,6 +124,7 @@ impl<'a> Persist for KVJsonPersister<'a> {
                     channel_setup: None,
                     id: None,
                     enforcement_state: EnforcementState::new(0),
+                    counterparty_points: None,
                 };
                 if txn.get(id.clone()).unwrap().is_some() {
                     return Err(TransactionError::Abort(kv::Error::Message(
@@ -87,6 +139,38 @@ impl<'a> Persist for KVJsonPersister<'a> {
         Ok(())
     }
 
+    fn delete_channel(&self, node_id: &PublicKey, channel_id: &ChannelId) -> Result<(), ()> {
+        let id = NodeChannelId::new(node_id, channel_id);
+        if !self.channel_bucket.contains(id.clone()).unwrap() {
+            error!("delete_channel: channel {} not found", channel_id);
+            return Err(());
+        }
+        self.channel_bucket.remove(id).expect("remove channel");
+        self.channel_bucket.flush().expect("flush");
+        Ok(())
+    }
+
+    fn update_channel_stub_points(
+        &self,
+        node_id: &PublicKey,
+        channel_id: &ChannelId,
+        points: &ChannelPublicKeys,
+    ) -> Result<(), ()> {
+        let id = NodeChannelId::new(node_id, channel_id);
+        let value = self.channel_bucket.get(id.clone()).unwrap().ok_or_else(|| {
+            error!("update_channel_stub_points: channel {} not found", channel_id);
+        })?;
+        let mut entry = value.0;
+        if entry.channel_setup.is_some() {
+            error!("update_channel_stub_points: channel {} is not a stub", channel_id);
+            return Err(());
+        }
+        entry.counterparty_points = Some(points.clone());
+        self.channel_bucket.set(id, Json(entry)).expect("update channel");
+        self.channel_bucket.flush().expect("flush");
+        Ok(())
+    }
+
     fn new_chain_tracker(&self, node_id: &PublicKey, tracker: &ChainTracker<ChainMonitor>) {
         let key = node_id.serialize().to_vec();
         assert!(!self.chain_tracker_bucket.contains(key.clone()).unwrap());
@@ -112,28 +196,26 @@ impl<'a> Persist for KVJsonPersister<'a> {
     }
 
     fn update_channel(&self, node_id: &PublicKey, channel: &Channel) -> Result<(), ()> {
-        let channel_value_satoshis = channel.setup.channel_value_sat;
+        self.update_channels_batch(node_id, &[channel])
+    }
 
+    fn update_channels_batch(&self, node_id: &PublicKey, updates: &[&Channel]) -> Result<(), ()> {
         self.channel_bucket
             .transaction(|txn| {
-                let node_channel_id = NodeChannelId::new(node_id, &channel.id0);
-                let entry = ChannelEntry {
-                    nonce: channel.nonce.clone(),
-                    channel_value_satoshis,
-                    channel_setup: Some(channel.setup.clone()),
-                    id: channel.id,
-                    enforcement_state: channel.enforcement_state.clone(),
-                };
-                if txn.get(node_channel_id.clone()).unwrap().is_none() {
-                    return Err(TransactionError::Abort(kv::Error::Message(
-                        "not found".to_string(),
-                    )));
+                for channel in updates {
+                    let node_channel_id = NodeChannelId::new(node_id, &channel.id0);
+                    if txn.get(node_channel_id.clone()).unwrap().is_none() {
+                        return Err(TransactionError::Abort(kv::Error::Message(format!(
+                            "channel {} not found",
+                            channel.id0
+                        ))));
+                    }
+                    txn.set(node_channel_id, Json(ChannelEntry::from(*channel)))
+                        .expect("update channel");
                 }
-                let json = Json(entry);
-                txn.set(node_channel_id, json).expect("update channel");
                 Ok(())
             })
-            .expect("update transaction");
+            .map_err(|err| error!("update_channels_batch: {}", err))?;
         self.channel_bucket.flush().expect("flush");
         Ok(())
     }
@@ -185,6 +267,44 @@ impl<'a> Persist for KVJsonPersister<'a> {
         entry2.unwrap().0.allowlist
     }
 
+    fn update_node_session_key_counter(&self, node_id: &PublicKey, counter: u64) -> Result<(), ()> {
+        let key = node_id.serialize().to_vec();
+        let entry = SessionKeyCounterEntry { counter };
+        self.session_key_counter_bucket.set(key, Json(entry)).map_err(|_| ())?;
+        self.session_key_counter_bucket.flush().map_err(|_| ())?;
+        Ok(())
+    }
+
+    fn get_node_session_key_counter(&self, node_id: &PublicKey) -> u64 {
+        let key = node_id.serialize().to_vec();
+        match self.session_key_counter_bucket.get(key) {
+            Ok(entry) => entry.map(|e| e.0.counter).unwrap_or(0),
+            Err(err) => {
+                error!("session key counter entry error {:?}", err);
+                0
+            }
+        }
+    }
+
+    fn update_node_block_height(&self, node_id: &PublicKey, height: u32) -> Result<(), ()> {
+        let key = node_id.serialize().to_vec();
+        let entry = BlockHeightEntry { height };
+        self.block_height_bucket.set(key, Json(entry)).map_err(|_| ())?;
+        self.block_height_bucket.flush().map_err(|_| ())?;
+        Ok(())
+    }
+
+    fn get_node_block_height(&self, node_id: &PublicKey) -> u32 {
+        let key = node_id.serialize().to_vec();
+        match self.block_height_bucket.get(key) {
+            Ok(entry) => entry.map(|e| e.0.height).unwrap_or(0),
+            Err(err) => {
+                error!("block height entry error {:?}", err);
+                0
+            }
+        }
+    }
+
     fn get_nodes(&self) -> Vec<(PublicKey, CoreNodeEntry)> {
         let mut res = Vec::new();
         for item_res in self.node_bucket.iter() {
@@ -197,9 +317,25 @@ impl<'a> Persist for KVJsonPersister<'a> {
         res
     }
 
+    fn list_node_ids(&self) -> Vec<PublicKey> {
+        let mut res = Vec::new();
+        for item_res in self.node_bucket.iter() {
+            let key: Vec<u8> = item_res.unwrap().key().unwrap();
+            res.push(PublicKey::from_slice(key.as_slice()).unwrap());
+        }
+        res
+    }
+
+    fn get_node(&self, node_id: &PublicKey) -> Option<CoreNodeEntry> {
+        let key = node_id.serialize().to_vec();
+        self.node_bucket.get(key).unwrap().map(|value| CoreNodeEntry::from(value.0))
+    }
+
     fn clear_database(&self) {
         self.channel_bucket.clear().unwrap();
         self.node_bucket.clear().unwrap();
+        self.session_key_counter_bucket.clear().unwrap();
+        self.block_height_bucket.clear().unwrap();
     }
 }
 
@@ -249,7 +385,8 @@ mod tests {
             persister.new_chain_tracker(&node_id, &node.get_tracker());
             persister.new_channel(&node_id, &stub).unwrap();
 
-            let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone());
+            let nodes =
+                Node::restore_nodes(Arc::clone(&persister), validator_factory.clone()).unwrap();
             let restored_node = nodes.get(&node_id).unwrap();
 
             {
@@ -275,7 +412,8 @@ mod tests {
                     node.ready_channel(channel_id0, Some(channel_id1), setup, &vec![]).unwrap();
                 persister.update_channel(&node_id, &channel).unwrap();
 
-                let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone());
+                let nodes =
+                    Node::restore_nodes(Arc::clone(&persister), validator_factory.clone()).unwrap();
                 let restored_node_arc = nodes.get(&node_id).unwrap();
                 let slot = restored_node_arc.get_channel(&stub.id0).unwrap();
                 assert!(node.channels().contains_key(&channel_id0));
@@ -294,6 +432,9 @@ mod tests {
             let persister1 = KVJsonPersister::new(path.as_str());
             let nodes = persister1.get_nodes();
             assert_eq!(nodes.len(), 1);
+            let node_ids = persister1.list_node_ids();
+            assert_eq!(node_ids, vec![nodes[0].0]);
+            assert_eq!(persister1.get_node(&node_ids[0]).unwrap().seed, nodes[0].1.seed);
         }
 
         drop(temp_dir);
@@ -302,9 +443,195 @@ mod tests {
             let persister1 = KVJsonPersister::new(path.as_str());
             let nodes = persister1.get_nodes();
             assert_eq!(nodes.len(), 0);
+            assert!(persister1.list_node_ids().is_empty());
         }
     }
 
+    #[test]
+    fn restore_node_skips_bad_allowlist_entries_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        let good = "tb1qhetd7l0rv6kca6wvmt25ax5ej05eaat9q29z7z".to_string();
+        let wrong_network = "bc1qhetd7l0rv6kca6wvmt25ax5ej05eaat92v7393".to_string();
+        let garbage = "not-an-address".to_string();
+        persister
+            .update_node_allowlist(&node_id, vec![good.clone(), wrong_network, garbage])
+            .unwrap();
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory).unwrap();
+        let restored_node = nodes.get(&node_id).unwrap();
+        assert_eq!(restored_node.allowlist().unwrap(), vec![good]);
+    }
+
+    #[test]
+    fn delete_channel_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, _node_arc, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_channel(&node_id, &stub).unwrap();
+        assert!(persister.get_channel(&node_id, &channel_id0).is_ok());
+
+        persister.delete_channel(&node_id, &channel_id0).unwrap();
+        assert!(persister.get_channel(&node_id, &channel_id0).is_err());
+        assert!(persister.get_node_channels(&node_id).is_empty());
+        assert!(persister.delete_channel(&node_id, &channel_id0).is_err());
+    }
+
+    #[test]
+    fn update_missing_channel_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, node_arc, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let channel = node_arc.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        assert!(persister.update_channel(&node_id, &channel).is_err());
+        assert!(persister.update_channels_batch(&node_id, &[&channel]).is_err());
+
+        persister.new_channel(&node_id, &stub).unwrap();
+        persister.update_channels_batch(&node_id, &[&channel]).unwrap();
+        let entry = persister.get_channel(&node_id, &channel_id0).unwrap();
+        assert_eq!(entry.channel_setup.unwrap().channel_value_sat, channel.setup.channel_value_sat);
+    }
+
+    #[test]
+    fn restore_node_fails_on_inconsistent_channel_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let setup = create_test_channel_setup(make_dummy_pubkey(0x12));
+        let mut channel = node_arc.ready_channel(channel_id0, None, setup, &vec![]).unwrap();
+        channel.enforcement_state.next_counterparty_revoke_num = 1;
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        persister.new_channel(&node_id, &stub).unwrap();
+        persister.update_channel(&node_id, &channel).unwrap();
+
+        let err = Node::restore_nodes(Arc::clone(&persister), validator_factory).err().unwrap();
+        assert_eq!(
+            err.message(),
+            format!("restore_node: node {}: could not restore channels: {}", node_id, channel_id0)
+        );
+    }
+
+    #[test]
+    fn restore_node_export_allowed_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+
+        // Rewrite the entry as it was persisted before export_allowed existed
+        let key = node_id.serialize().to_vec();
+        let entry = persister.node_bucket.get(key.clone()).unwrap().unwrap();
+        let mut value = serde_json::to_value(&entry.0).unwrap();
+        value.as_object_mut().unwrap().remove("export_allowed").unwrap();
+        let old_entry: NodeEntry = serde_json::from_value(value).unwrap();
+        assert!(!old_entry.export_allowed);
+        persister.node_bucket.set(key, Json(old_entry)).unwrap();
+
+        persister.set_export_allowed(true);
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone()).unwrap();
+        assert!(nodes.get(&node_id).unwrap().get_node_secret().is_ok());
+
+        assert!(persister.get_node(&node_id).unwrap().export_allowed);
+    }
+
+    #[test]
+    fn channel_stub_points_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let (node_id, _node_arc, stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+        let points = make_test_channel_setup().counterparty_points;
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        assert!(persister.update_channel_stub_points(&node_id, &channel_id0, &points).is_err());
+        persister.new_channel(&node_id, &stub).unwrap();
+        assert!(persister
+            .get_channel(&node_id, &channel_id0)
+            .unwrap()
+            .counterparty_points
+            .is_none());
+
+        persister.update_channel_stub_points(&node_id, &channel_id0, &points).unwrap();
+        let entry = persister.get_channel(&node_id, &channel_id0).unwrap();
+        assert!(entry.counterparty_points == Some(points));
+    }
+
+    #[test]
+    fn session_key_counter_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        assert_eq!(persister.get_node_session_key_counter(&node_id), 0);
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone()).unwrap();
+        let node = nodes.get(&node_id).unwrap();
+        let key0 = node.rotate_session_key().unwrap();
+        let key1 = node.rotate_session_key().unwrap();
+        assert_ne!(key0, key1);
+        assert_eq!(persister.get_node_session_key_counter(&node_id), 2);
+
+        // The counter continues after a restart
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory).unwrap();
+        let node = nodes.get(&node_id).unwrap();
+        node.rotate_session_key().unwrap();
+        assert_eq!(persister.get_node_session_key_counter(&node_id), 3);
+    }
+
+    #[test]
+    fn block_height_test() {
+        let channel_nonce = "nonce0".as_bytes().to_vec();
+        let channel_id0 = channel_nonce_to_id(&channel_nonce);
+        let validator_factory = Arc::new(SimpleValidatorFactory::new());
+        let (node_id, node_arc, _stub, seed) = make_node_and_channel(&channel_nonce, channel_id0);
+
+        let (persister, _temp_dir, _path) = make_temp_persister();
+        let persister: Arc<dyn Persist> = Arc::new(persister);
+        persister.new_node(&node_id, &TEST_NODE_CONFIG, &seed);
+        persister.new_chain_tracker(&node_id, &node_arc.get_tracker());
+        assert_eq!(persister.get_node_block_height(&node_id), 0);
+
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory.clone()).unwrap();
+        let node = nodes.get(&node_id).unwrap();
+        let tracker_height = node.get_block_height();
+        node.notify_new_block(tracker_height + 100, [0; 32]).unwrap();
+        assert_eq!(persister.get_node_block_height(&node_id), tracker_height + 100);
+
+        // The height survives a restart
+        let nodes = Node::restore_nodes(Arc::clone(&persister), validator_factory).unwrap();
+        let node = nodes.get(&node_id).unwrap();
+        assert_eq!(node.get_block_height(), tracker_height + 100);
+    }
+
     fn check_signer_roundtrip(existing_signer: &InMemorySigner, signer: &InMemorySigner) {
         let mut existing_w = VecWriter(Vec::new());
         existing_signer.write(&mut existing_w).unwrap();
@@ -5,7 +5,7 @@
 
 use std::borrow::Cow;
 use std::collections::BTreeSet as Set;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use crate::lightning;
 use bitcoin::hashes::Hash;
@@ -21,7 +21,7 @@ use serde_with::{DeserializeAs, SerializeAs};
 
 use lightning_signer::channel::{ChannelId, ChannelSetup, CommitmentType};
 use lightning_signer::monitor::State as ChainMonitorState;
-use lightning_signer::policy::validator::EnforcementState;
+use lightning_signer::policy::validator::{EnforcementState, ENFORCEMENT_STATE_VERSION};
 use lightning_signer::tx::tx::{CommitmentInfo2, HTLCInfo2};
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -174,6 +174,7 @@ pub enum CommitmentTypeDef {
     Legacy,
     StaticRemoteKey,
     Anchors,
+    SimpleTaprootChannels,
 }
 
 #[derive(Deserialize)]
@@ -242,11 +243,19 @@ pub struct ChannelSetupDef {
     pub holder_shutdown_script: Option<Script>,
     #[serde(with = "ChannelPublicKeysDef")]
     pub counterparty_points: ChannelPublicKeys,
+    #[serde_as(as = "Option<PublicKeyHandler>")]
+    #[serde(default)]
+    pub counterparty_node_id: Option<PublicKey>,
     pub counterparty_selected_contest_delay: u16,
     #[serde_as(as = "Option<ScriptDef>")]
     pub counterparty_shutdown_script: Option<Script>,
     #[serde_as(as = "CommitmentTypeDef")]
     pub commitment_type: CommitmentType,
+    #[serde(default)]
+    pub splice_pending_amount: Option<u64>,
+    #[serde_as(as = "Option<(OutPointDef, _)>")]
+    #[serde(default)]
+    pub splice_funding: Option<(OutPoint, u64)>,
 }
 
 #[derive(Deserialize)]
@@ -370,9 +379,10 @@ impl<'de> DeserializeAs<'de, CommitmentInfo2> for CommitmentInfo2Def {
 }
 
 #[serde_as]
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 #[serde(remote = "EnforcementState")]
 pub struct EnforcementStateDef {
+    pub version: u32,
     pub next_holder_commit_num: u64,
     pub next_counterparty_commit_num: u64,
     pub next_counterparty_revoke_num: u64,
@@ -385,12 +395,81 @@ pub struct EnforcementStateDef {
     #[serde_as(as = "Option<CommitmentInfo2Def>")]
     pub previous_counterparty_commit_info: Option<CommitmentInfo2>,
     pub mutual_close_signed: bool,
-    #[serde(default)] // TODO remove default once everyone upgrades
     pub initial_holder_value: u64,
+    pub counterparty_point_history: Vec<(u64, PublicKey)>,
+    #[serde_as(as = "Option<(OutPointDef, _)>")]
+    pub funding_confirmation: Option<(OutPoint, u32)>,
+    pub total_fees_paid_msat: u64,
+}
+
+// Entries written before the version field was introduced are version 1
+fn default_enforcement_state_version() -> u32 {
+    1
 }
 
+/// The persisted form of an [EnforcementState], of any version up to the
+/// current one.  Fields added after version 1 must have a serde default.
+#[serde_as]
 #[derive(Deserialize)]
-struct EnforcementStateHelper(#[serde(with = "EnforcementStateDef")] EnforcementState);
+pub struct StoredEnforcementState {
+    #[serde(default = "default_enforcement_state_version")]
+    pub version: u32,
+    pub next_holder_commit_num: u64,
+    pub next_counterparty_commit_num: u64,
+    pub next_counterparty_revoke_num: u64,
+    pub current_counterparty_point: Option<PublicKey>,
+    pub previous_counterparty_point: Option<PublicKey>,
+    #[serde_as(as = "Option<CommitmentInfo2Def>")]
+    pub current_holder_commit_info: Option<CommitmentInfo2>,
+    #[serde_as(as = "Option<CommitmentInfo2Def>")]
+    pub current_counterparty_commit_info: Option<CommitmentInfo2>,
+    #[serde_as(as = "Option<CommitmentInfo2Def>")]
+    pub previous_counterparty_commit_info: Option<CommitmentInfo2>,
+    pub mutual_close_signed: bool,
+    #[serde(default)] // TODO remove default once everyone upgrades
+    pub initial_holder_value: u64,
+    // added in version 2
+    #[serde(default)]
+    pub counterparty_point_history: Vec<(u64, PublicKey)>,
+    // added in version 3
+    #[serde_as(as = "Option<(OutPointDef, _)>")]
+    #[serde(default)]
+    pub funding_confirmation: Option<(OutPoint, u32)>,
+    // added in version 4
+    #[serde(default)]
+    pub total_fees_paid_msat: u64,
+}
+
+impl TryFrom<StoredEnforcementState> for EnforcementState {
+    type Error = String;
+
+    fn try_from(s: StoredEnforcementState) -> Result<Self, Self::Error> {
+        if s.version > ENFORCEMENT_STATE_VERSION {
+            return Err(format!(
+                "unsupported enforcement state version {} > {}",
+                s.version, ENFORCEMENT_STATE_VERSION
+            ));
+        }
+        // Older versions lack the point history, funding confirmation and
+        // fee total, which the serde defaults leave empty.
+        Ok(EnforcementState {
+            version: ENFORCEMENT_STATE_VERSION,
+            next_holder_commit_num: s.next_holder_commit_num,
+            next_counterparty_commit_num: s.next_counterparty_commit_num,
+            next_counterparty_revoke_num: s.next_counterparty_revoke_num,
+            current_counterparty_point: s.current_counterparty_point,
+            previous_counterparty_point: s.previous_counterparty_point,
+            current_holder_commit_info: s.current_holder_commit_info,
+            current_counterparty_commit_info: s.current_counterparty_commit_info,
+            previous_counterparty_commit_info: s.previous_counterparty_commit_info,
+            mutual_close_signed: s.mutual_close_signed,
+            initial_holder_value: s.initial_holder_value,
+            counterparty_point_history: s.counterparty_point_history,
+            funding_confirmation: s.funding_confirmation,
+            total_fees_paid_msat: s.total_fees_paid_msat,
+        })
+    }
+}
 
 impl SerializeAs<EnforcementState> for EnforcementStateDef {
     fn serialize_as<S>(value: &EnforcementState, serializer: S) -> Result<S::Ok, S::Error>
@@ -408,7 +487,9 @@ impl<'de> DeserializeAs<'de, EnforcementState> for EnforcementStateDef {
     where
         D: Deserializer<'de>,
     {
-        EnforcementStateHelper::deserialize(deserializer).map(|h| h.0)
+        StoredEnforcementState::deserialize(deserializer)?
+            .try_into()
+            .map_err(serde::de::Error::custom)
     }
 }
 
@@ -457,6 +538,10 @@ pub struct ChainMonitorStateDef {
     funding_outpoint: Option<OutPoint>,
     funding_double_spent_height: Option<u32>,
     closing_height: Option<u32>,
+    #[serde(default)]
+    splice_outpoint: Option<OutPoint>,
+    #[serde(default)]
+    pre_splice_funding: Option<(OutPoint, u32)>,
 }
 
 #[derive(Deserialize)]
@@ -513,4 +598,64 @@ mod tests {
         let _tracker_de: ChainTracker<ChainMonitor> = entry_de.into();
         Ok(())
     }
+
+    #[test]
+    fn test_enforcement_state_v1() {
+        let json = r#"{
+            "next_holder_commit_num": 3,
+            "next_counterparty_commit_num": 4,
+            "next_counterparty_revoke_num": 3,
+            "current_counterparty_point": null,
+            "previous_counterparty_point": null,
+            "current_holder_commit_info": null,
+            "current_counterparty_commit_info": null,
+            "previous_counterparty_commit_info": null,
+            "mutual_close_signed": false
+        }"#;
+        let stored: StoredEnforcementState = serde_json::from_str(json).expect("de json");
+        assert_eq!(stored.version, 1);
+        let state = EnforcementState::try_from(stored).expect("migrate");
+        assert_eq!(state.version, ENFORCEMENT_STATE_VERSION);
+        assert_eq!(state.next_counterparty_commit_num, 4);
+        assert_eq!(state.initial_holder_value, 0);
+        assert!(state.counterparty_point_history.is_empty());
+        assert!(state.funding_confirmation.is_none());
+        assert_eq!(state.total_fees_paid_msat, 0);
+    }
+
+    #[test]
+    fn test_enforcement_state_funding_confirmation() {
+        #[serde_as]
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde_as(as = "EnforcementStateDef")] EnforcementState);
+
+        let outpoint = OutPoint::new(Txid::from_slice(&[2u8; 32]).unwrap(), 1);
+        let mut state = EnforcementState::new(0);
+        state.funding_confirmation = Some((outpoint, 100));
+        let json = serde_json::to_string(&Wrapper(state)).expect("json");
+        assert!(json.contains("\"vout\":1"));
+        let Wrapper(state_de) = serde_json::from_str(&json).expect("de json");
+        assert_eq!(state_de.funding_confirmation, Some((outpoint, 100)));
+    }
+
+    #[test]
+    fn test_enforcement_state_future_version() {
+        let json = r#"{
+            "version": 99,
+            "next_holder_commit_num": 0,
+            "next_counterparty_commit_num": 0,
+            "next_counterparty_revoke_num": 0,
+            "current_counterparty_point": null,
+            "previous_counterparty_point": null,
+            "current_holder_commit_info": null,
+            "current_counterparty_commit_info": null,
+            "previous_counterparty_commit_info": null,
+            "mutual_close_signed": false
+        }"#;
+        let stored: StoredEnforcementState = serde_json::from_str(json).expect("de json");
+        assert_eq!(
+            EnforcementState::try_from(stored).unwrap_err(),
+            format!("unsupported enforcement state version 99 > {}", ENFORCEMENT_STATE_VERSION)
+        );
+    }
 }
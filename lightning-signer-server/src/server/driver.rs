@@ -37,6 +37,7 @@ use lightning_signer::util::debug_utils::DebugBytes;
 use lightning_signer::util::log_utils::{parse_log_level_filter, LOG_LEVEL_FILTER_NAMES};
 use lightning_signer::util::status;
 use lightning_signer::util::status::invalid_argument;
+use lightning_signer::util::{DEFAULT_MIN_INVOICE_CLTV_EXPIRY, INITIAL_COMMITMENT_NUMBER};
 use lightning_signer::{channel, containing_function, debug_vals, short_function, vals_str};
 use remotesigner::signer_server::{Signer, SignerServer};
 use remotesigner::*;
@@ -114,6 +115,10 @@ macro_rules! log_req_reply {
 struct SignServer {
     pub signer: MultiSigner,
     pub network: Network,
+    // Whether nodes may export their secrets, see NodeConfig::export_allowed
+    pub export_allowed: bool,
+    // Whether new nodes accept RBF-signalling funding inputs, see NodeConfig::allow_rbf_funding
+    pub allow_rbf_funding: bool,
 }
 
 pub(super) fn invalid_grpc_argument(msg: impl Into<String>) -> Status {
@@ -292,6 +297,8 @@ fn convert_commitment_type(proto_commitment_type: i32) -> channel::CommitmentTyp
 
 fn convert_node_config(
     network: Network,
+    export_allowed: bool,
+    allow_rbf_funding: bool,
     chainparams: ChainParams,
     proto_node_config: NodeConfig,
 ) -> anyhow::Result<node::NodeConfig> {
@@ -307,7 +314,18 @@ fn convert_node_config(
     if supplied_network != network {
         bail!("network mismatch {} vs configured {}", supplied_network, network);
     }
-    Ok(node::NodeConfig { network, key_derivation_style })
+    Ok(node::NodeConfig {
+        network,
+        key_derivation_style,
+        require_static_remote_key: false,
+        low_r_grinding: false,
+        allow_rbf_funding,
+        maximum_commitment_number: INITIAL_COMMITMENT_NUMBER,
+        export_allowed,
+        allowlist_match_derivable: false,
+        min_invoice_cltv_expiry: DEFAULT_MIN_INVOICE_CLTV_EXPIRY,
+        capabilities: node::Capabilities::ALL,
+    })
 }
 
 #[tonic::async_trait]
@@ -350,8 +368,14 @@ impl Signer for SignServer {
                 return Err(invalid_grpc_argument("hsm_secret must be no larger than 64 bytes"));
             }
         }
-        let node_config = convert_node_config(self.network, proto_chainparams, proto_node_config)
-            .map_err(|e| invalid_grpc_argument(e.to_string()))?;
+        let node_config = convert_node_config(
+            self.network,
+            self.export_allowed,
+            self.allow_rbf_funding,
+            proto_chainparams,
+            proto_node_config,
+        )
+        .map_err(|e| invalid_grpc_argument(e.to_string()))?;
 
         let node_id = if hsm_secret.len() == 0 {
             self.signer.new_node(node_config)
@@ -378,9 +402,9 @@ impl Signer for SignServer {
         log_req_enter!(&node_id, &req);
 
         let node = self.signer.get_node(&node_id)?;
-        let extpubkey = node.get_account_extended_pubkey();
+        let extpubkey = node.get_account_extended_pubkey()?;
         let bolt12_pubkey = node.get_bolt12_pubkey();
-        let node_secret = node.get_node_secret();
+        let node_secret = node.get_node_secret()?;
         let reply = GetNodeParamReply {
             xpub: Some(ExtPubKey { encoded: format!("{}", extpubkey) }),
             bolt12_pubkey: Some(XOnlyPubKey { data: bolt12_pubkey.serialize().to_vec() }),
@@ -498,6 +522,9 @@ impl Signer for SignServer {
             )?)
         };
 
+        let counterparty_node_id =
+            req.counterparty_node_id.map(|id| self.node_id(Some(id))).transpose()?;
+
         let holder_shutdown_key_path = req.holder_shutdown_key_path.to_vec();
         let setup = ChannelSetup {
             is_outbound: req.is_outbound,
@@ -506,10 +533,13 @@ impl Signer for SignServer {
             funding_outpoint,
             holder_selected_contest_delay: req.holder_selected_contest_delay as u16,
             counterparty_points,
+            counterparty_node_id,
             holder_shutdown_script,
             counterparty_selected_contest_delay: req.counterparty_selected_contest_delay as u16,
             counterparty_shutdown_script,
             commitment_type: convert_commitment_type(req.commitment_type),
+            splice_pending_amount: None,
+            splice_funding: None,
         };
         let node = self.signer.get_node(&node_id)?;
         node.ready_channel(channel_id0, opt_channel_id, setup, &holder_shutdown_key_path)?;
@@ -1101,9 +1131,7 @@ impl Signer for SignServer {
         let ca = req.channel_announcement;
         let (nsig, bsig) = self
             .signer
-            .with_ready_channel(&node_id, &channel_id, |chan| {
-                Ok(chan.sign_channel_announcement(&ca))
-            })
+            .with_ready_channel(&node_id, &channel_id, |chan| chan.sign_channel_announcement(&ca))
             .map_err(|e| Status::internal(e.to_string()))?;
         let reply = SignChannelAnnouncementReply {
             node_signature: Some(nsig.into()),
@@ -1152,7 +1180,7 @@ impl Signer for SignServer {
         log_req_enter!(&node_id, &other_key, &req);
 
         let node = self.signer.get_node(&node_id)?;
-        let data = node.ecdh(&other_key);
+        let data = node.ecdh(&other_key)?;
         let reply = EcdhReply { shared_secret: Some(Secret { data }) };
         log_req_reply!(&node_id, &other_key, &reply);
         Ok(Response::new(reply))
@@ -1368,7 +1396,13 @@ impl Signer for SignServer {
             .iter()
             .map(|(id, chan_mutex)| {
                 let chan = chan_mutex.lock().unwrap();
-                info!("chan id={} nonce={} id_in_obj={}", id, hex::encode(chan.nonce()), chan.id());
+                info!(
+                    "chan id={} nonce={} id_in_obj={} alternate_id={:?}",
+                    id,
+                    hex::encode(chan.nonce()),
+                    chan.channel_id(),
+                    chan.alternate_channel_id()
+                );
                 chan.nonce()
             })
             .map(|nonce| ChannelNonce { data: nonce })
@@ -1497,6 +1531,18 @@ pub async fn start() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("INFO")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("allow-export")
+                .about("allow nodes to export their secret and xpub, needed by get_node_param")
+                .long("allow-export")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("reject-rbf-funding")
+                .about("reject funding transactions with RBF-signalling inputs for new nodes")
+                .long("reject-rbf-funding")
+                .takes_value(false),
+        )
         .arg(
             Arg::new("initial-allowlist-file")
                 .about("specify file containing initial allowlist")
@@ -1531,10 +1577,14 @@ pub async fn start() -> Result<(), Box<dyn std::error::Error>> {
     info!("data directory {}", data_path);
 
     let test_mode = matches.is_present("test-mode");
+    let export_allowed = matches.is_present("allow-export");
     let persister: Arc<dyn Persist> = if matches.is_present("no-persist") {
         Arc::new(DummyPersister)
     } else {
-        Arc::new(KVJsonPersister::new(data_path.as_str()))
+        let persister = KVJsonPersister::new(data_path.as_str());
+        // The setting applies to nodes created by earlier runs as well
+        persister.set_export_allowed(export_allowed);
+        Arc::new(persister)
     };
     let mut initial_allowlist = vec![];
     if matches.is_present("initial-allowlist-file") {
@@ -1546,8 +1596,10 @@ pub async fn start() -> Result<(), Box<dyn std::error::Error>> {
     let policy = policy(&matches, network);
     let validator_factory = Arc::new(SimpleValidatorFactory::new_with_policy(policy));
     let signer =
-        MultiSigner::new_with_persister(persister, test_mode, initial_allowlist, validator_factory);
-    let server = SignServer { signer, network };
+        MultiSigner::new_with_persister(persister, test_mode, initial_allowlist, validator_factory)
+            .expect("restore nodes");
+    let allow_rbf_funding = !matches.is_present("reject-rbf-funding");
+    let server = SignServer { signer, network, export_allowed, allow_rbf_funding };
 
     let (shutdown_trigger, shutdown_signal) = triggered::trigger();
     ctrlc::set_handler(move || {
@@ -1578,11 +1630,18 @@ fn setup_tokio_log() {
 fn policy_args(app: App) -> App {
     app.arg(Arg::new("require_invoices").long("require_invoices").takes_value(false))
         .arg(Arg::new("enforce_balance").long("enforce_balance").takes_value(false))
+        .arg(
+            Arg::new("require_funding_anti_fee_sniping")
+                .long("require_funding_anti_fee_sniping")
+                .takes_value(false),
+        )
 }
 
 fn policy(matches: &ArgMatches, network: Network) -> SimplePolicy {
     let mut policy = make_simple_policy(network);
     policy.require_invoices = matches.is_present("require_invoices");
     policy.enforce_balance = matches.is_present("enforce_balance");
+    policy.require_funding_anti_fee_sniping =
+        matches.is_present("require_funding_anti_fee_sniping");
     policy
 }
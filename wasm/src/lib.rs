@@ -12,10 +12,11 @@ use wasm_bindgen::prelude::*;
 use web_sys;
 
 use lightning_signer::channel::{ChannelId, ChannelSetup, CommitmentType};
-use lightning_signer::node::{Node, NodeConfig};
+use lightning_signer::node::{Capabilities, Node, NodeConfig};
 use lightning_signer::persist::{DummyPersister, Persist};
 use lightning_signer::signer::my_keys_manager::KeyDerivationStyle;
 use lightning_signer::util::key_utils::make_test_key;
+use lightning_signer::util::{DEFAULT_MIN_INVOICE_CLTV_EXPIRY, INITIAL_COMMITMENT_NUMBER};
 use lightning_signer::Arc;
 use lightning_signer::{bitcoin, lightning};
 use lightning_signer::policy::simple_validator::SimpleValidatorFactory;
@@ -214,9 +215,12 @@ impl JSNode {
             holder_selected_contest_delay: s.holder_selected_contest_delay,
             holder_shutdown_script: None,
             counterparty_points: cp_points,
+            counterparty_node_id: None,
             counterparty_selected_contest_delay: s.counterparty_selected_contest_delay,
             counterparty_shutdown_script: None,
             commitment_type: CommitmentType::Legacy,
+            splice_pending_amount: None,
+            splice_funding: None,
         };
         let _channel = self.node.ready_channel(id.0, None, setup, &vec![]).map_err(from_status)?;
         Ok(())
@@ -250,8 +254,18 @@ fn from_status(s: Status) -> JSValidationError {
 
 #[wasm_bindgen]
 pub fn make_node() -> JSNode {
-    let config =
-        NodeConfig { network: Network::Testnet, key_derivation_style: KeyDerivationStyle::Native };
+    let config = NodeConfig {
+        network: Network::Testnet,
+        key_derivation_style: KeyDerivationStyle::Native,
+        require_static_remote_key: false,
+        low_r_grinding: false,
+        allow_rbf_funding: false,
+        maximum_commitment_number: INITIAL_COMMITMENT_NUMBER,
+        export_allowed: false,
+        allowlist_match_derivable: false,
+        min_invoice_cltv_expiry: DEFAULT_MIN_INVOICE_CLTV_EXPIRY,
+        capabilities: Capabilities::ALL,
+    };
     let mut seed = [0u8; 32];
     randomize_buffer(&mut seed);
     // TODO remove in production :)